@@ -0,0 +1,602 @@
+//! Windowed aggregation into periodic summary records
+//!
+//! Each `[[aggregate]]` entry turns the record stream into one summary per
+//! window: count, min/max/mean/stddev and first/last per node and metric,
+//! plus link figures (records vs. expected, CRC errors). With `slide_secs`
+//! unset windows are tumbling; a smaller `slide_secs` that divides
+//! `window_secs` gives overlapping sliding windows. Windows are aligned to
+//! the Unix epoch and use record time (the corrected wall clock).
+//!
+//! Late and missing data:
+//! - Records may arrive out of order by up to `allowed_lateness_secs`: a
+//!   window is only emitted once record time has passed its end by that
+//!   much, so they still land in the right window.
+//! - Records for a window that was already emitted are dropped and counted
+//!   in the next summary's `late_records`.
+//! - Windows without records are still emitted (zero records, 100 % loss) so
+//!   gaps show up downstream. While nothing arrives, record time advances
+//!   with the wall clock since the last record.
+//! - At shutdown, windows holding data are emitted early, marked `partial`.
+//!
+//! Summaries go to the sinks with `summaries = true` in their
+//! `[router.sinks.<name>]` section.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tracing::{debug, warn};
+
+use crate::telemetry::{parse_metric, ProcessedRecord, METRIC_NAMES};
+
+/// Longest run of empty windows emitted for a gap; longer gaps (e.g. the
+/// gateway was down for days) are skipped with a warning
+const MAX_EMPTY_WINDOWS: u64 = 1000;
+
+/// One aggregation (`[[aggregate]]` entry)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AggregateConfig {
+    /// Identifies this aggregation in its summaries
+    pub name: String,
+    pub window_secs: u64,
+    /// Start a window every `slide_secs` (unset = tumbling windows)
+    pub slide_secs: Option<u64>,
+    /// How long after a window's end late records are still accepted
+    pub allowed_lateness_secs: u64,
+    /// node1's report interval, for expected record counts and packet loss
+    pub expected_interval_secs: u64,
+    /// `<node>.<metric>` names to summarize (empty = all)
+    pub metrics: Vec<String>,
+}
+
+impl Default for AggregateConfig {
+    fn default() -> Self {
+        Self {
+            name: "summary".to_string(),
+            window_secs: 300,
+            slide_secs: None,
+            allowed_lateness_secs: 30,
+            expected_interval_secs: 10,
+            metrics: Vec::new(),
+        }
+    }
+}
+
+/// One metric over one window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricSummary {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Sample standard deviation (0 for a single value)
+    pub stddev: f64,
+    /// Value at the earliest record time in the window
+    pub first: f64,
+    /// Value at the latest record time in the window
+    pub last: f64,
+}
+
+/// Periodic summary record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowSummary {
+    /// `name` of the `[[aggregate]]` entry
+    pub aggregate: String,
+    /// Window start (inclusive), Unix ms
+    pub start_ms: u64,
+    /// Window end (exclusive), Unix ms
+    pub end_ms: u64,
+    pub records: u64,
+    pub expected_records: u64,
+    /// Share of expected records that never arrived
+    pub packet_loss_pct: f64,
+    /// CRC errors node2 counted during the window
+    pub crc_errors: u32,
+    /// Records dropped since the previous summary because their window had
+    /// already been emitted
+    pub late_records: u64,
+    /// Emitted at shutdown before the window was over
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    /// Per node, per metric
+    pub nodes: BTreeMap<String, BTreeMap<String, MetricSummary>>,
+}
+
+/// Running statistics for one metric (Welford's algorithm)
+#[derive(Debug, Clone)]
+struct Accumulator {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+    first: (u64, f64),
+    last: (u64, f64),
+}
+
+impl Accumulator {
+    fn new(at_ms: u64, value: f64) -> Self {
+        Self {
+            count: 1,
+            mean: value,
+            m2: 0.0,
+            min: value,
+            max: value,
+            first: (at_ms, value),
+            last: (at_ms, value),
+        }
+    }
+
+    fn add(&mut self, at_ms: u64, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        // Record time, not arrival order, decides first and last
+        if at_ms < self.first.0 {
+            self.first = (at_ms, value);
+        }
+        if at_ms >= self.last.0 {
+            self.last = (at_ms, value);
+        }
+    }
+
+    fn summary(&self) -> MetricSummary {
+        let variance = if self.count > 1 {
+            self.m2 / (self.count - 1) as f64
+        } else {
+            0.0
+        };
+        MetricSummary {
+            count: self.count,
+            min: self.min,
+            max: self.max,
+            mean: self.mean,
+            stddev: variance.sqrt(),
+            first: self.first.1,
+            last: self.last.1,
+        }
+    }
+}
+
+/// Everything collected for one window
+#[derive(Debug, Default)]
+struct Window {
+    records: u64,
+    metrics: BTreeMap<(&'static str, &'static str), Accumulator>,
+    /// node2's CRC error counter at the latest record before the window,
+    /// the errors since then count towards it
+    crc_before: Option<(u64, u32)>,
+    /// node2's CRC error counter at the earliest and latest record
+    crc_first: Option<(u64, u32)>,
+    crc_last: Option<(u64, u32)>,
+}
+
+impl Window {
+    fn starting_after(crc_before: Option<(u64, u32)>) -> Self {
+        Self {
+            crc_before,
+            ..Self::default()
+        }
+    }
+
+    /// A record from before the window arrived
+    fn observe_before(&mut self, crc: (u64, u32)) {
+        if self.crc_before.is_none_or(|(before, _)| crc.0 > before) {
+            self.crc_before = Some(crc);
+        }
+    }
+
+    fn add(&mut self, record: &ProcessedRecord, metrics: &[(&'static str, &'static str)]) {
+        let at_ms = record.timestamp_ms();
+        self.records += 1;
+        for metric in record.metrics() {
+            if !metrics.contains(&(metric.node, metric.name)) {
+                continue;
+            }
+            self.metrics
+                .entry((metric.node, metric.name))
+                .and_modify(|acc| acc.add(at_ms, metric.value))
+                .or_insert_with(|| Accumulator::new(at_ms, metric.value));
+        }
+        let err = record.packet.sts.err;
+        if self.crc_first.is_none_or(|(first, _)| at_ms < first) {
+            self.crc_first = Some((at_ms, err));
+        }
+        if self.crc_last.is_none_or(|(last, _)| at_ms >= last) {
+            self.crc_last = Some((at_ms, err));
+        }
+    }
+
+    fn crc_errors(&self) -> u32 {
+        // Without an earlier record, count from the window's first one
+        let baseline = self.crc_before.or(self.crc_first);
+        match (baseline, self.crc_last) {
+            // A counter that went backwards was reset (node2 rebooted)
+            (Some((_, first)), Some((_, last))) if last >= first => last - first,
+            (_, Some((_, last))) => last,
+            _ => 0,
+        }
+    }
+}
+
+/// Windows of one `[[aggregate]]` entry
+struct WindowSet {
+    name: String,
+    window_ms: u64,
+    slide_ms: u64,
+    lateness_ms: u64,
+    expected_records: u64,
+    metrics: Vec<(&'static str, &'static str)>,
+    /// Windows with data, by start time
+    open: BTreeMap<u64, Window>,
+    /// Start of the first window not emitted yet (`None` before the first
+    /// emission)
+    next_start: Option<u64>,
+    late: u64,
+    /// node2's CRC error counter at the latest record
+    latest_crc: Option<(u64, u32)>,
+}
+
+impl WindowSet {
+    fn new(config: &AggregateConfig) -> Result<Self> {
+        let slide_secs = config.slide_secs.unwrap_or(config.window_secs);
+        if config.name.is_empty() {
+            bail!("[[aggregate]] entries need a name");
+        }
+        if config.window_secs == 0
+            || slide_secs == 0
+            || !config.window_secs.is_multiple_of(slide_secs)
+        {
+            bail!(
+                "Aggregate '{}': slide_secs must divide window_secs (both > 0)",
+                config.name
+            );
+        }
+        let metrics = if config.metrics.is_empty() {
+            METRIC_NAMES.to_vec()
+        } else {
+            let mut metrics = Vec::with_capacity(config.metrics.len());
+            for name in &config.metrics {
+                match parse_metric(name) {
+                    Some(metric) => metrics.push(metric),
+                    None => bail!("Aggregate '{}': unknown metric '{}'", config.name, name),
+                }
+            }
+            metrics
+        };
+        Ok(Self {
+            name: config.name.clone(),
+            window_ms: config.window_secs * 1000,
+            slide_ms: slide_secs * 1000,
+            lateness_ms: config.allowed_lateness_secs * 1000,
+            expected_records: match config.expected_interval_secs {
+                0 => 0,
+                interval => config.window_secs / interval,
+            },
+            metrics,
+            open: BTreeMap::new(),
+            next_start: None,
+            late: 0,
+            latest_crc: None,
+        })
+    }
+
+    /// Add a record to every window containing it
+    fn add(&mut self, record: &ProcessedRecord) {
+        let at_ms = record.timestamp_ms();
+        let last_start = at_ms - at_ms % self.slide_ms;
+        let first_start = last_start.saturating_sub(self.window_ms - self.slide_ms);
+        // Until the first window is emitted, out-of-order records are fine
+        let next_start = self.next_start.unwrap_or(0);
+
+        // Windows starting after the record count CRC errors from it on
+        let crc = (at_ms, record.packet.sts.err);
+        for (_, window) in self.open.range_mut(last_start + 1..) {
+            window.observe_before(crc);
+        }
+        let latest_crc = self.latest_crc;
+
+        let mut late = false;
+        for start in (first_start..=last_start).step_by(self.slide_ms as usize) {
+            if start < next_start {
+                late = true;
+            } else {
+                self.open
+                    .entry(start)
+                    .or_insert_with(|| {
+                        Window::starting_after(latest_crc.filter(|&(at, _)| at < start))
+                    })
+                    .add(record, &self.metrics);
+            }
+        }
+        if latest_crc.is_none_or(|(latest, _)| at_ms >= latest) {
+            self.latest_crc = Some(crc);
+        }
+        if late {
+            debug!(aggregate = %self.name, at_ms, "Late record dropped from emitted window");
+            self.late += 1;
+        }
+    }
+
+    /// Emit every window that ended at least `lateness` before `watermark_ms`
+    fn emit(&mut self, watermark_ms: u64) -> Vec<WindowSummary> {
+        let mut summaries = Vec::new();
+        // Emission starts at the earliest window holding data
+        while let Some(start) = self.next_start.or(self.open.keys().next().copied()) {
+            if start + self.window_ms + self.lateness_ms > watermark_ms {
+                break;
+            }
+            self.next_start = Some(start);
+            self.skip_long_gap(start, watermark_ms);
+            let start = self.next_start.expect("set above");
+            if start + self.window_ms + self.lateness_ms > watermark_ms {
+                break;
+            }
+            let window = self.open.remove(&start).unwrap_or_default();
+            summaries.push(self.summary(start, &window, false));
+            self.next_start = Some(start + self.slide_ms);
+        }
+        summaries
+    }
+
+    /// Jump over a silence of more than `MAX_EMPTY_WINDOWS` windows
+    fn skip_long_gap(&mut self, start: u64, watermark_ms: u64) {
+        let gap_end = match self.open.keys().next() {
+            Some(&next_data) => next_data,
+            None => watermark_ms.saturating_sub(self.window_ms + self.lateness_ms),
+        };
+        let empty = gap_end.saturating_sub(start) / self.slide_ms;
+        if empty > MAX_EMPTY_WINDOWS {
+            let resume = gap_end - gap_end % self.slide_ms;
+            warn!(
+                aggregate = %self.name,
+                skipped = empty,
+                "No data for a long time, skipping empty windows"
+            );
+            self.next_start = Some(resume);
+        }
+    }
+
+    /// Emit the windows holding data now, marked partial
+    fn finish(&mut self) -> Vec<WindowSummary> {
+        std::mem::take(&mut self.open)
+            .iter()
+            .map(|(&start, window)| self.summary(start, window, true))
+            .collect()
+    }
+
+    fn summary(&mut self, start: u64, window: &Window, partial: bool) -> WindowSummary {
+        let mut nodes: BTreeMap<String, BTreeMap<String, MetricSummary>> = BTreeMap::new();
+        for (&(node, name), acc) in &window.metrics {
+            nodes
+                .entry(node.to_string())
+                .or_default()
+                .insert(name.to_string(), acc.summary());
+        }
+        let missing = self.expected_records.saturating_sub(window.records);
+        WindowSummary {
+            aggregate: self.name.clone(),
+            start_ms: start,
+            end_ms: start + self.window_ms,
+            records: window.records,
+            expected_records: self.expected_records,
+            packet_loss_pct: match self.expected_records {
+                0 => 0.0,
+                expected => missing as f64 * 100.0 / expected as f64,
+            },
+            crc_errors: window.crc_errors(),
+            late_records: std::mem::take(&mut self.late),
+            partial,
+            nodes,
+        }
+    }
+}
+
+/// All configured aggregations, driven by the processor
+pub struct Aggregator {
+    sets: Vec<WindowSet>,
+    /// Latest record time seen
+    max_record_ms: u64,
+    /// When the latest record arrived
+    last_arrival: Option<Instant>,
+}
+
+impl Aggregator {
+    pub fn new(configs: &[AggregateConfig]) -> Result<Self> {
+        let mut sets: Vec<WindowSet> = Vec::with_capacity(configs.len());
+        for config in configs {
+            if sets.iter().any(|set| set.name == config.name) {
+                bail!("Duplicate aggregate name '{}'", config.name);
+            }
+            sets.push(WindowSet::new(config)?);
+        }
+        Ok(Self {
+            sets,
+            max_record_ms: 0,
+            last_arrival: None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    /// Add a record; returns the windows it closed
+    pub fn observe(&mut self, record: &ProcessedRecord, now: Instant) -> Vec<WindowSummary> {
+        self.max_record_ms = self.max_record_ms.max(record.timestamp_ms());
+        self.last_arrival = Some(now);
+        for set in &mut self.sets {
+            set.add(record);
+        }
+        self.emit(self.max_record_ms)
+    }
+
+    /// Close windows while no records arrive, advancing record time with
+    /// the wall clock
+    pub fn tick(&mut self, now: Instant) -> Vec<WindowSummary> {
+        let Some(last_arrival) = self.last_arrival else {
+            return Vec::new();
+        };
+        let idle_ms = now.saturating_duration_since(last_arrival).as_millis() as u64;
+        self.emit(self.max_record_ms + idle_ms)
+    }
+
+    /// Emit what's left at shutdown
+    pub fn finish(&mut self) -> Vec<WindowSummary> {
+        self.sets.iter_mut().flat_map(WindowSet::finish).collect()
+    }
+
+    fn emit(&mut self, watermark_ms: u64) -> Vec<WindowSummary> {
+        self.sets
+            .iter_mut()
+            .flat_map(|set| set.emit(watermark_ms))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+    use std::time::Duration;
+
+    const T0: u64 = 1_767_225_600_000; // 2026-01-01T00:00:00Z
+
+    fn aggregator(window_secs: u64, slide_secs: Option<u64>) -> Aggregator {
+        Aggregator::new(&[AggregateConfig {
+            name: "test".to_string(),
+            window_secs,
+            slide_secs,
+            allowed_lateness_secs: 5,
+            expected_interval_secs: 10,
+            metrics: vec!["n1.temperature".to_string()],
+        }])
+        .unwrap()
+    }
+
+    /// Feed (offset s, temperature) readings, collecting summaries
+    fn feed(agg: &mut Aggregator, readings: &[(u64, f32)]) -> Vec<WindowSummary> {
+        let now = Instant::now();
+        readings
+            .iter()
+            .flat_map(|&(s, t)| agg.observe(&record(T0 + s * 1000, t), now))
+            .collect()
+    }
+
+    #[test]
+    fn test_tumbling_window_statistics() {
+        let mut agg = aggregator(60, None);
+        let out = feed(
+            &mut agg,
+            &[(0, 20.0), (10, 22.0), (20, 24.0), (30, 26.0), (66, 30.0)],
+        );
+        // The first window closes once record time passes 60 s + lateness
+        assert_eq!(out.len(), 1);
+        let s = &out[0];
+        assert_eq!((s.start_ms, s.end_ms), (T0, T0 + 60_000));
+        assert_eq!((s.records, s.expected_records), (4, 6));
+        assert!((s.packet_loss_pct - 33.333).abs() < 0.01);
+
+        let t = &s.nodes["n1"]["temperature"];
+        assert_eq!((t.count, t.min, t.max, t.mean), (4, 20.0, 26.0, 23.0));
+        assert!((t.stddev - 2.582).abs() < 0.001);
+        assert_eq!((t.first, t.last), (20.0, 26.0));
+        assert!(!s.nodes.contains_key("n2"));
+
+        let json = serde_json::to_value(s).unwrap();
+        assert_eq!(json["nodes"]["n1"]["temperature"]["mean"], 23.0);
+        assert!(json.get("partial").is_none());
+    }
+
+    #[test]
+    fn test_late_and_out_of_order_records() {
+        let mut agg = aggregator(60, None);
+        // 50 s arrives after 62 s but within the lateness, so it still counts
+        // and decides `last`; 55 s arrives after the window closed
+        let out = feed(
+            &mut agg,
+            &[(0, 20.0), (62, 21.0), (50, 25.0), (66, 22.0), (55, 99.0)],
+        );
+        assert_eq!(out.len(), 1);
+        let t = &out[0].nodes["n1"]["temperature"];
+        assert_eq!((t.count, t.last), (2, 25.0));
+
+        let out = feed(&mut agg, &[(126, 23.0)]);
+        assert_eq!(out[0].start_ms, T0 + 60_000);
+        assert_eq!(out[0].late_records, 1);
+    }
+
+    #[test]
+    fn test_gaps_emit_empty_windows() {
+        let mut agg = aggregator(60, None);
+        feed(&mut agg, &[(0, 20.0)]);
+        let start = Instant::now();
+        // Nothing arrives: record time follows the wall clock
+        assert!(agg.tick(start + Duration::from_secs(30)).is_empty());
+        let out = agg.tick(start + Duration::from_secs(185));
+        assert_eq!(out.len(), 3);
+        assert_eq!(out[0].records, 1);
+        assert_eq!(out[1].records, 0);
+        assert_eq!(out[1].packet_loss_pct, 100.0);
+        assert!(out[1].nodes.is_empty());
+    }
+
+    #[test]
+    fn test_sliding_windows_overlap() {
+        let mut agg = aggregator(60, Some(30));
+        let out = feed(&mut agg, &[(0, 20.0), (40, 30.0), (80, 40.0)]);
+        // [-30, 30) and [0, 60) closed, [30, 90) still open
+        let starts: Vec<u64> = out.iter().map(|s| s.start_ms).collect();
+        assert_eq!(starts, vec![T0 - 30_000, T0]);
+        assert_eq!(out[1].nodes["n1"]["temperature"].count, 2);
+
+        let rest = agg.finish();
+        assert!(rest.iter().all(|s| s.partial));
+        assert_eq!(rest[0].start_ms, T0 + 30_000);
+        assert_eq!(rest[0].nodes["n1"]["temperature"].mean, 35.0);
+    }
+
+    #[test]
+    fn test_crc_errors_between_windows_are_counted() {
+        let mut agg = aggregator(60, None);
+        let now = Instant::now();
+        let mut observe = |s: u64, err: u32| {
+            let mut r = record(T0 + s * 1000, 20.0);
+            r.packet.sts.err = err;
+            agg.observe(&r, now)
+        };
+        let mut out = observe(10, 5);
+        out.extend(observe(50, 5));
+        // Two errors between the windows, one during the next
+        out.extend(observe(70, 7));
+        out.extend(observe(110, 8));
+        // A single-record window still counts the errors since the last one
+        out.extend(observe(130, 9));
+        // node2 rebooted: its counter starts over
+        out.extend(observe(190, 1));
+        out.extend(observe(250, 1));
+
+        let errors: Vec<u32> = out.iter().map(|s| s.crc_errors).collect();
+        assert_eq!(errors, vec![0, 3, 1, 1]);
+    }
+
+    #[test]
+    fn test_invalid_configs_rejected() {
+        let bad = |config: AggregateConfig| Aggregator::new(&[config]).is_err();
+        assert!(bad(AggregateConfig {
+            slide_secs: Some(70),
+            ..AggregateConfig::default()
+        }));
+        assert!(bad(AggregateConfig {
+            metrics: vec!["n3.temperature".to_string()],
+            ..AggregateConfig::default()
+        }));
+        assert!(
+            Aggregator::new(&[AggregateConfig::default(), AggregateConfig::default()]).is_err()
+        );
+    }
+}
@@ -0,0 +1,713 @@
+//! HTTP query API over the telemetry database
+//!
+//! Query endpoints (JSON by default, CSV with `?format=csv`):
+//! - `GET /api/v1/latest`: latest value of every metric per node
+//! - `GET /api/v1/history?node=n1&metric=temperature&from=&to=&bucket=`:
+//!   raw readings, or avg/min/max/count per `bucket` seconds
+//! - `GET /api/v1/link?from=&to=`: RSSI/SNR and packet counter history
+//! - `GET /api/v1/link/quality`: live link analytics per node (see `link`,
+//!   JSON only, also without storage)
+//! - `GET /api/v1/stats`: gateway uptime, database summary and per-sink
+//!   queue counters (JSON only)
+//! - `GET /api/v1/stream/{ws,sse}`: live updates (see `stream`)
+//! - `GET /`: the built-in dashboard (see `dashboard`)
+//!
+//! Calibration profiles (see `calibration`, JSON only):
+//! - `GET /api/v1/calibration`: profiles in effect
+//! - `PUT /api/v1/calibration/{node}/{sensor}/{quantity}`: set an override,
+//!   body `{"gain": .., "offset": ..}` or `{"points": [[raw, true], ..]}`
+//! - `DELETE /api/v1/calibration/{node}/{sensor}/{quantity}`: remove it
+//!
+//! Writes need `Authorization: Bearer <token>` with the token from
+//! `admin_token_env`, and are refused (403) when that isn't configured.
+//!
+//! `from`/`to` are Unix ms (default: the last 24 hours). List endpoints are
+//! paginated with `limit`/`offset`; the next offset is returned in the JSON
+//! body and the `X-Next-Offset` header while more rows may follow.
+//!
+//! Queries run on the blocking pool against their own SQLite connection so
+//! they never hold up the storage writer (the database is in WAL mode).
+//! Without `[storage]` only streaming and stats are available; the query
+//! endpoints answer 503.
+
+use anyhow::{Context, Result};
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::calibration::{ActiveProfile, CalibrationError, CalibrationProfile, Calibrator};
+use crate::clock::unix_time_ms;
+use crate::dashboard;
+use crate::link::{LinkAnalytics, LinkReport};
+use crate::sink::{SinkRegistry, SinkStats};
+use crate::storage::{HistoryQuery, Page, Storage, StorageConfig, StorageSummary};
+use crate::stream::{self, StreamHub};
+use crate::telemetry::METRIC_NAMES;
+
+/// Default query window when `from` is omitted
+const DEFAULT_WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Largest accepted aggregation bucket (31 days)
+const MAX_BUCKET_S: u32 = 31 * 24 * 60 * 60;
+
+/// API configuration (`[api]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// Listen address
+    pub bind: SocketAddr,
+    /// Rows per page when `limit` is omitted
+    pub default_page_size: u32,
+    /// Largest accepted `limit`
+    pub max_page_size: u32,
+    /// Serve the built-in dashboard at `/`
+    pub dashboard: bool,
+    /// Environment variable holding the bearer token for write endpoints
+    /// (writes are disabled without it)
+    pub admin_token_env: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 8000)),
+            default_page_size: 100,
+            max_page_size: 1000,
+            dashboard: true,
+            admin_token_env: None,
+        }
+    }
+}
+
+/// API errors, returned to clients as `{"error": "..."}`
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(&'static str),
+    #[error("{0}")]
+    Forbidden(&'static str),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Unavailable(&'static str),
+    #[error("internal error")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(e) => {
+                error!(error = %format!("{:#}", e), "API query failed");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        let body = serde_json::json!({ "error": self.to_string() });
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<CalibrationError> for ApiError {
+    fn from(e: CalibrationError) -> Self {
+        match e {
+            CalibrationError::Invalid(_) => ApiError::BadRequest(e.to_string()),
+            CalibrationError::NotFound(message) => ApiError::NotFound(message),
+            CalibrationError::Save(e) => ApiError::Internal(e),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    storage: Option<Arc<Mutex<Storage>>>,
+    config: ApiConfig,
+    started_at: Instant,
+    started_at_ms: u64,
+    sinks: SinkRegistry,
+    calibration: Calibrator,
+    link: LinkAnalytics,
+    /// Bearer token for write endpoints (`None` = writes disabled)
+    admin_token: Option<String>,
+}
+
+impl AppState {
+    /// Run a database query on the blocking pool
+    async fn query<T, F>(&self, f: F) -> Result<T, ApiError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Storage) -> Result<T> + Send + 'static,
+    {
+        let storage = self
+            .storage
+            .clone()
+            .ok_or(ApiError::Unavailable("telemetry storage is not configured"))?;
+        tokio::task::spawn_blocking(move || {
+            let storage = storage
+                .lock()
+                .map_err(|_| anyhow::anyhow!("storage lock poisoned"))?;
+            f(&storage)
+        })
+        .await
+        .context("Query task failed")?
+        .map_err(ApiError::from)
+    }
+
+    fn page(&self, limit: Option<u32>, offset: u64) -> Result<Page, ApiError> {
+        let limit = limit.unwrap_or(self.config.default_page_size);
+        if limit == 0 || limit > self.config.max_page_size {
+            return Err(ApiError::BadRequest(format!(
+                "limit must be between 1 and {}",
+                self.config.max_page_size
+            )));
+        }
+        Ok(Page { limit, offset })
+    }
+
+    /// Check the bearer token on a write request
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), ApiError> {
+        let token = self.admin_token.as_deref().ok_or(ApiError::Forbidden(
+            "API writes are disabled (no admin token configured)",
+        ))?;
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiError::Unauthorized("missing bearer token"))?;
+        if !constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+            return Err(ApiError::Unauthorized("invalid bearer token"));
+        }
+        Ok(())
+    }
+}
+
+/// Compare without an early exit so response timing doesn't leak the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// `GET /api/v1/link` parameters
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LinkParams {
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<u32>,
+    #[serde(default)]
+    offset: u64,
+    #[serde(default)]
+    format: Format,
+}
+
+/// `GET /api/v1/history` parameters
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HistoryParams {
+    node: String,
+    metric: String,
+    bucket: Option<u32>,
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<u32>,
+    #[serde(default)]
+    offset: u64,
+    #[serde(default)]
+    format: Format,
+}
+
+/// Resolve `[from, to)`, defaulting to the last 24 hours
+fn time_range(from: Option<u64>, to: Option<u64>) -> Result<(u64, u64), ApiError> {
+    let to = to.unwrap_or_else(unix_time_ms);
+    let from = from.unwrap_or(to.saturating_sub(DEFAULT_WINDOW_MS));
+    if from >= to {
+        return Err(ApiError::BadRequest("from must be before to".to_string()));
+    }
+    Ok((from, to))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FormatParams {
+    #[serde(default)]
+    format: Format,
+}
+
+/// One page of results
+#[derive(Serialize)]
+struct Paginated<T> {
+    items: Vec<T>,
+    limit: u32,
+    offset: u64,
+    /// Offset of the next page, absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<u64>,
+}
+
+impl<T: Serialize> Paginated<T> {
+    fn new(items: Vec<T>, page: Page) -> Self {
+        let next_offset =
+            (items.len() as u32 == page.limit).then_some(page.offset + page.limit as u64);
+        Self {
+            items,
+            limit: page.limit,
+            offset: page.offset,
+            next_offset,
+        }
+    }
+
+    fn into_response(self, format: Format) -> Result<Response, ApiError> {
+        let next_offset = self.next_offset;
+        let mut response = match format {
+            Format::Json => Json(self).into_response(),
+            Format::Csv => csv_response(&self.items)?,
+        };
+        if let Some(next) = next_offset {
+            response
+                .headers_mut()
+                .insert("x-next-offset", HeaderValue::from(next));
+        }
+        Ok(response)
+    }
+}
+
+fn csv_response<T: Serialize>(rows: &[T]) -> Result<Response, ApiError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row).context("CSV serialization failed")?;
+    }
+    let body = writer.into_inner().context("CSV serialization failed")?;
+    Ok(([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], body).into_response())
+}
+
+async fn latest(
+    State(state): State<AppState>,
+    params: Result<Query<FormatParams>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(params) = params?;
+    let readings = state.query(|storage| storage.latest()).await?;
+    match params.format {
+        Format::Json => Ok(Json(readings).into_response()),
+        Format::Csv => csv_response(&readings),
+    }
+}
+
+async fn history(
+    State(state): State<AppState>,
+    params: Result<Query<HistoryParams>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(params) = params?;
+    if !METRIC_NAMES.contains(&(params.node.as_str(), params.metric.as_str())) {
+        return Err(ApiError::BadRequest(format!(
+            "unknown metric {}/{}",
+            params.node, params.metric
+        )));
+    }
+    if let Some(bucket) = params.bucket {
+        if bucket == 0 || bucket > MAX_BUCKET_S {
+            return Err(ApiError::BadRequest(format!(
+                "bucket must be between 1 and {} seconds",
+                MAX_BUCKET_S
+            )));
+        }
+    }
+    let (from_ms, to_ms) = time_range(params.from, params.to)?;
+    let page = state.page(params.limit, params.offset)?;
+    let format = params.format;
+
+    let points = state
+        .query(move |storage| {
+            let query = HistoryQuery {
+                node: &params.node,
+                metric: &params.metric,
+                from_ms,
+                to_ms,
+                bucket_s: params.bucket,
+            };
+            storage.history(&query, page)
+        })
+        .await?;
+    Paginated::new(points, page).into_response(format)
+}
+
+async fn link(
+    State(state): State<AppState>,
+    params: Result<Query<LinkParams>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(params) = params?;
+    let (from_ms, to_ms) = time_range(params.from, params.to)?;
+    let page = state.page(params.limit, params.offset)?;
+
+    let samples = state
+        .query(move |storage| storage.link_history(from_ms, to_ms, page))
+        .await?;
+    Paginated::new(samples, page).into_response(params.format)
+}
+
+#[derive(Serialize)]
+struct GatewayStats {
+    version: &'static str,
+    started_at_ms: u64,
+    uptime_s: u64,
+    /// Absent when storage isn't configured
+    storage: Option<StorageSummary>,
+    sinks: Vec<SinkStats>,
+}
+
+async fn stats(State(state): State<AppState>) -> Result<Json<GatewayStats>, ApiError> {
+    let storage = match state.storage {
+        Some(_) => Some(state.query(|storage| storage.summary()).await?),
+        None => None,
+    };
+    Ok(Json(GatewayStats {
+        version: env!("CARGO_PKG_VERSION"),
+        started_at_ms: state.started_at_ms,
+        uptime_s: state.started_at.elapsed().as_secs(),
+        storage,
+        sinks: state.sinks.stats(),
+    }))
+}
+
+async fn link_quality(State(state): State<AppState>) -> Json<LinkReport> {
+    Json(state.link.report())
+}
+
+async fn calibration(State(state): State<AppState>) -> Json<Vec<ActiveProfile>> {
+    Json(state.calibration.active())
+}
+
+/// `PUT /api/v1/calibration/...` body: the profile without its key
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CurveBody {
+    #[serde(default = "default_gain")]
+    gain: f64,
+    #[serde(default)]
+    offset: f64,
+    #[serde(default)]
+    points: Vec<[f64; 2]>,
+    note: Option<String>,
+}
+
+fn default_gain() -> f64 {
+    1.0
+}
+
+async fn set_calibration(
+    State(state): State<AppState>,
+    Path((node, sensor, quantity)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    body: Result<Json<CurveBody>, JsonRejection>,
+) -> Result<Json<ActiveProfile>, ApiError> {
+    state.authorize(&headers)?;
+    let Json(body) = body?;
+    let profile = CalibrationProfile {
+        node,
+        sensor,
+        quantity,
+        gain: body.gain,
+        offset: body.offset,
+        points: body.points,
+        note: body.note,
+    };
+    Ok(Json(state.calibration.set(profile)?))
+}
+
+async fn remove_calibration(
+    State(state): State<AppState>,
+    Path((node, sensor, quantity)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    state.authorize(&headers)?;
+    state.calibration.remove(&node, &sensor, &quantity)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/v1/latest", get(latest))
+        .route("/api/v1/history", get(history))
+        .route("/api/v1/link", get(link))
+        .route("/api/v1/link/quality", get(link_quality))
+        .route("/api/v1/stats", get(stats))
+        .route("/api/v1/calibration", get(calibration))
+        .route(
+            "/api/v1/calibration/{node}/{sensor}/{quantity}",
+            put(set_calibration).delete(remove_calibration),
+        )
+        .with_state(state)
+}
+
+/// Open a read connection to the database (if any) and start serving the API
+pub async fn spawn(
+    config: &ApiConfig,
+    storage: Option<&StorageConfig>,
+    hub: StreamHub,
+    sinks: SinkRegistry,
+    calibration: Calibrator,
+    link: LinkAnalytics,
+) -> Result<JoinHandle<()>> {
+    let storage = match storage {
+        Some(storage) => Some(Arc::new(Mutex::new(Storage::open(&storage.path)?))),
+        None => None,
+    };
+    let admin_token = match &config.admin_token_env {
+        Some(var) => Some(
+            std::env::var(var)
+                .with_context(|| format!("API admin token variable {} not set", var))?,
+        ),
+        None => None,
+    };
+    let state = AppState {
+        storage,
+        config: config.clone(),
+        started_at: Instant::now(),
+        started_at_ms: unix_time_ms(),
+        sinks,
+        calibration,
+        link,
+        admin_token,
+    };
+
+    let listener = tokio::net::TcpListener::bind(config.bind)
+        .await
+        .with_context(|| format!("Failed to bind API on {}", config.bind))?;
+    info!(bind = %config.bind, "HTTP API listening");
+
+    Ok(tokio::spawn(async move {
+        let dashboard_enabled = state.config.dashboard;
+        let mut app = router(state).merge(stream::router(hub));
+        if dashboard_enabled {
+            app = app.merge(dashboard::router());
+        }
+        if let Err(e) = axum::serve(listener, app).await {
+            error!(error = %e, "HTTP API server failed");
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::CalibrationConfig;
+    use crate::derived::DerivedConfig;
+    use crate::link::LinkConfig;
+    use crate::telemetry::test_support::record;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    const T0: u64 = 1_767_225_600_000; // 2026-01-01T00:00:00Z
+
+    fn test_router() -> Router {
+        let mut storage = Storage::open_in_memory().unwrap();
+        for i in 0..5 {
+            storage
+                .insert(&record(T0 + i * 10_000, 20.0 + i as f32))
+                .unwrap();
+        }
+        router(AppState {
+            storage: Some(Arc::new(Mutex::new(storage))),
+            config: ApiConfig::default(),
+            started_at: Instant::now(),
+            started_at_ms: T0,
+            sinks: SinkRegistry::default(),
+            calibration: Calibrator::new(&CalibrationConfig {
+                overrides_path: None,
+                ..CalibrationConfig::default()
+            })
+            .unwrap(),
+            link: LinkAnalytics::new(&LinkConfig::default(), &DerivedConfig::default()).unwrap(),
+            admin_token: Some("s3cret".to_string()),
+        })
+    }
+
+    async fn get(uri: &str) -> (StatusCode, Option<String>, String) {
+        let response = test_router()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let next = response
+            .headers()
+            .get("x-next-offset")
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, next, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_metric_names_cover_records() {
+        for metric in record(T0, 20.0).metrics() {
+            assert!(METRIC_NAMES.contains(&(metric.node, metric.name)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_paginates() {
+        let uri = format!(
+            "/api/v1/history?node=n1&metric=temperature&from={}&to={}&limit=3",
+            T0,
+            T0 + 60_000
+        );
+        let (status, next, body) = get(&uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(next.as_deref(), Some("3"));
+
+        let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["items"].as_array().unwrap().len(), 3);
+        assert_eq!(page["items"][2]["avg"], 22.0);
+
+        let (_, next, body) = get(&format!("{}&offset=3", uri)).await;
+        let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+        assert_eq!(next, None);
+        assert!(page.get("next_offset").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_history_csv_buckets() {
+        let uri = format!(
+            "/api/v1/history?node=n1&metric=temperature&from={}&to={}&bucket=60&format=csv",
+            T0,
+            T0 + 60_000
+        );
+        let (status, _, body) = get(&uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            format!("ts_ms,avg,min,max,count\n{},22.0,20.0,24.0,5\n", T0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_input() {
+        for uri in [
+            "/api/v1/history?node=n1&metric=bogus",
+            "/api/v1/history?node=n1&metric=temperature&bucket=0",
+            "/api/v1/history?node=n1&metric=temperature&from=10&to=5",
+            "/api/v1/history?node=n1&metric=temperature&limit=5000",
+            "/api/v1/link?from=abc",
+            "/api/v1/link?format=xml",
+        ] {
+            let (status, _, body) = get(uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+            let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert!(error["error"].is_string(), "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_latest_and_stats() {
+        let (status, _, body) = get("/api/v1/latest").await;
+        assert_eq!(status, StatusCode::OK);
+        let latest: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let temp = latest
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|r| r["node"] == "n1" && r["metric"] == "temperature")
+            .unwrap();
+        assert_eq!(temp["value"], 24.0);
+
+        let (status, _, body) = get("/api/v1/stats").await;
+        assert_eq!(status, StatusCode::OK);
+        let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(stats["storage"]["reports"], 5);
+        assert_eq!(stats["storage"]["last_link"]["crc_errors"], 0);
+        assert_eq!(stats["sinks"], serde_json::json!([]));
+
+        let (status, _, body) = get("/api/v1/link/quality").await;
+        assert_eq!(status, StatusCode::OK);
+        let quality: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(quality["nodes"], serde_json::json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_calibration_writes_need_token() {
+        let app = test_router();
+        let put = |token: Option<&str>, body: &str| {
+            let mut request = Request::put("/api/v1/calibration/n2/bmp280/temperature")
+                .header(header::CONTENT_TYPE, "application/json");
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+        let status = |request: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        let body = r#"{"offset": -2.5, "note": "board heating"}"#;
+        assert_eq!(status(put(None, body)).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(put(Some("guess"), body)).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status(put(Some("s3cret"), body)).await, StatusCode::OK);
+        let bad_curve = r#"{"points": [[1.0, 1.0]]}"#;
+        assert_eq!(
+            status(put(Some("s3cret"), bad_curve)).await,
+            StatusCode::BAD_REQUEST
+        );
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/calibration")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let profiles: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(profiles[0]["offset"], -2.5);
+        assert_eq!(profiles[0]["source"], "api");
+
+        let delete = |uri: &str| {
+            Request::delete(uri)
+                .header(header::AUTHORIZATION, "Bearer s3cret")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let uri = "/api/v1/calibration/n2/bmp280/temperature";
+        assert_eq!(status(delete(uri)).await, StatusCode::NO_CONTENT);
+        assert_eq!(status(delete(uri)).await, StatusCode::NOT_FOUND);
+    }
+}
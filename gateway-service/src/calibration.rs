@@ -0,0 +1,576 @@
+//! Per-sensor calibration profiles
+//!
+//! Profiles correct raw sensor readings before anything else sees them:
+//! derived metrics, IAQ, the pipeline and every sink (storage included) work
+//! with calibrated values. A profile is keyed by node, sensor and quantity:
+//!
+//! | Node | Sensor   | Quantities            |
+//! |------|----------|-----------------------|
+//! | `n1` | `sht31`  | temperature, humidity |
+//! | `n1` | `bme680` | gas_resistance        |
+//! | `n2` | `bmp280` | temperature, pressure |
+//!
+//! and is either linear (`gain * raw + offset`) or a piecewise-linear curve
+//! through `(raw, true)` points, extrapolated along the first and last
+//! segments.
+//!
+//! Profiles come from `[[calibration.profiles]]` and can be replaced or
+//! removed at runtime through the API. API changes are saved to
+//! `overrides_path` and take precedence over the config file, also after a
+//! restart; removing an override falls back to the config profile, if any.
+//!
+//! Each record lists the corrections applied to it under `calibration`,
+//! keyed by `<node>.<metric>`, with the raw reading.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+use crate::telemetry::TelemetryPacket;
+
+/// Calibratable readings: (node, sensor, quantity)
+pub const SENSOR_CHANNELS: &[(&str, &str, &str)] = &[
+    ("n1", "sht31", "temperature"),
+    ("n1", "sht31", "humidity"),
+    ("n1", "bme680", "gas_resistance"),
+    ("n2", "bmp280", "temperature"),
+    ("n2", "bmp280", "pressure"),
+];
+
+type Channel = (&'static str, &'static str, &'static str);
+
+/// Calibration configuration (`[calibration]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationConfig {
+    /// Where profiles set through the API are kept (`None` = memory only)
+    pub overrides_path: Option<PathBuf>,
+    /// Profiles from the config file
+    pub profiles: Vec<CalibrationProfile>,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            overrides_path: Some(PathBuf::from("calibration-overrides.json")),
+            profiles: Vec::new(),
+        }
+    }
+}
+
+/// One sensor reading's correction
+///
+/// Either `gain`/`offset` or `points` (at least two `[raw, true]` pairs in
+/// increasing raw order).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationProfile {
+    pub node: String,
+    pub sensor: String,
+    pub quantity: String,
+    pub gain: f64,
+    pub offset: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub points: Vec<[f64; 2]>,
+    /// Free text, e.g. where the reference values came from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl Default for CalibrationProfile {
+    fn default() -> Self {
+        Self {
+            node: String::new(),
+            sensor: String::new(),
+            quantity: String::new(),
+            gain: 1.0,
+            offset: 0.0,
+            points: Vec::new(),
+            note: None,
+        }
+    }
+}
+
+impl CalibrationProfile {
+    /// `<node>/<sensor>/<quantity>`, as in the API path
+    pub fn key(&self) -> String {
+        format!("{}/{}/{}", self.node, self.sensor, self.quantity)
+    }
+
+    fn channel(&self) -> Result<Channel> {
+        SENSOR_CHANNELS
+            .iter()
+            .find(|&&(node, sensor, quantity)| {
+                (node, sensor, quantity)
+                    == (
+                        self.node.as_str(),
+                        self.sensor.as_str(),
+                        self.quantity.as_str(),
+                    )
+            })
+            .copied()
+            .with_context(|| format!("Unknown sensor reading {}", self.key()))
+    }
+
+    fn curve(&self) -> Result<Curve> {
+        if self.points.is_empty() {
+            if !self.gain.is_finite() || !self.offset.is_finite() || self.gain == 0.0 {
+                bail!(
+                    "{}: gain must be finite and non-zero, offset finite",
+                    self.key()
+                );
+            }
+            return Ok(Curve::Linear {
+                gain: self.gain,
+                offset: self.offset,
+            });
+        }
+
+        if self.gain != 1.0 || self.offset != 0.0 {
+            bail!("{}: use either gain/offset or points", self.key());
+        }
+        if self.points.len() < 2 {
+            bail!("{}: a curve needs at least two points", self.key());
+        }
+        if self.points.iter().flatten().any(|v| !v.is_finite()) {
+            bail!("{}: curve points must be finite", self.key());
+        }
+        if self.points.windows(2).any(|w| w[0][0] >= w[1][0]) {
+            bail!(
+                "{}: curve points must be in increasing raw order",
+                self.key()
+            );
+        }
+        Ok(Curve::Piecewise(self.points.clone()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Curve {
+    Linear { gain: f64, offset: f64 },
+    Piecewise(Vec<[f64; 2]>),
+}
+
+impl Curve {
+    fn apply(&self, raw: f64) -> f64 {
+        match self {
+            Self::Linear { gain, offset } => gain * raw + offset,
+            Self::Piecewise(points) => {
+                // Segment containing `raw`, or the first/last one outside the curve
+                let i = points
+                    .windows(2)
+                    .position(|w| raw < w[1][0])
+                    .unwrap_or(points.len() - 2);
+                let ([x0, y0], [x1, y1]) = (points[i], points[i + 1]);
+                y0 + (raw - x0) * (y1 - y0) / (x1 - x0)
+            }
+        }
+    }
+}
+
+/// Where the profile in effect for a reading came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileSource {
+    Config,
+    Api,
+}
+
+impl ProfileSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Config => "config",
+            Self::Api => "api",
+        }
+    }
+}
+
+/// A profile in effect, as listed by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveProfile {
+    #[serde(flatten)]
+    pub profile: CalibrationProfile,
+    pub source: ProfileSource,
+}
+
+/// Record metadata: one correction applied to a reading
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedCalibration {
+    pub sensor: String,
+    /// The reading before calibration
+    pub raw: f64,
+    pub source: ProfileSource,
+}
+
+/// Profile management errors
+#[derive(Debug, thiserror::Error)]
+pub enum CalibrationError {
+    #[error("{0:#}")]
+    Invalid(anyhow::Error),
+    #[error("{0}")]
+    NotFound(String),
+    #[error(transparent)]
+    Save(anyhow::Error),
+}
+
+struct Entry {
+    profile: CalibrationProfile,
+    curve: Curve,
+}
+
+fn entry(profile: CalibrationProfile) -> Result<(Channel, Entry)> {
+    let channel = profile.channel()?;
+    let curve = profile.curve()?;
+    Ok((channel, Entry { profile, curve }))
+}
+
+fn entries(profiles: Vec<CalibrationProfile>) -> Result<BTreeMap<Channel, Entry>> {
+    let mut entries = BTreeMap::new();
+    for profile in profiles {
+        let key = profile.key();
+        let (channel, entry) = entry(profile)?;
+        if entries.insert(channel, entry).is_some() {
+            bail!("Duplicate calibration profile for {}", key);
+        }
+    }
+    Ok(entries)
+}
+
+struct Profiles {
+    config: BTreeMap<Channel, Entry>,
+    overrides: BTreeMap<Channel, Entry>,
+}
+
+impl Profiles {
+    fn active(&self, channel: &Channel) -> Option<(&Entry, ProfileSource)> {
+        match self.overrides.get(channel) {
+            Some(entry) => Some((entry, ProfileSource::Api)),
+            None => self
+                .config
+                .get(channel)
+                .map(|entry| (entry, ProfileSource::Config)),
+        }
+    }
+}
+
+/// Shared profile set, used by the processor and the API
+#[derive(Clone)]
+pub struct Calibrator {
+    profiles: Arc<RwLock<Profiles>>,
+    overrides_path: Option<PathBuf>,
+}
+
+impl Calibrator {
+    /// Validate the config profiles and load saved API overrides
+    pub fn new(config: &CalibrationConfig) -> Result<Self> {
+        let config_profiles = entries(config.profiles.clone())?;
+        let overrides = match &config.overrides_path {
+            Some(path) => entries(load_overrides(path)?)
+                .with_context(|| format!("Invalid calibration overrides in {}", path.display()))?,
+            None => BTreeMap::new(),
+        };
+        info!(
+            config = config_profiles.len(),
+            overrides = overrides.len(),
+            "Calibration profiles loaded"
+        );
+        Ok(Self {
+            profiles: Arc::new(RwLock::new(Profiles {
+                config: config_profiles,
+                overrides,
+            })),
+            overrides_path: config.overrides_path.clone(),
+        })
+    }
+
+    /// Calibrate a packet's sensor readings in place
+    ///
+    /// Returns the corrections made, keyed by `<node>.<metric>`. Readings the
+    /// packet doesn't carry are skipped.
+    pub fn apply(&self, packet: &mut TelemetryPacket) -> BTreeMap<String, AppliedCalibration> {
+        let profiles = self.profiles.read().unwrap_or_else(|e| e.into_inner());
+        let mut applied = BTreeMap::new();
+        for channel @ &(node, sensor, quantity) in SENSOR_CHANNELS {
+            let Some((entry, source)) = profiles.active(channel) else {
+                continue;
+            };
+            let Some(raw) = read(packet, node, quantity) else {
+                continue;
+            };
+            write(packet, node, quantity, entry.curve.apply(raw));
+            applied.insert(
+                format!("{}.{}", node, quantity),
+                AppliedCalibration {
+                    sensor: sensor.to_string(),
+                    raw,
+                    source,
+                },
+            );
+        }
+        applied
+    }
+
+    /// Profiles in effect, in `SENSOR_CHANNELS` order
+    pub fn active(&self) -> Vec<ActiveProfile> {
+        let profiles = self.profiles.read().unwrap_or_else(|e| e.into_inner());
+        SENSOR_CHANNELS
+            .iter()
+            .filter_map(|channel| profiles.active(channel))
+            .map(|(entry, source)| ActiveProfile {
+                profile: entry.profile.clone(),
+                source,
+            })
+            .collect()
+    }
+
+    /// Add or replace the API override for the profile's reading
+    pub fn set(&self, profile: CalibrationProfile) -> Result<ActiveProfile, CalibrationError> {
+        let key = profile.key();
+        let (channel, entry) = entry(profile).map_err(CalibrationError::Invalid)?;
+        let mut profiles = self.profiles.write().unwrap_or_else(|e| e.into_inner());
+        let previous = profiles.overrides.insert(channel, entry);
+        if let Err(e) = self.save(&profiles.overrides) {
+            // Keep memory and file in agreement
+            match previous {
+                Some(previous) => profiles.overrides.insert(channel, previous),
+                None => profiles.overrides.remove(&channel),
+            };
+            return Err(CalibrationError::Save(e));
+        }
+        info!(profile = %key, "Calibration profile set through the API");
+        Ok(ActiveProfile {
+            profile: profiles.overrides[&channel].profile.clone(),
+            source: ProfileSource::Api,
+        })
+    }
+
+    /// Remove the API override for a reading, falling back to the config profile
+    pub fn remove(&self, node: &str, sensor: &str, quantity: &str) -> Result<(), CalibrationError> {
+        let key = CalibrationProfile {
+            node: node.to_string(),
+            sensor: sensor.to_string(),
+            quantity: quantity.to_string(),
+            ..CalibrationProfile::default()
+        };
+        let channel = key.channel().map_err(CalibrationError::Invalid)?;
+        let mut profiles = self.profiles.write().unwrap_or_else(|e| e.into_inner());
+        let Some(previous) = profiles.overrides.remove(&channel) else {
+            let reason = if profiles.config.contains_key(&channel) {
+                "it comes from the config file"
+            } else {
+                "none is set"
+            };
+            return Err(CalibrationError::NotFound(format!(
+                "no API profile for {} ({})",
+                key.key(),
+                reason
+            )));
+        };
+        if let Err(e) = self.save(&profiles.overrides) {
+            profiles.overrides.insert(channel, previous);
+            return Err(CalibrationError::Save(e));
+        }
+        info!(profile = %key.key(), "Calibration profile removed through the API");
+        Ok(())
+    }
+
+    fn save(&self, overrides: &BTreeMap<Channel, Entry>) -> Result<()> {
+        let Some(path) = &self.overrides_path else {
+            return Ok(());
+        };
+        let profiles: Vec<&CalibrationProfile> =
+            overrides.values().map(|entry| &entry.profile).collect();
+        // Write then rename so a crash never leaves a truncated file behind
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&profiles)?)
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .with_context(|| format!("Failed to save calibration overrides to {}", path.display()))
+    }
+}
+
+fn load_overrides(path: &Path) -> Result<Vec<CalibrationProfile>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = std::fs::read_to_string(path)?;
+    serde_json::from_str(&text).context("Invalid calibration overrides file")
+}
+
+fn read(packet: &TelemetryPacket, node: &str, quantity: &str) -> Option<f64> {
+    match (node, quantity) {
+        ("n1", "temperature") => Some(packet.n1.t as f64),
+        ("n1", "humidity") => Some(packet.n1.h as f64),
+        ("n1", "gas_resistance") => Some(packet.n1.g as f64),
+        ("n2", "temperature") => packet.n2.t.map(f64::from),
+        ("n2", "pressure") => packet.n2.p.map(f64::from),
+        _ => None,
+    }
+}
+
+fn write(packet: &mut TelemetryPacket, node: &str, quantity: &str, value: f64) {
+    match (node, quantity) {
+        ("n1", "temperature") => packet.n1.t = value as f32,
+        ("n1", "humidity") => packet.n1.h = value as f32,
+        ("n1", "gas_resistance") => packet.n1.g = value.round().clamp(0.0, u32::MAX as f64) as u32,
+        ("n2", "temperature") => packet.n2.t = Some(value as f32),
+        ("n2", "pressure") => packet.n2.p = Some(value as f32),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::packet;
+
+    fn profile(node: &str, sensor: &str, quantity: &str) -> CalibrationProfile {
+        CalibrationProfile {
+            node: node.to_string(),
+            sensor: sensor.to_string(),
+            quantity: quantity.to_string(),
+            ..CalibrationProfile::default()
+        }
+    }
+
+    fn calibrator(
+        profiles: Vec<CalibrationProfile>,
+        overrides_path: Option<PathBuf>,
+    ) -> Calibrator {
+        Calibrator::new(&CalibrationConfig {
+            overrides_path,
+            profiles,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_linear_and_piecewise_curves() {
+        let linear = Curve::Linear {
+            gain: 1.02,
+            offset: -0.5,
+        };
+        assert!((linear.apply(20.0) - 19.9).abs() < 1e-9);
+
+        let curve = Curve::Piecewise(vec![[10.0, 11.0], [50.0, 49.0], [90.0, 88.0]]);
+        assert_eq!(curve.apply(30.0), 30.0);
+        assert_eq!(curve.apply(70.0), 68.5);
+        // Extrapolated along the end segments
+        assert_eq!(curve.apply(0.0), 1.5);
+        assert_eq!(curve.apply(100.0), 97.75);
+    }
+
+    #[test]
+    fn test_apply_records_raw_values() {
+        let c = calibrator(
+            vec![
+                CalibrationProfile {
+                    offset: -2.0,
+                    ..profile("n2", "bmp280", "temperature")
+                },
+                CalibrationProfile {
+                    points: vec![[0.0, 0.0], [100.0, 98.0]],
+                    ..profile("n1", "sht31", "humidity")
+                },
+            ],
+            None,
+        );
+        let mut p = packet(0, 21.0);
+        let applied = c.apply(&mut p);
+        assert_eq!(p.n2.t, Some(22.0));
+        assert_eq!(p.n1.h, 44.1);
+        assert_eq!(p.n1.t, 21.0);
+        assert_eq!(applied["n2.temperature"].raw, 24.0);
+        assert_eq!(applied["n2.temperature"].sensor, "bmp280");
+        assert_eq!(applied["n1.humidity"].source, ProfileSource::Config);
+        assert_eq!(applied.len(), 2);
+
+        // Missing readings stay missing
+        let mut p = packet(0, 21.0);
+        p.n2.t = None;
+        assert!(!c.apply(&mut p).contains_key("n2.temperature"));
+        assert_eq!(p.n2.t, None);
+    }
+
+    #[test]
+    fn test_invalid_profiles_rejected() {
+        let bad = [
+            profile("n1", "bmp280", "temperature"),
+            CalibrationProfile {
+                gain: 0.0,
+                ..profile("n1", "sht31", "temperature")
+            },
+            CalibrationProfile {
+                offset: 1.0,
+                points: vec![[0.0, 0.0], [1.0, 1.0]],
+                ..profile("n1", "sht31", "temperature")
+            },
+            CalibrationProfile {
+                points: vec![[0.0, 0.0]],
+                ..profile("n1", "sht31", "temperature")
+            },
+            CalibrationProfile {
+                points: vec![[1.0, 0.0], [1.0, 1.0]],
+                ..profile("n1", "sht31", "temperature")
+            },
+        ];
+        for p in bad {
+            assert!(entry(p.clone()).is_err(), "{:?}", p);
+        }
+        let duplicate = vec![
+            profile("n1", "sht31", "humidity"),
+            profile("n1", "sht31", "humidity"),
+        ];
+        assert!(entries(duplicate).is_err());
+    }
+
+    #[test]
+    fn test_example_profiles_are_valid() {
+        let config =
+            crate::config::GatewayConfig::from_toml(include_str!("../gateway.example.toml"))
+                .unwrap();
+        assert_eq!(entries(config.calibration.profiles).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_api_overrides_persist_and_fall_back() {
+        let path = std::env::temp_dir().join(format!("calibration-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = vec![CalibrationProfile {
+            offset: -1.0,
+            ..profile("n2", "bmp280", "temperature")
+        }];
+
+        let c = calibrator(config.clone(), Some(path.clone()));
+        let set = c
+            .set(CalibrationProfile {
+                offset: -3.0,
+                ..profile("n2", "bmp280", "temperature")
+            })
+            .unwrap();
+        assert_eq!(set.source, ProfileSource::Api);
+        assert!(matches!(
+            c.set(profile("n2", "bmp280", "humidity")),
+            Err(CalibrationError::Invalid(_))
+        ));
+
+        // The override survives a restart and wins over the config file
+        let restarted = calibrator(config, Some(path.clone()));
+        let mut p = packet(0, 20.0);
+        restarted.apply(&mut p);
+        assert_eq!(p.n2.t, Some(21.0));
+
+        restarted.remove("n2", "bmp280", "temperature").unwrap();
+        let active = restarted.active();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].source, ProfileSource::Config);
+        assert!(matches!(
+            restarted.remove("n2", "bmp280", "temperature"),
+            Err(CalibrationError::NotFound(_))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
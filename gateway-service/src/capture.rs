@@ -0,0 +1,393 @@
+//! Raw input capture and replay
+//!
+//! A capture file is NDJSON: a header line, then one record per raw input
+//! line with its host receive time:
+//!
+//! ```text
+//! {"capture":1,"source":"probe-rs","started_at_ms":1767225600000}
+//! {"t_ms":1767225600123,"line":"[INFO] JSON sent via VCP: {...}\n"}
+//! ```
+//!
+//! Lines are stored verbatim (trailing newline included) so a replay feeds
+//! the parser byte-identical input. Replay hands out the recorded receive
+//! times rather than the current time, so clock correlation and everything
+//! downstream of the parser behaves exactly as it did in the field.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, Lines};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::clock::{unix_time_ms, utc_compact};
+
+/// Capture file format version (header `capture` field)
+const CAPTURE_VERSION: u32 = 1;
+
+/// Slowest accepted replay speed factor
+const MIN_SPEED: f64 = 0.001;
+
+/// Raw input capture (`[capture]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Directory for capture files (`capture-<UTC timestamp>.ndjson`)
+    pub directory: PathBuf,
+    /// How often buffered lines are flushed to disk
+    pub flush_interval_ms: u64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("captures"),
+            flush_interval_ms: 1000,
+        }
+    }
+}
+
+/// One raw input line and when the host received it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawLine {
+    /// Host receive time (Unix ms)
+    pub t_ms: u64,
+    /// The line as read, including its newline
+    pub line: String,
+}
+
+/// First line of a capture file
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    capture: u32,
+    source: String,
+    started_at_ms: u64,
+}
+
+/// Where the parser's raw input lines come from
+pub trait LineSource {
+    /// Next line, or `None` once the input has ended
+    fn next_line(&mut self) -> impl Future<Output = Result<Option<RawLine>>> + Send;
+}
+
+/// Live input (probe-rs stdout), stamped with the current time
+pub struct LiveSource<R> {
+    reader: R,
+}
+
+impl<R: AsyncBufRead + Unpin + Send> LiveSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + Send> LineSource for LiveSource<R> {
+    async fn next_line(&mut self) -> Result<Option<RawLine>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            warn!("probe-rs process ended (EOF on stdout)");
+            return Ok(None);
+        }
+
+        // Stamp host receive time as close to the read as possible
+        Ok(Some(RawLine {
+            t_ms: unix_time_ms(),
+            line,
+        }))
+    }
+}
+
+/// Replay pacing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Original timing scaled by this factor (1 = real time, 10 = 10x faster)
+    Factor(f64),
+    /// No delays at all
+    Max,
+}
+
+impl FromStr for ReplaySpeed {
+    type Err = anyhow::Error;
+
+    /// `max`, or a speed-up factor such as `1`, `10` or `2.5x`
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("max") {
+            return Ok(Self::Max);
+        }
+        let factor: f64 = s
+            .trim_end_matches('x')
+            .parse()
+            .with_context(|| format!("Invalid replay speed '{}'", s))?;
+        if !factor.is_finite() || factor < MIN_SPEED {
+            bail!("Replay speed must be at least {}, got '{}'", MIN_SPEED, s);
+        }
+        Ok(Self::Factor(factor))
+    }
+}
+
+/// Replays a capture file with its original (optionally scaled) timing
+pub struct ReplaySource {
+    lines: Lines<BufReader<File>>,
+    speed: ReplaySpeed,
+    /// Replay start and the first record's receive time
+    origin: Option<(Instant, u64)>,
+    line_no: u64,
+}
+
+impl ReplaySource {
+    /// Open a capture file and check its header
+    pub async fn open(path: &Path, speed: ReplaySpeed) -> Result<Self> {
+        let file = File::open(path)
+            .await
+            .with_context(|| format!("Failed to open capture {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let first = lines.next_line().await?.context("Capture file is empty")?;
+        let header: Header = serde_json::from_str(&first).context("Invalid capture header")?;
+        if header.capture != CAPTURE_VERSION {
+            bail!("Unsupported capture version {}", header.capture);
+        }
+        info!(
+            path = %path.display(),
+            source = %header.source,
+            started_at_ms = header.started_at_ms,
+            ?speed,
+            "Replaying capture"
+        );
+
+        Ok(Self {
+            lines,
+            speed,
+            origin: None,
+            line_no: 1,
+        })
+    }
+
+    /// Sleep until `t_ms` is due relative to the first record
+    async fn pace(&mut self, t_ms: u64) -> Result<()> {
+        let ReplaySpeed::Factor(factor) = self.speed else {
+            return Ok(());
+        };
+        let (start, first_ms) = *self.origin.get_or_insert((Instant::now(), t_ms));
+        let offset = t_ms.saturating_sub(first_ms) as f64 / 1000.0 / factor;
+        let due = Duration::try_from_secs_f64(offset)
+            .ok()
+            .and_then(|offset| start.checked_add(offset))
+            .with_context(|| {
+                format!(
+                    "Replay delay out of range on line {} ({} s)",
+                    self.line_no, offset
+                )
+            })?;
+        tokio::time::sleep_until(due).await;
+        Ok(())
+    }
+}
+
+impl LineSource for ReplaySource {
+    async fn next_line(&mut self) -> Result<Option<RawLine>> {
+        loop {
+            let Some(text) = self.lines.next_line().await? else {
+                info!(lines = self.line_no - 1, "Replay finished");
+                return Ok(None);
+            };
+            self.line_no += 1;
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            let raw: RawLine = serde_json::from_str(&text)
+                .with_context(|| format!("Invalid capture record on line {}", self.line_no))?;
+            self.pace(raw.t_ms).await?;
+            return Ok(Some(raw));
+        }
+    }
+}
+
+/// Records raw input lines to a capture file
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    path: PathBuf,
+    flush_interval: Duration,
+    last_flush: Instant,
+    lines: u64,
+}
+
+impl CaptureWriter {
+    /// Start a new capture file in the configured directory
+    pub async fn create(config: &CaptureConfig) -> Result<Self> {
+        tokio::fs::create_dir_all(&config.directory)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to create capture directory {}",
+                    config.directory.display()
+                )
+            })?;
+        let now_ms = unix_time_ms();
+        let path = config
+            .directory
+            .join(format!("capture-{}.ndjson", utc_compact(now_ms)));
+        let flush_interval = Duration::from_millis(config.flush_interval_ms);
+        Self::create_at(&path, now_ms, flush_interval).await
+    }
+
+    /// Start a capture file at `path`
+    pub async fn create_at(path: &Path, now_ms: u64, flush_interval: Duration) -> Result<Self> {
+        let file = File::create(path)
+            .await
+            .with_context(|| format!("Failed to create capture {}", path.display()))?;
+        let mut writer = Self {
+            out: BufWriter::new(file),
+            path: path.to_path_buf(),
+            flush_interval,
+            last_flush: Instant::now(),
+            lines: 0,
+        };
+
+        let header = Header {
+            capture: CAPTURE_VERSION,
+            source: "probe-rs".to_string(),
+            started_at_ms: now_ms,
+        };
+        writer.write_json(&header).await?;
+        writer.out.flush().await?;
+        info!(path = %path.display(), "Recording raw input");
+        Ok(writer)
+    }
+
+    async fn write_json<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        let mut buf = serde_json::to_vec(value)?;
+        buf.push(b'\n');
+        self.out.write_all(&buf).await?;
+        Ok(())
+    }
+
+    /// Append one line (flushed at most every `flush_interval_ms`)
+    pub async fn record(&mut self, raw: &RawLine) -> Result<()> {
+        self.write_json(raw).await?;
+        self.lines += 1;
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.out.flush().await?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Flush and close the capture file
+    pub async fn finish(mut self) -> Result<()> {
+        self.out.flush().await?;
+        self.out.get_mut().sync_all().await?;
+        info!(path = %self.path.display(), lines = self.lines, "Capture closed");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("capture-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("capture.ndjson")
+    }
+
+    async fn write_capture(path: &Path, lines: &[RawLine]) {
+        let mut writer = CaptureWriter::create_at(path, 0, Duration::ZERO)
+            .await
+            .unwrap();
+        for line in lines {
+            writer.record(line).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+    }
+
+    async fn drain(mut source: impl LineSource) -> Vec<RawLine> {
+        let mut out = Vec::new();
+        while let Some(line) = source.next_line().await.unwrap() {
+            out.push(line);
+        }
+        out
+    }
+
+    #[test]
+    fn test_parse_replay_speed() {
+        assert_eq!("max".parse::<ReplaySpeed>().unwrap(), ReplaySpeed::Max);
+        assert_eq!(
+            "1".parse::<ReplaySpeed>().unwrap(),
+            ReplaySpeed::Factor(1.0)
+        );
+        assert_eq!(
+            "2.5x".parse::<ReplaySpeed>().unwrap(),
+            ReplaySpeed::Factor(2.5)
+        );
+        assert!("0".parse::<ReplaySpeed>().is_err());
+        assert!("1e-300".parse::<ReplaySpeed>().is_err());
+        assert!("fast".parse::<ReplaySpeed>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_live_lines_round_trip_verbatim() {
+        let input =
+            "[INFO] boot\n[INFO] JSON sent via VCP: {\"ts\":1}\\n (src/main.rs:1)\nno newline";
+        let live = drain(LiveSource::new(BufReader::new(input.as_bytes()))).await;
+        assert_eq!(live.len(), 3);
+        assert_eq!(live[2].line, "no newline");
+
+        let path = scratch("roundtrip");
+        write_capture(&path, &live).await;
+        let replayed = drain(ReplaySource::open(&path, ReplaySpeed::Max).await.unwrap()).await;
+        assert_eq!(replayed, live);
+        assert_eq!(
+            replayed.iter().map(|l| l.line.as_str()).collect::<String>(),
+            input
+        );
+
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_replay_scales_original_timing() {
+        let path = scratch("timing");
+        let lines: Vec<RawLine> = [1_000, 1_100, 1_400]
+            .iter()
+            .map(|&t_ms| RawLine {
+                t_ms,
+                line: "x\n".to_string(),
+            })
+            .collect();
+        write_capture(&path, &lines).await;
+
+        // 400 ms of capture at 4x takes ~100 ms, and keeps the recorded times
+        let start = std::time::Instant::now();
+        let replayed = drain(
+            ReplaySource::open(&path, ReplaySpeed::Factor(4.0))
+                .await
+                .unwrap(),
+        )
+        .await;
+        let elapsed = start.elapsed();
+        assert_eq!(replayed, lines);
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_version() {
+        let path = scratch("version");
+        std::fs::write(
+            &path,
+            "{\"capture\":99,\"source\":\"x\",\"started_at_ms\":0}\n",
+        )
+        .unwrap();
+        assert!(ReplaySource::open(&path, ReplaySpeed::Max).await.is_err());
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+}
@@ -0,0 +1,263 @@
+//! Wall-clock timestamping and device-uptime correlation
+//!
+//! node2's `ts` is its `uptime_ms`: a u32 bumped by 500 on every TIM2 tick.
+//! It has no relation to real time, drifts with the HSI oscillator, wraps
+//! after ~49.7 days and restarts from zero on reboot. The correlator:
+//! - Unwraps `ts` into a monotonic 64-bit device uptime
+//! - Tells a wrap from a reboot by comparing against host elapsed time
+//! - Fits host receive time against device uptime over a sliding window
+//!   (least squares) to estimate clock offset and drift
+//! - Maps each packet's device time onto corrected wall-clock time
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// `ts` is a u32 millisecond counter
+const DEVICE_WRAP_MS: u64 = 1 << 32;
+
+/// Current host time in milliseconds since the Unix epoch
+pub fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Civil date and time (UTC) for Unix ms: (year, month, day, hour, minute, second)
+pub fn utc_datetime(unix_ms: u64) -> (i64, u32, u32, u32, u32, u32) {
+    // Howard Hinnant's days-to-civil algorithm
+    let secs = unix_ms / 1000;
+    let days = (secs / 86_400) as i64;
+    let rem = (secs % 86_400) as u32;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+/// Compact UTC timestamp for file names, e.g. `20260101T000000Z`
+pub fn utc_compact(unix_ms: u64) -> String {
+    let (y, mo, d, h, mi, s) = utc_datetime(unix_ms);
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", y, mo, d, h, mi, s)
+}
+
+/// Clock correlation configuration (`[clock]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockConfig {
+    /// Samples in the offset/drift regression window
+    pub window_size: usize,
+    /// How far a wrapped `ts` may disagree with host elapsed time before the
+    /// jump is treated as a reboot instead
+    pub wrap_tolerance_ms: u64,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 60,
+            wrap_tolerance_ms: 10_000,
+        }
+    }
+}
+
+/// Device and wall-clock time for one record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordTime {
+    /// Raw device timestamp (`ts`, ms since node2 boot, wrapping)
+    pub device_ts_ms: u32,
+    /// Unwrapped device uptime in ms since the current boot
+    pub device_uptime_ms: u64,
+    /// Host receive time, Unix ms
+    pub received_at_ms: u64,
+    /// Device time mapped onto the host clock, Unix ms
+    pub corrected_at_ms: u64,
+    /// Estimated wall-clock time of device boot, Unix ms
+    pub boot_time_ms: u64,
+    /// Estimated device clock drift in ppm (positive = device runs slow)
+    pub drift_ppm: f64,
+    /// Device boots seen since the gateway started (1 = first boot)
+    pub boot_epoch: u32,
+    /// This packet is the first one after a detected device reboot
+    pub reboot_detected: bool,
+}
+
+/// Sliding-window estimator of device clock offset and drift
+pub struct ClockCorrelator {
+    config: ClockConfig,
+    /// (device uptime ms, host receive ms) pairs for the current boot
+    window: VecDeque<(u64, u64)>,
+    last_ts: Option<u32>,
+    last_received_ms: u64,
+    wraps: u64,
+    boot_epoch: u32,
+}
+
+impl ClockCorrelator {
+    pub fn new(config: ClockConfig) -> Self {
+        Self {
+            config,
+            window: VecDeque::new(),
+            last_ts: None,
+            last_received_ms: 0,
+            wraps: 0,
+            boot_epoch: 1,
+        }
+    }
+
+    /// Correlate a device timestamp with the host time it was received at
+    pub fn observe(&mut self, ts: u32, received_at_ms: u64) -> RecordTime {
+        let mut reboot_detected = false;
+
+        if let Some(last) = self.last_ts {
+            if ts < last {
+                let host_elapsed = received_at_ms.saturating_sub(self.last_received_ms);
+                let wrapped_elapsed = DEVICE_WRAP_MS - last as u64 + ts as u64;
+
+                if wrapped_elapsed.abs_diff(host_elapsed) <= self.config.wrap_tolerance_ms {
+                    self.wraps += 1;
+                    info!(ts, "Device uptime counter wrapped");
+                } else {
+                    self.boot_epoch += 1;
+                    self.wraps = 0;
+                    self.window.clear();
+                    reboot_detected = true;
+                    warn!(
+                        previous_ts = last,
+                        ts,
+                        boot_epoch = self.boot_epoch,
+                        "Device reboot detected (uptime went backwards)"
+                    );
+                }
+            }
+        }
+        self.last_ts = Some(ts);
+        self.last_received_ms = received_at_ms;
+
+        let device_uptime_ms = self.wraps * DEVICE_WRAP_MS + ts as u64;
+        self.window.push_back((device_uptime_ms, received_at_ms));
+        while self.window.len() > self.config.window_size.max(2) {
+            self.window.pop_front();
+        }
+
+        let (boot_time_ms, slope) = self.fit();
+        let corrected_at_ms = boot_time_ms + slope * device_uptime_ms as f64;
+
+        RecordTime {
+            device_ts_ms: ts,
+            device_uptime_ms,
+            received_at_ms,
+            corrected_at_ms: corrected_at_ms.round().max(0.0) as u64,
+            boot_time_ms: boot_time_ms.round().max(0.0) as u64,
+            drift_ppm: (slope - 1.0) * 1e6,
+            boot_epoch: self.boot_epoch,
+            reboot_detected,
+        }
+    }
+
+    /// Least-squares fit of `host = boot_time + slope * device` over the window
+    fn fit(&self) -> (f64, f64) {
+        let n = self.window.len() as f64;
+        let (first_device, first_host) = self.window[0];
+
+        // Work relative to the first sample to keep f64 precision
+        let points = self.window.iter().map(|&(device, host)| {
+            (
+                device as f64 - first_device as f64,
+                host as f64 - first_host as f64,
+            )
+        });
+        let (sum_x, sum_y) = points
+            .clone()
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let (mean_x, mean_y) = (sum_x / n, sum_y / n);
+        let (cov, var) = points.fold((0.0, 0.0), |(cov, var), (x, y)| {
+            let dx = x - mean_x;
+            (cov + dx * (y - mean_y), var + dx * dx)
+        });
+
+        // Need some spread in device time before the slope means anything
+        let slope = if var > 0.0 { cov / var } else { 1.0 };
+        let intercept = first_host as f64 + mean_y - slope * (first_device as f64 + mean_x);
+        (intercept, slope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST_START_MS: u64 = 1_767_225_600_000; // 2026-01-01T00:00:00Z
+
+    #[test]
+    fn test_first_packet_maps_to_receive_time() {
+        let mut clock = ClockCorrelator::new(ClockConfig::default());
+        let time = clock.observe(12_000, HOST_START_MS);
+        assert_eq!(time.corrected_at_ms, HOST_START_MS);
+        assert_eq!(time.boot_time_ms, HOST_START_MS - 12_000);
+        assert_eq!(time.drift_ppm, 0.0);
+        assert!(!time.reboot_detected);
+    }
+
+    #[test]
+    fn test_estimates_drift_and_smooths_jitter() {
+        let mut clock = ClockCorrelator::new(ClockConfig::default());
+        // Device clock runs 1% slow; receive times jitter by up to ±200 ms
+        let mut last = None;
+        for i in 0..60u64 {
+            let device = 10_000 * i;
+            let jitter = [0i64, 200, -150, 80, -200][(i % 5) as usize];
+            let host = (HOST_START_MS + device * 101 / 100) as i64 + jitter;
+            last = Some(clock.observe(device as u32, host as u64));
+        }
+
+        let time = last.unwrap();
+        assert!(
+            (time.drift_ppm - 10_000.0).abs() < 200.0,
+            "{}",
+            time.drift_ppm
+        );
+        let ideal = HOST_START_MS + time.device_uptime_ms * 101 / 100;
+        assert!(time.corrected_at_ms.abs_diff(ideal) < 150);
+    }
+
+    #[test]
+    fn test_counter_wrap_is_not_a_reboot() {
+        let mut clock = ClockCorrelator::new(ClockConfig::default());
+        let before = u32::MAX - 4_999;
+        clock.observe(before, HOST_START_MS);
+
+        let time = clock.observe(5_000, HOST_START_MS + 10_000);
+        assert!(!time.reboot_detected);
+        assert_eq!(time.boot_epoch, 1);
+        assert_eq!(time.device_uptime_ms, DEVICE_WRAP_MS + 5_000);
+        assert_eq!(time.corrected_at_ms, HOST_START_MS + 10_000);
+    }
+
+    #[test]
+    fn test_reboot_resets_correlation() {
+        let mut clock = ClockCorrelator::new(ClockConfig::default());
+        clock.observe(600_000, HOST_START_MS);
+        clock.observe(610_000, HOST_START_MS + 10_000);
+
+        let time = clock.observe(2_000, HOST_START_MS + 30_000);
+        assert!(time.reboot_detected);
+        assert_eq!(time.boot_epoch, 2);
+        assert_eq!(time.device_uptime_ms, 2_000);
+        assert_eq!(time.boot_time_ms, HOST_START_MS + 28_000);
+
+        let next = clock.observe(12_000, HOST_START_MS + 40_000);
+        assert!(!next.reboot_detected);
+        assert_eq!(next.boot_epoch, 2);
+    }
+}
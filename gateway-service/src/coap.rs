@@ -0,0 +1,827 @@
+//! CoAP (UDP) server with per-node resources and Observe
+//!
+//! | Path                | Contents                                        |
+//! |---------------------|-------------------------------------------------|
+//! | `/.well-known/core` | Resource directory (CoRE link format, RFC 6690) |
+//! | `/nodes/n1`         | Node 1 values, as named in the query API        |
+//! | `/nodes/n2`         | Node 2 values plus its link counters            |
+//! | `/telemetry`        | The full processed record                       |
+//!
+//! Node and telemetry resources are JSON (content-format 50, the default) or
+//! CBOR (60), picked with the Accept option; anything else gets 4.06. They
+//! read as `null` until the first record arrives.
+//!
+//! A GET with `Observe: 0` registers an observation (RFC 7641): the client
+//! gets the current state, then a notification for every new record until it
+//! sends `Observe: 1` with the same token or answers a notification with RST.
+//! Notifications are non-confirmable, except that at least every
+//! `confirm_interval_secs` one is sent confirmable (RFC 7641 §4.5); an
+//! observer that doesn't acknowledge it after the RFC 7252 retransmissions
+//! is dropped, so clients that went away don't hold a slot forever.
+
+use anyhow::{Context, Result};
+use coap_lite::option_value::OptionValueU16;
+use coap_lite::{
+    CoapOption, CoapResponse, ContentFormat, MessageClass, MessageType, Packet, RequestType,
+    ResponseType,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::sink::TelemetrySink;
+use crate::telemetry::ProcessedRecord;
+
+/// Observe sequence numbers are 24 bits on the wire
+const OBSERVE_SEQ_MASK: u32 = 0xFF_FFFF;
+
+/// First retransmission timeout of a confirmable notification (RFC 7252)
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Retransmissions before an unacknowledged observer is dropped (RFC 7252)
+const MAX_RETRANSMIT: u32 = 4;
+
+/// Nodes with a `/nodes/<node>` resource
+const NODES: &[&str] = &["n1", "n2"];
+
+/// CoAP server (`[coap]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoapConfig {
+    /// UDP listen address (5683 is the standard CoAP port)
+    pub bind: SocketAddr,
+    /// Most concurrent observations; further registrations get a plain response
+    pub max_observers: usize,
+    /// Longest an observer goes without a confirmable notification (RFC 7641
+    /// asks for at most 24 h)
+    pub confirm_interval_secs: u64,
+}
+
+impl Default for CoapConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 5683)),
+            max_observers: 64,
+            confirm_interval_secs: 300,
+        }
+    }
+}
+
+/// Representation of a node or telemetry resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Format {
+    Json,
+    Cbor,
+}
+
+impl Format {
+    /// Pick the representation from the request's Accept option
+    fn negotiate(request: &Packet) -> Option<Self> {
+        match accept(request) {
+            None => Some(Self::Json),
+            Some(ContentFormat::ApplicationJSON) => Some(Self::Json),
+            Some(ContentFormat::ApplicationCBOR) => Some(Self::Cbor),
+            Some(_) => None,
+        }
+    }
+
+    fn content_format(self) -> ContentFormat {
+        match self {
+            Self::Json => ContentFormat::ApplicationJSON,
+            Self::Cbor => ContentFormat::ApplicationCBOR,
+        }
+    }
+
+    fn encode(self, value: &Value) -> Result<Vec<u8>> {
+        match self {
+            Self::Json => Ok(serde_json::to_vec(value)?),
+            Self::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(value, &mut buf)?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+/// The Accept option, if present (unknown formats map to `Some(TextPlain)`)
+fn accept(request: &Packet) -> Option<ContentFormat> {
+    let value = request.get_first_option_as::<OptionValueU16>(CoapOption::Accept)?;
+    Some(
+        value
+            .ok()
+            .and_then(|v| ContentFormat::try_from(usize::from(v.0)).ok())
+            .unwrap_or(ContentFormat::TextPlain),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Resource {
+    Directory,
+    Node(&'static str),
+    Telemetry,
+}
+
+impl Resource {
+    fn from_path(path: &[&str]) -> Option<Self> {
+        match path {
+            [".well-known", "core"] => Some(Self::Directory),
+            ["nodes", node] => NODES.iter().find(|&n| n == node).map(|&n| Self::Node(n)),
+            ["telemetry"] => Some(Self::Telemetry),
+            _ => None,
+        }
+    }
+
+    /// Current state of an observable resource
+    fn body(self, latest: Option<&ProcessedRecord>) -> Result<Value> {
+        let Some(record) = latest else {
+            return Ok(Value::Null);
+        };
+        match self {
+            Self::Node(node) => Ok(node_body(record, node)),
+            Self::Telemetry => Ok(serde_json::to_value(record)?),
+            Self::Directory => Ok(Value::String(directory())),
+        }
+    }
+}
+
+/// A node's values keyed by metric name, plus the record time
+fn node_body(record: &ProcessedRecord, node: &str) -> Value {
+    let mut body = Map::new();
+    body.insert("ts_ms".to_string(), record.timestamp_ms().into());
+    for metric in record.metrics().into_iter().filter(|m| m.node == node) {
+        body.insert(metric.name.to_string(), metric.value.into());
+    }
+    if node == "n2" {
+        let sts = &record.packet.sts;
+        body.insert("packets_received".to_string(), sts.rx.into());
+        body.insert("crc_errors".to_string(), sts.err.into());
+        body.insert("rejected_frames".to_string(), sts.rej.into());
+    }
+    Value::Object(body)
+}
+
+/// `/.well-known/core` in CoRE link format
+fn directory() -> String {
+    let formats = format!(
+        "ct=\"{} {}\"",
+        usize::from(ContentFormat::ApplicationJSON),
+        usize::from(ContentFormat::ApplicationCBOR)
+    );
+    let mut links: Vec<String> = NODES
+        .iter()
+        .map(|node| format!("</nodes/{}>;rt=\"sensor\";obs;{}", node, formats))
+        .collect();
+    links.push(format!("</telemetry>;rt=\"telemetry\";obs;{}", formats));
+    links.join(",")
+}
+
+/// One registered observation
+#[derive(Debug)]
+struct Observer {
+    peer: SocketAddr,
+    token: Vec<u8>,
+    resource: Resource,
+    format: Format,
+    /// Message id of the last notification, matched against RSTs
+    last_message_id: Option<u16>,
+    /// Registration or last acknowledged notification
+    confirmed_at: Instant,
+    /// Confirmable notification waiting for its ACK
+    pending: Option<Pending>,
+}
+
+/// A confirmable notification in flight
+#[derive(Debug)]
+struct Pending {
+    packet: Packet,
+    retransmissions: u32,
+    retry_at: Instant,
+}
+
+/// Request handling and observation state, driven by `serve`
+struct Server {
+    latest: Option<Arc<ProcessedRecord>>,
+    observers: Vec<Observer>,
+    max_observers: usize,
+    confirm_interval: Duration,
+    next_message_id: u16,
+    observe_seq: u32,
+}
+
+impl Server {
+    fn new(max_observers: usize, confirm_interval: Duration) -> Self {
+        Self {
+            latest: None,
+            observers: Vec::new(),
+            max_observers,
+            confirm_interval,
+            // Avoid reusing recent message ids across restarts
+            next_message_id: crate::clock::unix_time_ms() as u16,
+            observe_seq: 0,
+        }
+    }
+
+    fn message_id(&mut self) -> u16 {
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+        self.next_message_id
+    }
+
+    /// Answer one incoming message (`None` when no reply is due)
+    fn handle(&mut self, request: &Packet, peer: SocketAddr, now: Instant) -> Option<Packet> {
+        let message_id = request.header.message_id;
+        match request.header.get_type() {
+            MessageType::Reset => {
+                self.observers
+                    .retain(|o| !(o.peer == peer && o.last_message_id == Some(message_id)));
+                return None;
+            }
+            MessageType::Acknowledgement => {
+                if let Some(observer) = self.observers.iter_mut().find(|o| {
+                    o.peer == peer
+                        && o.pending.as_ref().map(|p| p.packet.header.message_id)
+                            == Some(message_id)
+                }) {
+                    observer.pending = None;
+                    observer.confirmed_at = now;
+                }
+                return None;
+            }
+            MessageType::Confirmable | MessageType::NonConfirmable => {}
+        }
+
+        let MessageClass::Request(method) = request.header.code else {
+            // Empty CON is a CoAP ping, answered with RST
+            if request.header.code == MessageClass::Empty
+                && request.header.get_type() == MessageType::Confirmable
+            {
+                let mut pong = Packet::new();
+                pong.header.set_version(1);
+                pong.header.set_type(MessageType::Reset);
+                pong.header.message_id = request.header.message_id;
+                return Some(pong);
+            }
+            return None;
+        };
+
+        let mut response = CoapResponse::new(request)?.message;
+        if response.header.get_type() == MessageType::NonConfirmable {
+            response.header.message_id = self.message_id();
+        }
+        let status = self.respond(request, method, peer, now, &mut response);
+        response.header.code = MessageClass::Response(status);
+        Some(response)
+    }
+
+    /// Fill in `response` and return its status
+    fn respond(
+        &mut self,
+        request: &Packet,
+        method: RequestType,
+        peer: SocketAddr,
+        now: Instant,
+        response: &mut Packet,
+    ) -> ResponseType {
+        let segments: Vec<String> = request
+            .get_option(CoapOption::UriPath)
+            .map(|list| {
+                list.iter()
+                    .map(|s| String::from_utf8_lossy(s).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let path: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let Some(resource) = Resource::from_path(&path) else {
+            return ResponseType::NotFound;
+        };
+        if method != RequestType::Get {
+            return ResponseType::MethodNotAllowed;
+        }
+
+        if resource == Resource::Directory {
+            if !matches!(
+                accept(request),
+                None | Some(ContentFormat::ApplicationLinkFormat)
+            ) {
+                return ResponseType::NotAcceptable;
+            }
+            response.set_content_format(ContentFormat::ApplicationLinkFormat);
+            response.payload = directory().into_bytes();
+            return ResponseType::Content;
+        }
+
+        let Some(format) = Format::negotiate(request) else {
+            return ResponseType::NotAcceptable;
+        };
+        let payload = match resource
+            .body(self.latest.as_deref())
+            .and_then(|body| format.encode(&body))
+        {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!(error = %e, "Failed to encode CoAP resource");
+                return ResponseType::InternalServerError;
+            }
+        };
+
+        let token = request.get_token();
+        match request.get_observe_value() {
+            Some(Ok(0)) => {
+                if self.register(peer, token, resource, format, now) {
+                    response.set_observe_value(self.observe_seq);
+                }
+            }
+            Some(Ok(1)) => self.deregister(peer, token),
+            _ => {}
+        }
+        response.set_content_format(format.content_format());
+        response.payload = payload;
+        ResponseType::Content
+    }
+
+    /// Add or refresh an observation; `false` when the server is full
+    fn register(
+        &mut self,
+        peer: SocketAddr,
+        token: &[u8],
+        resource: Resource,
+        format: Format,
+        now: Instant,
+    ) -> bool {
+        if let Some(existing) = self
+            .observers
+            .iter_mut()
+            .find(|o| o.peer == peer && o.token == token)
+        {
+            existing.resource = resource;
+            existing.format = format;
+            return true;
+        }
+        if self.observers.len() >= self.max_observers {
+            debug!(%peer, "CoAP observer limit reached");
+            return false;
+        }
+        debug!(%peer, ?resource, ?format, "CoAP observer registered");
+        self.observers.push(Observer {
+            peer,
+            token: token.to_vec(),
+            resource,
+            format,
+            last_message_id: None,
+            confirmed_at: now,
+            pending: None,
+        });
+        true
+    }
+
+    fn deregister(&mut self, peer: SocketAddr, token: &[u8]) {
+        self.observers
+            .retain(|o| !(o.peer == peer && o.token == token));
+    }
+
+    /// Latch a new record and build one notification per observer
+    fn notify(&mut self, record: Arc<ProcessedRecord>, now: Instant) -> Vec<(SocketAddr, Packet)> {
+        self.latest = Some(record);
+        self.observe_seq = (self.observe_seq + 1) & OBSERVE_SEQ_MASK;
+
+        // Each representation is encoded once, however many observe it
+        let mut payloads: HashMap<(Resource, Format), Vec<u8>> = HashMap::new();
+        let mut out = Vec::with_capacity(self.observers.len());
+        for i in 0..self.observers.len() {
+            let (resource, format) = (self.observers[i].resource, self.observers[i].format);
+            let payload = match payloads.get(&(resource, format)) {
+                Some(payload) => payload.clone(),
+                None => match resource
+                    .body(self.latest.as_deref())
+                    .and_then(|body| format.encode(&body))
+                {
+                    Ok(payload) => payloads
+                        .entry((resource, format))
+                        .or_insert(payload)
+                        .clone(),
+                    Err(e) => {
+                        warn!(error = %e, "Failed to encode CoAP notification");
+                        continue;
+                    }
+                },
+            };
+
+            let message_id = self.message_id();
+            let observer = &mut self.observers[i];
+            observer.last_message_id = Some(message_id);
+            // A newer state replaces one still being retransmitted, keeping
+            // its retransmission count (RFC 7641 §4.5.2)
+            let confirmable =
+                observer.pending.is_some() || now >= observer.confirmed_at + self.confirm_interval;
+
+            let mut packet = Packet::new();
+            packet.header.set_version(1);
+            packet.header.set_type(if confirmable {
+                MessageType::Confirmable
+            } else {
+                MessageType::NonConfirmable
+            });
+            packet.header.code = MessageClass::Response(ResponseType::Content);
+            packet.header.message_id = message_id;
+            packet.set_token(observer.token.clone());
+            packet.set_observe_value(self.observe_seq);
+            packet.set_content_format(format.content_format());
+            packet.payload = payload;
+            if confirmable {
+                let pending = observer.pending.get_or_insert(Pending {
+                    packet: Packet::new(),
+                    retransmissions: 0,
+                    retry_at: now + ACK_TIMEOUT,
+                });
+                pending.packet = packet.clone();
+            }
+            out.push((observer.peer, packet));
+        }
+        out
+    }
+
+    /// Retransmit confirmable notifications that are due and drop observers
+    /// that never acknowledged theirs
+    fn retransmit(&mut self, now: Instant) -> Vec<(SocketAddr, Packet)> {
+        self.observers.retain(|o| match &o.pending {
+            Some(p) if now >= p.retry_at && p.retransmissions >= MAX_RETRANSMIT => {
+                debug!(peer = %o.peer, "CoAP observer stopped acknowledging, dropped");
+                false
+            }
+            _ => true,
+        });
+
+        let mut out = Vec::new();
+        for observer in &mut self.observers {
+            let Some(pending) = observer.pending.as_mut() else {
+                continue;
+            };
+            if now >= pending.retry_at {
+                pending.retransmissions += 1;
+                pending.retry_at = now + ACK_TIMEOUT * 2u32.pow(pending.retransmissions);
+                out.push((observer.peer, pending.packet.clone()));
+            }
+        }
+        out
+    }
+}
+
+async fn send(socket: &UdpSocket, packet: &Packet, peer: SocketAddr) {
+    let bytes = match packet.to_bytes() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!(%peer, error = %e, "Failed to encode CoAP message");
+            return;
+        }
+    };
+    if let Err(e) = socket.send_to(&bytes, peer).await {
+        debug!(%peer, error = %e, "Failed to send CoAP message");
+    }
+}
+
+/// Serve requests and push notifications until the handle is dropped
+async fn serve(
+    socket: UdpSocket,
+    mut records: watch::Receiver<Option<Arc<ProcessedRecord>>>,
+    max_observers: usize,
+    confirm_interval: Duration,
+) {
+    let mut server = Server::new(max_observers, confirm_interval);
+    let mut buf = vec![0u8; Packet::MAX_SIZE];
+    let mut retransmit = tokio::time::interval(Duration::from_millis(500));
+    retransmit.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                let (len, peer) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        debug!(error = %e, "CoAP receive failed");
+                        continue;
+                    }
+                };
+                let request = match Packet::from_bytes(&buf[..len]) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        debug!(%peer, error = %e, "Ignoring malformed CoAP message");
+                        continue;
+                    }
+                };
+                if let Some(response) = server.handle(&request, peer, Instant::now()) {
+                    send(&socket, &response, peer).await;
+                }
+            }
+            changed = records.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let Some(record) = records.borrow_and_update().clone() else {
+                    continue;
+                };
+                for (peer, packet) in server.notify(record, Instant::now()) {
+                    send(&socket, &packet, peer).await;
+                }
+            }
+            _ = retransmit.tick() => {
+                for (peer, packet) in server.retransmit(Instant::now()) {
+                    send(&socket, &packet, peer).await;
+                }
+            }
+        }
+    }
+    info!("CoAP server stopped");
+}
+
+/// Handle for publishing records to CoAP observers
+pub struct CoapHandle {
+    records: watch::Sender<Option<Arc<ProcessedRecord>>>,
+}
+
+impl CoapHandle {
+    /// Latch `record` as the latest state and notify observers
+    pub fn update(&self, record: &ProcessedRecord) {
+        self.records.send_replace(Some(Arc::new(record.clone())));
+    }
+}
+
+impl TelemetrySink for CoapHandle {
+    async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+        self.update(record);
+        Ok(())
+    }
+}
+
+/// Bind the UDP socket and start serving
+pub async fn spawn(config: &CoapConfig) -> Result<CoapHandle> {
+    let socket = UdpSocket::bind(config.bind)
+        .await
+        .with_context(|| format!("Failed to bind CoAP server to {}", config.bind))?;
+    info!(bind = %config.bind, "CoAP server listening");
+
+    let (records, rx) = watch::channel(None);
+    tokio::spawn(serve(
+        socket,
+        rx,
+        config.max_observers,
+        Duration::from_secs(config.confirm_interval_secs),
+    ));
+    Ok(CoapHandle { records })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+
+    async fn start() -> (CoapHandle, SocketAddr, UdpSocket) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let (records, rx) = watch::channel(None);
+        tokio::spawn(serve(socket, rx, 4, Duration::from_secs(300)));
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        (CoapHandle { records }, addr, client)
+    }
+
+    fn get(
+        path: &str,
+        token: &[u8],
+        accept: Option<ContentFormat>,
+        observe: Option<u32>,
+    ) -> Packet {
+        let mut packet = Packet::new();
+        packet.header.set_version(1);
+        packet.header.set_type(MessageType::Confirmable);
+        packet.header.code = MessageClass::Request(RequestType::Get);
+        packet.header.message_id = 42;
+        packet.set_token(token.to_vec());
+        if let Some(observe) = observe {
+            packet.set_observe_value(observe);
+        }
+        for segment in path.trim_start_matches('/').split('/') {
+            packet.add_option(CoapOption::UriPath, segment.as_bytes().to_vec());
+        }
+        if let Some(accept) = accept {
+            let accept = u16::try_from(usize::from(accept)).unwrap();
+            packet.add_option_as(CoapOption::Accept, OptionValueU16(accept));
+        }
+        packet
+    }
+
+    async fn exchange(client: &UdpSocket, server: SocketAddr, packet: &Packet) -> Packet {
+        client
+            .send_to(&packet.to_bytes().unwrap(), server)
+            .await
+            .unwrap();
+        recv(client).await
+    }
+
+    async fn recv(client: &UdpSocket) -> Packet {
+        let mut buf = [0u8; 2048];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(5), client.recv_from(&mut buf))
+            .await
+            .expect("no CoAP message within 5 s")
+            .unwrap();
+        Packet::from_bytes(&buf[..len]).unwrap()
+    }
+
+    fn status(packet: &Packet) -> ResponseType {
+        match packet.header.code {
+            MessageClass::Response(status) => status,
+            other => panic!("not a response: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_directory_and_errors() {
+        let (_handle, addr, client) = start().await;
+
+        let response = exchange(&client, addr, &get("/.well-known/core", b"d", None, None)).await;
+        assert_eq!(status(&response), ResponseType::Content);
+        assert_eq!(response.header.get_type(), MessageType::Acknowledgement);
+        assert_eq!(
+            response.get_content_format(),
+            Some(ContentFormat::ApplicationLinkFormat)
+        );
+        let links = String::from_utf8(response.payload).unwrap();
+        assert!(links.contains("</nodes/n1>;rt=\"sensor\";obs;ct=\"50 60\""));
+        assert!(links.contains("</nodes/n2>"));
+        assert!(links.contains("</telemetry>"));
+
+        let missing = exchange(&client, addr, &get("/nodes/n3", b"m", None, None)).await;
+        assert_eq!(status(&missing), ResponseType::NotFound);
+
+        let xml = exchange(
+            &client,
+            addr,
+            &get("/nodes/n1", b"x", Some(ContentFormat::ApplicationXML), None),
+        )
+        .await;
+        assert_eq!(status(&xml), ResponseType::NotAcceptable);
+    }
+
+    #[tokio::test]
+    async fn test_get_json_and_cbor() {
+        let (handle, addr, client) = start().await;
+
+        // Before the first record the resource exists but is empty
+        let empty = exchange(&client, addr, &get("/nodes/n1", b"a", None, None)).await;
+        assert_eq!(status(&empty), ResponseType::Content);
+        assert_eq!(empty.payload, b"null");
+
+        handle.update(&record(1_767_225_600_000, 21.5));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let json = exchange(&client, addr, &get("/nodes/n1", b"b", None, None)).await;
+        assert_eq!(
+            json.get_content_format(),
+            Some(ContentFormat::ApplicationJSON)
+        );
+        let body: Value = serde_json::from_slice(&json.payload).unwrap();
+        assert_eq!(body["temperature"], 21.5);
+        assert_eq!(body["ts_ms"], 1_767_225_600_000u64);
+
+        let cbor = exchange(
+            &client,
+            addr,
+            &get(
+                "/nodes/n2",
+                b"c",
+                Some(ContentFormat::ApplicationCBOR),
+                None,
+            ),
+        )
+        .await;
+        assert_eq!(
+            cbor.get_content_format(),
+            Some(ContentFormat::ApplicationCBOR)
+        );
+        let body: Value = ciborium::from_reader(cbor.payload.as_slice()).unwrap();
+        assert_eq!(body["pressure"], 1013.25);
+        assert_eq!(body["packets_received"], 1);
+
+        let full = exchange(&client, addr, &get("/telemetry", b"t", None, None)).await;
+        let body: Value = serde_json::from_slice(&full.payload).unwrap();
+        assert_eq!(body["id"], "N2");
+        assert!(body["derived"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_observe_notifies_until_reset() {
+        let (handle, addr, client) = start().await;
+
+        let registered = exchange(
+            &client,
+            addr,
+            &get(
+                "/nodes/n1",
+                b"obs",
+                Some(ContentFormat::ApplicationCBOR),
+                Some(0),
+            ),
+        )
+        .await;
+        assert_eq!(status(&registered), ResponseType::Content);
+        let first_seq = registered.get_observe_value().unwrap().unwrap();
+
+        handle.update(&record(1_000, 22.0));
+        let notification = recv(&client).await;
+        assert_eq!(notification.get_token(), b"obs");
+        assert_eq!(notification.header.get_type(), MessageType::NonConfirmable);
+        assert!(notification.get_observe_value().unwrap().unwrap() > first_seq);
+        let body: Value = ciborium::from_reader(notification.payload.as_slice()).unwrap();
+        assert_eq!(body["temperature"], 22.0);
+
+        // RST on a notification cancels the observation
+        let mut reset = Packet::new();
+        reset.header.set_version(1);
+        reset.header.set_type(MessageType::Reset);
+        reset.header.message_id = notification.header.message_id;
+        client
+            .send_to(&reset.to_bytes().unwrap(), addr)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        handle.update(&record(2_000, 23.0));
+        let mut buf = [0u8; 64];
+        let silent =
+            tokio::time::timeout(Duration::from_millis(200), client.recv_from(&mut buf)).await;
+        assert!(silent.is_err(), "notified after RST");
+    }
+
+    #[test]
+    fn test_observer_limit_and_deregister() {
+        let mut server = Server::new(1, Duration::from_secs(300));
+        let peer = SocketAddr::from(([127, 0, 0, 1], 9999));
+        let node = Resource::Node("n1");
+        let now = Instant::now();
+
+        assert!(server.register(peer, b"a", node, Format::Json, now));
+        // Re-registering the same token refreshes rather than adds
+        assert!(server.register(peer, b"a", Resource::Telemetry, Format::Cbor, now));
+        assert!(!server.register(peer, b"b", node, Format::Json, now));
+        assert_eq!(server.observers.len(), 1);
+
+        let notifications = server.notify(Arc::new(record(1_000, 20.0)), now);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(
+            notifications[0].1.get_content_format(),
+            Some(ContentFormat::ApplicationCBOR)
+        );
+
+        server.deregister(peer, b"a");
+        assert!(server.notify(Arc::new(record(2_000, 20.0)), now).is_empty());
+    }
+
+    #[test]
+    fn test_unacknowledged_observer_expires() {
+        let interval = Duration::from_secs(300);
+        let mut server = Server::new(4, interval);
+        let (gone, alive) = (
+            SocketAddr::from(([127, 0, 0, 1], 9998)),
+            SocketAddr::from(([127, 0, 0, 1], 9999)),
+        );
+        let start = Instant::now();
+        server.register(gone, b"g", Resource::Telemetry, Format::Json, start);
+        server.register(alive, b"a", Resource::Telemetry, Format::Json, start);
+
+        let types = |out: &[(SocketAddr, Packet)]| {
+            out.iter()
+                .map(|(_, p)| p.header.get_type())
+                .collect::<Vec<_>>()
+        };
+        let notified = server.notify(Arc::new(record(1_000, 20.0)), start);
+        assert_eq!(types(&notified), [MessageType::NonConfirmable; 2]);
+
+        // Past the interval the next notification must be acknowledged
+        let mut now = start + interval;
+        let notified = server.notify(Arc::new(record(2_000, 20.0)), now);
+        assert_eq!(types(&notified), [MessageType::Confirmable; 2]);
+        let mut ack = Packet::new();
+        ack.header.set_version(1);
+        ack.header.set_type(MessageType::Acknowledgement);
+        ack.header.message_id = notified[1].1.header.message_id;
+        assert!(server.handle(&ack, alive, now).is_none());
+
+        // `gone` gets the RFC 7252 retransmissions, then is dropped
+        let mut resent = 0;
+        while server.observers.len() == 2 {
+            now += Duration::from_secs(1);
+            for (peer, packet) in server.retransmit(now) {
+                assert_eq!((peer, packet.get_token()), (gone, b"g".as_slice()));
+                resent += 1;
+            }
+        }
+        assert_eq!(resent, MAX_RETRANSMIT);
+        assert_eq!(server.observers[0].peer, alive);
+        let notified = server.notify(Arc::new(record(3_000, 20.0)), now);
+        assert_eq!(types(&notified), [MessageType::NonConfirmable]);
+    }
+}
@@ -0,0 +1,132 @@
+//! Gateway configuration
+//!
+//! Loaded from a TOML file (`gateway.toml` in the working directory, or the
+//! path in `GATEWAY_CONFIG`). Every section is optional - a missing file or
+//! section just means that feature stays at its defaults / disabled.
+//!
+//! See `gateway-service/gateway.example.toml` for a documented example.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::aggregate::AggregateConfig;
+use crate::api::ApiConfig;
+use crate::calibration::CalibrationConfig;
+use crate::capture::CaptureConfig;
+use crate::clock::ClockConfig;
+use crate::coap::CoapConfig;
+use crate::derived::DerivedConfig;
+use crate::downlink::DownlinkConfig;
+use crate::export::ExportConfig;
+use crate::iaq::IaqConfig;
+use crate::link::LinkConfig;
+use crate::modbus::ModbusConfig;
+use crate::opcua_server::OpcUaConfig;
+use crate::pipeline::StageConfig;
+use crate::sink::RouterConfig;
+use crate::storage::StorageConfig;
+use crate::stream::StreamConfig;
+use crate::tui::TuiConfig;
+use crate::webhook::WebhookConfig;
+
+/// Default config file name (relative to the working directory)
+const DEFAULT_CONFIG_PATH: &str = "gateway.toml";
+
+/// Environment variable that overrides the config file path
+const CONFIG_PATH_ENV: &str = "GATEWAY_CONFIG";
+
+/// Top-level gateway configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GatewayConfig {
+    /// Windowed summaries (`[[aggregate]]`, none by default)
+    pub aggregate: Vec<AggregateConfig>,
+    /// HTTP query and streaming API (disabled when absent)
+    pub api: Option<ApiConfig>,
+    /// Per-sensor calibration profiles
+    pub calibration: CalibrationConfig,
+    /// Raw input capture for later replay (disabled when absent)
+    pub capture: Option<CaptureConfig>,
+    /// Device clock correlation
+    pub clock: ClockConfig,
+    /// CoAP server with Observe (disabled when absent)
+    pub coap: Option<CoapConfig>,
+    /// Derived environmental metrics
+    pub derived: DerivedConfig,
+    /// VCP downlink to node2 (disabled when absent)
+    pub downlink: Option<DownlinkConfig>,
+    /// File export sinks (`[[export]]`, any number)
+    pub export: Vec<ExportConfig>,
+    /// BME680 indoor air quality estimation
+    pub iaq: IaqConfig,
+    /// Radio link analytics
+    pub link: LinkConfig,
+    /// Modbus TCP server for PLCs (disabled when absent)
+    pub modbus: Option<ModbusConfig>,
+    /// OPC UA server (disabled when absent)
+    pub opcua: Option<OpcUaConfig>,
+    /// Processing stages (`[[pipeline]]`, in order; default: just `log`)
+    pub pipeline: Option<Vec<StageConfig>>,
+    /// Sink fan-out queues and backpressure policies
+    pub router: RouterConfig,
+    /// SQLite telemetry history (disabled when absent)
+    pub storage: Option<StorageConfig>,
+    /// Live WebSocket/SSE streaming (served by `[api]`)
+    pub stream: StreamConfig,
+    /// Terminal monitor (`tui` mode)
+    pub tui: TuiConfig,
+    /// Webhook notifier (disabled when absent)
+    pub webhook: Option<WebhookConfig>,
+}
+
+impl GatewayConfig {
+    /// Resolve the config file path from `GATEWAY_CONFIG` or the default
+    pub fn default_path() -> PathBuf {
+        std::env::var_os(CONFIG_PATH_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH))
+    }
+
+    /// Load configuration from `path`, falling back to defaults if it doesn't exist
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::from_toml(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Parse configuration from TOML text
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        config.router.validate()?;
+        config.iaq.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        let config = GatewayConfig::from_toml("").unwrap();
+        assert!(config.webhook.is_none());
+    }
+
+    #[test]
+    fn test_example_config_parses() {
+        let config = GatewayConfig::from_toml(include_str!("../gateway.example.toml")).unwrap();
+        assert!(config.webhook.is_some());
+        assert!(crate::aggregate::Aggregator::new(&config.aggregate).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_section_rejected() {
+        assert!(GatewayConfig::from_toml("[mqtt]\nbroker = \"x\"").is_err());
+    }
+}
@@ -0,0 +1,42 @@
+//! Built-in web dashboard
+//!
+//! A single self-contained HTML page compiled into the binary and served at
+//! `/` by the API server. It backfills from the REST API and then follows
+//! the SSE stream, so it needs nothing beyond `[api]` (charts stay empty
+//! until live data arrives when `[storage]` is off).
+
+use axum::response::Html;
+use axum::routing::get;
+use axum::Router;
+
+const INDEX_HTML: &str = include_str!("../dashboard/index.html");
+
+pub fn router() -> Router {
+    Router::new().route("/", get(|| async { Html(INDEX_HTML) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_serves_embedded_page() {
+        let response = router()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let html = std::str::from_utf8(&body).unwrap();
+        assert!(html.contains("/api/v1/stream/sse"));
+        assert!(html.contains("/api/v1/history"));
+    }
+}
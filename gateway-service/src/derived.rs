@@ -0,0 +1,280 @@
+//! Derived environmental metrics
+//!
+//! Computed from node1's SHT31 temperature/humidity and node2's BMP280
+//! pressure:
+//! - Dew point (Magnus formula, Sonntag 1990 coefficients)
+//! - Absolute humidity in g/m³
+//! - Heat index (NWS Rothfusz regression with its low/high humidity adjustments)
+//! - Barometric altitude (international barometric formula) against a
+//!   configurable sea-level reference pressure
+//!
+//! And from the RSSI/SNR node2 measured on node1's packet:
+//! - LoRa link margin: received signal above the receiver sensitivity for
+//!   the configured spreading factor and bandwidth
+
+use node_protocol::adr::{snr_floor_tenths_db, BANDWIDTH_KHZ};
+use serde::{Deserialize, Serialize};
+
+use crate::telemetry::TelemetryPacket;
+
+/// Standard atmosphere sea-level pressure in hPa
+const STANDARD_SEA_LEVEL_HPA: f32 = 1013.25;
+
+/// SX126x/SX127x receiver noise figure in dB
+const LORA_NOISE_FIGURE_DB: f64 = 6.0;
+
+// Magnus coefficients over water, valid -45°C..60°C
+const MAGNUS_A: f64 = 17.62;
+const MAGNUS_B: f64 = 243.12;
+
+/// Derived metrics configuration (`[derived]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DerivedConfig {
+    /// Sea-level reference pressure for altitude (QNH), in hPa
+    pub sea_level_pressure_hpa: f32,
+    /// Spreading factor node1 boots with (first `AT+PARAMETER` field); packets
+    /// reporting the ADR-chosen SF override it
+    pub lora_spreading_factor: u8,
+    /// LoRa bandwidth in kHz (`AT+PARAMETER` codes 7/8/9 = 125/250/500)
+    pub lora_bandwidth_khz: f32,
+}
+
+impl Default for DerivedConfig {
+    fn default() -> Self {
+        Self {
+            sea_level_pressure_hpa: STANDARD_SEA_LEVEL_HPA,
+            lora_spreading_factor: 7,
+            lora_bandwidth_khz: BANDWIDTH_KHZ as f32,
+        }
+    }
+}
+
+/// Metrics derived from a single telemetry packet
+///
+/// Fields are `None` when their inputs are missing or out of range
+/// (e.g. no BMP280 pressure yet, or 0% humidity).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DerivedMetrics {
+    /// Node 1 dew point in °C
+    pub dew_point_c: Option<f32>,
+    /// Node 1 absolute humidity in g/m³
+    pub absolute_humidity_gm3: Option<f32>,
+    /// Node 1 heat index ("feels like") in °C
+    pub heat_index_c: Option<f32>,
+    /// Node 2 barometric altitude in metres
+    pub altitude_m: Option<f32>,
+    /// node1 → node2 link margin in dB (`None` for an unsupported SF/BW)
+    pub link_margin_db: Option<f32>,
+}
+
+impl DerivedMetrics {
+    /// Compute all derived metrics for a packet
+    pub fn compute(packet: &TelemetryPacket, config: &DerivedConfig) -> Self {
+        let t = packet.n1.t;
+        let rh = packet.n1.h;
+
+        Self {
+            dew_point_c: dew_point(t, rh),
+            absolute_humidity_gm3: absolute_humidity(t, rh),
+            heat_index_c: heat_index(t, rh),
+            altitude_m: packet
+                .n2
+                .p
+                .and_then(|p| barometric_altitude(p, config.sea_level_pressure_hpa)),
+            link_margin_db: link_margin(
+                packet.sig.rssi,
+                packet.sig.snr,
+                packet.sig.sf.unwrap_or(config.lora_spreading_factor),
+                config.lora_bandwidth_khz,
+            ),
+        }
+    }
+}
+
+fn valid_humidity(rh: f32) -> bool {
+    rh > 0.0 && rh <= 100.0
+}
+
+/// Dew point in °C from temperature (°C) and relative humidity (%)
+pub fn dew_point(temp_c: f32, rh_pct: f32) -> Option<f32> {
+    if !valid_humidity(rh_pct) {
+        return None;
+    }
+    let t = temp_c as f64;
+    let gamma = (rh_pct as f64 / 100.0).ln() + MAGNUS_A * t / (MAGNUS_B + t);
+    Some((MAGNUS_B * gamma / (MAGNUS_A - gamma)) as f32)
+}
+
+/// Absolute humidity in g/m³ from temperature (°C) and relative humidity (%)
+pub fn absolute_humidity(temp_c: f32, rh_pct: f32) -> Option<f32> {
+    if !valid_humidity(rh_pct) {
+        return None;
+    }
+    let t = temp_c as f64;
+    // Saturation vapour pressure (hPa), then ideal gas law for water vapour
+    let saturation_hpa = 6.112 * (17.67 * t / (t + 243.5)).exp();
+    Some((saturation_hpa * rh_pct as f64 * 2.1674 / (273.15 + t)) as f32)
+}
+
+/// Heat index in °C from temperature (°C) and relative humidity (%)
+///
+/// Below ~27°C the heat index is essentially the air temperature; the NWS
+/// simple formula handles that range and the full regression takes over above.
+pub fn heat_index(temp_c: f32, rh_pct: f32) -> Option<f32> {
+    if !(0.0..=100.0).contains(&rh_pct) {
+        return None;
+    }
+    let t = temp_c as f64 * 9.0 / 5.0 + 32.0;
+    let rh = rh_pct as f64;
+
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let hi_f = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.049_015_23 * t + 10.143_331_27 * rh
+            - 0.224_755_41 * t * rh
+            - 0.006_837_83 * t * t
+            - 0.054_817_17 * rh * rh
+            + 0.001_228_74 * t * t * rh
+            + 0.000_852_82 * t * rh * rh
+            - 0.000_001_99 * t * t * rh * rh;
+
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= ((13.0 - rh) / 4.0) * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += ((rh - 85.0) / 10.0) * ((87.0 - t) / 5.0);
+        }
+        hi
+    };
+
+    Some(((hi_f - 32.0) * 5.0 / 9.0) as f32)
+}
+
+/// Altitude in metres from station pressure and sea-level reference (both hPa)
+pub fn barometric_altitude(pressure_hpa: f32, sea_level_hpa: f32) -> Option<f32> {
+    if pressure_hpa <= 0.0 || sea_level_hpa <= 0.0 {
+        return None;
+    }
+    let ratio = pressure_hpa as f64 / sea_level_hpa as f64;
+    Some((44_330.0 * (1.0 - ratio.powf(1.0 / 5.255))) as f32)
+}
+
+/// LoRa receiver sensitivity in dBm: thermal noise over the bandwidth, plus
+/// the noise figure and the spreading factor's SNR floor (SF5..=12)
+pub fn lora_sensitivity(spreading_factor: u8, bandwidth_khz: f32) -> Option<f32> {
+    if !(5..=12).contains(&spreading_factor) || !(bandwidth_khz > 0.0 && bandwidth_khz.is_finite())
+    {
+        return None;
+    }
+    let noise_floor = -174.0 + 10.0 * (bandwidth_khz as f64 * 1000.0).log10();
+    let snr_floor = snr_floor_tenths_db(spreading_factor) as f64 / 10.0;
+    Some((noise_floor + LORA_NOISE_FIGURE_DB + snr_floor) as f32)
+}
+
+/// Link margin in dB: how far the received signal is above sensitivity
+///
+/// Below 0 dB SNR the RSSI reading is mostly noise, so the signal power is
+/// estimated as RSSI + SNR there.
+pub fn link_margin(
+    rssi_dbm: i16,
+    snr_db: i16,
+    spreading_factor: u8,
+    bandwidth_khz: f32,
+) -> Option<f32> {
+    let signal = rssi_dbm as f32 + (snr_db as f32).min(0.0);
+    Some(signal - lora_sensitivity(spreading_factor, bandwidth_khz)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f32>, expected: f32, tolerance: f32) {
+        let actual = actual.expect("value should be computed");
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected} ± {tolerance}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn test_dew_point_reference_values() {
+        assert_close(dew_point(25.0, 60.0), 16.7, 0.1);
+        assert_close(dew_point(20.0, 100.0), 20.0, 0.01);
+        assert_close(dew_point(0.0, 50.0), -9.2, 0.1);
+        assert_eq!(dew_point(25.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_absolute_humidity_reference_values() {
+        assert_close(absolute_humidity(25.0, 60.0), 13.8, 0.1);
+        assert_close(absolute_humidity(20.0, 100.0), 17.3, 0.1);
+    }
+
+    #[test]
+    fn test_heat_index_reference_values() {
+        // NWS heat index chart: 90°F / 70% -> 106°F, 100°F / 40% -> 109°F
+        assert_close(heat_index(32.22, 70.0), 41.1, 0.6);
+        assert_close(heat_index(37.78, 40.0), 42.8, 0.6);
+        // Mild conditions: feels like the air temperature
+        assert_close(heat_index(20.0, 50.0), 19.6, 0.5);
+    }
+
+    #[test]
+    fn test_barometric_altitude_reference_values() {
+        assert_close(barometric_altitude(1013.25, 1013.25), 0.0, 0.01);
+        // ICAO standard atmosphere: 898.76 hPa at 1000 m
+        assert_close(barometric_altitude(898.76, 1013.25), 1000.0, 2.0);
+        // Higher QNH on the same station pressure means a higher altitude
+        assert!(
+            barometric_altitude(1000.0, 1020.0).unwrap()
+                > barometric_altitude(1000.0, 1013.25).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lora_sensitivity_reference_values() {
+        // SX1276 datasheet: -124 dBm at SF7/125 kHz, -137 dBm at SF12/125 kHz
+        assert_close(lora_sensitivity(7, 125.0), -124.5, 1.0);
+        assert_close(lora_sensitivity(12, 125.0), -137.0, 1.0);
+        // Doubling the bandwidth costs 3 dB
+        assert_close(lora_sensitivity(7, 250.0), -121.5, 0.1);
+        assert_eq!(lora_sensitivity(13, 125.0), None);
+
+        assert_close(link_margin(-100, 8, 7, 125.0), 24.5, 0.1);
+        // Negative SNR: the signal sits below the RSSI reading
+        assert_close(link_margin(-118, -4, 7, 125.0), 2.5, 0.1);
+    }
+
+    #[test]
+    fn test_default_bandwidth_matches_firmware() {
+        // The nodes run AT+PARAMETER bandwidth code 9
+        assert_eq!(DerivedConfig::default().lora_bandwidth_khz, 500.0);
+    }
+
+    #[test]
+    fn test_compute_without_pressure() {
+        let packet: TelemetryPacket = serde_json::from_str(
+            r#"{"ts":1,"id":"N2","n1":{"t":25.0,"h":60.0,"g":1},"n2":{},"sig":{"rssi":-40,"snr":10},"sts":{"rx":1,"err":0}}"#,
+        )
+        .unwrap();
+        let derived = DerivedMetrics::compute(&packet, &DerivedConfig::default());
+        assert!(derived.dew_point_c.is_some());
+        assert_eq!(derived.altitude_m, None);
+    }
+
+    #[test]
+    fn test_link_margin_follows_reported_sf() {
+        let packet: TelemetryPacket = serde_json::from_str(
+            r#"{"ts":1,"id":"N2","n1":{"t":25.0,"h":60.0,"g":1},"n2":{},"sig":{"rssi":-110,"snr":-2,"sf":9,"pwr":16},"sts":{"rx":1,"err":0}}"#,
+        )
+        .unwrap();
+        let config = DerivedConfig::default();
+        let margin = DerivedMetrics::compute(&packet, &config).link_margin_db;
+        // SF9 demodulates 5 dB deeper than the configured SF7
+        assert_eq!(margin, link_margin(-110, -2, 9, config.lora_bandwidth_khz));
+        let at_sf7 = link_margin(-110, -2, 7, config.lora_bandwidth_khz).unwrap();
+        assert_close(margin, at_sf7 + 5.0, 0.1);
+    }
+}
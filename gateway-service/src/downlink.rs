@@ -0,0 +1,166 @@
+//! VCP downlink to node2
+//!
+//! node2 streams JSON out of USART2 (ST-Link VCP) and also listens on it for
+//! line-based commands (`node_protocol::downlink`). This task owns the write
+//! side of the serial port and sends a `TIME=<unix_ms>` beacon on a fixed
+//! interval so node2 (and node1, via ACKs) can keep wall-clock time. Other
+//! parts of the gateway (the TUI) queue extra commands via `DownlinkHandle`.
+
+use anyhow::{Context, Result};
+use node_protocol::downlink::Downlink;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_serial::SerialPortBuilderExt;
+use tracing::{debug, error, info, warn};
+
+use crate::clock::unix_time_ms;
+
+/// Downlink configuration (`[downlink]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DownlinkConfig {
+    /// ST-Link VCP device (e.g. /dev/ttyACM0)
+    pub port: String,
+    /// Must match node2's USART2 configuration
+    pub baud_rate: u32,
+    /// Seconds between time sync beacons
+    pub time_sync_interval_secs: u64,
+}
+
+impl Default for DownlinkConfig {
+    fn default() -> Self {
+        Self {
+            port: "/dev/ttyACM0".to_string(),
+            baud_rate: 115_200,
+            time_sync_interval_secs: 60,
+        }
+    }
+}
+
+/// Commands queued ahead of the serial writer
+const COMMAND_QUEUE_CAPACITY: usize = 16;
+
+/// Handle for queueing commands to node2
+#[derive(Clone)]
+pub struct DownlinkHandle {
+    tx: mpsc::Sender<Downlink>,
+}
+
+impl DownlinkHandle {
+    /// Queue a command without blocking
+    pub fn send(&self, command: Downlink) -> Result<()> {
+        self.tx
+            .try_send(command)
+            .map_err(|e| anyhow::anyhow!("Downlink queue full or closed: {}", e))
+    }
+}
+
+/// Open the VCP and start the downlink task
+pub fn spawn(config: &DownlinkConfig) -> Result<(DownlinkHandle, JoinHandle<()>)> {
+    let port = tokio_serial::new(&config.port, config.baud_rate)
+        .open_native_async()
+        .with_context(|| format!("Failed to open VCP downlink {}", config.port))?;
+
+    info!(
+        port = %config.port,
+        baud = config.baud_rate,
+        time_sync_interval_secs = config.time_sync_interval_secs,
+        "VCP downlink opened"
+    );
+
+    let interval = Duration::from_secs(config.time_sync_interval_secs.max(1));
+    let (tx, rx) = mpsc::channel(COMMAND_QUEUE_CAPACITY);
+    let task = tokio::spawn(run_downlink(port, interval, rx));
+    Ok((DownlinkHandle { tx }, task))
+}
+
+/// Write periodic time beacons and queued commands to `writer`
+async fn run_downlink<W: AsyncWrite + Unpin>(
+    mut writer: W,
+    time_sync_interval: Duration,
+    mut commands: mpsc::Receiver<Downlink>,
+) {
+    let mut ticker = tokio::time::interval(time_sync_interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut commands_open = true;
+
+    loop {
+        let command = tokio::select! {
+            _ = ticker.tick() => Downlink::TimeSync {
+                unix_ms: unix_time_ms(),
+            },
+            command = commands.recv(), if commands_open => match command {
+                Some(command) => command,
+                None => {
+                    // Every handle dropped: keep sending beacons
+                    commands_open = false;
+                    continue;
+                }
+            },
+        };
+
+        let line = format!("{}\n", command);
+        if let Err(e) = writer.write_all(line.as_bytes()).await {
+            error!(error = %e, "Failed to write to VCP downlink");
+            continue;
+        }
+        if let Err(e) = writer.flush().await {
+            warn!(error = %e, "Failed to flush VCP downlink");
+        }
+        debug!(command = %command, "Downlink command sent");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node_protocol::downlink::parse_downlink;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    #[tokio::test]
+    async fn test_sends_parseable_time_beacons() {
+        let (writer, reader) = tokio::io::duplex(256);
+        let before = unix_time_ms();
+        let (_commands, rx) = mpsc::channel(1);
+        let task = tokio::spawn(run_downlink(writer, Duration::from_millis(10), rx));
+
+        let mut lines = BufReader::new(reader).lines();
+        let mut last = 0;
+        for _ in 0..2 {
+            let beacon = lines.next_line().await.unwrap().unwrap();
+            match parse_downlink(&beacon) {
+                Some(Downlink::TimeSync { unix_ms }) => {
+                    assert!(unix_ms >= before && unix_ms >= last);
+                    last = unix_ms;
+                }
+                other => panic!("unexpected beacon {:?}", other),
+            }
+        }
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_writes_queued_commands() {
+        let (writer, reader) = tokio::io::duplex(256);
+        let (tx, rx) = mpsc::channel(1);
+        let handle = DownlinkHandle { tx };
+        // Long interval: only the immediate first beacon fires
+        let task = tokio::spawn(run_downlink(writer, Duration::from_secs(3600), rx));
+
+        let mut lines = BufReader::new(reader).lines();
+        lines.next_line().await.unwrap().unwrap();
+
+        let command = Downlink::TimeSync {
+            unix_ms: 1_767_225_600_000,
+        };
+        handle.send(command).unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(parse_downlink(&line), Some(command));
+
+        task.abort();
+    }
+}
@@ -0,0 +1,787 @@
+//! File export sinks (CSV, NDJSON, Parquet)
+//!
+//! Each `[[export]]` entry gets its own writer thread fed by a bounded
+//! queue. Files are written as `<name>.<ext>.part` and renamed when closed,
+//! so anything without `.part` is complete. A file is closed (rotated) when
+//! it exceeds `max_file_bytes`, when `rotate_interval_secs` have passed
+//! since it was opened, and on shutdown.
+//!
+//! With `compress = true`, closed CSV/NDJSON files are gzipped (`.csv.gz`);
+//! Parquet files instead use zstd-compressed column chunks so they stay
+//! directly readable.
+//!
+//! CSV and Parquet share one flat column layout (`ExportRow`); NDJSON
+//! writes the full processed record as serialized for webhooks.
+
+use anyhow::{anyhow, Context, Result};
+use arrow_array::{
+    ArrayRef, Float32Array, Float64Array, Int16Array, Int64Array, RecordBatch, StringArray,
+    UInt32Array,
+};
+use flate2::write::GzEncoder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::clock::{unix_time_ms, utc_compact, utc_datetime};
+use crate::sink::TelemetrySink;
+use crate::telemetry::ProcessedRecord;
+
+/// Export file format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// One file sink (`[[export]]` entry)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    pub format: ExportFormat,
+    /// Output directory (created if missing)
+    pub directory: PathBuf,
+    /// File name without extension; `{timestamp}` (UTC, 20260101T000000Z),
+    /// `{date}` (2026-01-01), `{seq}` (file counter) and `{format}` are replaced;
+    /// without `{seq}`, a `-N` suffix is added when the name is already taken
+    pub file_name: String,
+    /// Rotate once a file reaches this size (0 = no size limit)
+    pub max_file_bytes: u64,
+    /// Rotate files after this long (0 = no time limit)
+    pub rotate_interval_secs: u64,
+    /// Gzip closed CSV/NDJSON files; zstd column chunks for Parquet
+    pub compress: bool,
+    /// How often buffered data is pushed to disk
+    pub flush_interval_secs: u64,
+    /// Parquet rows per row group
+    pub parquet_row_group_rows: usize,
+    /// Records buffered for the writer; delivery waits for room when full
+    pub queue_capacity: usize,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Csv,
+            directory: PathBuf::from("exports"),
+            file_name: "telemetry-{timestamp}".to_string(),
+            max_file_bytes: 64 * 1024 * 1024,
+            rotate_interval_secs: 3600,
+            compress: false,
+            flush_interval_secs: 5,
+            parquet_row_group_rows: 1000,
+            queue_capacity: 1000,
+        }
+    }
+}
+
+/// Flat per-record row shared by the CSV and Parquet writers
+#[derive(Debug, Clone, Serialize)]
+struct ExportRow {
+    ts_ms: i64,
+    received_at_ms: i64,
+    gateway_id: String,
+    device_ts_ms: u32,
+    boot_epoch: u32,
+    n1_temperature: f32,
+    n1_humidity: f32,
+    n1_gas_resistance: u32,
+    n1_ts: Option<u32>,
+    n2_temperature: Option<f32>,
+    n2_pressure: Option<f32>,
+    rssi: i16,
+    snr: i16,
+    packets_received: u32,
+    crc_errors: u32,
+    rejected_frames: u32,
+    dew_point: Option<f32>,
+    absolute_humidity: Option<f32>,
+    heat_index: Option<f32>,
+    altitude: Option<f32>,
+    iaq: Option<f32>,
+    iaq_accuracy: &'static str,
+    drift_ppm: f64,
+}
+
+impl From<&ProcessedRecord> for ExportRow {
+    fn from(record: &ProcessedRecord) -> Self {
+        let p = &record.packet;
+        let d = &record.derived;
+        Self {
+            ts_ms: record.timestamp_ms() as i64,
+            received_at_ms: record.time.received_at_ms as i64,
+            gateway_id: p.id.clone(),
+            device_ts_ms: p.ts,
+            boot_epoch: record.time.boot_epoch,
+            n1_temperature: p.n1.t,
+            n1_humidity: p.n1.h,
+            n1_gas_resistance: p.n1.g,
+            n1_ts: p.n1.ts,
+            n2_temperature: p.n2.t,
+            n2_pressure: p.n2.p,
+            rssi: p.sig.rssi,
+            snr: p.sig.snr,
+            packets_received: p.sts.rx,
+            crc_errors: p.sts.err,
+            rejected_frames: p.sts.rej,
+            dew_point: d.dew_point_c,
+            absolute_humidity: d.absolute_humidity_gm3,
+            heat_index: d.heat_index_c,
+            altitude: d.altitude_m,
+            iaq: record.iaq.iaq,
+            iaq_accuracy: record.iaq.accuracy.as_str(),
+            drift_ppm: record.time.drift_ppm,
+        }
+    }
+}
+
+/// Columnar form of `rows` (same column names and order as the CSV header)
+fn record_batch(rows: &[ExportRow]) -> Result<RecordBatch> {
+    macro_rules! column {
+        ($array:ident, $field:ident) => {
+            Arc::new($array::from_iter_values(rows.iter().map(|r| r.$field))) as ArrayRef
+        };
+        ($array:ident, $field:ident, optional) => {
+            Arc::new($array::from_iter(rows.iter().map(|r| r.$field))) as ArrayRef
+        };
+    }
+
+    let columns = vec![
+        ("ts_ms", column!(Int64Array, ts_ms)),
+        ("received_at_ms", column!(Int64Array, received_at_ms)),
+        (
+            "gateway_id",
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| r.gateway_id.as_str()),
+            )) as ArrayRef,
+        ),
+        ("device_ts_ms", column!(UInt32Array, device_ts_ms)),
+        ("boot_epoch", column!(UInt32Array, boot_epoch)),
+        ("n1_temperature", column!(Float32Array, n1_temperature)),
+        ("n1_humidity", column!(Float32Array, n1_humidity)),
+        ("n1_gas_resistance", column!(UInt32Array, n1_gas_resistance)),
+        ("n1_ts", column!(UInt32Array, n1_ts, optional)),
+        (
+            "n2_temperature",
+            column!(Float32Array, n2_temperature, optional),
+        ),
+        ("n2_pressure", column!(Float32Array, n2_pressure, optional)),
+        ("rssi", column!(Int16Array, rssi)),
+        ("snr", column!(Int16Array, snr)),
+        ("packets_received", column!(UInt32Array, packets_received)),
+        ("crc_errors", column!(UInt32Array, crc_errors)),
+        ("rejected_frames", column!(UInt32Array, rejected_frames)),
+        ("dew_point", column!(Float32Array, dew_point, optional)),
+        (
+            "absolute_humidity",
+            column!(Float32Array, absolute_humidity, optional),
+        ),
+        ("heat_index", column!(Float32Array, heat_index, optional)),
+        ("altitude", column!(Float32Array, altitude, optional)),
+        ("iaq", column!(Float32Array, iaq, optional)),
+        (
+            "iaq_accuracy",
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| r.iaq_accuracy),
+            )) as ArrayRef,
+        ),
+        ("drift_ppm", column!(Float64Array, drift_ppm)),
+    ];
+    Ok(RecordBatch::try_from_iter(columns)?)
+}
+
+/// `Write` adapter that counts bytes for size-based rotation
+struct CountingWriter<W> {
+    inner: W,
+    bytes: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+enum FormatWriter {
+    Csv {
+        out: CountingWriter<BufWriter<File>>,
+        header_written: bool,
+    },
+    Ndjson(CountingWriter<BufWriter<File>>),
+    Parquet {
+        writer: Box<ArrowWriter<File>>,
+        pending: Vec<ExportRow>,
+    },
+}
+
+/// The file currently being written
+struct OpenFile {
+    writer: FormatWriter,
+    /// `<final path>.part`
+    part_path: PathBuf,
+    final_path: PathBuf,
+    opened_at_ms: u64,
+}
+
+impl OpenFile {
+    fn size(&self) -> u64 {
+        match &self.writer {
+            FormatWriter::Csv { out, .. } | FormatWriter::Ndjson(out) => out.bytes,
+            FormatWriter::Parquet { writer, .. } => {
+                writer.bytes_written() as u64 + writer.in_progress_size() as u64
+            }
+        }
+    }
+
+    fn write(&mut self, record: &ProcessedRecord, row_group_rows: usize) -> Result<()> {
+        match &mut self.writer {
+            FormatWriter::Csv {
+                out,
+                header_written,
+            } => {
+                // Row by row, so the byte count never lags behind a csv buffer
+                let mut row = csv::WriterBuilder::new()
+                    .has_headers(!*header_written)
+                    .from_writer(Vec::new());
+                row.serialize(ExportRow::from(record))?;
+                out.write_all(&row.into_inner().map_err(|e| e.into_error())?)?;
+                *header_written = true;
+            }
+            FormatWriter::Ndjson(w) => {
+                serde_json::to_writer(&mut *w, record)?;
+                w.write_all(b"\n")?;
+            }
+            FormatWriter::Parquet { writer, pending } => {
+                pending.push(ExportRow::from(record));
+                if pending.len() >= row_group_rows {
+                    writer.write(&record_batch(pending)?)?;
+                    pending.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Push buffered data towards disk (Parquet data only becomes readable on close)
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.writer {
+            FormatWriter::Csv { out, .. } | FormatWriter::Ndjson(out) => out.flush()?,
+            FormatWriter::Parquet { writer, pending } => {
+                if !pending.is_empty() {
+                    writer.write(&record_batch(pending)?)?;
+                    pending.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish the file and move it to its final name, returning that path
+    fn close(mut self) -> Result<PathBuf> {
+        self.flush()?;
+        match self.writer {
+            FormatWriter::Csv { out, .. } | FormatWriter::Ndjson(out) => {
+                out.inner
+                    .into_inner()
+                    .map_err(|e| e.into_error())?
+                    .sync_all()?;
+            }
+            FormatWriter::Parquet { writer, .. } => {
+                writer.close()?;
+            }
+        }
+        std::fs::rename(&self.part_path, &self.final_path)?;
+        Ok(self.final_path)
+    }
+}
+
+/// Expand the `file_name` pattern
+fn expand_file_name(pattern: &str, format: ExportFormat, unix_ms: u64, seq: u32) -> String {
+    let (y, mo, d, ..) = utc_datetime(unix_ms);
+    pattern
+        .replace("{timestamp}", &utc_compact(unix_ms))
+        .replace("{date}", &format!("{:04}-{:02}-{:02}", y, mo, d))
+        .replace("{seq}", &format!("{:04}", seq))
+        .replace("{format}", format.extension())
+}
+
+/// A rotating file sink; all I/O is blocking
+struct FileSink {
+    config: ExportConfig,
+    current: Option<OpenFile>,
+    seq: u32,
+    /// When the current file was last flushed
+    flushed_at_ms: u64,
+}
+
+impl FileSink {
+    fn new(config: ExportConfig) -> Result<Self> {
+        if config.file_name.is_empty() || config.file_name.contains(['/', '\\']) {
+            anyhow::bail!("Invalid export file_name {:?}", config.file_name);
+        }
+        std::fs::create_dir_all(&config.directory).with_context(|| {
+            format!(
+                "Failed to create export directory {}",
+                config.directory.display()
+            )
+        })?;
+        Ok(Self {
+            config,
+            current: None,
+            seq: 0,
+            flushed_at_ms: 0,
+        })
+    }
+
+    fn open(&mut self, now_ms: u64) -> Result<OpenFile> {
+        let ext = self.config.format.extension();
+        // Never clobber an existing export (e.g. same {timestamp} after a restart
+        // or a size rotation within one second); patterns without {seq} get a
+        // numeric suffix until the name is free
+        let has_seq = self.config.file_name.contains("{seq}");
+        let mut attempt = 0u32;
+        let (final_path, part_path, file) = loop {
+            let mut name =
+                expand_file_name(&self.config.file_name, self.config.format, now_ms, self.seq);
+            self.seq += 1;
+            if !has_seq && attempt > 0 {
+                name = format!("{}-{}", name, attempt);
+            }
+            attempt += 1;
+            let final_path = self.config.directory.join(format!("{}.{}", name, ext));
+            let part_path = final_path.with_extension(format!("{}.part", ext));
+            let gz_path = final_path.with_extension(format!("{}.gz", ext));
+            if final_path.exists() || gz_path.exists() {
+                continue;
+            }
+            match File::create_new(&part_path) {
+                Ok(file) => break (final_path, part_path, file),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to create {}", part_path.display()))
+                }
+            }
+        };
+
+        let writer = match self.config.format {
+            ExportFormat::Csv => FormatWriter::Csv {
+                out: CountingWriter {
+                    inner: BufWriter::new(file),
+                    bytes: 0,
+                },
+                header_written: false,
+            },
+            ExportFormat::Ndjson => FormatWriter::Ndjson(CountingWriter {
+                inner: BufWriter::new(file),
+                bytes: 0,
+            }),
+            ExportFormat::Parquet => {
+                let compression = if self.config.compress {
+                    Compression::ZSTD(ZstdLevel::default())
+                } else {
+                    Compression::UNCOMPRESSED
+                };
+                let props = WriterProperties::builder()
+                    .set_compression(compression)
+                    .set_max_row_group_size(self.config.parquet_row_group_rows.max(1))
+                    .build();
+                let schema = record_batch(&[])?.schema();
+                FormatWriter::Parquet {
+                    writer: Box::new(ArrowWriter::try_new(file, schema, Some(props))?),
+                    pending: Vec::new(),
+                }
+            }
+        };
+
+        info!(path = %final_path.display(), "Export file opened");
+        Ok(OpenFile {
+            writer,
+            part_path,
+            final_path,
+            opened_at_ms: now_ms,
+        })
+    }
+
+    fn write(&mut self, record: &ProcessedRecord, now_ms: u64) -> Result<()> {
+        // Deadlines are checked here too: while records keep coming, ticks
+        // may not get through the writer queue
+        self.tick(now_ms)?;
+        if self.current.is_none() {
+            self.current = Some(self.open(now_ms)?);
+            self.flushed_at_ms = now_ms;
+        }
+        let file = self.current.as_mut().expect("file opened above");
+        file.write(record, self.config.parquet_row_group_rows.max(1))?;
+
+        if self.config.max_file_bytes > 0 && file.size() >= self.config.max_file_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Time-based rotation and the periodic flush, if due
+    fn tick(&mut self, now_ms: u64) -> Result<()> {
+        let Some(file) = &mut self.current else {
+            return Ok(());
+        };
+        let interval_ms = self.config.rotate_interval_secs * 1000;
+        if interval_ms > 0 && now_ms >= file.opened_at_ms + interval_ms {
+            return self.rotate();
+        }
+        if now_ms >= self.flushed_at_ms + self.config.flush_interval_secs.max(1) * 1000 {
+            file.flush()?;
+            self.flushed_at_ms = now_ms;
+        }
+        Ok(())
+    }
+
+    /// Close the current file (if any) and compress it if configured
+    fn rotate(&mut self) -> Result<()> {
+        let Some(file) = self.current.take() else {
+            return Ok(());
+        };
+        let path = file.close()?;
+
+        if self.config.compress && self.config.format != ExportFormat::Parquet {
+            let path = gzip_file(&path)?;
+            info!(path = %path.display(), "Export file closed and compressed");
+        } else {
+            info!(path = %path.display(), "Export file closed");
+        }
+        Ok(())
+    }
+}
+
+/// Gzip `path` to `path.gz` and remove the original
+fn gzip_file(path: &Path) -> Result<PathBuf> {
+    let mut gz_name = path.as_os_str().to_owned();
+    gz_name.push(".gz");
+    let gz_path = PathBuf::from(gz_name);
+
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(&gz_path)?),
+        flate2::Compression::default(),
+    );
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder
+        .finish()?
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    std::fs::remove_file(path)?;
+    Ok(gz_path)
+}
+
+/// What goes through an export writer's queue
+enum Job {
+    Record(Box<ProcessedRecord>),
+    /// Check the flush and rotation deadlines
+    Tick,
+}
+
+/// Handle for queueing records to one export sink
+#[derive(Clone)]
+pub struct ExportHandle {
+    tx: mpsc::Sender<Job>,
+    format: ExportFormat,
+}
+
+impl TelemetrySink for ExportHandle {
+    /// Queues the record for this export's file writer thread
+    async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+        self.tx
+            .send(Job::Record(Box::new(record.clone())))
+            .await
+            .map_err(|_| anyhow!("{:?} export writer stopped", self.format))
+    }
+
+    /// NDJSON writes `record.units`; CSV/Parquet columns are °C/hPa
+    fn accepts_units(&self) -> bool {
+        self.format == ExportFormat::Ndjson
+    }
+}
+
+/// Start an export writer
+///
+/// The writer closes its file and exits once every `ExportHandle` is
+/// dropped, so awaiting the returned task flushes everything to disk.
+pub fn spawn(config: &ExportConfig) -> Result<(ExportHandle, JoinHandle<()>)> {
+    let mut sink = FileSink::new(config.clone())?;
+    let (tx, mut rx) = mpsc::channel::<Job>(config.queue_capacity.max(1));
+    let flush_interval = Duration::from_secs(config.flush_interval_secs.max(1));
+    let format = config.format;
+
+    info!(
+        format = ?format,
+        directory = %config.directory.display(),
+        "Export sink started"
+    );
+
+    // Wakes the writer when no records come in; holds only a weak sender,
+    // so the writer still stops once every handle is dropped
+    let ticks = tx.downgrade();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(flush_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            let Some(tx) = ticks.upgrade() else {
+                break;
+            };
+            // A full queue means records are coming and `write` checks the deadlines
+            let _ = tx.try_send(Job::Tick);
+        }
+    });
+
+    let task = tokio::task::spawn_blocking(move || {
+        while let Some(job) = rx.blocking_recv() {
+            let result = match job {
+                Job::Record(record) => sink.write(&record, unix_time_ms()),
+                Job::Tick => sink.tick(unix_time_ms()),
+            };
+            if let Err(e) = result {
+                error!(error = %format!("{:#}", e), format = ?format, "Export write failed");
+                // Start over with a fresh file rather than failing forever
+                sink.current = None;
+            }
+        }
+
+        if let Err(e) = sink.rotate() {
+            error!(error = %format!("{:#}", e), format = ?format, "Failed to close export file");
+        }
+        info!(format = ?format, "Export sink stopped");
+    });
+
+    Ok((ExportHandle { tx, format }, task))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::io::{BufRead, BufReader};
+
+    const T0: u64 = 1_767_225_600_000; // 2026-01-01T00:00:00Z
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("export-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_file_name_pattern() {
+        assert_eq!(
+            expand_file_name("n2-{timestamp}-{seq}", ExportFormat::Csv, T0 + 3_723_000, 7),
+            "n2-20260101T010203Z-0007"
+        );
+        assert_eq!(
+            expand_file_name("{format}-{date}", ExportFormat::Ndjson, 951_782_400_000, 0),
+            "ndjson-2000-02-29"
+        );
+    }
+
+    #[test]
+    fn test_csv_rotates_by_size() {
+        let dir = test_dir("csv");
+        let mut sink = FileSink::new(ExportConfig {
+            directory: dir.clone(),
+            file_name: "t-{seq}".to_string(),
+            max_file_bytes: 1,
+            ..ExportConfig::default()
+        })
+        .unwrap();
+
+        for i in 0..3 {
+            sink.write(&record(T0 + i * 10_000, 20.0 + i as f32), T0)
+                .unwrap();
+        }
+        sink.rotate().unwrap();
+
+        assert_eq!(files(&dir), ["t-0000.csv", "t-0001.csv", "t-0002.csv"]);
+        let text = std::fs::read_to_string(dir.join("t-0002.csv")).unwrap();
+        let mut lines = text.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("ts_ms,received_at_ms,gateway_id"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with(&format!("{},", T0 + 20_000)));
+    }
+
+    #[test]
+    fn test_rotations_within_one_timestamp_get_unique_names() {
+        let dir = test_dir("same-second");
+        let config = ExportConfig {
+            directory: dir.clone(),
+            max_file_bytes: 1,
+            ..ExportConfig::default()
+        };
+        let mut sink = FileSink::new(config.clone()).unwrap();
+        sink.write(&record(T0, 20.0), T0).unwrap();
+        sink.write(&record(T0 + 100, 21.0), T0 + 100).unwrap();
+        sink.write(&record(T0 + 200, 22.0), T0 + 200).unwrap();
+
+        // A restart within the same second must not hang or clobber either
+        let mut sink = FileSink::new(config).unwrap();
+        sink.write(&record(T0 + 300, 23.0), T0 + 300).unwrap();
+
+        assert_eq!(
+            files(&dir),
+            [
+                "telemetry-20260101T000000Z-1.csv",
+                "telemetry-20260101T000000Z-2.csv",
+                "telemetry-20260101T000000Z-3.csv",
+                "telemetry-20260101T000000Z.csv",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ndjson_rotates_by_time_and_compresses() {
+        let dir = test_dir("ndjson");
+        let mut sink = FileSink::new(ExportConfig {
+            format: ExportFormat::Ndjson,
+            directory: dir.clone(),
+            rotate_interval_secs: 60,
+            compress: true,
+            ..ExportConfig::default()
+        })
+        .unwrap();
+
+        sink.write(&record(T0, 20.0), T0).unwrap();
+        sink.write(&record(T0 + 10_000, 21.0), T0 + 10_000).unwrap();
+        sink.tick(T0 + 30_000).unwrap();
+        // Still open and unfinished
+        assert_eq!(files(&dir), ["telemetry-20260101T000000Z.ndjson.part"]);
+
+        sink.tick(T0 + 60_000).unwrap();
+        assert_eq!(files(&dir), ["telemetry-20260101T000000Z.ndjson.gz"]);
+
+        let gz = File::open(dir.join("telemetry-20260101T000000Z.ndjson.gz")).unwrap();
+        let lines: Vec<serde_json::Value> = BufReader::new(flate2::read::GzDecoder::new(gz))
+            .lines()
+            .map(|l| serde_json::from_str(&l.unwrap()).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["n1"]["t"], 21.0);
+        assert_eq!(lines[1]["time"]["corrected_at_ms"], T0 + 10_000);
+    }
+
+    #[test]
+    fn test_writes_alone_flush_and_rotate() {
+        let dir = test_dir("busy");
+        let mut sink = FileSink::new(ExportConfig {
+            directory: dir.clone(),
+            rotate_interval_secs: 60,
+            flush_interval_secs: 5,
+            ..ExportConfig::default()
+        })
+        .unwrap();
+
+        // No ticks in between, as when records outpace them
+        sink.write(&record(T0, 20.0), T0).unwrap();
+        sink.write(&record(T0 + 6_000, 21.0), T0 + 6_000).unwrap();
+        let part = dir.join("telemetry-20260101T000000Z.csv.part");
+        assert_eq!(std::fs::read_to_string(&part).unwrap().lines().count(), 2);
+
+        sink.write(&record(T0 + 60_000, 22.0), T0 + 60_000).unwrap();
+        assert_eq!(
+            files(&dir),
+            [
+                "telemetry-20260101T000000Z.csv",
+                "telemetry-20260101T000100Z.csv.part"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parquet_roundtrip() {
+        let dir = test_dir("parquet");
+        let mut sink = FileSink::new(ExportConfig {
+            format: ExportFormat::Parquet,
+            directory: dir.clone(),
+            compress: true,
+            parquet_row_group_rows: 2,
+            ..ExportConfig::default()
+        })
+        .unwrap();
+
+        for i in 0..3 {
+            sink.write(&record(T0 + i * 10_000, 20.0 + i as f32), T0)
+                .unwrap();
+        }
+        sink.rotate().unwrap();
+
+        let path = dir.join("telemetry-20260101T000000Z.parquet");
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut rows = 0;
+        let mut temps = Vec::new();
+        for batch in &mut reader {
+            let batch = batch.unwrap();
+            rows += batch.num_rows();
+            let column = batch.column_by_name("n1_temperature").unwrap();
+            let column = column.as_any().downcast_ref::<Float32Array>().unwrap();
+            temps.extend(column.values().iter().copied());
+        }
+        assert_eq!(rows, 3);
+        assert_eq!(temps, [20.0, 21.0, 22.0]);
+    }
+
+    #[test]
+    fn test_csv_and_parquet_columns_match() {
+        let mut csv = csv::Writer::from_writer(Vec::new());
+        csv.serialize(ExportRow::from(&record(T0, 20.0))).unwrap();
+        let text = String::from_utf8(csv.into_inner().unwrap()).unwrap();
+        let header: Vec<&str> = text.lines().next().unwrap().split(',').collect();
+
+        let schema = record_batch(&[]).unwrap().schema();
+        let columns: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(header, columns);
+    }
+}
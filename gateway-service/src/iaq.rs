@@ -0,0 +1,410 @@
+//! Indoor air quality (IAQ) estimation from BME680 gas resistance
+//!
+//! The BME680 reports a raw gas resistance: higher means cleaner air, but the
+//! absolute value differs per sensor and drifts with age. So we score each
+//! reading against a per-node baseline:
+//! - Burn-in: the first `burn_in_samples` readings are collected and the
+//!   baseline starts as the mean of the second half (heater has settled)
+//! - Rolling baseline: follows cleaner air quickly, decays slowly otherwise
+//! - Score: 75% gas ratio to baseline + 25% humidity distance from the
+//!   ideal, mapped onto the familiar 0 (excellent) .. 500 (hazardous) scale
+//!
+//! Baselines are persisted to a JSON file so a restart doesn't mean another
+//! burn-in.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Share of the air quality score carried by humidity (the rest is gas)
+const HUMIDITY_WEIGHT: f32 = 0.25;
+
+/// IAQ configuration (`[iaq]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IaqConfig {
+    /// Readings collected before the first score (10 s per reading from node1)
+    pub burn_in_samples: u32,
+    /// Ideal relative humidity in %
+    pub humidity_baseline_pct: f32,
+    /// Baseline tracking rate when gas resistance rises (cleaner air)
+    pub baseline_rise_rate: f32,
+    /// Baseline tracking rate when gas resistance falls (pollution or drift)
+    pub baseline_decay_rate: f32,
+    /// Where baselines are persisted (`None` keeps them in memory only)
+    pub baseline_path: Option<PathBuf>,
+    /// Persist baselines every N readings
+    pub save_interval_samples: u32,
+}
+
+impl Default for IaqConfig {
+    fn default() -> Self {
+        Self {
+            burn_in_samples: 30,
+            humidity_baseline_pct: 40.0,
+            baseline_rise_rate: 0.1,
+            baseline_decay_rate: 0.001,
+            baseline_path: Some(PathBuf::from("iaq-baseline.json")),
+            save_interval_samples: 30,
+        }
+    }
+}
+
+impl IaqConfig {
+    /// Reject settings the score can't be computed with
+    pub fn validate(&self) -> Result<()> {
+        // The humidity score divides by the distance to 0 % and to 100 %
+        if !(self.humidity_baseline_pct > 0.0 && self.humidity_baseline_pct < 100.0) {
+            bail!(
+                "[iaq] humidity_baseline_pct must be between 0 and 100 (exclusive), got {}",
+                self.humidity_baseline_pct
+            );
+        }
+        // Each reading moves the baseline this share of the way towards it
+        for (name, rate) in [
+            ("baseline_rise_rate", self.baseline_rise_rate),
+            ("baseline_decay_rate", self.baseline_decay_rate),
+        ] {
+            if !(rate > 0.0 && rate <= 1.0) {
+                bail!("[iaq] {} must be in (0, 1], got {}", name, rate);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How far the estimate can be trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IaqAccuracy {
+    /// Still learning the baseline, no score yet
+    BurnIn,
+    /// Baseline just established
+    Low,
+    /// Baseline has tracked a few burn-in periods
+    Medium,
+    /// Baseline well established
+    High,
+}
+
+impl IaqAccuracy {
+    /// Same name as the serialized form
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IaqAccuracy::BurnIn => "burn_in",
+            IaqAccuracy::Low => "low",
+            IaqAccuracy::Medium => "medium",
+            IaqAccuracy::High => "high",
+        }
+    }
+}
+
+/// IAQ estimate attached to each processed record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IaqReading {
+    /// IAQ index, 0 (excellent) .. 500 (hazardous); `None` during burn-in
+    pub iaq: Option<f32>,
+    pub accuracy: IaqAccuracy,
+    /// Burn-in completion in %
+    pub burn_in_pct: u8,
+    /// Current gas resistance baseline in ohms (0 during burn-in)
+    pub baseline_ohm: f32,
+}
+
+/// Learned baseline for one node
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GasBaseline {
+    /// Baseline gas resistance in ohms (0 until burn-in completes)
+    pub baseline_ohm: f32,
+    /// Readings seen since the baseline was first learned
+    pub samples: u64,
+    /// Burn-in readings (cleared once the baseline exists)
+    #[serde(skip)]
+    burn_in: Vec<f32>,
+}
+
+impl GasBaseline {
+    fn is_ready(&self) -> bool {
+        self.baseline_ohm > 0.0
+    }
+}
+
+/// Per-node IAQ estimator
+pub struct IaqEstimator {
+    config: IaqConfig,
+    baselines: HashMap<String, GasBaseline>,
+    unsaved: u32,
+}
+
+impl IaqEstimator {
+    /// Create an estimator, restoring persisted baselines if available
+    pub fn new(config: IaqConfig) -> Self {
+        let baselines = match &config.baseline_path {
+            Some(path) => match load_baselines(path) {
+                Ok(baselines) => {
+                    if !baselines.is_empty() {
+                        info!(
+                            path = %path.display(),
+                            nodes = baselines.len(),
+                            "Restored IAQ gas baselines"
+                        );
+                    }
+                    baselines
+                }
+                Err(e) => {
+                    warn!(error = %e, "Failed to load IAQ baselines, starting burn-in");
+                    HashMap::new()
+                }
+            },
+            None => HashMap::new(),
+        };
+
+        Self {
+            config,
+            baselines,
+            unsaved: 0,
+        }
+    }
+
+    /// Feed one reading and get the IAQ estimate for it
+    pub fn update(&mut self, node: &str, gas_ohm: u32, humidity_pct: f32) -> IaqReading {
+        let burn_in_samples = self.config.burn_in_samples.max(2);
+        let gas = gas_ohm as f32;
+        let baseline = self.baselines.entry(node.to_string()).or_default();
+
+        if !baseline.is_ready() {
+            baseline.burn_in.push(gas);
+            let collected = baseline.burn_in.len() as u32;
+
+            if collected < burn_in_samples {
+                return IaqReading {
+                    iaq: None,
+                    accuracy: IaqAccuracy::BurnIn,
+                    burn_in_pct: (collected * 100 / burn_in_samples) as u8,
+                    baseline_ohm: 0.0,
+                };
+            }
+
+            // Heater needs time to settle: only trust the second half
+            let settled = &baseline.burn_in[baseline.burn_in.len() / 2..];
+            baseline.baseline_ohm = settled.iter().sum::<f32>() / settled.len() as f32;
+            baseline.burn_in.clear();
+            info!(
+                node,
+                baseline_ohm = baseline.baseline_ohm,
+                "IAQ burn-in complete"
+            );
+        } else {
+            let rate = if gas > baseline.baseline_ohm {
+                self.config.baseline_rise_rate
+            } else {
+                self.config.baseline_decay_rate
+            };
+            baseline.baseline_ohm += rate * (gas - baseline.baseline_ohm);
+        }
+        baseline.samples += 1;
+
+        let reading = IaqReading {
+            iaq: Some(iaq_score(
+                gas,
+                baseline.baseline_ohm,
+                humidity_pct,
+                self.config.humidity_baseline_pct,
+            )),
+            accuracy: accuracy_for(baseline.samples, burn_in_samples as u64),
+            burn_in_pct: 100,
+            baseline_ohm: baseline.baseline_ohm,
+        };
+
+        self.unsaved += 1;
+        if self.unsaved >= self.config.save_interval_samples.max(1) {
+            self.save();
+        }
+
+        reading
+    }
+
+    /// Persist learned baselines (no-op without a `baseline_path`)
+    pub fn save(&mut self) {
+        self.unsaved = 0;
+        let Some(path) = &self.config.baseline_path else {
+            return;
+        };
+
+        let ready: HashMap<&String, &GasBaseline> = self
+            .baselines
+            .iter()
+            .filter(|(_, b)| b.is_ready())
+            .collect();
+        if ready.is_empty() {
+            return;
+        }
+
+        if let Err(e) = save_baselines(path, &ready) {
+            warn!(error = %e, path = %path.display(), "Failed to save IAQ baselines");
+        }
+    }
+}
+
+fn accuracy_for(samples: u64, burn_in_samples: u64) -> IaqAccuracy {
+    if samples < burn_in_samples * 2 {
+        IaqAccuracy::Low
+    } else if samples < burn_in_samples * 4 {
+        IaqAccuracy::Medium
+    } else {
+        IaqAccuracy::High
+    }
+}
+
+/// IAQ index (0..500) from gas resistance and humidity against their baselines
+pub fn iaq_score(
+    gas_ohm: f32,
+    baseline_ohm: f32,
+    humidity_pct: f32,
+    humidity_baseline: f32,
+) -> f32 {
+    let humidity_max = HUMIDITY_WEIGHT * 100.0;
+    let gas_max = 100.0 - humidity_max;
+
+    // Humidity: full marks at the ideal, falling linearly towards 0% / 100%
+    let offset = humidity_pct - humidity_baseline;
+    let humidity_score = if offset > 0.0 {
+        (100.0 - humidity_baseline - offset) / (100.0 - humidity_baseline) * humidity_max
+    } else {
+        (humidity_baseline + offset) / humidity_baseline * humidity_max
+    }
+    .clamp(0.0, humidity_max);
+
+    // Gas: full marks at or above the clean-air baseline
+    let gas_score = if baseline_ohm > 0.0 {
+        (gas_ohm / baseline_ohm * gas_max).min(gas_max)
+    } else {
+        0.0
+    };
+
+    // 100 = perfect air quality -> IAQ 0
+    (100.0 - humidity_score - gas_score) * 5.0
+}
+
+fn load_baselines(path: &Path) -> Result<HashMap<String, GasBaseline>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let text = std::fs::read_to_string(path)?;
+    serde_json::from_str(&text).context("Invalid IAQ baseline file")
+}
+
+fn save_baselines(path: &Path, baselines: &HashMap<&String, &GasBaseline>) -> Result<()> {
+    // Write then rename so a crash never leaves a truncated file behind
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(baselines)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(baseline_path: Option<PathBuf>) -> IaqConfig {
+        IaqConfig {
+            burn_in_samples: 4,
+            baseline_path,
+            ..IaqConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_iaq_score_reference_points() {
+        // Clean air at ideal humidity is perfect
+        assert_eq!(iaq_score(50_000.0, 50_000.0, 40.0, 40.0), 0.0);
+        // Half the baseline resistance costs half the gas score
+        assert!((iaq_score(25_000.0, 50_000.0, 40.0, 40.0) - 187.5).abs() < 0.01);
+        // Completely dry air at baseline gas loses the humidity share
+        assert!((iaq_score(50_000.0, 50_000.0, 0.0, 40.0) - 125.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_humidity_baseline_must_be_inside_range() {
+        for pct in [0.0, 100.0, -5.0, f32::NAN] {
+            let config = IaqConfig {
+                humidity_baseline_pct: pct,
+                ..IaqConfig::default()
+            };
+            assert!(config.validate().is_err(), "{}", pct);
+        }
+        assert!(IaqConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_baseline_rates_must_be_fractions() {
+        for rate in [0.0, -0.1, 1.5, f32::NAN, f32::INFINITY] {
+            let rise = IaqConfig {
+                baseline_rise_rate: rate,
+                ..IaqConfig::default()
+            };
+            let decay = IaqConfig {
+                baseline_decay_rate: rate,
+                ..IaqConfig::default()
+            };
+            assert!(rise.validate().is_err(), "{}", rate);
+            assert!(decay.validate().is_err(), "{}", rate);
+        }
+        let full = IaqConfig {
+            baseline_rise_rate: 1.0,
+            ..IaqConfig::default()
+        };
+        assert!(full.validate().is_ok());
+    }
+
+    #[test]
+    fn test_burn_in_then_scores() {
+        let mut estimator = IaqEstimator::new(test_config(None));
+
+        for (i, gas) in [10_000, 40_000, 50_000].into_iter().enumerate() {
+            let reading = estimator.update("N1", gas, 40.0);
+            assert_eq!(reading.iaq, None);
+            assert_eq!(reading.accuracy, IaqAccuracy::BurnIn);
+            assert_eq!(reading.burn_in_pct as usize, (i + 1) * 25);
+        }
+
+        // Baseline is the mean of the settled half (50k, 50k), not the cold start
+        let reading = estimator.update("N1", 50_000, 40.0);
+        assert_eq!(reading.baseline_ohm, 50_000.0);
+        assert_eq!(reading.iaq, Some(0.0));
+        assert_eq!(reading.accuracy, IaqAccuracy::Low);
+
+        // Polluted air scores worse; the baseline barely moves
+        let reading = estimator.update("N1", 20_000, 40.0);
+        assert!(reading.iaq.unwrap() > 200.0);
+        assert!(reading.baseline_ohm > 49_900.0);
+
+        // Other nodes have their own burn-in
+        assert_eq!(
+            estimator.update("N3", 50_000, 40.0).accuracy,
+            IaqAccuracy::BurnIn
+        );
+    }
+
+    #[test]
+    fn test_baseline_persists_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("iaq-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("baseline.json");
+        let _ = std::fs::remove_file(&path);
+
+        let mut estimator = IaqEstimator::new(test_config(Some(path.clone())));
+        for _ in 0..12 {
+            estimator.update("N1", 60_000, 40.0);
+        }
+        estimator.save();
+
+        let mut restored = IaqEstimator::new(test_config(Some(path)));
+        let reading = restored.update("N1", 60_000, 40.0);
+        assert_eq!(reading.accuracy, IaqAccuracy::Medium);
+        assert_eq!(reading.baseline_ohm, 60_000.0);
+        assert_eq!(reading.iaq, Some(0.0));
+    }
+}
@@ -0,0 +1,502 @@
+//! Link budget and radio quality analytics
+//!
+//! Per node (node1 is the only one transmitting to node2 today), over the
+//! last `window` reports:
+//! - The node's current radio settings: the spreading factor and power ADR
+//!   picked, as reported with the latest packet (the `[derived]` boot SF for
+//!   firmware that doesn't report them)
+//! - RSSI, SNR and link margin distributions (min, p10, median, p90, max,
+//!   mean); the margin is the derived `n1.link_margin` metric, shifted to
+//!   the current spreading factor for packets sent at another one
+//! - Packet error rate from node2's `sts.rx`/`sts.err` counters: CRC
+//!   failures out of all packets heard during the window (node2 reboots,
+//!   which reset the counters, are handled)
+//! - A spreading factor recommendation: move up when the 10th percentile
+//!   margin is below `min_margin_db` or the packet error rate is above
+//!   `max_per_pct`. Each SF step buys 2.5 dB of sensitivity at roughly twice
+//!   the airtime, so the smallest sufficient step is suggested.
+//!
+//! The analytics are fed as the `link` sink, served at
+//! `GET /api/v1/link/quality`, and recommendation changes are logged.
+
+use anyhow::{bail, Result};
+use node_protocol::adr::snr_floor_tenths_db;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{info, warn};
+
+use crate::derived::{lora_sensitivity, DerivedConfig};
+use crate::sink::TelemetrySink;
+use crate::telemetry::ProcessedRecord;
+
+/// Sensitivity gained per spreading factor step, in dB
+const SF_STEP_DB: f64 = 2.5;
+
+/// Link analytics configuration (`[link]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkConfig {
+    /// Reports kept per node (360 = one hour at node1's 10 s interval)
+    pub window: usize,
+    /// Reports needed before recommending anything
+    pub min_samples: usize,
+    /// Fade margin the 10th percentile should keep, in dB
+    pub min_margin_db: f64,
+    /// Highest acceptable packet error rate, in %
+    pub max_per_pct: f64,
+    /// Highest spreading factor the radio supports (RYLR998: 11)
+    pub max_spreading_factor: u8,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            window: 360,
+            min_samples: 30,
+            min_margin_db: 10.0,
+            max_per_pct: 5.0,
+            max_spreading_factor: 11,
+        }
+    }
+}
+
+/// A node's current LoRa settings
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RadioSettings {
+    pub spreading_factor: u8,
+    /// Output power in dBm (`None` until the node reports it)
+    pub tx_power_dbm: Option<u8>,
+    pub bandwidth_khz: f32,
+    /// `None` for a spreading factor outside 5..=12
+    pub sensitivity_dbm: Option<f32>,
+    /// Lowest SNR the spreading factor demodulates
+    pub snr_limit_db: f32,
+}
+
+/// Spread of one quantity over the window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Distribution {
+    pub count: usize,
+    pub min: f64,
+    pub p10: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Distribution {
+    fn of(values: impl Iterator<Item = f64>) -> Option<Self> {
+        let mut sorted: Vec<f64> = values.collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+        // Nearest-rank percentile
+        let rank = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).saturating_sub(1)];
+        Some(Self {
+            count: sorted.len(),
+            min: sorted[0],
+            p10: rank(0.1),
+            median: rank(0.5),
+            p90: rank(0.9),
+            max: sorted[sorted.len() - 1],
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkAction {
+    /// The link is healthy (or nothing better is available)
+    Keep,
+    /// Move the node to `spreading_factor`
+    IncreaseSf,
+    /// Fewer than `min_samples` reports so far
+    InsufficientData,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Recommendation {
+    pub action: LinkAction,
+    /// Spreading factor to use (the current one unless `increase_sf`)
+    pub spreading_factor: u8,
+    pub reason: String,
+}
+
+/// Link quality of one node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeLinkReport {
+    /// Settings of the latest report
+    pub radio: RadioSettings,
+    /// Reports in the window
+    pub samples: usize,
+    /// Record time of the latest report, Unix ms
+    pub last_seen_ms: u64,
+    pub rssi_dbm: Option<Distribution>,
+    pub snr_db: Option<Distribution>,
+    /// At the current spreading factor
+    pub margin_db: Option<Distribution>,
+    /// Packets node2 decoded / failed CRC on during the window
+    pub packets_received: u64,
+    pub crc_errors: u64,
+    /// `crc_errors` out of all packets heard, in % (`None` before any)
+    pub packet_error_rate_pct: Option<f64>,
+    pub recommendation: Recommendation,
+}
+
+/// `GET /api/v1/link/quality` body
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LinkReport {
+    pub nodes: BTreeMap<String, NodeLinkReport>,
+}
+
+/// One report's link figures
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at_ms: u64,
+    rssi: Option<f64>,
+    snr: Option<f64>,
+    margin: Option<f64>,
+    spreading_factor: u8,
+    tx_power_dbm: Option<u8>,
+    /// node2's cumulative counters
+    rx: u32,
+    err: u32,
+}
+
+#[derive(Default)]
+struct NodeLink {
+    samples: VecDeque<Sample>,
+    last_action: Option<LinkAction>,
+}
+
+impl NodeLink {
+    /// Packets received and CRC errors between the first and last sample
+    fn counter_deltas(&self) -> (u64, u64) {
+        let mut received = 0;
+        let mut errors = 0;
+        for (a, b) in self.samples.iter().zip(self.samples.iter().skip(1)) {
+            if b.rx >= a.rx && b.err >= a.err {
+                received += (b.rx - a.rx) as u64;
+                errors += (b.err - a.err) as u64;
+            } else {
+                // node2 rebooted: its counters started over
+                received += b.rx as u64;
+                errors += b.err as u64;
+            }
+        }
+        (received, errors)
+    }
+}
+
+struct State {
+    nodes: BTreeMap<&'static str, NodeLink>,
+}
+
+/// Per-node link statistics, fed by the router and read by the API
+#[derive(Clone)]
+pub struct LinkAnalytics {
+    config: LinkConfig,
+    /// SF assumed for packets that don't report one
+    default_sf: u8,
+    bandwidth_khz: f32,
+    state: Arc<Mutex<State>>,
+}
+
+impl LinkAnalytics {
+    pub fn new(config: &LinkConfig, derived: &DerivedConfig) -> Result<Self> {
+        let sf = derived.lora_spreading_factor;
+        if lora_sensitivity(sf, derived.lora_bandwidth_khz).is_none() {
+            bail!(
+                "Unsupported LoRa settings: SF{} at {} kHz",
+                sf,
+                derived.lora_bandwidth_khz
+            );
+        }
+        if config.window == 0 {
+            bail!("[link] window must be at least 1");
+        }
+        if lora_sensitivity(config.max_spreading_factor, derived.lora_bandwidth_khz).is_none() {
+            bail!("[link] max_spreading_factor must be 5..=12");
+        }
+        Ok(Self {
+            config: config.clone(),
+            default_sf: sf,
+            bandwidth_khz: derived.lora_bandwidth_khz,
+            state: Arc::new(Mutex::new(State {
+                nodes: BTreeMap::new(),
+            })),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add a record's link figures, logging when the recommendation changes
+    pub fn observe(&self, record: &ProcessedRecord) {
+        // RSSI/SNR are node2's measurements of node1's transmission
+        let node = "n1";
+        let sample = Sample {
+            at_ms: record.timestamp_ms(),
+            rssi: record.metric(node, "rssi"),
+            snr: record.metric(node, "snr"),
+            margin: record.metric(node, "link_margin"),
+            spreading_factor: record.packet.sig.sf.unwrap_or(self.default_sf),
+            tx_power_dbm: record.packet.sig.pwr,
+            rx: record.packet.sts.rx,
+            err: record.packet.sts.err,
+        };
+
+        let mut state = self.lock();
+        let link = state.nodes.entry(node).or_default();
+        if link.samples.len() == self.config.window {
+            link.samples.pop_front();
+        }
+        link.samples.push_back(sample);
+
+        let report = self.node_report(link);
+        let recommendation = &report.recommendation;
+        if link.last_action.replace(recommendation.action) != Some(recommendation.action) {
+            match recommendation.action {
+                LinkAction::IncreaseSf => warn!(
+                    node,
+                    spreading_factor = recommendation.spreading_factor,
+                    reason = %recommendation.reason,
+                    "Link too weak, higher spreading factor recommended"
+                ),
+                LinkAction::Keep => info!(node, reason = %recommendation.reason, "Link OK"),
+                LinkAction::InsufficientData => {}
+            }
+        }
+    }
+
+    /// Current statistics for every node
+    pub fn report(&self) -> LinkReport {
+        let state = self.lock();
+        LinkReport {
+            nodes: state
+                .nodes
+                .iter()
+                .map(|(node, link)| (node.to_string(), self.node_report(link)))
+                .collect(),
+        }
+    }
+
+    fn radio(&self, spreading_factor: u8, tx_power_dbm: Option<u8>) -> RadioSettings {
+        RadioSettings {
+            spreading_factor,
+            tx_power_dbm,
+            bandwidth_khz: self.bandwidth_khz,
+            sensitivity_dbm: lora_sensitivity(spreading_factor, self.bandwidth_khz),
+            snr_limit_db: snr_floor_tenths_db(spreading_factor) as f32 / 10.0,
+        }
+    }
+
+    fn node_report(&self, link: &NodeLink) -> NodeLinkReport {
+        let samples = &link.samples;
+        let radio = match samples.back() {
+            Some(latest) => self.radio(latest.spreading_factor, latest.tx_power_dbm),
+            None => self.radio(self.default_sf, None),
+        };
+        // A packet at another SF had the same signal against a different
+        // demodulation floor
+        let sf = radio.spreading_factor;
+        let margin_db = Distribution::of(samples.iter().filter_map(|s| {
+            let shift = snr_floor_tenths_db(s.spreading_factor) - snr_floor_tenths_db(sf);
+            Some(s.margin? + shift as f64 / 10.0)
+        }));
+        let (packets_received, crc_errors) = link.counter_deltas();
+        let heard = packets_received + crc_errors;
+        let packet_error_rate_pct = (heard > 0).then(|| crc_errors as f64 * 100.0 / heard as f64);
+        NodeLinkReport {
+            samples: samples.len(),
+            last_seen_ms: samples.back().map_or(0, |s| s.at_ms),
+            rssi_dbm: Distribution::of(samples.iter().filter_map(|s| s.rssi)),
+            snr_db: Distribution::of(samples.iter().filter_map(|s| s.snr)),
+            recommendation: self.recommend(
+                sf,
+                samples.len(),
+                margin_db.as_ref(),
+                packet_error_rate_pct,
+            ),
+            radio,
+            margin_db,
+            packets_received,
+            crc_errors,
+            packet_error_rate_pct,
+        }
+    }
+
+    fn recommend(
+        &self,
+        sf: u8,
+        samples: usize,
+        margin: Option<&Distribution>,
+        per_pct: Option<f64>,
+    ) -> Recommendation {
+        let recommendation = |action, spreading_factor, reason: String| Recommendation {
+            action,
+            spreading_factor,
+            reason,
+        };
+        let Some(margin) = margin.filter(|_| samples >= self.config.min_samples) else {
+            return recommendation(
+                LinkAction::InsufficientData,
+                sf,
+                format!("{} of {} reports", samples, self.config.min_samples),
+            );
+        };
+
+        let deficit = self.config.min_margin_db - margin.p10;
+        let lossy = per_pct.is_some_and(|per| per > self.config.max_per_pct);
+        if deficit <= 0.0 && !lossy {
+            return recommendation(
+                LinkAction::Keep,
+                sf,
+                format!("p10 margin {:.1} dB", margin.p10),
+            );
+        }
+
+        // Enough steps to cover the margin deficit, at least one for losses
+        let steps = ((deficit.max(0.0) / SF_STEP_DB).ceil() as u8).max(1);
+        let target = sf
+            .saturating_add(steps)
+            .min(self.config.max_spreading_factor);
+        let reason = match per_pct.filter(|_| lossy) {
+            Some(per) => format!("packet error rate {:.1} %", per),
+            None => format!(
+                "p10 margin {:.1} dB below {:.1} dB",
+                margin.p10, self.config.min_margin_db
+            ),
+        };
+        if target <= sf {
+            return recommendation(
+                LinkAction::Keep,
+                sf,
+                format!("{}, already at the highest spreading factor", reason),
+            );
+        }
+        recommendation(LinkAction::IncreaseSf, target, reason)
+    }
+}
+
+impl TelemetrySink for LinkAnalytics {
+    async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+        self.observe(record);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derived::DerivedMetrics;
+    use crate::telemetry::test_support::record;
+
+    fn analytics(min_samples: usize) -> LinkAnalytics {
+        let config = LinkConfig {
+            window: 10,
+            min_samples,
+            ..LinkConfig::default()
+        };
+        LinkAnalytics::new(&config, &DerivedConfig::default()).unwrap()
+    }
+
+    /// A report with the given RSSI/SNR and node2 counters
+    fn feed(link: &LinkAnalytics, at_ms: u64, rssi: i16, snr: i16, rx: u32, err: u32) {
+        let mut r = record(at_ms, 20.0);
+        r.packet.sig.rssi = rssi;
+        r.packet.sig.snr = snr;
+        r.packet.sts.rx = rx;
+        r.packet.sts.err = err;
+        r.derived = DerivedMetrics::compute(&r.packet, &DerivedConfig::default());
+        link.observe(&r);
+    }
+
+    #[test]
+    fn test_distributions_and_healthy_link() {
+        let link = analytics(5);
+        for i in 0..10 {
+            feed(&link, i * 10_000, -80 - i as i16, 8, 100 + i as u32, 0);
+        }
+        let report = link.report();
+        let n1 = &report.nodes["n1"];
+        assert_eq!(n1.radio.spreading_factor, 7);
+        assert_eq!(n1.samples, 10);
+        let rssi = n1.rssi_dbm.as_ref().unwrap();
+        assert_eq!(
+            (rssi.min, rssi.p10, rssi.median, rssi.max),
+            (-89.0, -89.0, -85.0, -80.0)
+        );
+        assert_eq!(rssi.mean, -84.5);
+        // SF7/500 kHz sensitivity is about -118.5 dBm
+        assert!((n1.margin_db.as_ref().unwrap().min - 29.5).abs() < 0.1);
+        assert_eq!((n1.packets_received, n1.crc_errors), (9, 0));
+        assert_eq!(n1.packet_error_rate_pct, Some(0.0));
+        assert_eq!(n1.recommendation.action, LinkAction::Keep);
+    }
+
+    #[test]
+    fn test_weak_link_recommends_higher_sf() {
+        let link = analytics(5);
+        // About 5 dB of margin: two SF steps short of the 10 dB target
+        for i in 0..6 {
+            feed(&link, i * 10_000, -110, -3, i as u32, 0);
+        }
+        let recommendation = link.report().nodes["n1"].recommendation.clone();
+        assert_eq!(recommendation.action, LinkAction::IncreaseSf);
+        assert_eq!(recommendation.spreading_factor, 9);
+
+        // Too few reports for a verdict
+        let early = analytics(30);
+        feed(&early, 0, -110, -3, 0, 0);
+        let action = early.report().nodes["n1"].recommendation.action;
+        assert_eq!(action, LinkAction::InsufficientData);
+    }
+
+    #[test]
+    fn test_recommendation_follows_adr_sf() {
+        let link = analytics(5);
+        // The weak link from above, but ADR already moved node1 to SF9: its
+        // margin there is 5 dB higher and enough
+        for i in 0..6 {
+            let mut r = record(i * 10_000, 20.0);
+            r.packet.sig.rssi = -110;
+            r.packet.sig.snr = -3;
+            r.packet.sig.sf = Some(if i < 3 { 7 } else { 9 });
+            r.packet.sig.pwr = Some(22);
+            r.packet.sts.rx = i as u32;
+            r.derived = DerivedMetrics::compute(&r.packet, &DerivedConfig::default());
+            link.observe(&r);
+        }
+        let n1 = &link.report().nodes["n1"];
+        assert_eq!(n1.radio.spreading_factor, 9);
+        assert_eq!(n1.radio.tx_power_dbm, Some(22));
+        assert_eq!(n1.radio.snr_limit_db, -12.5);
+        // SF7 packets count as if sent at SF9
+        let margin = n1.margin_db.as_ref().unwrap();
+        assert!((margin.min - margin.max).abs() < 0.1);
+        assert!((margin.p10 - 10.5).abs() < 0.1);
+        assert_eq!(n1.recommendation.action, LinkAction::Keep);
+        assert_eq!(n1.recommendation.spreading_factor, 9);
+    }
+
+    #[test]
+    fn test_packet_error_rate_across_counter_reset() {
+        let link = analytics(1);
+        feed(&link, 0, -70, 9, 100, 10);
+        feed(&link, 10_000, -70, 9, 118, 12);
+        // node2 rebooted, its counters start over
+        feed(&link, 20_000, -70, 9, 8, 2);
+        let n1 = &link.report().nodes["n1"];
+        assert_eq!((n1.packets_received, n1.crc_errors), (26, 4));
+        assert!((n1.packet_error_rate_pct.unwrap() - 13.33).abs() < 0.01);
+        // Plenty of margin, but too many losses
+        assert_eq!(n1.recommendation.action, LinkAction::IncreaseSf);
+        assert_eq!(n1.recommendation.spreading_factor, 8);
+    }
+}
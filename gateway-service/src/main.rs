@@ -5,68 +5,62 @@
 //! - Captures stdout and parses JSON telemetry
 //! - Demonstrates Tokio async patterns and structured logging
 //!
-//! Architecture: probe-rs → stdout → parser → channel → processor
+//! Architecture: probe-rs → stdout (or a replayed capture) → parser →
+//! channel → processor
+//! (wall-clock time, derived metrics, IAQ) → pipeline stages (filter,
+//! transform, enrich, log) → windowed aggregation → sink router, one queue
+//! per sink: SQLite, files, webhook, Modbus, OPC UA, CoAP, live stream, TUI,
+//! link analytics; SQLite → HTTP query API → dashboard
+
+mod aggregate;
+mod api;
+mod calibration;
+mod capture;
+mod clock;
+mod coap;
+mod config;
+mod dashboard;
+mod derived;
+mod downlink;
+mod export;
+mod iaq;
+mod link;
+mod modbus;
+mod opcua_server;
+mod pipeline;
+mod sink;
+mod storage;
+mod stream;
+mod telemetry;
+mod tui;
+mod units;
+mod webhook;
 
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::time::{Duration, Instant};
+use tokio::io::BufReader;
 use tokio::process::Command;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tracing::{error, info, warn};
-
-/// Telemetry packet from Node 2 gateway (matches Week 5 JSON format)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct TelemetryPacket {
-    /// Timestamp in milliseconds since boot
-    ts: u32,
-    /// Node ID (should be "N2" for gateway)
-    id: String,
-    /// Node 1 sensor data (remote sensor via LoRa)
-    n1: Node1Data,
-    /// Node 2 sensor data (gateway local sensor)
-    n2: Node2Data,
-    /// Signal quality metrics
-    sig: SignalQuality,
-    /// Statistics
-    sts: Statistics,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Node1Data {
-    /// Temperature in °C
-    t: f32,
-    /// Humidity in %
-    h: f32,
-    /// Gas resistance in ohms
-    g: u32,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Node2Data {
-    /// Temperature in °C (optional, BMP280 may not be reading yet)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    t: Option<f32>,
-    /// Pressure in hPa (optional)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    p: Option<f32>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct SignalQuality {
-    /// RSSI in dBm
-    rssi: i16,
-    /// SNR in dB
-    snr: i16,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Statistics {
-    /// Packets received
-    rx: u32,
-    /// CRC errors
-    err: u32,
-}
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+use aggregate::Aggregator;
+use calibration::Calibrator;
+use capture::{CaptureWriter, LineSource, LiveSource, RawLine, ReplaySource, ReplaySpeed};
+use clock::ClockCorrelator;
+use config::GatewayConfig;
+use derived::{DerivedConfig, DerivedMetrics};
+use iaq::IaqEstimator;
+use link::LinkAnalytics;
+use pipeline::Pipeline;
+use sink::SinkRouter;
+use stream::StreamHub;
+use telemetry::{ProcessedRecord, ReceivedPacket, TelemetryPacket};
+use tui::MonitorHandle;
+use webhook::WebhookNotifier;
 
 /// Extract JSON from probe-rs log line
 ///
@@ -98,24 +92,40 @@ fn extract_json_from_log_line(line: &str) -> Option<String> {
     }
 }
 
-/// Parse probe-rs stdout and send telemetry packets to channel
-async fn parse_probe_rs_output(
-    mut reader: BufReader<tokio::process::ChildStdout>,
-    tx: mpsc::Sender<TelemetryPacket>,
+/// Parse probe-rs output lines and send telemetry packets to channel
+///
+/// Lines come from live probe-rs stdout or a replayed capture; when
+/// `capture` is set every line is also recorded. In TUI mode every line
+/// goes to the monitor's firmware log instead of being passed through to
+/// stdout.
+async fn parse_probe_rs_output<S: LineSource>(
+    mut source: S,
+    tx: mpsc::Sender<ReceivedPacket>,
+    monitor: Option<MonitorHandle>,
+    mut capture: Option<CaptureWriter>,
 ) -> Result<()> {
-    let mut line_buf = String::new();
-
     info!("Starting probe-rs output parser");
 
     loop {
-        line_buf.clear();
+        match source.next_line().await {
+            Ok(None) => break,
+            Ok(Some(raw)) => {
+                if let Some(writer) = &mut capture {
+                    if let Err(e) = writer.record(&raw).await {
+                        warn!(error = %e, "Capture write failed, recording stopped");
+                        capture = None;
+                    }
+                }
+
+                let RawLine {
+                    t_ms: received_at_ms,
+                    line: line_buf,
+                } = raw;
+
+                if let Some(monitor) = &monitor {
+                    monitor.firmware_log(&line_buf);
+                }
 
-        match reader.read_line(&mut line_buf).await {
-            Ok(0) => {
-                warn!("probe-rs process ended (EOF on stdout)");
-                break;
-            }
-            Ok(_) => {
                 // Try to extract JSON from this line
                 if let Some(json_str) = extract_json_from_log_line(&line_buf) {
                     match serde_json::from_str::<TelemetryPacket>(&json_str) {
@@ -129,7 +139,11 @@ async fn parse_probe_rs_output(
                                 "Telemetry packet received"
                             );
 
-                            if let Err(e) = tx.send(packet).await {
+                            let received = ReceivedPacket {
+                                packet,
+                                received_at_ms,
+                            };
+                            if let Err(e) = tx.send(received).await {
                                 error!(error = %e, "Failed to send packet to channel");
                                 break;
                             }
@@ -138,7 +152,7 @@ async fn parse_probe_rs_output(
                             warn!(error = %e, json = %json_str, "Failed to parse JSON");
                         }
                     }
-                } else {
+                } else if monitor.is_none() {
                     // Not a JSON line, just pass through for debugging
                     // (Could filter these to only show important logs)
                     if line_buf.contains("[INFO]") || line_buf.contains("[WARN]") || line_buf.contains("[ERROR]") {
@@ -147,53 +161,170 @@ async fn parse_probe_rs_output(
                 }
             }
             Err(e) => {
-                error!(error = %e, "Error reading from probe-rs stdout");
+                error!(error = %e, "Error reading probe-rs output");
                 break;
             }
         }
     }
 
+    if let Some(writer) = capture {
+        writer.finish().await?;
+    }
+
     Ok(())
 }
 
+/// Run the parser on its own task
+fn spawn_parser<S: LineSource + Send + 'static>(
+    source: S,
+    tx: mpsc::Sender<ReceivedPacket>,
+    monitor: Option<MonitorHandle>,
+    capture: Option<CaptureWriter>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        if let Err(e) = parse_probe_rs_output(source, tx, monitor, capture).await {
+            error!(error = %e, "Parser task failed");
+        }
+    })
+}
+
+const USAGE: &str = "usage: wk6-async-gateway [tui | replay <capture-file> [speed|max]]";
+
+/// What to run, from the command line
+#[derive(Debug, PartialEq)]
+enum Mode {
+    /// Run probe-rs and log to the terminal
+    Service,
+    /// Run probe-rs with the full-screen monitor
+    Tui,
+    /// Feed a recorded capture through the pipeline instead of probe-rs
+    Replay { path: PathBuf, speed: ReplaySpeed },
+}
+
+impl Mode {
+    fn from_args(args: &[String]) -> Result<Self> {
+        match args {
+            [] => Ok(Self::Service),
+            [mode] if mode == "tui" => Ok(Self::Tui),
+            [mode, path, rest @ ..] if mode == "replay" && rest.len() <= 1 => Ok(Self::Replay {
+                path: PathBuf::from(path),
+                speed: match rest.first() {
+                    Some(speed) => speed.parse()?,
+                    None => ReplaySpeed::Factor(1.0),
+                },
+            }),
+            _ => anyhow::bail!("Invalid arguments ({})", USAGE),
+        }
+    }
+}
+
 /// Process telemetry packets (placeholder for Week 7 MQTT publishing)
-async fn process_telemetry(mut rx: mpsc::Receiver<TelemetryPacket>) {
+#[allow(clippy::too_many_arguments)]
+async fn process_telemetry(
+    mut rx: mpsc::Receiver<ReceivedPacket>,
+    mut clock: ClockCorrelator,
+    calibrator: Calibrator,
+    derived_config: DerivedConfig,
+    mut iaq: IaqEstimator,
+    mut pipeline: Pipeline,
+    mut aggregator: Aggregator,
+    router: SinkRouter,
+) {
     info!("Starting telemetry processor");
 
-    while let Some(packet) = rx.recv().await {
-        // Log Node 1 (remote sensor) data
-        info!(
-            timestamp_ms = packet.ts,
-            node_id = %packet.id,
-            n1_temperature = packet.n1.t,
-            n1_humidity = packet.n1.h,
-            n1_gas_resistance = packet.n1.g,
-            rssi = packet.sig.rssi,
-            snr = packet.sig.snr,
-            packets_received = packet.sts.rx,
-            crc_errors = packet.sts.err,
-            "Processing telemetry packet"
-        );
+    // Closes aggregation windows while no packets arrive
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
 
-        // Log Node 2 (gateway local sensor) data if available
-        if packet.n2.t.is_some() || packet.n2.p.is_some() {
-            info!(
-                n2_temperature = ?packet.n2.t,
-                n2_pressure = ?packet.n2.p,
-                "Gateway local sensor (BMP280)"
-            );
+    loop {
+        let ReceivedPacket {
+            mut packet,
+            received_at_ms,
+        } = tokio::select! {
+            received = rx.recv() => match received {
+                Some(received) => received,
+                None => break,
+            },
+            _ = ticker.tick(), if !aggregator.is_empty() => {
+                for summary in aggregator.tick(Instant::now()) {
+                    router.publish_summary(summary).await;
+                }
+                continue;
+            }
+        };
+
+        // Correct the sensor readings before anything is computed from them
+        let calibration = calibrator.apply(&mut packet);
+
+        let record = ProcessedRecord {
+            time: clock.observe(packet.ts, received_at_ms),
+            derived: DerivedMetrics::compute(&packet, &derived_config),
+            iaq: iaq.update("N1", packet.n1.g, packet.n1.h),
+            packet,
+            calibration,
+            filtered: Default::default(),
+            anomalies: Default::default(),
+            tags: Default::default(),
+            units: Default::default(),
+        };
+
+        // Configured filter/transform/enrich stages (may drop the record)
+        let Some(record) = pipeline.run(record) else {
+            continue;
+        };
+
+        // Fan out to every sink through its own queue (only a full `block`
+        // sink can make this wait), then any windows the record closed
+        let summaries = aggregator.observe(&record, Instant::now());
+        router.publish(record).await;
+        for summary in summaries {
+            router.publish_summary(summary).await;
         }
 
         // TODO Week 7: Publish to MQTT
         // TODO Week 7: Write to InfluxDB
     }
 
+    // Keep the learned gas baseline for the next run
+    iaq.save();
+
+    // Summarize the windows still open
+    for summary in aggregator.finish() {
+        router.publish_summary(summary).await;
+    }
+
+    // Let sinks drain what's still queued
+    router.shutdown().await;
+
     info!("Telemetry processor stopped");
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // `tui` runs the full-screen monitor instead of logging to the terminal,
+    // `replay` feeds a capture file through the pipeline instead of probe-rs
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mode = Mode::from_args(&args)?;
+    let tui_mode = mode == Mode::Tui;
+    let replay = matches!(mode, Mode::Replay { .. });
+
+    // Load optional configuration file
+    let config_path = GatewayConfig::default_path();
+    let config = GatewayConfig::load(&config_path)?;
+
     // Initialize tracing subscriber for structured logging
+    // (to a file in TUI mode, the terminal belongs to the monitor)
+    let log_writer = if tui_mode {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.tui.log_file)
+            .with_context(|| {
+                format!("Failed to open log file {}", config.tui.log_file.display())
+            })?;
+        BoxMakeWriter::new(std::sync::Mutex::new(file))
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
@@ -201,74 +332,230 @@ async fn main() -> Result<()> {
         )
         .with_target(false)
         .with_thread_ids(true)
+        .with_ansi(!tui_mode)
+        .with_writer(log_writer)
         .init();
 
     info!("Week 6 Async Gateway Service starting");
+    info!(path = %config_path.display(), "Configuration loaded");
+
+    // Start VCP downlink (time sync beacons) if configured
+    // (not when replaying, there's no live node to talk to)
+    let downlink = match &config.downlink {
+        Some(downlink_config) if !replay => Some(downlink::spawn(downlink_config)?.0),
+        _ => None,
+    };
+
+    // Terminal monitor (TUI mode only)
+    let (monitor, monitor_handle) = if tui_mode {
+        let (monitor, handle) = tui::channel(&config.tui, downlink);
+        (Some(monitor), Some(handle))
+    } else {
+        (None, None)
+    };
+
+    // Per-sensor calibration, shared with the API
+    let calibrator =
+        Calibrator::new(&config.calibration).context("Invalid [calibration] configuration")?;
+
+    // Processing stages between the processor and the sinks
+    let pipeline = Pipeline::from_config(config.pipeline.as_deref(), &config.derived)
+        .context("Invalid [[pipeline]] configuration")?;
+    info!(stages = ?pipeline.names(), "Processing pipeline");
+
+    // Periodic summaries of the processed records
+    let aggregator =
+        Aggregator::new(&config.aggregate).context("Invalid [[aggregate]] configuration")?;
+
+    // Every output below is a sink with its own queue (see `sink`)
+    let mut router = SinkRouter::new(&config.router);
+
+    // Per-node radio link statistics, shared with the API
+    let link = LinkAnalytics::new(&config.link, &config.derived)
+        .context("Invalid [link] configuration")?;
+    router.add("link", link.clone())?;
+    if let Some(handle) = monitor_handle.clone() {
+        router.add("monitor", handle)?;
+    }
+
+    // Start webhook notifier if configured
+    let webhook_task = match &config.webhook {
+        Some(webhook_config) => {
+            let (handle, task) = WebhookNotifier::new(webhook_config)
+                .context("Invalid webhook configuration")?
+                .spawn();
+            router.add("webhook", handle)?;
+            Some(task)
+        }
+        None => None,
+    };
+
+    // Open telemetry database if configured
+    let storage_task = match &config.storage {
+        Some(storage_config) => {
+            let (handle, task) = storage::spawn_writer(storage_config)?;
+            router.add("storage", handle)?;
+            Some(task)
+        }
+        None => None,
+    };
+
+    // Start file export sinks (named export-1, export-2, ... in config order)
+    let mut export_tasks = Vec::new();
+    for (i, export_config) in config.export.iter().enumerate() {
+        let (handle, task) = export::spawn(export_config)?;
+        router.add(&format!("export-{}", i + 1), handle)?;
+        export_tasks.push(task);
+    }
+
+    // Serve the query and streaming API if configured
+    if let Some(api_config) = &config.api {
+        let hub = StreamHub::new(&config.stream);
+        api::spawn(
+            api_config,
+            config.storage.as_ref(),
+            hub.clone(),
+            router.registry(),
+            calibrator.clone(),
+            link,
+        )
+        .await?;
+        router.add("stream", hub)?;
+    }
+
+    // Serve the latest values to PLCs over Modbus TCP if configured
+    if let Some(modbus_config) = &config.modbus {
+        router.add("modbus", modbus::spawn(modbus_config).await?)?;
+    }
 
-    // Configuration for probe-rs (from your alias)
-    let probe_id = "0483:374b:066DFF3833584B3043115433"; // Node 2
-    let chip = "STM32F446RETx";
-    let firmware_path = "target/thumbv7em-none-eabihf/release/node2-firmware";
-
-    info!(
-        probe = probe_id,
-        chip = chip,
-        firmware = firmware_path,
-        "Spawning probe-rs subprocess"
-    );
-
-    // Spawn probe-rs as subprocess
-    let mut child = Command::new("probe-rs")
-        .args(&[
-            "run",
-            "--probe",
-            probe_id,
-            "--chip",
-            chip,
-            firmware_path,
-        ])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit()) // Pass through stderr for errors
-        .spawn()
-        .context("Failed to spawn probe-rs process")?;
-
-    let stdout = child
-        .stdout
-        .take()
-        .context("Failed to capture probe-rs stdout")?;
+    // Host the OPC UA information model if configured
+    if let Some(opcua_config) = &config.opcua {
+        router.add("opcua", opcua_server::spawn(opcua_config).await?)?;
+    }
+
+    // Serve CoAP resources (with Observe) if configured
+    if let Some(coap_config) = &config.coap {
+        router.add("coap", coap::spawn(coap_config).await?)?;
+    }
+    router.check_config();
 
     // Create channel for telemetry packets
-    let (tx, rx) = mpsc::channel::<TelemetryPacket>(100);
+    let (tx, rx) = mpsc::channel::<ReceivedPacket>(100);
 
-    // Spawn parser task
-    let parser_handle = tokio::spawn(async move {
-        let reader = BufReader::new(stdout);
-        if let Err(e) = parse_probe_rs_output(reader, tx).await {
-            error!(error = %e, "Parser task failed");
-        }
-    });
+    // Spawn parser task on a replayed capture or live probe-rs output
+    let parser_monitor = monitor_handle.clone();
+    let (child, mut parser_handle) = if let Mode::Replay { path, speed } = &mode {
+        let source = ReplaySource::open(path, *speed).await?;
+        (None, spawn_parser(source, tx, parser_monitor, None))
+    } else {
+        // Configuration for probe-rs (from your alias)
+        let probe_id = "0483:374b:066DFF3833584B3043115433"; // Node 2
+        let chip = "STM32F446RETx";
+        let firmware_path = "target/thumbv7em-none-eabihf/release/node2-firmware";
 
-    // Spawn processor task
-    let processor_handle = tokio::spawn(process_telemetry(rx));
+        info!(
+            probe = probe_id,
+            chip = chip,
+            firmware = firmware_path,
+            "Spawning probe-rs subprocess"
+        );
 
-    // Wait for Ctrl+C
-    info!("Service running. Press Ctrl+C to stop.");
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            info!("Received Ctrl+C, shutting down gracefully");
+        // Spawn probe-rs as subprocess
+        let mut child = Command::new("probe-rs")
+            .args([
+                "run",
+                "--probe",
+                probe_id,
+                "--chip",
+                chip,
+                firmware_path,
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit()) // Pass through stderr for errors
+            .spawn()
+            .context("Failed to spawn probe-rs process")?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .context("Failed to capture probe-rs stdout")?;
+
+        // Record the raw input if configured
+        let capture = match &config.capture {
+            Some(capture_config) => Some(CaptureWriter::create(capture_config).await?),
+            None => None,
+        };
+
+        let source = LiveSource::new(BufReader::new(stdout));
+        (Some(child), spawn_parser(source, tx, parser_monitor, capture))
+    };
+
+    // Spawn processor task
+    let processor_handle = tokio::spawn(process_telemetry(
+        rx,
+        ClockCorrelator::new(config.clock.clone()),
+        calibrator,
+        config.derived.clone(),
+        IaqEstimator::new(config.iaq.clone()),
+        pipeline,
+        aggregator,
+        router,
+    ));
+
+    if let Some(monitor) = monitor {
+        // The monitor owns the terminal until the user quits
+        info!("TUI monitor running");
+        if let Err(e) = monitor.run().await {
+            error!(error = %e, "TUI monitor failed");
         }
-        _ = parser_handle => {
-            warn!("Parser task ended unexpectedly");
+        info!("TUI monitor closed, shutting down");
+    } else {
+        // Wait for Ctrl+C
+        info!("Service running. Press Ctrl+C to stop.");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                info!("Received Ctrl+C, shutting down gracefully");
+            }
+            _ = &mut parser_handle => {
+                if replay {
+                    info!("Replay complete, shutting down");
+                } else {
+                    warn!("Parser task ended unexpectedly");
+                }
+            }
         }
     }
 
+    // Stop replaying: the processor only finishes once the parser drops its
+    // sender, which would otherwise wait for the rest of the capture
+    if replay {
+        parser_handle.abort();
+    }
+
     // Kill probe-rs subprocess
-    info!("Killing probe-rs subprocess");
-    child.kill().await.ok();
+    if let Some(mut child) = child {
+        info!("Killing probe-rs subprocess");
+        child.kill().await.ok();
+    }
 
     // Wait for processor to finish
     processor_handle.await.ok();
 
+    // Deliver notifications still queued for the webhook
+    if let Some(task) = webhook_task {
+        task.await.ok();
+    }
+
+    // Flush records still queued for the database
+    if let Some(task) = storage_task {
+        task.await.ok();
+    }
+
+    // Close export files (renamed from .part, compressed if configured)
+    for task in export_tasks {
+        task.await.ok();
+    }
+
     info!("Week 6 Async Gateway Service stopped");
     Ok(())
 }
@@ -290,4 +577,56 @@ mod tests {
         let result = extract_json_from_log_line(line);
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn test_mode_from_args() {
+        let args = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(Mode::from_args(&args("")).unwrap(), Mode::Service);
+        assert_eq!(Mode::from_args(&args("tui")).unwrap(), Mode::Tui);
+        assert_eq!(
+            Mode::from_args(&args("replay cap.ndjson max")).unwrap(),
+            Mode::Replay {
+                path: PathBuf::from("cap.ndjson"),
+                speed: ReplaySpeed::Max,
+            }
+        );
+        assert!(Mode::from_args(&args("replay")).is_err());
+        assert!(Mode::from_args(&args("tui extra")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_feeds_parser_with_recorded_times() {
+        let dir = std::env::temp_dir().join(format!("replay-parser-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.ndjson");
+
+        // Record a live session, then replay it through the parser
+        let input = concat!(
+            "[INFO] Gateway ready\n",
+            "[INFO] JSON sent via VCP: {\"ts\":1000,\"id\":\"N2\",",
+            "\"n1\":{\"t\":21.5,\"h\":40.0,\"p\":1000.0,\"g\":50000},",
+            "\"n2\":{\"t\":22.0,\"p\":1001.0,\"a\":100.0},",
+            "\"sig\":{\"rssi\":-60,\"snr\":8},",
+            "\"sts\":{\"rx\":1,\"err\":0}}\\n (src/main.rs:573)\n",
+        );
+        let capture = CaptureWriter::create_at(&path, 0, std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        let (tx, mut rx) = mpsc::channel(10);
+        let live = LiveSource::new(BufReader::new(input.as_bytes()));
+        parse_probe_rs_output(live, tx, None, Some(capture)).await.unwrap();
+        let recorded = rx.recv().await.unwrap();
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let source = ReplaySource::open(&path, ReplaySpeed::Max).await.unwrap();
+        parse_probe_rs_output(source, tx, None, None).await.unwrap();
+        let replayed = rx.recv().await.unwrap();
+        assert!(rx.recv().await.is_none());
+
+        assert_eq!(replayed.received_at_ms, recorded.received_at_ms);
+        assert_eq!(replayed.packet.ts, 1000);
+        assert_eq!(replayed.packet.n1.t, 21.5);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
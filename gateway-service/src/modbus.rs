@@ -0,0 +1,465 @@
+//! Modbus TCP server exposing the latest telemetry as registers
+//!
+//! Every mapped value is readable both as input registers (FC 04) and as
+//! holding registers (FC 03, read-only) at the same addresses, so PLCs that
+//! only poll one table work unchanged. Values are scaled integers; 32-bit
+//! types occupy two registers, high word first. Unmapped addresses below
+//! the highest mapped one read as 0.
+//!
+//! Default register map (replace it with `[[modbus.registers]]` entries):
+//!
+//! | Address | Source                  | Type | Scale | Meaning                   |
+//! |---------|-------------------------|------|-------|---------------------------|
+//! | 0       | `status.stale`          | u16  | 1     | 1 = no fresh data         |
+//! | 1       | `status.age_s`          | u16  | 1     | Seconds since last update |
+//! | 2       | `n1.temperature`        | i16  | 100   | °C × 100                  |
+//! | 3       | `n1.humidity`           | u16  | 100   | % × 100                   |
+//! | 4-5     | `n1.gas_resistance`     | u32  | 1     | Ω                         |
+//! | 6       | `n2.temperature`        | i16  | 100   | °C × 100                  |
+//! | 7       | `n2.pressure`           | u16  | 10    | hPa × 10                  |
+//! | 8       | `n1.rssi`               | i16  | 1     | dBm                       |
+//! | 9       | `n1.snr`                | i16  | 1     | dB                        |
+//! | 10-11   | `link.packets_received` | u32  | 1     | node2 packet counter      |
+//! | 12-13   | `link.crc_errors`       | u32  | 1     | node2 CRC error counter   |
+//!
+//! A value that isn't available (never received, or a sensor not reporting)
+//! reads as the type's "not available" marker: 0x8000 for signed and 0xFFFF
+//! for unsigned types (0x8000_0000 / 0xFFFF_FFFF for 32-bit). Real values are
+//! clamped one step short of those markers.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::future::{ready, Ready};
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio_modbus::server::tcp::Server;
+use tokio_modbus::server::Service;
+use tokio_modbus::{ExceptionCode, Request, Response, SlaveRequest};
+use tracing::{error, info, warn};
+
+use crate::sink::TelemetrySink;
+use crate::telemetry::{parse_metric, ProcessedRecord};
+
+/// Most registers one read may return (Modbus spec limit)
+const MAX_READ_REGISTERS: u16 = 125;
+
+/// Modbus TCP server (`[modbus]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModbusConfig {
+    /// Listen address (502 is the standard port but needs privileges)
+    pub bind: SocketAddr,
+    /// Only answer requests for this unit id (all when absent)
+    pub unit_id: Option<u8>,
+    /// `status.stale` is set when the last record is older than this
+    pub stale_after_secs: u64,
+    /// Register map
+    pub registers: Vec<RegisterConfig>,
+}
+
+impl Default for ModbusConfig {
+    fn default() -> Self {
+        let register = |address, source: &str, kind, scale| RegisterConfig {
+            address,
+            source: source.to_string(),
+            kind,
+            scale,
+        };
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 5020)),
+            unit_id: None,
+            stale_after_secs: 30,
+            registers: vec![
+                register(0, "status.stale", RegisterType::U16, 1.0),
+                register(1, "status.age_s", RegisterType::U16, 1.0),
+                register(2, "n1.temperature", RegisterType::I16, 100.0),
+                register(3, "n1.humidity", RegisterType::U16, 100.0),
+                register(4, "n1.gas_resistance", RegisterType::U32, 1.0),
+                register(6, "n2.temperature", RegisterType::I16, 100.0),
+                register(7, "n2.pressure", RegisterType::U16, 10.0),
+                register(8, "n1.rssi", RegisterType::I16, 1.0),
+                register(9, "n1.snr", RegisterType::I16, 1.0),
+                register(10, "link.packets_received", RegisterType::U32, 1.0),
+                register(12, "link.crc_errors", RegisterType::U32, 1.0),
+            ],
+        }
+    }
+}
+
+/// One mapped value (`[[modbus.registers]]` entry)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterConfig {
+    /// First register address
+    pub address: u16,
+    /// `<node>.<metric>`, `link.packets_received`, `link.crc_errors`,
+    /// `status.stale` or `status.age_s`
+    pub source: String,
+    /// Integer encoding
+    #[serde(rename = "type", default)]
+    pub kind: RegisterType,
+    /// Multiplier applied before rounding
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// Integer encoding of a mapped value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterType {
+    #[default]
+    I16,
+    U16,
+    I32,
+    U32,
+}
+
+impl RegisterType {
+    /// Registers occupied
+    fn width(self) -> u16 {
+        match self {
+            Self::I16 | Self::U16 => 1,
+            Self::I32 | Self::U32 => 2,
+        }
+    }
+
+    /// Encode `value` (already scaled), or the "not available" marker
+    fn encode(self, value: Option<f64>) -> [u16; 2] {
+        let word = |v: u32| [(v >> 16) as u16, v as u16];
+        let (min, max, missing) = match self {
+            Self::I16 => (-(i16::MAX as f64), i16::MAX as f64, 0x8000),
+            Self::U16 => (0.0, (u16::MAX - 1) as f64, 0xFFFF),
+            Self::I32 => (-(i32::MAX as f64), i32::MAX as f64, 0x8000_0000),
+            Self::U32 => (0.0, (u32::MAX - 1) as f64, 0xFFFF_FFFF),
+        };
+        let raw = match value.filter(|v| v.is_finite()) {
+            Some(v) => {
+                let v = v.round().clamp(min, max) as i64;
+                v as u32
+            }
+            None => missing,
+        };
+        match self {
+            Self::I16 | Self::U16 => [raw as u16, 0],
+            Self::I32 | Self::U32 => word(raw),
+        }
+    }
+}
+
+/// Where a register's value comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    Metric {
+        node: &'static str,
+        name: &'static str,
+    },
+    PacketsReceived,
+    CrcErrors,
+    Stale,
+    AgeSecs,
+}
+
+impl Source {
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "link.packets_received" => return Ok(Self::PacketsReceived),
+            "link.crc_errors" => return Ok(Self::CrcErrors),
+            "status.stale" => return Ok(Self::Stale),
+            "status.age_s" => return Ok(Self::AgeSecs),
+            _ => {}
+        }
+        parse_metric(s)
+            .map(|(node, name)| Self::Metric { node, name })
+            .with_context(|| format!("Unknown register source '{}'", s))
+    }
+
+    /// Value from a record (status sources are computed at read time)
+    fn value(self, record: &ProcessedRecord) -> Option<f64> {
+        match self {
+            Self::Metric { node, name } => record.metric(node, name),
+            Self::PacketsReceived => Some(record.packet.sts.rx as f64),
+            Self::CrcErrors => Some(record.packet.sts.err as f64),
+            Self::Stale | Self::AgeSecs => None,
+        }
+    }
+}
+
+/// A validated register map entry
+#[derive(Debug, Clone)]
+struct Mapping {
+    address: u16,
+    source: Source,
+    kind: RegisterType,
+    scale: f64,
+}
+
+/// Validate the configured register map
+fn build_map(registers: &[RegisterConfig]) -> Result<Vec<Mapping>> {
+    let mut map: Vec<Mapping> = Vec::with_capacity(registers.len());
+    for register in registers {
+        let source = Source::parse(&register.source)?;
+        let start = u32::from(register.address);
+        let end = start + u32::from(register.kind.width());
+        if end > u32::from(u16::MAX) + 1 {
+            bail!("Register '{}' runs past address 65535", register.source);
+        }
+        if let Some(other) = map.iter().find(|m| {
+            let other_start = u32::from(m.address);
+            other_start < end && start < other_start + u32::from(m.kind.width())
+        }) {
+            bail!(
+                "Registers at {} and {} overlap",
+                other.address,
+                register.address
+            );
+        }
+        map.push(Mapping {
+            address: register.address,
+            source,
+            kind: register.kind,
+            scale: register.scale,
+        });
+    }
+    Ok(map)
+}
+
+/// Last record's values, one per mapping
+struct Snapshot {
+    values: Vec<Option<f64>>,
+    updated: Option<Instant>,
+}
+
+/// Register image shared by the handle and the server
+struct RegisterBank {
+    map: Vec<Mapping>,
+    stale_after: Duration,
+    snapshot: RwLock<Snapshot>,
+}
+
+impl RegisterBank {
+    fn new(map: Vec<Mapping>, stale_after: Duration) -> Self {
+        let snapshot = Snapshot {
+            values: vec![None; map.len()],
+            updated: None,
+        };
+        Self {
+            map,
+            stale_after,
+            snapshot: RwLock::new(snapshot),
+        }
+    }
+
+    fn update(&self, record: &ProcessedRecord) {
+        let values = self.map.iter().map(|m| m.source.value(record)).collect();
+        let mut snapshot = self.snapshot.write().unwrap_or_else(|e| e.into_inner());
+        snapshot.values = values;
+        snapshot.updated = Some(Instant::now());
+    }
+
+    /// Current register image, from address 0 to the highest mapped register
+    fn image(&self) -> Vec<u16> {
+        let snapshot = self.snapshot.read().unwrap_or_else(|e| e.into_inner());
+        let age = snapshot.updated.map(|at| at.elapsed());
+        let stale = age.is_none_or(|age| age > self.stale_after);
+
+        let len = self
+            .map
+            .iter()
+            .map(|m| usize::from(m.address) + usize::from(m.kind.width()))
+            .max()
+            .unwrap_or(0);
+        let mut image = vec![0; len];
+        for (mapping, value) in self.map.iter().zip(&snapshot.values) {
+            let value = match mapping.source {
+                Source::Stale => Some(f64::from(u8::from(stale))),
+                Source::AgeSecs => age.map(|age| age.as_secs() as f64),
+                _ => *value,
+            };
+            let words = mapping.kind.encode(value.map(|v| v * mapping.scale));
+            let start = usize::from(mapping.address);
+            let width = usize::from(mapping.kind.width());
+            image[start..start + width].copy_from_slice(&words[..width]);
+        }
+        image
+    }
+
+    /// Read `count` registers from `address`
+    fn read(&self, address: u16, count: u16) -> Result<Vec<u16>, ExceptionCode> {
+        if count == 0 || count > MAX_READ_REGISTERS {
+            return Err(ExceptionCode::IllegalDataValue);
+        }
+        let image = self.image();
+        let start = usize::from(address);
+        image
+            .get(start..start + usize::from(count))
+            .map(<[u16]>::to_vec)
+            .ok_or(ExceptionCode::IllegalDataAddress)
+    }
+}
+
+/// Handle for publishing records to the Modbus register bank
+#[derive(Clone)]
+pub struct ModbusHandle {
+    bank: Arc<RegisterBank>,
+}
+
+impl TelemetrySink for ModbusHandle {
+    /// Latch `record` as the latest values
+    async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+        self.bank.update(record);
+        Ok(())
+    }
+}
+
+/// Per-connection request handler
+struct ModbusService {
+    bank: Arc<RegisterBank>,
+    unit_id: Option<u8>,
+}
+
+impl Service for ModbusService {
+    type Request = SlaveRequest<'static>;
+    type Response = Option<Response>;
+    type Exception = ExceptionCode;
+    type Future = Ready<Result<Self::Response, Self::Exception>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        // Requests for other units on a shared gateway go unanswered
+        if self.unit_id.is_some_and(|unit| unit != req.slave) {
+            return ready(Ok(None));
+        }
+        let response = match req.request {
+            Request::ReadInputRegisters(address, count) => self
+                .bank
+                .read(address, count)
+                .map(Response::ReadInputRegisters),
+            Request::ReadHoldingRegisters(address, count) => self
+                .bank
+                .read(address, count)
+                .map(Response::ReadHoldingRegisters),
+            _ => Err(ExceptionCode::IllegalFunction),
+        };
+        ready(response.map(Some))
+    }
+}
+
+/// Serve `bank` on `listener` until the process exits
+async fn serve(listener: TcpListener, bank: Arc<RegisterBank>, unit_id: Option<u8>) {
+    let server = Server::new(listener);
+    let on_connected = |stream, addr| {
+        let service = ModbusService {
+            bank: bank.clone(),
+            unit_id,
+        };
+        async move {
+            info!(client = %addr, "Modbus client connected");
+            std::io::Result::Ok(Some((service, stream)))
+        }
+    };
+    let on_error = |e| warn!(error = %e, "Modbus connection failed");
+    if let Err(e) = server.serve(&on_connected, on_error).await {
+        error!(error = %e, "Modbus server stopped");
+    }
+}
+
+/// Start the Modbus TCP server
+pub async fn spawn(config: &ModbusConfig) -> Result<ModbusHandle> {
+    let map = build_map(&config.registers).context("Invalid Modbus register map")?;
+    let listener = TcpListener::bind(config.bind)
+        .await
+        .with_context(|| format!("Failed to bind Modbus server to {}", config.bind))?;
+    info!(addr = %config.bind, registers = map.len(), "Modbus TCP server listening");
+
+    let bank = Arc::new(RegisterBank::new(
+        map,
+        Duration::from_secs(config.stale_after_secs),
+    ));
+    tokio::spawn(serve(listener, bank.clone(), config.unit_id));
+    Ok(ModbusHandle { bank })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+    use tokio_modbus::client::{tcp, Reader};
+
+    fn bank() -> Arc<RegisterBank> {
+        let map = build_map(&ModbusConfig::default().registers).unwrap();
+        Arc::new(RegisterBank::new(map, Duration::from_secs(30)))
+    }
+
+    #[test]
+    fn test_encodes_scaled_values_and_markers() {
+        assert_eq!(
+            RegisterType::I16.encode(Some(-21.5 * 100.0))[0],
+            (-2150i16) as u16
+        );
+        assert_eq!(RegisterType::I16.encode(None)[0], 0x8000);
+        assert_eq!(RegisterType::I16.encode(Some(-1e9))[0], (-32767i16) as u16);
+        assert_eq!(RegisterType::U16.encode(Some(70_000.0))[0], 0xFFFE);
+        assert_eq!(RegisterType::U32.encode(Some(123_456.0)), [0x0001, 0xE240]);
+        assert_eq!(RegisterType::I32.encode(Some(-2.0)), [0xFFFF, 0xFFFE]);
+        assert_eq!(RegisterType::U32.encode(None), [0xFFFF, 0xFFFF]);
+    }
+
+    #[test]
+    fn test_rejects_bad_maps() {
+        let register = |address, source: &str, kind| RegisterConfig {
+            address,
+            source: source.to_string(),
+            kind,
+            scale: 1.0,
+        };
+        assert!(build_map(&[register(0, "n1.nope", RegisterType::I16)]).is_err());
+        assert!(build_map(&[
+            register(4, "n1.gas_resistance", RegisterType::U32),
+            register(5, "n1.rssi", RegisterType::I16),
+        ])
+        .is_err());
+        assert!(build_map(&[register(65535, "n1.rssi", RegisterType::U32)]).is_err());
+        assert!(build_map(&[register(65535, "n1.rssi", RegisterType::I16)]).is_ok());
+    }
+
+    #[test]
+    fn test_stale_until_first_record() {
+        let bank = bank();
+        let image = bank.image();
+        assert_eq!(image.len(), 14);
+        assert_eq!(image[0], 1, "stale before any data");
+        assert_eq!(image[1], 0xFFFF, "no age yet");
+        assert_eq!(image[2], 0x8000, "no temperature yet");
+
+        bank.update(&record(0, 21.5));
+        let image = bank.image();
+        assert_eq!(image[0], 0);
+        assert_eq!(image[1], 0);
+        assert_eq!(image[2], 2150);
+        assert_eq!(bank.read(12, 3), Err(ExceptionCode::IllegalDataAddress));
+        assert_eq!(bank.read(0, 0), Err(ExceptionCode::IllegalDataValue));
+    }
+
+    #[tokio::test]
+    async fn test_serves_input_and_holding_registers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let bank = bank();
+        bank.update(&record(0, -3.25));
+        tokio::spawn(serve(listener, bank, None));
+
+        let mut client = tcp::connect(addr).await.unwrap();
+        let input = client.read_input_registers(0, 14).await.unwrap().unwrap();
+        let holding = client.read_holding_registers(0, 14).await.unwrap().unwrap();
+        assert_eq!(input, holding);
+        assert_eq!(input[2], (-325i16) as u16);
+
+        let err = client.read_input_registers(10, 10).await.unwrap();
+        assert_eq!(err, Err(ExceptionCode::IllegalDataAddress));
+    }
+}
@@ -0,0 +1,789 @@
+//! OPC UA server exposing the sensor network as an information model
+//!
+//! Address space (namespace `urn:wk6-async-gateway:telemetry`):
+//!
+//! ```text
+//! Objects
+//! └─ Gateway
+//!    ├─ LastUpdate      DateTime, corrected time of the latest record
+//!    ├─ DataStale       Boolean, no record within `stale_after_secs`
+//!    └─ Nodes
+//!       ├─ N1 ─ BME680 (Temperature, Humidity, GasResistance)
+//!       │     ├ Derived (DewPoint, AbsoluteHumidity, HeatIndex, IAQ)
+//!       │     └ Radio (RSSI, SNR)
+//!       └─ N2 ─ BMP280 (Temperature, Pressure)
+//!             ├ Derived (Altitude)
+//!             └ Radio (PacketsReceived, CrcErrors)
+//! ```
+//!
+//! Every sensor variable carries its value, an `EngineeringUnits` property,
+//! the record's corrected time as source timestamp, and a status code:
+//! - `Good`: fresh value from the latest record
+//! - `BadWaitingForInitialData`: never received
+//! - `UncertainLastUsableValue`: the latest record didn't include it (e.g. a
+//!   `Node2Data` field that is `None`); the last value is kept
+//! - `UncertainNoCommunicationLastUsableValue`: no record for
+//!   `stale_after_secs`; the last value is kept
+//!
+//! Updates go through the server's subscription cache, so monitored items
+//! are notified on every change.
+
+use anyhow::{anyhow, Context, Result};
+use opcua::crypto::SecurityPolicy;
+use opcua::nodes::{ObjectBuilder, VariableBuilder};
+use opcua::server::diagnostics::NamespaceMetadata;
+use opcua::server::node_manager::memory::{simple_node_manager, SimpleNodeManager};
+use opcua::server::{ServerBuilder, ServerEndpoint, SubscriptionCache, ANONYMOUS_USER_TOKEN_ID};
+use opcua::types::{
+    DataTypeId, DataValue, DateTime, EUInformation, MessageSecurityMode, NodeId, ObjectId,
+    ObjectTypeId, StatusCode, VariableTypeId, Variant,
+};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+use crate::sink::TelemetrySink;
+use crate::telemetry::ProcessedRecord;
+
+/// Namespace of the gateway's nodes
+const NAMESPACE_URI: &str = "urn:wk6-async-gateway:telemetry";
+
+/// Namespace for UNECE unit codes in `EUInformation`
+const UNECE_NAMESPACE: &str = "http://www.opcfoundation.org/UA/units/un/cefact";
+
+/// How often staleness is checked
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// OPC UA server (`[opcua]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpcUaConfig {
+    /// Host name or address to listen on (also used in endpoint URLs)
+    pub host: String,
+    /// TCP port (4840 is the registered OPC UA port)
+    pub port: u16,
+    /// Application URI in the server certificate
+    pub application_uri: String,
+    /// Certificate store; a self-signed certificate is created on first run
+    pub pki_dir: PathBuf,
+    /// Accept any client certificate on the encrypted endpoint
+    /// (otherwise move it from `<pki_dir>/rejected` to `<pki_dir>/trusted`)
+    pub trust_client_certs: bool,
+    /// Values turn `UncertainNoCommunicationLastUsableValue` after this long
+    /// without a record
+    pub stale_after_secs: u64,
+}
+
+impl Default for OpcUaConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 4840,
+            application_uri: "urn:wk6-async-gateway".to_string(),
+            pki_dir: PathBuf::from("pki"),
+            trust_client_certs: false,
+            stale_after_secs: 30,
+        }
+    }
+}
+
+/// Engineering unit of a variable
+struct Unit {
+    /// UNECE common code ("" when there is none)
+    code: &'static str,
+    symbol: &'static str,
+    description: &'static str,
+}
+
+const CELSIUS: Unit = Unit {
+    code: "CEL",
+    symbol: "°C",
+    description: "degree Celsius",
+};
+const PERCENT: Unit = Unit {
+    code: "P1",
+    symbol: "%",
+    description: "percent",
+};
+const OHM: Unit = Unit {
+    code: "OHM",
+    symbol: "Ω",
+    description: "ohm",
+};
+const GRAM_PER_M3: Unit = Unit {
+    code: "A93",
+    symbol: "g/m³",
+    description: "gram per cubic metre",
+};
+const HECTOPASCAL: Unit = Unit {
+    code: "A97",
+    symbol: "hPa",
+    description: "hectopascal",
+};
+const METRE: Unit = Unit {
+    code: "MTR",
+    symbol: "m",
+    description: "metre",
+};
+const DECIBEL: Unit = Unit {
+    code: "2N",
+    symbol: "dB",
+    description: "decibel",
+};
+const DBM: Unit = Unit {
+    code: "",
+    symbol: "dBm",
+    description: "decibel-milliwatt",
+};
+
+impl Unit {
+    fn information(&self) -> EUInformation {
+        // UNECE codes map to an id by packing their ASCII bytes
+        let unit_id = match self.code {
+            "" => -1,
+            code => code.bytes().fold(0, |id, b| (id << 8) | i32::from(b)),
+        };
+        EUInformation {
+            namespace_uri: UNECE_NAMESPACE.into(),
+            unit_id,
+            display_name: self.symbol.into(),
+            description: self.description.into(),
+        }
+    }
+}
+
+/// Where a variable's value comes from
+enum Source {
+    /// `ProcessedRecord::metrics` entry (node, metric)
+    Metric(&'static str, &'static str),
+    PacketsReceived,
+    CrcErrors,
+}
+
+/// A sensor variable in the address space
+struct Point {
+    node: &'static str,
+    sensor: &'static str,
+    name: &'static str,
+    source: Source,
+    unit: Option<Unit>,
+}
+
+impl Point {
+    fn value(&self, record: &ProcessedRecord) -> Option<Variant> {
+        match self.source {
+            Source::Metric(node, name) => record
+                .metrics()
+                .into_iter()
+                .find(|m| m.node == node && m.name == name)
+                .map(|m| Variant::Double(m.value)),
+            Source::PacketsReceived => Some(Variant::UInt32(record.packet.sts.rx)),
+            Source::CrcErrors => Some(Variant::UInt32(record.packet.sts.err)),
+        }
+    }
+
+    fn data_type(&self) -> DataTypeId {
+        match self.source {
+            Source::Metric(..) => DataTypeId::Double,
+            Source::PacketsReceived | Source::CrcErrors => DataTypeId::UInt32,
+        }
+    }
+}
+
+macro_rules! point {
+    ($node:literal, $sensor:literal, $name:literal, $source:expr, $unit:expr) => {
+        Point {
+            node: $node,
+            sensor: $sensor,
+            name: $name,
+            source: $source,
+            unit: $unit,
+        }
+    };
+}
+
+/// Every sensor variable, grouped by node and sensor
+const POINTS: &[Point] = &[
+    point!(
+        "N1",
+        "BME680",
+        "Temperature",
+        Source::Metric("n1", "temperature"),
+        Some(CELSIUS)
+    ),
+    point!(
+        "N1",
+        "BME680",
+        "Humidity",
+        Source::Metric("n1", "humidity"),
+        Some(PERCENT)
+    ),
+    point!(
+        "N1",
+        "BME680",
+        "GasResistance",
+        Source::Metric("n1", "gas_resistance"),
+        Some(OHM)
+    ),
+    point!(
+        "N1",
+        "Derived",
+        "DewPoint",
+        Source::Metric("n1", "dew_point"),
+        Some(CELSIUS)
+    ),
+    point!(
+        "N1",
+        "Derived",
+        "AbsoluteHumidity",
+        Source::Metric("n1", "absolute_humidity"),
+        Some(GRAM_PER_M3)
+    ),
+    point!(
+        "N1",
+        "Derived",
+        "HeatIndex",
+        Source::Metric("n1", "heat_index"),
+        Some(CELSIUS)
+    ),
+    point!("N1", "Derived", "IAQ", Source::Metric("n1", "iaq"), None),
+    point!(
+        "N1",
+        "Radio",
+        "RSSI",
+        Source::Metric("n1", "rssi"),
+        Some(DBM)
+    ),
+    point!(
+        "N1",
+        "Radio",
+        "SNR",
+        Source::Metric("n1", "snr"),
+        Some(DECIBEL)
+    ),
+    point!(
+        "N2",
+        "BMP280",
+        "Temperature",
+        Source::Metric("n2", "temperature"),
+        Some(CELSIUS)
+    ),
+    point!(
+        "N2",
+        "BMP280",
+        "Pressure",
+        Source::Metric("n2", "pressure"),
+        Some(HECTOPASCAL)
+    ),
+    point!(
+        "N2",
+        "Derived",
+        "Altitude",
+        Source::Metric("n2", "altitude"),
+        Some(METRE)
+    ),
+    point!(
+        "N2",
+        "Radio",
+        "PacketsReceived",
+        Source::PacketsReceived,
+        None
+    ),
+    point!("N2", "Radio", "CrcErrors", Source::CrcErrors, None),
+];
+
+/// OPC UA `DateTime` for Unix ms (100 ns ticks since 1601-01-01)
+fn ua_time(unix_ms: u64) -> DateTime {
+    const UNIX_EPOCH_OFFSET_MS: i64 = 11_644_473_600_000;
+    DateTime::from((unix_ms as i64 + UNIX_EPOCH_OFFSET_MS) * 10_000)
+}
+
+/// A data value with an explicit status (`Variant::Empty` when there's no
+/// value; reads drop the status of a value-less `DataValue`)
+fn data_value(value: Variant, status: StatusCode, source: Option<DateTime>) -> DataValue {
+    DataValue {
+        value: Some(value),
+        status: Some(status),
+        source_timestamp: source,
+        server_timestamp: Some(DateTime::now()),
+        ..Default::default()
+    }
+}
+
+/// Last good values and data freshness
+struct ModelState {
+    /// Last good value and its source time, per point
+    last: Vec<Option<(Variant, DateTime)>>,
+    /// When the latest record arrived (host) and its corrected time
+    last_update: Option<(Instant, DateTime)>,
+    stale: bool,
+}
+
+impl ModelState {
+    fn new() -> Self {
+        Self {
+            last: vec![None; POINTS.len()],
+            last_update: None,
+            stale: true,
+        }
+    }
+
+    /// Point values for the current state (`LastUpdate`, `DataStale` follow)
+    fn values(&self, fresh: &[bool]) -> Vec<DataValue> {
+        let mut values: Vec<DataValue> = self
+            .last
+            .iter()
+            .zip(fresh)
+            .map(|(last, &fresh)| match last {
+                None => data_value(Variant::Empty, StatusCode::BadWaitingForInitialData, None),
+                Some((value, at)) => {
+                    let status = if self.stale {
+                        StatusCode::UncertainNoCommunicationLastUsableValue
+                    } else if fresh {
+                        StatusCode::Good
+                    } else {
+                        StatusCode::UncertainLastUsableValue
+                    };
+                    data_value(value.clone(), status, Some(*at))
+                }
+            })
+            .collect();
+
+        values.push(match self.last_update {
+            Some((_, at)) => {
+                data_value(Variant::DateTime(Box::new(at)), StatusCode::Good, Some(at))
+            }
+            None => data_value(Variant::Empty, StatusCode::BadWaitingForInitialData, None),
+        });
+        values.push(data_value(
+            Variant::Boolean(self.stale),
+            StatusCode::Good,
+            None,
+        ));
+        values
+    }
+
+    /// Take in a record; returns the new values for every variable
+    fn apply(&mut self, record: &ProcessedRecord) -> Vec<DataValue> {
+        let at = ua_time(record.timestamp_ms());
+        let fresh: Vec<bool> = POINTS
+            .iter()
+            .zip(&mut self.last)
+            .map(|(point, last)| match point.value(record) {
+                Some(value) => {
+                    *last = Some((value, at));
+                    true
+                }
+                None => false,
+            })
+            .collect();
+        self.last_update = Some((Instant::now(), at));
+        self.stale = false;
+        self.values(&fresh)
+    }
+
+    /// Mark the data stale once it's older than `stale_after`; returns the
+    /// new values if that changed anything
+    fn check_stale(&mut self, stale_after: Duration) -> Option<Vec<DataValue>> {
+        let (received, _) = self.last_update?;
+        if self.stale || received.elapsed() <= stale_after {
+            return None;
+        }
+        self.stale = true;
+        Some(self.values(&vec![false; POINTS.len()]))
+    }
+}
+
+/// The gateway's nodes in the server's address space
+struct Model {
+    node_manager: Arc<SimpleNodeManager>,
+    subscriptions: Arc<SubscriptionCache>,
+    /// Point variables, then `LastUpdate` and `DataStale`
+    ids: Vec<NodeId>,
+    stale_after: Duration,
+    state: Mutex<ModelState>,
+}
+
+impl Model {
+    /// Create the Gateway → Nodes → Sensors → Variables hierarchy
+    fn build(
+        node_manager: Arc<SimpleNodeManager>,
+        subscriptions: Arc<SubscriptionCache>,
+        ns: u16,
+        stale_after: Duration,
+    ) -> Self {
+        let id = |path: &str| NodeId::new(ns, path);
+        let gateway = id("Gateway");
+        let nodes = id("Gateway.Nodes");
+        let mut ids = Vec::with_capacity(POINTS.len() + 2);
+        {
+            let mut space = node_manager.address_space().write();
+            ObjectBuilder::new(&gateway, "Gateway", "Gateway")
+                .has_type_definition(ObjectTypeId::BaseObjectType)
+                .organized_by(ObjectId::ObjectsFolder)
+                .insert(&mut *space);
+            ObjectBuilder::new(&nodes, "Nodes", "Nodes")
+                .is_folder()
+                .organized_by(gateway.clone())
+                .insert(&mut *space);
+
+            for point in POINTS {
+                let node = id(point.node);
+                let sensor = id(&format!("{}.{}", point.node, point.sensor));
+                if !space.node_exists(&node) {
+                    ObjectBuilder::new(&node, point.node, point.node)
+                        .has_type_definition(ObjectTypeId::BaseObjectType)
+                        .organized_by(nodes.clone())
+                        .insert(&mut *space);
+                }
+                if !space.node_exists(&sensor) {
+                    ObjectBuilder::new(&sensor, point.sensor, point.sensor)
+                        .has_type_definition(ObjectTypeId::BaseObjectType)
+                        .component_of(node.clone())
+                        .insert(&mut *space);
+                }
+
+                let variable = id(&format!("{}.{}.{}", point.node, point.sensor, point.name));
+                VariableBuilder::new(&variable, point.name, point.name)
+                    .data_type(point.data_type())
+                    .has_type_definition(match point.unit {
+                        Some(_) => VariableTypeId::BaseAnalogType,
+                        None => VariableTypeId::BaseDataVariableType,
+                    })
+                    .component_of(sensor.clone())
+                    .insert(&mut *space);
+                if let Some(unit) = &point.unit {
+                    let property = id(&format!("{}.EngineeringUnits", variable.identifier));
+                    VariableBuilder::new(&property, "EngineeringUnits", "EngineeringUnits")
+                        .data_type(DataTypeId::EUInformation)
+                        .has_type_definition(VariableTypeId::PropertyType)
+                        .value(unit.information())
+                        .property_of(variable.clone())
+                        .insert(&mut *space);
+                }
+                ids.push(variable);
+            }
+
+            for (name, data_type) in [
+                ("LastUpdate", DataTypeId::DateTime),
+                ("DataStale", DataTypeId::Boolean),
+            ] {
+                let variable = id(&format!("Gateway.{}", name));
+                VariableBuilder::new(&variable, name, name)
+                    .data_type(data_type)
+                    .has_type_definition(VariableTypeId::BaseDataVariableType)
+                    .component_of(gateway.clone())
+                    .insert(&mut *space);
+                ids.push(variable);
+            }
+        }
+
+        let model = Self {
+            node_manager,
+            subscriptions,
+            ids,
+            stale_after,
+            state: Mutex::new(ModelState::new()),
+        };
+        let initial = model.lock().values(&vec![false; POINTS.len()]);
+        model.publish(initial);
+        model
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ModelState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Write values to the address space and notify subscribers
+    fn publish(&self, values: Vec<DataValue>) {
+        let updates = self
+            .ids
+            .iter()
+            .zip(values)
+            .map(|(id, value)| (id, None, value));
+        if let Err(status) = self.node_manager.set_values(&self.subscriptions, updates) {
+            error!(%status, "Failed to update OPC UA values");
+        }
+    }
+
+    fn update(&self, record: &ProcessedRecord) {
+        let values = self.lock().apply(record);
+        self.publish(values);
+    }
+
+    fn check_stale(&self) {
+        let values = self.lock().check_stale(self.stale_after);
+        if let Some(values) = values {
+            info!("No telemetry received recently, OPC UA values marked stale");
+            self.publish(values);
+        }
+    }
+}
+
+/// Handle for publishing records to the OPC UA address space
+#[derive(Clone)]
+pub struct OpcUaHandle {
+    model: Arc<Model>,
+}
+
+impl OpcUaHandle {
+    /// Update every variable from `record`
+    pub fn update(&self, record: &ProcessedRecord) {
+        self.model.update(record);
+    }
+}
+
+impl TelemetrySink for OpcUaHandle {
+    async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+        self.update(record);
+        Ok(())
+    }
+}
+
+/// Build the server and populate the address space
+fn build(config: &OpcUaConfig, port: u16) -> Result<(opcua::server::Server, OpcUaHandle)> {
+    let none = ServerEndpoint::new_none("/", &[ANONYMOUS_USER_TOKEN_ID.to_string()]);
+    let secure = ServerEndpoint::new(
+        "/",
+        SecurityPolicy::Basic256Sha256,
+        MessageSecurityMode::SignAndEncrypt,
+        &[ANONYMOUS_USER_TOKEN_ID.to_string()],
+    );
+    let (server, handle) = ServerBuilder::new()
+        .application_name("wk6-async-gateway")
+        .application_uri(&config.application_uri)
+        .product_uri(&config.application_uri)
+        .host(&config.host)
+        .port(port)
+        .discovery_urls(vec![format!("opc.tcp://{}:{}/", config.host, port)])
+        .pki_dir(&config.pki_dir)
+        .create_sample_keypair(true)
+        .trust_client_certs(config.trust_client_certs)
+        .add_endpoint("none", none)
+        .add_endpoint("basic256sha256_sign_encrypt", secure)
+        .with_node_manager(simple_node_manager(
+            NamespaceMetadata {
+                namespace_uri: NAMESPACE_URI.to_string(),
+                ..Default::default()
+            },
+            "gateway",
+        ))
+        .build()
+        .map_err(|e| anyhow!("Invalid OPC UA server configuration: {}", e))?;
+
+    let node_manager = handle
+        .node_managers()
+        .get_of_type::<SimpleNodeManager>()
+        .context("OPC UA node manager missing")?;
+    let ns = handle
+        .get_namespace_index(NAMESPACE_URI)
+        .context("OPC UA namespace missing")?;
+    let model = Model::build(
+        node_manager,
+        handle.subscriptions().clone(),
+        ns,
+        Duration::from_secs(config.stale_after_secs),
+    );
+    Ok((
+        server,
+        OpcUaHandle {
+            model: Arc::new(model),
+        },
+    ))
+}
+
+/// Start the OPC UA server
+pub async fn spawn(config: &OpcUaConfig) -> Result<OpcUaHandle> {
+    let listener = TcpListener::bind((config.host.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind OPC UA server to {}:{}",
+                config.host, config.port
+            )
+        })?;
+    let (server, handle) = build(config, config.port)?;
+    info!(
+        endpoint = %format!("opc.tcp://{}:{}/", config.host, config.port),
+        variables = POINTS.len(),
+        "OPC UA server listening"
+    );
+
+    tokio::spawn(async move {
+        if let Err(e) = server.run_with(listener).await {
+            error!(error = %e, "OPC UA server stopped");
+        }
+    });
+
+    let stale_check = handle.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(STALE_CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            stale_check.model.check_stale();
+        }
+    });
+
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+
+    fn status(value: &DataValue) -> StatusCode {
+        value.status.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_subscription_sees_updates() {
+        use opcua::client::{ClientBuilder, DataChangeCallback, IdentityToken};
+        use opcua::types::{
+            AttributeId, MonitoredItemCreateRequest, MonitoringMode, MonitoringParameters,
+            ReadValueId, TimestampsToReturn,
+        };
+
+        let dir = std::env::temp_dir().join(format!("opcua-{}", std::process::id()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = OpcUaConfig {
+            pki_dir: dir.join("server"),
+            ..OpcUaConfig::default()
+        };
+        let (server, handle) = build(&config, port).unwrap();
+        tokio::spawn(server.run_with(listener));
+
+        let mut client = ClientBuilder::new()
+            .application_name("gateway-test")
+            .application_uri("urn:gateway-test")
+            .pki_dir(dir.join("client"))
+            .create_sample_keypair(true)
+            .trust_server_certs(true)
+            .session_retry_limit(1)
+            .client()
+            .unwrap();
+        let url = format!("opc.tcp://127.0.0.1:{}/", port);
+        let (session, event_loop) = client
+            .connect_to_matching_endpoint(
+                (
+                    url.as_str(),
+                    SecurityPolicy::None.to_str(),
+                    MessageSecurityMode::None,
+                ),
+                IdentityToken::Anonymous,
+            )
+            .await
+            .unwrap();
+        event_loop.spawn();
+        session.wait_for_connection().await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let subscription = session
+            .create_subscription(
+                Duration::from_millis(100),
+                100,
+                20,
+                0,
+                0,
+                true,
+                DataChangeCallback::new(move |value, _| tx.send(value).unwrap()),
+            )
+            .await
+            .unwrap();
+        let ns = handle.model.ids[0].namespace;
+        let item = MonitoredItemCreateRequest {
+            item_to_monitor: ReadValueId {
+                node_id: NodeId::new(ns, "N1.BME680.Temperature"),
+                attribute_id: AttributeId::Value as u32,
+                ..Default::default()
+            },
+            monitoring_mode: MonitoringMode::Reporting,
+            requested_parameters: MonitoringParameters {
+                sampling_interval: 0.0,
+                queue_size: 10,
+                discard_oldest: true,
+                ..Default::default()
+            },
+        };
+        session
+            .create_monitored_items(subscription, TimestampsToReturn::Both, vec![item])
+            .await
+            .unwrap();
+
+        async fn recv(rx: &mut tokio::sync::mpsc::UnboundedReceiver<DataValue>) -> DataValue {
+            let next = tokio::time::timeout(Duration::from_secs(5), rx.recv());
+            next.await.unwrap().unwrap()
+        }
+        let initial = recv(&mut rx).await;
+        assert_eq!(initial.status, Some(StatusCode::BadWaitingForInitialData));
+
+        handle.update(&record(1_767_225_600_000, 21.5));
+        let update = recv(&mut rx).await;
+        assert_eq!(update.value, Some(Variant::Double(21.5)));
+        assert_eq!(update.status.unwrap_or(StatusCode::Good), StatusCode::Good);
+        assert_eq!(update.source_timestamp, Some(ua_time(1_767_225_600_000)));
+
+        session.disconnect().await.ok();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_unit_ids_pack_unece_codes() {
+        assert_eq!(CELSIUS.information().unit_id, 4_408_652);
+        assert_eq!(PERCENT.information().unit_id, 20_529);
+        assert_eq!(DBM.information().unit_id, -1);
+    }
+
+    #[test]
+    fn test_ua_time_from_unix_ms() {
+        let at = ua_time(1_767_225_600_123);
+        assert_eq!(at.as_chrono().timestamp_millis(), 1_767_225_600_123);
+    }
+
+    #[test]
+    fn test_status_codes_follow_data() {
+        let mut state = ModelState::new();
+        let initial = state.values(&[false; POINTS.len()]);
+        assert!(initial[..POINTS.len()]
+            .iter()
+            .all(|v| status(v) == StatusCode::BadWaitingForInitialData));
+        assert_eq!(
+            initial[POINTS.len() + 1].value,
+            Some(Variant::Boolean(true))
+        );
+
+        let values = state.apply(&record(1_000, 21.5));
+        assert_eq!(values[0].value, Some(Variant::Double(21.5)));
+        assert_eq!(status(&values[0]), StatusCode::Good);
+        assert_eq!(
+            values[POINTS.len() + 1].value,
+            Some(Variant::Boolean(false))
+        );
+
+        // node2's BMP280 stops reporting: last value kept, flagged uncertain
+        let n2_temp = POINTS.iter().position(|p| p.node == "N2").unwrap();
+        let mut missing = record(2_000, 22.0);
+        missing.packet.n2.t = None;
+        let values = state.apply(&missing);
+        assert_eq!(status(&values[0]), StatusCode::Good);
+        assert_eq!(
+            status(&values[n2_temp]),
+            StatusCode::UncertainLastUsableValue
+        );
+        assert_eq!(values[n2_temp].value, Some(Variant::Double(24.0)));
+
+        // No records for a while: everything uncertain, DataStale set
+        assert!(state.check_stale(Duration::from_secs(60)).is_none());
+        let values = state.check_stale(Duration::ZERO).unwrap();
+        assert_eq!(
+            status(&values[0]),
+            StatusCode::UncertainNoCommunicationLastUsableValue
+        );
+        assert_eq!(values[POINTS.len() + 1].value, Some(Variant::Boolean(true)));
+        assert!(state.check_stale(Duration::ZERO).is_none(), "only once");
+    }
+}
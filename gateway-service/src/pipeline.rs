@@ -0,0 +1,194 @@
+//! Configurable processing stages between the processor and the sinks
+//!
+//! Each `[[pipeline]]` entry is one stage, run in order on every record
+//! after clock correlation, derived metrics and IAQ:
+//!
+//! | `type`      | Stage                                                      |
+//! |-------------|------------------------------------------------------------|
+//! | `filter`    | Drop records from other source ids or with values out of range |
+//! | `smooth`    | Median, Hampel outlier rejection or EMA per metric         |
+//! | `anomaly`   | Flag link degradation, spikes and drift from learned baselines |
+//! | `round`     | Round values to a number of decimals                       |
+//! | `tag`       | Rename the source id and attach site metadata              |
+//! | `log`       | Log each record                                            |
+//!
+//! Metrics are named `<node>.<metric>` as in the query API. Values stay in
+//! °C and hPa; sinks can ask for other units under `[router.sinks.<name>]`. Without any
+//! `[[pipeline]]` entries the pipeline is a single `log` stage.
+
+mod anomaly;
+mod enrich;
+mod filter;
+mod log;
+mod smooth;
+mod transform;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::derived::DerivedConfig;
+use crate::telemetry::{parse_metric, ProcessedRecord};
+
+pub use anomaly::{Anomaly, AnomalyConfig, AnomalyDetector};
+pub use enrich::{Tag, TagConfig};
+pub use filter::{Filter, FilterConfig};
+pub use log::Log;
+pub use smooth::{FilteredValue, Smooth, SmoothConfig};
+pub use transform::{Round, RoundConfig};
+
+/// One processing stage
+pub trait Processor: Send {
+    /// Stage name, as used for `type` in the config
+    fn name(&self) -> &'static str;
+
+    /// Inspect or modify a record in place
+    fn process(&mut self, record: &mut ProcessedRecord) -> Flow;
+}
+
+/// What happens to a record after a stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Pass it on to the next stage (or the sinks)
+    Continue,
+    /// Discard it; later stages and the sinks never see it
+    Drop,
+}
+
+/// One `[[pipeline]]` entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum StageConfig {
+    Filter(FilterConfig),
+    Smooth(SmoothConfig),
+    Anomaly(AnomalyConfig),
+    Round(RoundConfig),
+    Tag(TagConfig),
+    Log,
+}
+
+/// Metrics computed by `DerivedMetrics` (recomputed when a stage changes
+/// the readings they come from)
+const DERIVED_METRICS: &[(&str, &str)] = &[
+    ("n1", "dew_point"),
+    ("n1", "absolute_humidity"),
+    ("n1", "heat_index"),
+    ("n1", "link_margin"),
+    ("n2", "altitude"),
+];
+
+/// Resolve a `<node>.<metric>` name from a stage's config
+fn metric(name: &str, stage: &str) -> Result<(&'static str, &'static str)> {
+    parse_metric(name).with_context(|| format!("Unknown metric '{}' in {} stage", name, stage))
+}
+
+/// The configured stages, in order
+pub struct Pipeline {
+    stages: Vec<Box<dyn Processor>>,
+}
+
+impl Pipeline {
+    /// Build from `[[pipeline]]` (`None` = the default pipeline, just `log`)
+    pub fn from_config(stages: Option<&[StageConfig]>, derived: &DerivedConfig) -> Result<Self> {
+        let Some(configs) = stages else {
+            return Ok(Self {
+                stages: vec![Box::new(Log)],
+            });
+        };
+
+        let mut stages: Vec<Box<dyn Processor>> = Vec::with_capacity(configs.len());
+        for config in configs {
+            let stage: Box<dyn Processor> = match config {
+                StageConfig::Filter(c) => Box::new(Filter::new(c)?),
+                StageConfig::Smooth(c) => Box::new(Smooth::new(c, derived)?),
+                StageConfig::Anomaly(c) => Box::new(AnomalyDetector::new(c)?),
+                StageConfig::Round(c) => Box::new(Round::new(c)?),
+                StageConfig::Tag(c) => Box::new(Tag::new(c)),
+                StageConfig::Log => Box::new(Log),
+            };
+            stages.push(stage);
+        }
+        Ok(Self { stages })
+    }
+
+    /// Stage names in order
+    pub fn names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    /// Run every stage in order; `None` if one of them dropped the record
+    pub fn run(&mut self, mut record: ProcessedRecord) -> Option<ProcessedRecord> {
+        for stage in &mut self.stages {
+            if stage.process(&mut record) == Flow::Drop {
+                debug!(
+                    stage = stage.name(),
+                    ts = record.packet.ts,
+                    "Record dropped"
+                );
+                return None;
+            }
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GatewayConfig;
+    use crate::telemetry::test_support::record;
+
+    fn pipeline(toml: &str) -> Result<Pipeline> {
+        let config = GatewayConfig::from_toml(toml)?;
+        Pipeline::from_config(config.pipeline.as_deref(), &DerivedConfig::default())
+    }
+
+    #[test]
+    fn test_default_pipeline_just_logs() {
+        assert_eq!(pipeline("").unwrap().names(), vec!["log"]);
+    }
+
+    #[test]
+    fn test_example_pipeline_builds() {
+        let p = pipeline(include_str!("../gateway.example.toml")).unwrap();
+        assert_eq!(
+            p.names(),
+            vec!["filter", "smooth", "anomaly", "round", "tag", "log"]
+        );
+    }
+
+    #[test]
+    fn test_stages_run_in_config_order() {
+        let mut p = pipeline(
+            r#"
+            [[pipeline]]
+            type = "filter"
+            ranges = { "n1.temperature" = [-40.0, 85.0] }
+
+            [[pipeline]]
+            type = "round"
+            decimals = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(p.names(), vec!["filter", "round"]);
+
+        let out = p.run(record(1_000, 21.26)).unwrap();
+        assert_eq!(out.packet.n1.t, 21.3);
+
+        assert!(p.run(record(2_000, 120.0)).is_none());
+    }
+
+    #[test]
+    fn test_invalid_pipelines_rejected() {
+        // Calibration is [calibration] and unit conversion per sink, not stages
+        assert!(pipeline("[[pipeline]]\ntype = \"calibrate\"").is_err());
+        assert!(pipeline("[[pipeline]]\ntype = \"convert\"\ntemperature = \"kelvin\"").is_err());
+        assert!(pipeline("[[pipeline]]\ntype = \"round\"\nmetrics = [\"n9.x\"]").is_err());
+        assert!(pipeline("[[pipeline]]\ntype = \"tag\"\nsite = \"lab\"").is_err());
+        assert!(pipeline("[[pipeline]]\ntype = \"resample\"").is_err());
+        let unknown_filter = "[[pipeline]]\ntype = \"smooth\"\n\
+            metrics = { \"n1.humidity\" = { filter = \"kalman\" } }";
+        assert!(pipeline(unknown_filter).is_err());
+    }
+}
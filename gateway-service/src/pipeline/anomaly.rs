@@ -0,0 +1,482 @@
+//! `anomaly` stage: learned baselines per metric, flagging what breaks them
+//!
+//! Two detectors run on every monitored `<node>.<metric>`:
+//! - Sudden change: z-score of the reading against the last `window`
+//!   readings. A drop in `rssi`/`snr` is reported as `link_degradation`,
+//!   other jumps as `spike` (link quality improving isn't an anomaly).
+//! - Drift (sensor readings only): a two-sided CUSUM over the reading's
+//!   deviation from a seasonal baseline (EWMA mean and variance per UTC hour
+//!   of day), reported as `drift` once the accumulated shift passes
+//!   `drift_threshold`. Sudden changes are kept out of it.
+//!
+//! Confidence is the Chebyshev bound `1 - 1/z²` on the score, so it holds
+//! whatever the noise distribution. Scores are in standard deviations, with
+//! the deviation floored at `min_std` so a perfectly steady signal doesn't
+//! turn every small step into an anomaly.
+//!
+//! Detected anomalies are logged, listed in `record.anomalies` and sent as
+//! `anomaly` events by the stream and webhook sinks. Everything depends only
+//! on the readings and their timestamps, so the same series always gives the
+//! same anomalies.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use tracing::warn;
+
+use super::{metric, Flow, Processor};
+use crate::telemetry::ProcessedRecord;
+
+const MS_PER_HOUR: u64 = 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnomalyConfig {
+    /// `<node>.<metric>` names to monitor
+    pub metrics: Vec<String>,
+    /// Readings in the sudden-change window
+    pub window: usize,
+    /// Readings a window or hourly baseline needs before it is used
+    pub min_samples: u64,
+    /// |z| above which a reading is a sudden change
+    pub z_threshold: f64,
+    /// Weight of a new reading in its hourly baseline
+    pub seasonal_alpha: f64,
+    /// Deviation (in std) the CUSUM tolerates per reading
+    pub drift_slack: f64,
+    /// Accumulated deviation (in std) reported as drift
+    pub drift_threshold: f64,
+    /// Standard deviation floor per `<node>.<metric>`, in native units
+    /// (defaults per metric, see `default_min_std`)
+    pub min_std: BTreeMap<String, f64>,
+    /// Quiet period per metric and kind after an anomaly is reported
+    pub cooldown_secs: u64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            metrics: ["n1.rssi", "n1.snr", "n1.temperature", "n1.gas_resistance"]
+                .map(String::from)
+                .to_vec(),
+            window: 60,
+            min_samples: 20,
+            z_threshold: 4.0,
+            seasonal_alpha: 0.05,
+            drift_slack: 0.5,
+            drift_threshold: 10.0,
+            min_std: BTreeMap::new(),
+            cooldown_secs: 600,
+        }
+    }
+}
+
+/// Smallest meaningful change per metric: sensor resolution or noise floor
+pub(super) fn default_min_std(name: &str) -> f64 {
+    match name {
+        "rssi" | "snr" => 1.0,
+        "temperature" | "dew_point" | "heat_index" => 0.05,
+        "humidity" => 0.2,
+        "pressure" => 0.05,
+        "gas_resistance" => 500.0,
+        "altitude" => 0.5,
+        _ => 0.01,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// Sudden jump in a sensor reading
+    Spike,
+    /// Sudden drop in RSSI or SNR
+    LinkDegradation,
+    /// Slow, sustained shift away from the learned baseline
+    Drift,
+}
+
+impl AnomalyKind {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Record metadata and event payload: one detected anomaly
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Anomaly {
+    /// `<node>.<metric>`
+    pub metric: String,
+    pub kind: AnomalyKind,
+    pub value: f64,
+    /// Baseline the reading was compared against (window or hourly mean)
+    pub expected: f64,
+    /// Signed deviation in standard deviations (mean shift for drift)
+    pub score: f64,
+    /// 0..1, lower bound on the probability this isn't normal noise
+    pub confidence: f64,
+}
+
+/// Chebyshev: at most 1/z² of any distribution lies beyond z std
+fn confidence(z: f64) -> f64 {
+    (1.0 - 1.0 / (z * z)).max(0.0)
+}
+
+/// EWMA mean and variance (a plain average until `1/n` drops below alpha)
+#[derive(Debug, Clone, Copy, Default)]
+struct Baseline {
+    n: u64,
+    mean: f64,
+    var: f64,
+}
+
+impl Baseline {
+    fn update(&mut self, x: f64, alpha: f64) {
+        self.n += 1;
+        let a = alpha.max(1.0 / self.n as f64);
+        let d = x - self.mean;
+        self.mean += a * d;
+        self.var = (1.0 - a) * (self.var + a * d * d);
+    }
+}
+
+/// Two-sided CUSUM: accumulated positive and negative deviation, and the
+/// readings each has been accumulating for
+#[derive(Debug, Clone, Copy, Default)]
+struct Cusum {
+    up: f64,
+    up_n: u32,
+    down: f64,
+    down_n: u32,
+}
+
+impl Cusum {
+    /// Add a residual (in std); the detected mean shift once past `threshold`
+    fn update(&mut self, r: f64, slack: f64, threshold: f64) -> Option<(f64, u32)> {
+        fn side(sum: &mut f64, n: &mut u32, r: f64, slack: f64) {
+            *sum = (*sum + r - slack).max(0.0);
+            *n = if *sum > 0.0 { *n + 1 } else { 0 };
+        }
+        side(&mut self.up, &mut self.up_n, r, slack);
+        side(&mut self.down, &mut self.down_n, -r, slack);
+
+        let shift = if self.up > threshold {
+            Some((self.up / self.up_n as f64 + slack, self.up_n))
+        } else if self.down > threshold {
+            Some((-(self.down / self.down_n as f64 + slack), self.down_n))
+        } else {
+            None
+        };
+        if shift.is_some() {
+            *self = Self::default();
+        }
+        shift
+    }
+}
+
+/// Detector state for one metric
+struct MetricDetector {
+    node: &'static str,
+    name: &'static str,
+    min_std: f64,
+    window: VecDeque<f64>,
+    /// Baseline per UTC hour of day
+    hourly: [Baseline; 24],
+    cusum: Cusum,
+    /// Last report time per `AnomalyKind`
+    last_reported_ms: [Option<u64>; 3],
+}
+
+impl MetricDetector {
+    fn link_quality(&self) -> bool {
+        matches!(self.name, "rssi" | "snr")
+    }
+
+    fn sudden_change(&self, x: f64, config: &AnomalyConfig) -> Option<Anomaly> {
+        let n = self.window.len();
+        if (n as u64) < config.min_samples.max(2) {
+            return None;
+        }
+        let mean = self.window.iter().sum::<f64>() / n as f64;
+        let var = self.window.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        let z = (x - mean) / var.sqrt().max(self.min_std);
+        if z.abs() <= config.z_threshold {
+            return None;
+        }
+        let kind = match (self.link_quality(), z < 0.0) {
+            (true, true) => AnomalyKind::LinkDegradation,
+            (true, false) => return None,
+            (false, _) => AnomalyKind::Spike,
+        };
+        Some(self.anomaly(kind, x, mean, z))
+    }
+
+    fn drift(&mut self, x: f64, hour: usize, config: &AnomalyConfig) -> Option<Anomaly> {
+        let baseline = self.hourly[hour];
+        if baseline.n < config.min_samples {
+            return None;
+        }
+        let r = (x - baseline.mean) / baseline.var.sqrt().max(self.min_std);
+        let (shift, n) = self
+            .cusum
+            .update(r, config.drift_slack, config.drift_threshold)?;
+        let mut anomaly = self.anomaly(AnomalyKind::Drift, x, baseline.mean, shift);
+        // The mean of n readings has 1/√n of their spread
+        anomaly.confidence = confidence(shift * (n as f64).sqrt());
+        Some(anomaly)
+    }
+
+    fn anomaly(&self, kind: AnomalyKind, value: f64, expected: f64, score: f64) -> Anomaly {
+        Anomaly {
+            metric: format!("{}.{}", self.node, self.name),
+            kind,
+            value,
+            expected,
+            score,
+            confidence: confidence(score),
+        }
+    }
+
+    /// Anomalies for a new reading, then learn from it
+    fn observe(&mut self, x: f64, at_ms: u64, config: &AnomalyConfig) -> Vec<Anomaly> {
+        let hour = ((at_ms / MS_PER_HOUR) % 24) as usize;
+        let sudden = self.sudden_change(x, config);
+        let drift = match sudden {
+            None if !self.link_quality() => self.drift(x, hour, config),
+            _ => None,
+        };
+
+        if self.window.len() == config.window {
+            self.window.pop_front();
+        }
+        self.window.push_back(x);
+        // Outliers would skew the long-term baseline
+        if sudden.is_none() {
+            self.hourly[hour].update(x, config.seasonal_alpha);
+        }
+
+        let cooldown_ms = config.cooldown_secs * 1000;
+        [sudden, drift]
+            .into_iter()
+            .flatten()
+            .filter(|anomaly| {
+                let last = &mut self.last_reported_ms[anomaly.kind.index()];
+                if last.is_some_and(|last| at_ms.saturating_sub(last) < cooldown_ms) {
+                    return false;
+                }
+                *last = Some(at_ms);
+                true
+            })
+            .collect()
+    }
+}
+
+/// Flags readings that break a metric's learned behaviour
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    metrics: Vec<MetricDetector>,
+}
+
+impl AnomalyDetector {
+    pub fn new(config: &AnomalyConfig) -> Result<Self> {
+        if config.window < 2 || config.z_threshold <= 0.0 || config.drift_threshold <= 0.0 {
+            bail!("anomaly: window must be at least 2 and thresholds positive");
+        }
+        if !(config.seasonal_alpha > 0.0 && config.seasonal_alpha <= 1.0) {
+            bail!("anomaly: seasonal_alpha must be in (0, 1]");
+        }
+        for name in config.min_std.keys() {
+            if !config.metrics.contains(name) {
+                bail!("anomaly: min_std for unmonitored metric '{}'", name);
+            }
+        }
+
+        let mut metrics = Vec::with_capacity(config.metrics.len());
+        for name in &config.metrics {
+            let (node, metric) = metric(name, "anomaly")?;
+            metrics.push(MetricDetector {
+                node,
+                name: metric,
+                min_std: config
+                    .min_std
+                    .get(name)
+                    .copied()
+                    .unwrap_or_else(|| default_min_std(metric)),
+                window: VecDeque::with_capacity(config.window),
+                hourly: [Baseline::default(); 24],
+                cusum: Cusum::default(),
+                last_reported_ms: [None; 3],
+            });
+        }
+        Ok(Self {
+            config: config.clone(),
+            metrics,
+        })
+    }
+}
+
+impl Processor for AnomalyDetector {
+    fn name(&self) -> &'static str {
+        "anomaly"
+    }
+
+    fn process(&mut self, record: &mut ProcessedRecord) -> Flow {
+        let at_ms = record.timestamp_ms();
+        for detector in &mut self.metrics {
+            let Some(value) = record.metric(detector.node, detector.name) else {
+                continue;
+            };
+            for anomaly in detector.observe(value, at_ms, &self.config) {
+                warn!(
+                    metric = %anomaly.metric,
+                    kind = ?anomaly.kind,
+                    value = anomaly.value,
+                    expected = anomaly.expected,
+                    score = anomaly.score,
+                    confidence = anomaly.confidence,
+                    "Anomaly detected"
+                );
+                record.anomalies.push(anomaly);
+            }
+        }
+        Flow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+
+    const T0: u64 = 1_767_225_600_000; // 2026-01-01T00:00:00Z
+    const STEP_MS: u64 = 10 * 60 * 1000;
+
+    /// Deterministic noise in [-1, 1)
+    fn noise(i: u64) -> f64 {
+        (i.wrapping_mul(7919).wrapping_add(13) % 200) as f64 / 100.0 - 1.0
+    }
+
+    fn detector(metrics: &[&str]) -> AnomalyDetector {
+        AnomalyDetector::new(&AnomalyConfig {
+            metrics: metrics.iter().map(|m| m.to_string()).collect(),
+            min_samples: 12,
+            ..AnomalyConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Run a temperature series (10-minute steps) and collect the anomalies
+    fn run_temperature(
+        d: &mut AnomalyDetector,
+        series: impl Fn(u64) -> f64,
+        n: u64,
+    ) -> Vec<(u64, Anomaly)> {
+        let mut found = Vec::new();
+        for i in 0..n {
+            let mut r = record(T0 + i * STEP_MS, series(i) as f32);
+            d.process(&mut r);
+            found.extend(r.anomalies.into_iter().map(|a| (i, a)));
+        }
+        found
+    }
+
+    /// Diurnal cycle ±3 °C around 20 °C plus ±0.1 °C noise
+    fn diurnal(i: u64) -> f64 {
+        let day_fraction = (i % 144) as f64 / 144.0;
+        20.0 + 3.0 * (day_fraction * std::f64::consts::TAU).sin() + 0.1 * noise(i)
+    }
+
+    #[test]
+    fn test_link_degradation() {
+        let mut d = detector(&["n1.rssi"]);
+        let mut anomalies = Vec::new();
+        for i in 0..120 {
+            let mut r = record(T0 + i * 10_000, 20.0);
+            let rssi = if i < 100 { -60.0 } else { -78.0 };
+            r.packet.sig.rssi = (rssi + 2.0 * noise(i)).round() as i16;
+            d.process(&mut r);
+            anomalies.extend(r.anomalies.into_iter().map(|a| (i, a)));
+        }
+
+        // Reported once at the drop, then held back by the cooldown
+        assert_eq!(anomalies.len(), 1, "{:?}", anomalies);
+        let (at, anomaly) = &anomalies[0];
+        assert_eq!(*at, 100);
+        assert_eq!(anomaly.kind, AnomalyKind::LinkDegradation);
+        assert_eq!(anomaly.metric, "n1.rssi");
+        assert!(anomaly.score < -4.0);
+        assert!(anomaly.confidence > 0.9);
+    }
+
+    #[test]
+    fn test_steady_diurnal_cycle_is_normal() {
+        let mut d = detector(&["n1.temperature"]);
+        let found = run_temperature(&mut d, diurnal, 144 * 10);
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn test_slow_drift_detected() {
+        let mut d = detector(&["n1.temperature"]);
+        // Four days to learn the cycle, then +0.5 °C per day
+        let warmup = 144 * 4;
+        let drifting = |i: u64| diurnal(i) + i.saturating_sub(warmup) as f64 * 0.5 / 144.0;
+        let found = run_temperature(&mut d, drifting, warmup + 144 * 2);
+
+        assert!(!found.is_empty());
+        assert!(found.iter().all(|(_, a)| a.kind == AnomalyKind::Drift));
+        let (at, first) = &found[0];
+        assert!(*at > warmup && *at < warmup + 144, "{}", at);
+        assert!(first.score > 0.0);
+        assert!(first.confidence > 0.9);
+        // Same series, same anomalies
+        let mut again = detector(&["n1.temperature"]);
+        assert_eq!(
+            run_temperature(&mut again, drifting, warmup + 144 * 2),
+            found
+        );
+    }
+
+    #[test]
+    fn test_spike_and_record_output() {
+        let mut d = detector(&["n1.gas_resistance"]);
+        let mut last = None;
+        for i in 0..40 {
+            let mut r = record(T0 + i * 10_000, 20.0);
+            r.packet.n1.g = if i == 39 {
+                150_000
+            } else {
+                50_000 + (i % 5) as u32 * 400
+            };
+            d.process(&mut r);
+            last = Some(r);
+        }
+        let r = last.unwrap();
+        assert_eq!(r.anomalies.len(), 1);
+        assert_eq!(r.anomalies[0].kind, AnomalyKind::Spike);
+
+        let json = serde_json::to_value(&r).unwrap();
+        assert_eq!(json["anomalies"][0]["kind"], "spike");
+        assert_eq!(json["anomalies"][0]["metric"], "n1.gas_resistance");
+    }
+
+    #[test]
+    fn test_invalid_config_rejected() {
+        let bad = [
+            AnomalyConfig {
+                metrics: vec!["n1.bogus".to_string()],
+                ..AnomalyConfig::default()
+            },
+            AnomalyConfig {
+                window: 1,
+                ..AnomalyConfig::default()
+            },
+            AnomalyConfig {
+                min_std: BTreeMap::from([("n2.pressure".to_string(), 0.1)]),
+                ..AnomalyConfig::default()
+            },
+        ];
+        for config in bad {
+            assert!(AnomalyDetector::new(&config).is_err());
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! `tag` stage: source renaming and site metadata
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use super::{Flow, Processor};
+use crate::telemetry::ProcessedRecord;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TagConfig {
+    /// Replaces the packet's source `id` (e.g. "N2" → "greenhouse-gw")
+    pub id: Option<String>,
+    /// Added to `record.tags` (site, building, room, coordinates, ...)
+    pub tags: BTreeMap<String, String>,
+}
+
+pub struct Tag {
+    config: TagConfig,
+}
+
+impl Tag {
+    pub fn new(config: &TagConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+}
+
+impl Processor for Tag {
+    fn name(&self) -> &'static str {
+        "tag"
+    }
+
+    fn process(&mut self, record: &mut ProcessedRecord) -> Flow {
+        if let Some(id) = &self.config.id {
+            record.packet.id.clone_from(id);
+        }
+        record
+            .tags
+            .extend(self.config.tags.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Flow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+
+    #[test]
+    fn test_renames_and_tags() {
+        let mut stage = Tag::new(&TagConfig {
+            id: Some("greenhouse-gw".to_string()),
+            tags: BTreeMap::from([("site".to_string(), "lab-3".to_string())]),
+        });
+        let mut r = record(0, 20.0);
+        stage.process(&mut r);
+        assert_eq!(r.packet.id, "greenhouse-gw");
+        assert_eq!(r.tags["site"], "lab-3");
+
+        let json = serde_json::to_value(&r).unwrap();
+        assert_eq!(json["id"], "greenhouse-gw");
+        assert_eq!(json["tags"]["site"], "lab-3");
+    }
+}
@@ -0,0 +1,112 @@
+//! `filter` stage: drop records by source id or value range
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tracing::debug;
+
+use super::{metric, Flow, Processor};
+use crate::telemetry::ProcessedRecord;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// Only keep records from these source ids (the packet `id`; empty = all)
+    pub ids: Vec<String>,
+    /// Drop records with a value outside `[min, max]`, keyed by `<node>.<metric>`
+    /// (records where the value isn't available pass)
+    pub ranges: BTreeMap<String, [f64; 2]>,
+}
+
+pub struct Filter {
+    ids: Vec<String>,
+    ranges: Vec<(&'static str, &'static str, f64, f64)>,
+}
+
+impl Filter {
+    pub fn new(config: &FilterConfig) -> Result<Self> {
+        let mut ranges = Vec::with_capacity(config.ranges.len());
+        for (name, &[min, max]) in &config.ranges {
+            let (node, metric) = metric(name, "filter")?;
+            if min > max {
+                bail!("Empty filter range for '{}': {} > {}", name, min, max);
+            }
+            ranges.push((node, metric, min, max));
+        }
+        Ok(Self {
+            ids: config.ids.clone(),
+            ranges,
+        })
+    }
+}
+
+impl Processor for Filter {
+    fn name(&self) -> &'static str {
+        "filter"
+    }
+
+    fn process(&mut self, record: &mut ProcessedRecord) -> Flow {
+        if !self.ids.is_empty() && !self.ids.contains(&record.packet.id) {
+            debug!(id = %record.packet.id, "Filtered record from unlisted source");
+            return Flow::Drop;
+        }
+        for &(node, name, min, max) in &self.ranges {
+            if let Some(value) = record.metric(node, name) {
+                if value < min || value > max {
+                    debug!(node, metric = name, value, "Filtered out-of-range record");
+                    return Flow::Drop;
+                }
+            }
+        }
+        Flow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+
+    fn filter(ids: &[&str], ranges: &[(&str, f64, f64)]) -> Filter {
+        Filter::new(&FilterConfig {
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+            ranges: ranges
+                .iter()
+                .map(|&(name, min, max)| (name.to_string(), [min, max]))
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_filters_by_source_id() {
+        let mut only_n2 = filter(&["N2"], &[]);
+        assert_eq!(only_n2.process(&mut record(0, 20.0)), Flow::Continue);
+
+        let mut only_gw7 = filter(&["GW7"], &[]);
+        assert_eq!(only_gw7.process(&mut record(0, 20.0)), Flow::Drop);
+    }
+
+    #[test]
+    fn test_filters_by_range() {
+        let mut f = filter(&[], &[("n1.temperature", -40.0, 85.0)]);
+        assert_eq!(f.process(&mut record(0, 85.0)), Flow::Continue);
+        assert_eq!(f.process(&mut record(0, 85.1)), Flow::Drop);
+
+        // Unavailable values pass
+        let mut f = filter(&[], &[("n2.pressure", 900.0, 1100.0)]);
+        let mut no_pressure = record(0, 20.0);
+        no_pressure.packet.n2.p = None;
+        assert_eq!(f.process(&mut no_pressure), Flow::Continue);
+    }
+
+    #[test]
+    fn test_rejects_bad_ranges() {
+        let config = |name: &str, min: f64, max: f64| FilterConfig {
+            ranges: BTreeMap::from([(name.to_string(), [min, max])]),
+            ..FilterConfig::default()
+        };
+        assert!(Filter::new(&config("n1.temperature", 10.0, 0.0)).is_err());
+        assert!(Filter::new(&config("n1.pressure", 0.0, 10.0)).is_err());
+    }
+}
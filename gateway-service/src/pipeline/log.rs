@@ -0,0 +1,64 @@
+//! `log` stage: structured log lines for each record
+
+use tracing::info;
+
+use super::{Flow, Processor};
+use crate::telemetry::ProcessedRecord;
+
+pub struct Log;
+
+impl Processor for Log {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn process(&mut self, record: &mut ProcessedRecord) -> Flow {
+        let packet = &record.packet;
+
+        // Log Node 1 (remote sensor) data
+        info!(
+            timestamp_ms = packet.ts,
+            corrected_at_ms = record.time.corrected_at_ms,
+            drift_ppm = record.time.drift_ppm,
+            node_id = %packet.id,
+            n1_temperature = packet.n1.t,
+            n1_humidity = packet.n1.h,
+            n1_gas_resistance = packet.n1.g,
+            rssi = packet.sig.rssi,
+            snr = packet.sig.snr,
+            packets_received = packet.sts.rx,
+            crc_errors = packet.sts.err,
+            rejected_frames = packet.sts.rej,
+            "Processing telemetry packet"
+        );
+
+        // Log Node 2 (gateway local sensor) data if available
+        if packet.n2.t.is_some() || packet.n2.p.is_some() {
+            info!(
+                n2_temperature = ?packet.n2.t,
+                n2_pressure = ?packet.n2.p,
+                "Gateway local sensor (BMP280)"
+            );
+        }
+
+        // Log derived environmental metrics
+        info!(
+            dew_point = ?record.derived.dew_point_c,
+            absolute_humidity = ?record.derived.absolute_humidity_gm3,
+            heat_index = ?record.derived.heat_index_c,
+            altitude_m = ?record.derived.altitude_m,
+            link_margin_db = ?record.derived.link_margin_db,
+            "Derived metrics"
+        );
+
+        info!(
+            iaq = ?record.iaq.iaq,
+            accuracy = ?record.iaq.accuracy,
+            burn_in_pct = record.iaq.burn_in_pct,
+            baseline_ohm = record.iaq.baseline_ohm,
+            "Node 1 air quality (BME680)"
+        );
+
+        Flow::Continue
+    }
+}
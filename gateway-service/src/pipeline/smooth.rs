@@ -0,0 +1,434 @@
+//! `smooth` stage: per-metric outlier rejection and smoothing
+//!
+//! Filters keep a short history per metric and replace the current value:
+//! - `median`: median of the last `window` readings
+//! - `hampel`: readings more than `threshold` scaled MADs from the median of
+//!   the last `window` are rejected and replaced by that median; the scaled
+//!   MAD is floored at `min_deviation` so a steady (quantized) signal doesn't
+//!   reject every change
+//! - `ema`: exponential moving average, `alpha` weighting the newest reading
+//!
+//! The unfiltered value is kept in `record.filtered` together with a
+//! `rejected` flag. History restarts after a node reboot or a gap longer
+//! than `max_gap_secs`.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use tracing::debug;
+
+use super::anomaly::default_min_std;
+use super::{metric, Flow, Processor, DERIVED_METRICS};
+use crate::derived::{DerivedConfig, DerivedMetrics};
+use crate::telemetry::ProcessedRecord;
+
+/// Scales the median absolute deviation to a standard deviation for
+/// normally distributed noise
+const MAD_SCALE: f64 = 1.4826;
+
+/// Largest accepted `window`
+const MAX_WINDOW: usize = 101;
+
+/// One metric's filter
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "filter", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum SmoothingFilter {
+    Median {
+        window: usize,
+    },
+    Hampel {
+        window: usize,
+        /// Rejection threshold in (scaled) MADs
+        #[serde(default = "default_threshold")]
+        threshold: f64,
+        /// Floor for the scaled MAD in native units (default per metric,
+        /// as the anomaly stage's `min_std`)
+        #[serde(default)]
+        min_deviation: Option<f64>,
+    },
+    Ema {
+        alpha: f64,
+    },
+}
+
+fn default_threshold() -> f64 {
+    3.0
+}
+
+impl SmoothingFilter {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Median { .. } => "median",
+            Self::Hampel { .. } => "hampel",
+            Self::Ema { .. } => "ema",
+        }
+    }
+
+    fn validate(self, metric: &str) -> Result<()> {
+        match self {
+            Self::Median { window } if !(1..=MAX_WINDOW).contains(&window) => {
+                bail!("{}: median window must be 1..={}", metric, MAX_WINDOW)
+            }
+            Self::Hampel { window, .. } if !(3..=MAX_WINDOW).contains(&window) => {
+                bail!("{}: hampel window must be 3..={}", metric, MAX_WINDOW)
+            }
+            Self::Hampel { threshold, .. } if !(threshold > 0.0 && threshold.is_finite()) => {
+                bail!("{}: hampel threshold must be positive", metric)
+            }
+            Self::Hampel {
+                min_deviation: Some(floor),
+                ..
+            } if !(floor >= 0.0 && floor.is_finite()) => {
+                bail!("{}: hampel min_deviation must not be negative", metric)
+            }
+            Self::Ema { alpha } if !(alpha > 0.0 && alpha <= 1.0) => {
+                bail!("{}: ema alpha must be in (0, 1]", metric)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmoothConfig {
+    /// Filter per `<node>.<metric>`
+    pub metrics: BTreeMap<String, SmoothingFilter>,
+    /// Start over after this long without a reading (0 = never)
+    pub max_gap_secs: u64,
+}
+
+impl Default for SmoothConfig {
+    fn default() -> Self {
+        Self {
+            metrics: BTreeMap::new(),
+            max_gap_secs: 300,
+        }
+    }
+}
+
+/// Record metadata: a value run through a `smooth` filter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilteredValue {
+    /// Filter that produced the value ("median", "hampel", "ema")
+    pub filter: String,
+    /// The value before filtering
+    pub raw: f64,
+    /// The reading was an outlier and replaced
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rejected: bool,
+}
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut sorted: Vec<f64> = values.collect();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Filter state for one metric
+struct MetricFilter {
+    node: &'static str,
+    name: &'static str,
+    filter: SmoothingFilter,
+    /// Smallest scaled MAD (Hampel)
+    min_deviation: f64,
+    /// Recent raw readings (median/Hampel)
+    history: VecDeque<f64>,
+    /// Current average (EMA)
+    average: Option<f64>,
+}
+
+impl MetricFilter {
+    fn reset(&mut self) {
+        self.history.clear();
+        self.average = None;
+    }
+
+    /// Filtered value for a new raw reading, and whether it was rejected
+    fn update(&mut self, raw: f64) -> (f64, bool) {
+        match self.filter {
+            SmoothingFilter::Median { window } => {
+                self.push(raw, window);
+                (median(self.history.iter().copied()), false)
+            }
+            SmoothingFilter::Hampel {
+                window, threshold, ..
+            } => {
+                self.push(raw, window);
+                // Too little history to tell noise from outliers
+                if self.history.len() < 3 {
+                    return (raw, false);
+                }
+                let m = median(self.history.iter().copied());
+                let mad = median(self.history.iter().map(|v| (v - m).abs()));
+                let deviation = (MAD_SCALE * mad).max(self.min_deviation);
+                if (raw - m).abs() > threshold * deviation {
+                    (m, true)
+                } else {
+                    (raw, false)
+                }
+            }
+            SmoothingFilter::Ema { alpha } => {
+                let average = match self.average {
+                    Some(average) => alpha * raw + (1.0 - alpha) * average,
+                    None => raw,
+                };
+                self.average = Some(average);
+                (average, false)
+            }
+        }
+    }
+
+    fn push(&mut self, raw: f64, window: usize) {
+        if self.history.len() == window {
+            self.history.pop_front();
+        }
+        self.history.push_back(raw);
+    }
+}
+
+/// Rejects outliers and smooths noisy metrics
+///
+/// Sensor readings are filtered first, derived metrics are recomputed from
+/// them, then filters on derived metrics apply. The IAQ estimate has
+/// already seen the unfiltered gas resistance.
+pub struct Smooth {
+    filters: Vec<MetricFilter>,
+    max_gap_ms: u64,
+    last_ms: Option<u64>,
+    derived: DerivedConfig,
+}
+
+impl Smooth {
+    pub fn new(config: &SmoothConfig, derived: &DerivedConfig) -> Result<Self> {
+        let mut filters = Vec::with_capacity(config.metrics.len());
+        for (name, &filter) in &config.metrics {
+            let (node, metric) = metric(name, "smooth")?;
+            filter.validate(name)?;
+            let min_deviation = match filter {
+                SmoothingFilter::Hampel {
+                    min_deviation: Some(floor),
+                    ..
+                } => floor,
+                _ => default_min_std(metric),
+            };
+            filters.push(MetricFilter {
+                node,
+                name: metric,
+                filter,
+                min_deviation,
+                history: VecDeque::new(),
+                average: None,
+            });
+        }
+        Ok(Self {
+            filters,
+            max_gap_ms: config.max_gap_secs * 1000,
+            last_ms: None,
+            derived: derived.clone(),
+        })
+    }
+
+    fn apply(&mut self, record: &mut ProcessedRecord, derived: bool) -> bool {
+        let mut changed = false;
+        for f in &mut self.filters {
+            if DERIVED_METRICS.contains(&(f.node, f.name)) != derived {
+                continue;
+            }
+            let Some(raw) = record.metric(f.node, f.name) else {
+                continue;
+            };
+            let (value, rejected) = f.update(raw);
+            if rejected {
+                debug!(
+                    node = f.node,
+                    metric = f.name,
+                    raw,
+                    value,
+                    "Rejected outlier"
+                );
+            }
+            if value != raw {
+                changed |= record.set_metric(f.node, f.name, value);
+            }
+            record.filtered.insert(
+                format!("{}.{}", f.node, f.name),
+                FilteredValue {
+                    filter: f.filter.name().to_string(),
+                    raw,
+                    rejected,
+                },
+            );
+        }
+        changed
+    }
+}
+
+impl Processor for Smooth {
+    fn name(&self) -> &'static str {
+        "smooth"
+    }
+
+    fn process(&mut self, record: &mut ProcessedRecord) -> Flow {
+        let now_ms = record.timestamp_ms();
+        let gap = self.last_ms.is_some_and(|last| {
+            self.max_gap_ms > 0 && now_ms.saturating_sub(last) > self.max_gap_ms
+        });
+        if gap || record.time.reboot_detected {
+            self.filters.iter_mut().for_each(MetricFilter::reset);
+        }
+        self.last_ms = Some(now_ms);
+
+        if self.apply(record, false) {
+            record.derived = DerivedMetrics::compute(&record.packet, &self.derived);
+        }
+        self.apply(record, true);
+        Flow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+
+    fn stage(filters: &[(&str, SmoothingFilter)]) -> Smooth {
+        let config = SmoothConfig {
+            metrics: filters
+                .iter()
+                .map(|&(name, filter)| (name.to_string(), filter))
+                .collect(),
+            ..SmoothConfig::default()
+        };
+        Smooth::new(&config, &DerivedConfig::default()).unwrap()
+    }
+
+    fn run(stage: &mut Smooth, at_ms: u64, temp: f32) -> ProcessedRecord {
+        let mut r = record(at_ms, temp);
+        stage.process(&mut r);
+        r
+    }
+
+    #[test]
+    fn test_hampel_rejects_spikes() {
+        let hampel = SmoothingFilter::Hampel {
+            window: 5,
+            threshold: 3.0,
+            min_deviation: None,
+        };
+        let mut s = stage(&[("n1.temperature", hampel)]);
+        for (i, t) in [20.0, 20.2, 19.9, 20.1].into_iter().enumerate() {
+            let r = run(&mut s, i as u64 * 10_000, t);
+            assert!(!r.filtered["n1.temperature"].rejected);
+            assert_eq!(r.packet.n1.t, t);
+        }
+
+        let spike = run(&mut s, 40_000, 35.0);
+        assert_eq!(spike.packet.n1.t, 20.1);
+        assert_eq!(spike.filtered["n1.temperature"].raw, 35.0);
+        assert!(spike.filtered["n1.temperature"].rejected);
+        // Derived metrics follow the replaced value
+        let reference = DerivedMetrics::compute(&spike.packet, &DerivedConfig::default());
+        assert_eq!(spike.derived.dew_point_c, reference.dew_point_c);
+
+        let json = serde_json::to_value(&spike).unwrap();
+        assert_eq!(json["filtered"]["n1.temperature"]["filter"], "hampel");
+        assert_eq!(json["filtered"]["n1.temperature"]["rejected"], true);
+    }
+
+    #[test]
+    fn test_median_and_ema() {
+        let mut s = stage(&[
+            ("n1.temperature", SmoothingFilter::Median { window: 3 }),
+            ("n2.pressure", SmoothingFilter::Ema { alpha: 0.5 }),
+        ]);
+        let temps = [20.0, 30.0, 21.0, 22.0];
+        let out: Vec<f32> = temps
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| run(&mut s, i as u64 * 10_000, t).packet.n1.t)
+            .collect();
+        assert_eq!(out, vec![20.0, 25.0, 21.0, 22.0]);
+
+        let mut r = record(40_000, 20.0);
+        r.packet.n2.p = Some(1015.25);
+        s.process(&mut r);
+        // 1013.25 four times, then halfway to the new reading
+        assert_eq!(r.packet.n2.p, Some(1014.25));
+        assert_eq!(r.filtered["n2.pressure"].raw, 1015.25);
+    }
+
+    #[test]
+    fn test_hampel_follows_step_after_steady_window() {
+        let hampel = SmoothingFilter::Hampel {
+            window: 5,
+            threshold: 3.0,
+            min_deviation: None,
+        };
+        let mut s = stage(&[("n1.temperature", hampel)]);
+        // Quantized readings: the MAD is 0
+        for i in 0..5 {
+            run(&mut s, i * 10_000, 20.0);
+        }
+
+        // A one-step change is within the noise floor and passes through
+        // instead of being replaced by the old median
+        let step = run(&mut s, 50_000, 20.1);
+        assert!(!step.filtered["n1.temperature"].rejected);
+        assert_eq!(step.packet.n1.t, 20.1);
+        for i in 6..9 {
+            assert_eq!(run(&mut s, i * 10_000, 20.1).packet.n1.t, 20.1);
+        }
+
+        // Spikes beyond the floor are still rejected
+        let spike = run(&mut s, 90_000, 30.0);
+        assert!(spike.filtered["n1.temperature"].rejected);
+        assert_eq!(spike.packet.n1.t, 20.1);
+    }
+
+    #[test]
+    fn test_history_resets_after_gap() {
+        let mut s = stage(&[("n1.temperature", SmoothingFilter::Ema { alpha: 0.1 })]);
+        run(&mut s, 0, 10.0);
+        assert_eq!(run(&mut s, 10_000, 20.0).packet.n1.t, 11.0);
+        assert_eq!(run(&mut s, 10_000 + 301_000, 20.0).packet.n1.t, 20.0);
+    }
+
+    #[test]
+    fn test_invalid_filters_rejected() {
+        let config = |filter| SmoothConfig {
+            metrics: BTreeMap::from([("n1.temperature".to_string(), filter)]),
+            ..SmoothConfig::default()
+        };
+        for filter in [
+            SmoothingFilter::Median { window: 0 },
+            SmoothingFilter::Hampel {
+                window: 2,
+                threshold: 3.0,
+                min_deviation: None,
+            },
+            SmoothingFilter::Hampel {
+                window: 7,
+                threshold: 0.0,
+                min_deviation: None,
+            },
+            SmoothingFilter::Hampel {
+                window: 7,
+                threshold: 3.0,
+                min_deviation: Some(-0.1),
+            },
+            SmoothingFilter::Ema { alpha: 1.5 },
+        ] {
+            assert!(
+                Smooth::new(&config(filter), &DerivedConfig::default()).is_err(),
+                "{:?}",
+                filter
+            );
+        }
+    }
+}
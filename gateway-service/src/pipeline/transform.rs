@@ -0,0 +1,92 @@
+//! Value transforms: the `round` stage
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::{metric, Flow, Processor};
+use crate::telemetry::{ProcessedRecord, METRIC_NAMES};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoundConfig {
+    /// Decimal places to keep
+    pub decimals: u32,
+    /// `<node>.<metric>` names to round (empty = all)
+    pub metrics: Vec<String>,
+}
+
+impl Default for RoundConfig {
+    fn default() -> Self {
+        Self {
+            decimals: 2,
+            metrics: Vec::new(),
+        }
+    }
+}
+
+pub struct Round {
+    factor: f64,
+    metrics: Vec<(&'static str, &'static str)>,
+}
+
+impl Round {
+    pub fn new(config: &RoundConfig) -> Result<Self> {
+        let metrics = if config.metrics.is_empty() {
+            METRIC_NAMES.to_vec()
+        } else {
+            config
+                .metrics
+                .iter()
+                .map(|name| metric(name, "round"))
+                .collect::<Result<_>>()?
+        };
+        Ok(Self {
+            factor: 10f64.powi(config.decimals.min(9) as i32),
+            metrics,
+        })
+    }
+}
+
+impl Processor for Round {
+    fn name(&self) -> &'static str {
+        "round"
+    }
+
+    fn process(&mut self, record: &mut ProcessedRecord) -> Flow {
+        for &(node, name) in &self.metrics {
+            if let Some(value) = record.metric(node, name) {
+                record.set_metric(node, name, (value * self.factor).round() / self.factor);
+            }
+        }
+        Flow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+
+    #[test]
+    fn test_round_selected_metrics() {
+        let mut stage = Round::new(&RoundConfig {
+            decimals: 1,
+            metrics: vec!["n1.temperature".to_string()],
+        })
+        .unwrap();
+        let mut r = record(0, 21.46);
+        r.packet.n1.h = 45.55;
+        stage.process(&mut r);
+        assert_eq!(r.packet.n1.t, 21.5);
+        assert_eq!(r.packet.n1.h, 45.55);
+
+        let mut all = Round::new(&RoundConfig::default()).unwrap();
+        all.process(&mut r);
+        let dew_point = r.derived.dew_point_c.unwrap().to_string();
+        assert!(
+            dew_point.split('.').nth(1).map_or(0, str::len) <= 2,
+            "{}",
+            dew_point
+        );
+    }
+}
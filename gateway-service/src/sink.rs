@@ -0,0 +1,1009 @@
+//! Sink fan-out with per-sink queues and backpressure
+//!
+//! The processor hands each record to a `SinkRouter`, which fans it out to
+//! every registered `TelemetrySink` through that sink's own bounded queue
+//! and worker task, so a slow sink only backs up its own queue. What happens
+//! when a queue is full is the sink's backpressure policy:
+//!
+//! - `block`: the processor waits for space (lossless, but stalls all sinks)
+//! - `drop-oldest`: the oldest queued record is discarded (the default)
+//! - `drop-newest`: the incoming record is discarded
+//! - `spill-to-disk`: overflow is appended to `<spill_directory>/<sink>.ndjson`
+//!   and fed back in order once the sink catches up; anything still queued
+//!   or spilled at shutdown is delivered on the next start
+//!
+//! Sinks that opt in with `summaries = true` also get the aggregator's
+//! window summaries through the same queue, and `records = false` leaves a
+//! sink with summaries only.
+//!
+//! Records are in °C and hPa. A sink that can carry its own units (webhook,
+//! export) can ask for others with `units`; it gets a converted copy.
+//!
+//! Delivery errors and panics are logged and counted, never propagated to the
+//! processor or other sinks. Per-sink counters are logged periodically and
+//! included in `/api/v1/stats`.
+
+use anyhow::{bail, Context, Result};
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::aggregate::WindowSummary;
+use crate::telemetry::ProcessedRecord;
+use crate::units::UnitsConfig;
+
+/// An output that processed records are delivered to
+pub trait TelemetrySink: Send + Sync + 'static {
+    /// Deliver one record
+    ///
+    /// A sink that hands records on to a writer of its own should wait for
+    /// room there rather than drop them: waiting backs up only this sink's
+    /// queue, where its backpressure policy decides what gives.
+    fn deliver(&self, record: &ProcessedRecord) -> impl Future<Output = Result<()>> + Send;
+
+    /// Whether the sink can take window summaries (`summaries = true`)
+    fn accepts_summaries(&self) -> bool {
+        false
+    }
+
+    /// Whether the sink can take records in other units (`units`), i.e. it
+    /// passes `record.units` on instead of assuming °C/hPa
+    fn accepts_units(&self) -> bool {
+        false
+    }
+
+    /// Deliver one window summary
+    fn deliver_summary(&self, _summary: &WindowSummary) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+}
+
+/// What goes through a sink's queue
+#[derive(Clone)]
+enum Item {
+    Record(Arc<ProcessedRecord>),
+    Summary(Arc<WindowSummary>),
+}
+
+/// Spill file line: a record as-is, a summary wrapped as `{"summary": ...}`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpillLine {
+    Summary { summary: Box<WindowSummary> },
+    Record(Box<ProcessedRecord>),
+}
+
+/// What to do with a record when a sink's queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackpressurePolicy {
+    Block,
+    DropOldest,
+    DropNewest,
+    SpillToDisk,
+}
+
+/// Sink router (`[router]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouterConfig {
+    /// Directory for `spill-to-disk` overflow files
+    pub spill_directory: PathBuf,
+    /// How often per-sink counters are logged (0 = never)
+    pub stats_interval_secs: u64,
+    /// How long shutdown waits for sinks to drain their queues
+    pub shutdown_timeout_secs: u64,
+    /// Per-sink settings, keyed by sink name (`[router.sinks.<name>]`)
+    pub sinks: HashMap<String, SinkConfig>,
+}
+
+impl RouterConfig {
+    /// Reject settings the queues can't work with
+    pub fn validate(&self) -> Result<()> {
+        for (name, sink) in &self.sinks {
+            // A zero-capacity `block` queue never has space: the processor would hang
+            if sink.queue_capacity == 0 {
+                bail!("[router.sinks.{}] queue_capacity must be at least 1", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            spill_directory: PathBuf::from("spill"),
+            stats_interval_secs: 300,
+            shutdown_timeout_secs: 10,
+            sinks: HashMap::new(),
+        }
+    }
+}
+
+/// Queue settings for one sink
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SinkConfig {
+    pub policy: BackpressurePolicy,
+    /// Records buffered in memory
+    pub queue_capacity: usize,
+    /// Spill file size limit; further overflow is dropped (`spill-to-disk` only)
+    pub max_spill_bytes: u64,
+    /// Deliver processed records
+    pub records: bool,
+    /// Deliver `[[aggregate]]` window summaries
+    pub summaries: bool,
+    /// Units for this sink's records (default °C/hPa)
+    pub units: UnitsConfig,
+}
+
+impl Default for SinkConfig {
+    fn default() -> Self {
+        Self {
+            policy: BackpressurePolicy::DropOldest,
+            queue_capacity: 1024,
+            max_spill_bytes: 64 * 1024 * 1024,
+            records: true,
+            summaries: false,
+            units: UnitsConfig::default(),
+        }
+    }
+}
+
+/// Snapshot of one sink's queue and counters
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SinkStats {
+    pub name: String,
+    pub policy: BackpressurePolicy,
+    pub queue_capacity: usize,
+    /// Records waiting in memory
+    pub queued: usize,
+    /// Records waiting in the spill file
+    pub spill_pending: u64,
+    pub delivered: u64,
+    pub failed: u64,
+    pub dropped: u64,
+    /// Records that overflowed to disk (delivered later unless dropped)
+    pub spilled: u64,
+    pub panics: u64,
+    pub last_error: Option<String>,
+}
+
+/// Overflow file for a `spill-to-disk` sink (NDJSON, one record per line)
+///
+/// Appends go to the end and reads follow behind; once the reader catches
+/// up the file is truncated. Writes are single small appends, done inline.
+/// If the sink doesn't drain before shutdown, the file is rewritten with only
+/// the undelivered records, so nothing is sent twice after a restart.
+struct Spill {
+    path: PathBuf,
+    file: File,
+    reader: BufReader<File>,
+    pending: u64,
+    bytes: u64,
+    max_bytes: u64,
+}
+
+impl Spill {
+    /// Open (or resume) the spill file at `path`
+    fn open(path: &Path, max_bytes: u64) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create spill directory {}", dir.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path)
+            .with_context(|| format!("Failed to open spill file {}", path.display()))?;
+        let reader = BufReader::new(File::open(path)?);
+        let bytes = file.metadata()?.len();
+        let pending = count_lines(BufReader::new(File::open(path)?));
+        if pending > 0 {
+            info!(path = %path.display(), records = pending, "Resuming spilled records");
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            reader,
+            pending,
+            bytes,
+            max_bytes,
+        })
+    }
+
+    /// Append an item; `false` if the file is at its size limit
+    fn append(&mut self, item: &Item) -> Result<bool> {
+        let line = spill_line(item)?;
+        if self.bytes + line.len() as u64 > self.max_bytes {
+            return Ok(false);
+        }
+        self.file.write_all(&line)?;
+        self.bytes += line.len() as u64;
+        self.pending += 1;
+        Ok(true)
+    }
+
+    /// Read back up to `max` items in order
+    fn read(&mut self, max: usize) -> Result<Vec<Item>> {
+        let mut records = Vec::new();
+        let mut line = String::new();
+        while self.pending > 0 && records.len() < max {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                // Counted lines are gone (file changed underneath us)
+                self.pending = 0;
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.pending -= 1;
+            match serde_json::from_str(&line) {
+                Ok(SpillLine::Record(record)) => records.push(Item::Record(Arc::from(record))),
+                Ok(SpillLine::Summary { summary }) => {
+                    records.push(Item::Summary(Arc::from(summary)))
+                }
+                Err(e) => {
+                    warn!(path = %self.path.display(), error = %e, "Skipping bad spill record")
+                }
+            }
+        }
+        if self.pending == 0 {
+            self.file.set_len(0)?;
+            self.reader = BufReader::new(File::open(&self.path)?);
+            self.bytes = 0;
+        }
+        Ok(records)
+    }
+
+    /// Replace the file with `front` followed by the unread records
+    ///
+    /// `front` holds items taken off the queue but not delivered, which are
+    /// older than anything still in the file. Returns how many of them didn't
+    /// fit under the size limit.
+    fn rewrite(&mut self, front: &[Item]) -> Result<usize> {
+        let mut rest = Vec::new();
+        self.reader.read_to_end(&mut rest)?;
+        let mut contents = Vec::new();
+        let mut lost = 0;
+        for item in front {
+            let line = spill_line(item)?;
+            if (contents.len() + line.len() + rest.len()) as u64 > self.max_bytes {
+                lost += 1;
+                continue;
+            }
+            contents.extend(line);
+        }
+        contents.extend(rest);
+
+        let tmp = self.path.with_extension("ndjson.tmp");
+        std::fs::write(&tmp, &contents)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+        self.file = OpenOptions::new()
+            .append(true)
+            .read(true)
+            .open(&self.path)?;
+        self.reader = BufReader::new(File::open(&self.path)?);
+        self.pending = count_lines(&contents[..]);
+        self.bytes = contents.len() as u64;
+        Ok(lost)
+    }
+}
+
+/// One spill file line for `item`
+fn spill_line(item: &Item) -> Result<Vec<u8>> {
+    let mut line = match item {
+        Item::Record(record) => serde_json::to_vec(record.as_ref())?,
+        Item::Summary(summary) => {
+            serde_json::to_vec(&serde_json::json!({ "summary": summary.as_ref() }))?
+        }
+    };
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn count_lines(reader: impl BufRead) -> u64 {
+    reader
+        .split(b'\n')
+        .filter(|line| line.as_ref().is_ok_and(|line| !line.is_empty()))
+        .count() as u64
+}
+
+struct QueueState {
+    buf: VecDeque<Item>,
+    spill: Option<Spill>,
+    closed: bool,
+}
+
+impl QueueState {
+    fn spill_pending(&self) -> u64 {
+        self.spill.as_ref().map_or(0, |spill| spill.pending)
+    }
+}
+
+/// One sink's queue and counters, shared by the router and the sink's worker
+struct SinkQueue {
+    name: String,
+    config: SinkConfig,
+    state: Mutex<QueueState>,
+    /// Signalled when records are queued (or the queue closes)
+    items: Notify,
+    /// Signalled when the worker takes a record
+    space: Notify,
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    spilled: AtomicU64,
+    panics: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl SinkQueue {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Queue an item according to the sink's policy
+    async fn push(&self, item: &Item) {
+        loop {
+            {
+                let mut state = self.lock();
+                // Behind spilled records, everything goes to disk to keep order
+                if state.buf.len() < self.config.queue_capacity && state.spill_pending() == 0 {
+                    state.buf.push_back(item.clone());
+                    self.items.notify_one();
+                    return;
+                }
+                match self.config.policy {
+                    BackpressurePolicy::Block => {}
+                    BackpressurePolicy::DropOldest => {
+                        state.buf.pop_front();
+                        state.buf.push_back(item.clone());
+                        Self::count(&self.dropped);
+                        self.items.notify_one();
+                        return;
+                    }
+                    BackpressurePolicy::DropNewest => {
+                        Self::count(&self.dropped);
+                        return;
+                    }
+                    BackpressurePolicy::SpillToDisk => {
+                        let spill = state
+                            .spill
+                            .as_mut()
+                            .expect("spill-to-disk sink has a spill file");
+                        match spill.append(item) {
+                            Ok(true) => Self::count(&self.spilled),
+                            Ok(false) => Self::count(&self.dropped),
+                            Err(e) => {
+                                warn!(sink = %self.name, error = %e, "Failed to spill record");
+                                Self::count(&self.dropped);
+                            }
+                        }
+                        self.items.notify_one();
+                        return;
+                    }
+                }
+            }
+            // Block: wait for the worker to take a record
+            self.space.notified().await;
+        }
+    }
+
+    /// Next item for the worker; `None` once closed and drained
+    async fn pop(&self) -> Option<Item> {
+        loop {
+            {
+                let mut state = self.lock();
+                if let Some(record) = state.buf.pop_front() {
+                    self.space.notify_one();
+                    return Some(record);
+                }
+                if state.spill_pending() > 0 {
+                    let capacity = self.config.queue_capacity.max(1);
+                    let spill = state.spill.as_mut().expect("pending spill");
+                    match spill.read(capacity) {
+                        Ok(records) => state.buf.extend(records),
+                        Err(e) => {
+                            error!(sink = %self.name, error = %e, "Failed to read spill file, discarding it");
+                            spill.pending = 0;
+                        }
+                    }
+                    continue;
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.items.notified().await;
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.items.notify_one();
+    }
+
+    /// Give up on queued records at shutdown (spilled if the policy allows)
+    fn abandon(&self) -> usize {
+        let mut state = self.lock();
+        let records: Vec<_> = state.buf.drain(..).collect();
+        let Some(spill) = state.spill.as_mut() else {
+            return records.len();
+        };
+        // Queued records go ahead of the spilled ones, which are newer
+        match spill.rewrite(&records) {
+            Ok(lost) => lost,
+            Err(e) => {
+                error!(sink = %self.name, error = %e, "Failed to save queued records");
+                records.len()
+            }
+        }
+    }
+
+    fn stats(&self) -> SinkStats {
+        let (queued, spill_pending) = {
+            let state = self.lock();
+            (state.buf.len(), state.spill_pending())
+        };
+        SinkStats {
+            name: self.name.clone(),
+            policy: self.config.policy,
+            queue_capacity: self.config.queue_capacity,
+            queued,
+            spill_pending,
+            delivered: self.delivered.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+            panics: self.panics.load(Ordering::Relaxed),
+            last_error: self
+                .last_error
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        }
+    }
+
+    fn record_error(&self, error: String) {
+        Self::count(&self.failed);
+        *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(error);
+    }
+}
+
+/// Deliver queued items until the queue is closed and drained
+async fn run_worker<S: TelemetrySink>(sink: S, queue: Arc<SinkQueue>) {
+    while let Some(item) = queue.pop().await {
+        let delivery = async {
+            match &item {
+                Item::Record(record) => sink.deliver(record).await,
+                Item::Summary(summary) => sink.deliver_summary(summary).await,
+            }
+        };
+        match AssertUnwindSafe(delivery).catch_unwind().await {
+            Ok(Ok(())) => SinkQueue::count(&queue.delivered),
+            Ok(Err(e)) => {
+                warn!(sink = %queue.name, error = %e, "Sink delivery failed");
+                queue.record_error(e.to_string());
+            }
+            Err(_) => {
+                error!(sink = %queue.name, "Sink panicked delivering a record");
+                SinkQueue::count(&queue.panics);
+                queue.record_error("panicked".to_string());
+            }
+        }
+    }
+}
+
+/// Read-only view of every sink's counters (for the API)
+#[derive(Clone, Default)]
+pub struct SinkRegistry {
+    queues: Arc<RwLock<Vec<Arc<SinkQueue>>>>,
+}
+
+impl SinkRegistry {
+    pub fn stats(&self) -> Vec<SinkStats> {
+        self.queues
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|queue| queue.stats())
+            .collect()
+    }
+}
+
+/// Fans processed records out to the registered sinks
+pub struct SinkRouter {
+    config: RouterConfig,
+    registry: SinkRegistry,
+    queues: Vec<Arc<SinkQueue>>,
+    workers: Vec<JoinHandle<()>>,
+    stats_task: Option<JoinHandle<()>>,
+}
+
+impl SinkRouter {
+    pub fn new(config: &RouterConfig) -> Self {
+        let registry = SinkRegistry::default();
+        let stats_task = (config.stats_interval_secs > 0).then(|| {
+            let registry = registry.clone();
+            let interval = Duration::from_secs(config.stats_interval_secs);
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    for s in registry.stats() {
+                        info!(
+                            sink = %s.name,
+                            queued = s.queued,
+                            spill_pending = s.spill_pending,
+                            delivered = s.delivered,
+                            failed = s.failed,
+                            dropped = s.dropped,
+                            spilled = s.spilled,
+                            panics = s.panics,
+                            "Sink stats"
+                        );
+                    }
+                }
+            })
+        });
+        Self {
+            config: config.clone(),
+            registry,
+            queues: Vec::new(),
+            workers: Vec::new(),
+            stats_task,
+        }
+    }
+
+    /// Counters for every sink, including ones added later
+    pub fn registry(&self) -> SinkRegistry {
+        self.registry.clone()
+    }
+
+    /// Register a sink under `name` (its key in `[router.sinks]`)
+    pub fn add<S: TelemetrySink>(&mut self, name: &str, sink: S) -> Result<()> {
+        let config = self.config.sinks.get(name).cloned().unwrap_or_default();
+        if config.summaries && !sink.accepts_summaries() {
+            bail!("Sink '{}' can't deliver window summaries", name);
+        }
+        if !config.units.is_native() {
+            if !sink.accepts_units() {
+                bail!("Sink '{}' only takes records in °C and hPa", name);
+            }
+            // Summaries carry no units and would stay in °C/hPa
+            if config.summaries {
+                bail!("Sink '{}' can't use `units` with summaries = true", name);
+            }
+        }
+        let spill = match config.policy {
+            BackpressurePolicy::SpillToDisk => Some(Spill::open(
+                &self.config.spill_directory.join(format!("{}.ndjson", name)),
+                config.max_spill_bytes,
+            )?),
+            _ => None,
+        };
+        info!(
+            sink = name,
+            policy = ?config.policy,
+            queue_capacity = config.queue_capacity,
+            records = config.records,
+            summaries = config.summaries,
+            "Sink registered"
+        );
+
+        let queue = Arc::new(SinkQueue {
+            name: name.to_string(),
+            config,
+            state: Mutex::new(QueueState {
+                buf: VecDeque::new(),
+                spill,
+                closed: false,
+            }),
+            items: Notify::new(),
+            space: Notify::new(),
+            delivered: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            spilled: AtomicU64::new(0),
+            panics: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        });
+        // Leftovers from a previous run go out first
+        if queue.lock().spill_pending() > 0 {
+            queue.items.notify_one();
+        }
+        self.registry
+            .queues
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(queue.clone());
+        self.workers
+            .push(tokio::spawn(run_worker(sink, queue.clone())));
+        self.queues.push(queue);
+        Ok(())
+    }
+
+    /// Warn about `[router.sinks]` entries that match no registered sink
+    pub fn check_config(&self) {
+        for name in self.config.sinks.keys() {
+            if !self.queues.iter().any(|queue| &queue.name == name) {
+                warn!(sink = %name, "[router.sinks] entry matches no enabled sink");
+            }
+        }
+    }
+
+    /// Hand a record to every sink (waits only on `block` sinks that are full)
+    pub async fn publish(&self, record: ProcessedRecord) {
+        let native = Item::Record(Arc::new(record.clone()));
+        for queue in self.queues.iter().filter(|queue| queue.config.records) {
+            if queue.config.units.is_native() {
+                queue.push(&native).await;
+            } else {
+                let converted = Item::Record(Arc::new(queue.config.units.convert(&record)));
+                queue.push(&converted).await;
+            }
+        }
+    }
+
+    /// Hand a window summary to the sinks with `summaries = true`
+    pub async fn publish_summary(&self, summary: WindowSummary) {
+        let summary = Item::Summary(Arc::new(summary));
+        for queue in self.queues.iter().filter(|queue| queue.config.summaries) {
+            queue.push(&summary).await;
+        }
+    }
+
+    /// Let sinks drain their queues, up to `shutdown_timeout_secs`
+    pub async fn shutdown(self) {
+        for queue in &self.queues {
+            queue.close();
+        }
+        let deadline =
+            tokio::time::Instant::now() + Duration::from_secs(self.config.shutdown_timeout_secs);
+        for (queue, mut worker) in self.queues.iter().zip(self.workers) {
+            if tokio::time::timeout_at(deadline, &mut worker)
+                .await
+                .is_err()
+            {
+                worker.abort();
+                let lost = queue.abandon();
+                warn!(sink = %queue.name, lost, "Sink did not drain before shutdown");
+            }
+        }
+        if let Some(task) = self.stats_task {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+    use tokio::sync::Semaphore;
+
+    /// Records delivered, gated by a semaphore so tests can stall the sink
+    #[derive(Clone)]
+    struct Gated {
+        gate: Arc<Semaphore>,
+        seen: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Gated {
+        fn new(open: bool) -> Self {
+            let permits = if open { Semaphore::MAX_PERMITS } else { 0 };
+            Self {
+                gate: Arc::new(Semaphore::new(permits)),
+                seen: Arc::default(),
+            }
+        }
+
+        fn seen(&self) -> Vec<u64> {
+            self.seen.lock().unwrap().clone()
+        }
+    }
+
+    impl TelemetrySink for Gated {
+        async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+            self.gate.acquire().await?.forget();
+            self.seen.lock().unwrap().push(record.timestamp_ms());
+            Ok(())
+        }
+
+        fn accepts_summaries(&self) -> bool {
+            true
+        }
+
+        async fn deliver_summary(&self, summary: &WindowSummary) -> Result<()> {
+            self.gate.acquire().await?.forget();
+            self.seen.lock().unwrap().push(summary.end_ms);
+            Ok(())
+        }
+    }
+
+    struct Faulty;
+
+    impl TelemetrySink for Faulty {
+        async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+            match record.timestamp_ms() {
+                1 => anyhow::bail!("sink offline"),
+                2 => panic!("sink bug"),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn config(policy: BackpressurePolicy, capacity: usize, spill_dir: &Path) -> RouterConfig {
+        let sink = SinkConfig {
+            policy,
+            queue_capacity: capacity,
+            ..SinkConfig::default()
+        };
+        RouterConfig {
+            spill_directory: spill_dir.to_path_buf(),
+            stats_interval_secs: 0,
+            shutdown_timeout_secs: 5,
+            sinks: HashMap::from([("slow".to_string(), sink)]),
+        }
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sink-{}-{}", name, std::process::id()))
+    }
+
+    /// Publish records 1..=n to a stalled sink, then let it drain
+    async fn run_stalled(policy: BackpressurePolicy, n: u64, dir: &Path) -> (Vec<u64>, SinkStats) {
+        let mut router = SinkRouter::new(&config(policy, 2, dir));
+        let slow = Gated::new(false);
+        let fast = Gated::new(true);
+        router.add("slow", slow.clone()).unwrap();
+        router.add("fast", fast.clone()).unwrap();
+        for at_ms in 1..=n {
+            router.publish(record(at_ms, 20.0)).await;
+        }
+        tokio::task::yield_now().await;
+
+        // A stalled sink never holds up the others
+        assert_eq!(fast.seen(), (1..=n).collect::<Vec<_>>());
+
+        slow.gate.add_permits(Semaphore::MAX_PERMITS / 2);
+        let registry = router.registry();
+        router.shutdown().await;
+        let stats = registry.stats().remove(0);
+        (slow.seen(), stats)
+    }
+
+    #[tokio::test]
+    async fn test_drop_policies() {
+        let dir = scratch("drop");
+        // All six are published before the worker first runs; two fit
+        let (seen, stats) = run_stalled(BackpressurePolicy::DropOldest, 6, &dir).await;
+        assert_eq!(seen, vec![5, 6]);
+        assert_eq!(stats.dropped, 4);
+        assert_eq!(stats.delivered, 2);
+
+        let (seen, stats) = run_stalled(BackpressurePolicy::DropNewest, 6, &dir).await;
+        assert_eq!(seen, vec![1, 2]);
+        assert_eq!(stats.dropped, 4);
+    }
+
+    #[tokio::test]
+    async fn test_spill_to_disk_keeps_order() {
+        let dir = scratch("spill");
+        let (seen, stats) = run_stalled(BackpressurePolicy::SpillToDisk, 8, &dir).await;
+        assert_eq!(seen, (1..=8).collect::<Vec<_>>());
+        assert_eq!(stats.spilled, 6);
+        assert_eq!(stats.dropped, 0);
+        assert_eq!(stats.spill_pending, 0);
+        assert_eq!(std::fs::metadata(dir.join("slow.ndjson")).unwrap().len(), 0);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    fn summary(end_ms: u64) -> WindowSummary {
+        WindowSummary {
+            aggregate: "test".to_string(),
+            start_ms: end_ms - 1,
+            end_ms,
+            records: 0,
+            expected_records: 0,
+            packet_loss_pct: 0.0,
+            crc_errors: 0,
+            late_records: 0,
+            partial: false,
+            nodes: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summaries_only_reach_opted_in_sinks() {
+        let dir = scratch("summary");
+        let mut config = config(BackpressurePolicy::SpillToDisk, 1, &dir);
+        let slow = config.sinks.get_mut("slow").unwrap();
+        slow.summaries = true;
+        slow.records = false;
+        config.sinks.insert(
+            "faulty".to_string(),
+            SinkConfig {
+                summaries: true,
+                ..SinkConfig::default()
+            },
+        );
+        let mut router = SinkRouter::new(&config);
+        assert!(router.add("faulty", Faulty).is_err());
+
+        let slow = Gated::new(false);
+        let fast = Gated::new(true);
+        router.add("slow", slow.clone()).unwrap();
+        router.add("fast", fast.clone()).unwrap();
+        router.publish(record(1, 20.0)).await;
+        // Overflow summaries go through the spill file and come back intact
+        for end_ms in [10, 20, 30] {
+            router.publish_summary(summary(end_ms)).await;
+        }
+        router.publish(record(2, 20.0)).await;
+        slow.gate.add_permits(10);
+        router.shutdown().await;
+
+        assert_eq!(slow.seen(), vec![10, 20, 30]);
+        assert_eq!(fast.seen(), vec![1, 2]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// node1 temperatures delivered, in whatever units the sink asked for
+    #[derive(Clone, Default)]
+    struct Temperatures(Arc<Mutex<Vec<f32>>>);
+
+    impl TelemetrySink for Temperatures {
+        async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+            self.0.lock().unwrap().push(record.packet.n1.t);
+            Ok(())
+        }
+
+        fn accepts_units(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_units_convert_per_sink() {
+        let units: UnitsConfig = toml::from_str("temperature = \"fahrenheit\"").unwrap();
+        let mut config = config(BackpressurePolicy::Block, 4, &scratch("units"));
+        config.sinks.insert(
+            "fahrenheit".to_string(),
+            SinkConfig {
+                units: units.clone(),
+                ..SinkConfig::default()
+            },
+        );
+        config.sinks.insert(
+            "faulty".to_string(),
+            SinkConfig {
+                units,
+                ..SinkConfig::default()
+            },
+        );
+        let mut router = SinkRouter::new(&config);
+        assert!(router.add("faulty", Faulty).is_err());
+
+        let fahrenheit = Temperatures::default();
+        let native = Temperatures::default();
+        router.add("fahrenheit", fahrenheit.clone()).unwrap();
+        router.add("native", native.clone()).unwrap();
+        router.publish(record(1, 100.0)).await;
+        router.shutdown().await;
+
+        assert_eq!(*fahrenheit.0.lock().unwrap(), vec![212.0]);
+        assert_eq!(*native.0.lock().unwrap(), vec![100.0]);
+    }
+
+    #[tokio::test]
+    async fn test_spill_survives_shutdown_timeout() {
+        let dir = scratch("resume");
+        let mut config = config(BackpressurePolicy::SpillToDisk, 2, &dir);
+        config.shutdown_timeout_secs = 0;
+        let mut router = SinkRouter::new(&config);
+        let registry = router.registry();
+        let slow = Gated::new(false);
+        router.add("slow", slow.clone()).unwrap();
+        for at_ms in 1..=8 {
+            router.publish(record(at_ms, 20.0)).await;
+        }
+
+        // Deliver 1 and 2 from memory; 3 and 4 come back from the file and
+        // the worker stalls on 3, leaving 4 queued and 5..=8 spilled
+        slow.gate.add_permits(2);
+        loop {
+            let stats = registry.stats().remove(0);
+            if (stats.queued, stats.spill_pending) == (1, 4) {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(slow.seen(), vec![1, 2]);
+        router.shutdown().await;
+
+        // 3 was in flight; the rest resumes in order, nothing twice
+        config.shutdown_timeout_secs = 5;
+        let mut router = SinkRouter::new(&config);
+        let resumed = Gated::new(true);
+        router.add("slow", resumed.clone()).unwrap();
+        router.shutdown().await;
+        assert_eq!(resumed.seen(), vec![4, 5, 6, 7, 8]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_zero_capacity_rejected() {
+        let dir = scratch("zero");
+        assert!(config(BackpressurePolicy::Block, 0, &dir)
+            .validate()
+            .is_err());
+        assert!(config(BackpressurePolicy::Block, 1, &dir)
+            .validate()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_space() {
+        let dir = scratch("block");
+        let mut router = SinkRouter::new(&config(BackpressurePolicy::Block, 1, &dir));
+        let slow = Gated::new(false);
+        router.add("slow", slow.clone()).unwrap();
+        router.publish(record(1, 20.0)).await;
+        router.publish(record(2, 20.0)).await;
+
+        // Queue full and the worker stalled: the third publish must wait
+        let mut third = Box::pin(router.publish(record(3, 20.0)));
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut third)
+            .await
+            .is_err());
+        slow.gate.add_permits(10);
+        third.await;
+        router.shutdown().await;
+        assert_eq!(slow.seen(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_errors_and_panics_are_isolated() {
+        let mut router = SinkRouter::new(&RouterConfig {
+            stats_interval_secs: 0,
+            ..RouterConfig::default()
+        });
+        let healthy = Gated::new(true);
+        router.add("faulty", Faulty).unwrap();
+        router.add("healthy", healthy.clone()).unwrap();
+        for at_ms in 1..=3 {
+            router.publish(record(at_ms, 20.0)).await;
+        }
+        let registry = router.registry();
+        router.shutdown().await;
+
+        let stats = registry.stats();
+        assert_eq!(stats[0].delivered, 1);
+        assert_eq!(stats[0].failed, 2);
+        assert_eq!(stats[0].panics, 1);
+        assert_eq!(healthy.seen(), vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,789 @@
+//! SQLite telemetry history
+//!
+//! Normalized schema:
+//! - `reports`: one row per processed packet (wall-clock and device time)
+//! - `readings`: one row per (report, node, metric) value
+//! - `link_stats`: RSSI/SNR and node2's packet counters per report
+//! - `calibrations`: raw sensor readings behind calibrated `readings`
+//! - `rollups`: min/max/sum/count per 1-minute and 1-hour bucket, maintained
+//!   incrementally on insert so they survive raw-data retention
+//!
+//! Migrations are plain SQL applied in order against `PRAGMA user_version`.
+//! Writes happen on a blocking thread fed by a bounded queue so the async
+//! processor never waits on disk.
+
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::clock::unix_time_ms;
+use crate::sink::TelemetrySink;
+use crate::telemetry::ProcessedRecord;
+
+/// Rollup bucket sizes in seconds (1 minute, 1 hour)
+pub const ROLLUP_RESOLUTIONS_S: [u32; 2] = [60, 3600];
+
+const MS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// Schema migrations; entry N upgrades `user_version` N to N+1. Append only.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    r#"
+    CREATE TABLE reports (
+        id INTEGER PRIMARY KEY,
+        gateway_id TEXT NOT NULL,
+        ts_ms INTEGER NOT NULL,
+        received_at_ms INTEGER NOT NULL,
+        device_ts_ms INTEGER NOT NULL,
+        boot_epoch INTEGER NOT NULL
+    );
+    CREATE INDEX reports_ts ON reports (ts_ms);
+
+    CREATE TABLE readings (
+        report_id INTEGER NOT NULL REFERENCES reports (id) ON DELETE CASCADE,
+        node TEXT NOT NULL,
+        metric TEXT NOT NULL,
+        value REAL NOT NULL,
+        PRIMARY KEY (report_id, node, metric)
+    ) WITHOUT ROWID;
+    CREATE INDEX readings_node_metric ON readings (node, metric);
+
+    CREATE TABLE link_stats (
+        report_id INTEGER PRIMARY KEY REFERENCES reports (id) ON DELETE CASCADE,
+        rssi INTEGER NOT NULL,
+        snr INTEGER NOT NULL,
+        packets_received INTEGER NOT NULL,
+        crc_errors INTEGER NOT NULL
+    );
+
+    CREATE TABLE rollups (
+        resolution_s INTEGER NOT NULL,
+        bucket_start_ms INTEGER NOT NULL,
+        node TEXT NOT NULL,
+        metric TEXT NOT NULL,
+        min REAL NOT NULL,
+        max REAL NOT NULL,
+        sum REAL NOT NULL,
+        count INTEGER NOT NULL,
+        PRIMARY KEY (resolution_s, node, metric, bucket_start_ms)
+    ) WITHOUT ROWID;
+    "#,
+    // 2: calibration metadata
+    r#"
+    CREATE TABLE calibrations (
+        report_id INTEGER NOT NULL REFERENCES reports (id) ON DELETE CASCADE,
+        node TEXT NOT NULL,
+        metric TEXT NOT NULL,
+        sensor TEXT NOT NULL,
+        raw REAL NOT NULL,
+        source TEXT NOT NULL,
+        PRIMARY KEY (report_id, node, metric)
+    ) WITHOUT ROWID;
+    "#,
+    // 3: frames node2 refused, apart from CRC errors
+    r#"
+    ALTER TABLE link_stats ADD COLUMN rejected_frames INTEGER NOT NULL DEFAULT 0;
+    "#,
+];
+
+/// Storage configuration (`[storage]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// SQLite database file
+    pub path: PathBuf,
+    /// Days of raw reports to keep (0 keeps everything)
+    pub raw_retention_days: u64,
+    /// Days of 1-minute rollups to keep (0 keeps everything)
+    pub minute_rollup_retention_days: u64,
+    /// Days of 1-hour rollups to keep (0 keeps everything)
+    pub hour_rollup_retention_days: u64,
+    /// How often retention is enforced
+    pub prune_interval_secs: u64,
+    /// Records buffered for the writer; delivery waits for room when full
+    pub queue_capacity: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("telemetry.db"),
+            raw_retention_days: 7,
+            minute_rollup_retention_days: 90,
+            hour_rollup_retention_days: 0,
+            prune_interval_secs: 3600,
+            queue_capacity: 1000,
+        }
+    }
+}
+
+/// Rows removed by one retention pass
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PruneStats {
+    pub reports: usize,
+    pub rollups: usize,
+}
+
+/// Most recent value of one metric
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatestReading {
+    pub node: String,
+    pub metric: String,
+    pub value: f64,
+    /// Record time, Unix ms
+    pub ts_ms: i64,
+}
+
+/// Time-range query for one metric
+#[derive(Debug, Clone)]
+pub struct HistoryQuery<'a> {
+    pub node: &'a str,
+    pub metric: &'a str,
+    /// Inclusive start, Unix ms
+    pub from_ms: u64,
+    /// Exclusive end, Unix ms
+    pub to_ms: u64,
+    /// Aggregate into buckets of this many seconds (`None` returns raw readings)
+    pub bucket_s: Option<u32>,
+}
+
+/// One history point: a bucket aggregate, or a raw reading as a bucket of one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryPoint {
+    /// Reading time or bucket start, Unix ms
+    pub ts_ms: i64,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+    pub count: i64,
+}
+
+/// Link quality and node2's packet counters for one report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LinkSample {
+    pub ts_ms: i64,
+    pub rssi: i64,
+    pub snr: i64,
+    pub packets_received: i64,
+    pub crc_errors: i64,
+    pub rejected_frames: i64,
+}
+
+/// What the database currently holds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StorageSummary {
+    pub reports: i64,
+    pub first_report_ms: Option<i64>,
+    pub last_report_ms: Option<i64>,
+    pub rollup_buckets: i64,
+    pub last_link: Option<LinkSample>,
+}
+
+/// Result window for paginated queries
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    pub limit: u32,
+    pub offset: u64,
+}
+
+/// Telemetry database
+pub struct Storage {
+    conn: Connection,
+}
+
+impl Storage {
+    /// Open (or create) the database and bring its schema up to date
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open database {}", path.display()))?;
+        // WAL lets readers (API queries) run alongside the writer
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        let mut storage = Self { conn };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    /// Apply pending schema migrations
+    fn migrate(&mut self) -> Result<()> {
+        let version: usize = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        if version > MIGRATIONS.len() {
+            anyhow::bail!(
+                "Database schema version {} is newer than this gateway supports ({})",
+                version,
+                MIGRATIONS.len()
+            );
+        }
+
+        for (index, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(sql)
+                .with_context(|| format!("Schema migration {} failed", index + 1))?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+            info!(version = index + 1, "Applied database migration");
+        }
+        Ok(())
+    }
+
+    /// Store a processed record and fold it into the rollups
+    pub fn insert(&mut self, record: &ProcessedRecord) -> Result<i64> {
+        let tx = self.conn.transaction()?;
+        let packet = &record.packet;
+        let ts_ms = record.timestamp_ms() as i64;
+
+        tx.execute(
+            "INSERT INTO reports (gateway_id, ts_ms, received_at_ms, device_ts_ms, boot_epoch)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                packet.id,
+                ts_ms,
+                record.time.received_at_ms as i64,
+                packet.ts,
+                record.time.boot_epoch,
+            ],
+        )?;
+        let report_id = tx.last_insert_rowid();
+
+        tx.execute(
+            "INSERT INTO link_stats
+                 (report_id, rssi, snr, packets_received, crc_errors, rejected_frames)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                report_id,
+                packet.sig.rssi,
+                packet.sig.snr,
+                packet.sts.rx,
+                packet.sts.err,
+                packet.sts.rej
+            ],
+        )?;
+
+        for metric in record.metrics() {
+            tx.execute(
+                "INSERT INTO readings (report_id, node, metric, value) VALUES (?1, ?2, ?3, ?4)",
+                params![report_id, metric.node, metric.name, metric.value],
+            )?;
+            update_rollups(&tx, ts_ms, metric.node, metric.name, metric.value)?;
+        }
+
+        for (name, applied) in &record.calibration {
+            let Some((node, metric)) = name.split_once('.') else {
+                continue;
+            };
+            tx.execute(
+                "INSERT INTO calibrations (report_id, node, metric, sensor, raw, source)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    report_id,
+                    node,
+                    metric,
+                    applied.sensor,
+                    applied.raw,
+                    applied.source.as_str()
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(report_id)
+    }
+
+    /// Enforce retention relative to `now_ms`
+    pub fn prune(&mut self, now_ms: u64, config: &StorageConfig) -> Result<PruneStats> {
+        let cutoff = |days: u64| (now_ms.saturating_sub(days * MS_PER_DAY)) as i64;
+        let mut stats = PruneStats::default();
+
+        if config.raw_retention_days > 0 {
+            // readings and link_stats follow via ON DELETE CASCADE
+            stats.reports = self.conn.execute(
+                "DELETE FROM reports WHERE ts_ms < ?1",
+                params![cutoff(config.raw_retention_days)],
+            )?;
+        }
+
+        for (resolution_s, days) in [
+            (ROLLUP_RESOLUTIONS_S[0], config.minute_rollup_retention_days),
+            (ROLLUP_RESOLUTIONS_S[1], config.hour_rollup_retention_days),
+        ] {
+            if days > 0 {
+                stats.rollups += self.conn.execute(
+                    "DELETE FROM rollups WHERE resolution_s = ?1 AND bucket_start_ms < ?2",
+                    params![resolution_s, cutoff(days)],
+                )?;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Latest value of every metric, per node
+    pub fn latest(&self) -> Result<Vec<LatestReading>> {
+        let mut stmt = self.conn.prepare_cached(
+            "WITH latest AS (
+                 SELECT node, MAX(report_id) AS report_id FROM readings GROUP BY node
+             )
+             SELECT rd.node, rd.metric, rd.value, rp.ts_ms
+             FROM latest
+             JOIN readings rd ON rd.report_id = latest.report_id AND rd.node = latest.node
+             JOIN reports rp ON rp.id = rd.report_id
+             ORDER BY rd.node, rd.metric",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(LatestReading {
+                node: row.get(0)?,
+                metric: row.get(1)?,
+                value: row.get(2)?,
+                ts_ms: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Readings or bucket aggregates for one metric, oldest first
+    ///
+    /// Buckets that are a whole number of minutes are served from the
+    /// rollups (so they outlive raw retention) and cover rollup buckets
+    /// starting inside the range; other bucket sizes aggregate raw readings.
+    pub fn history(&self, query: &HistoryQuery, page: Page) -> Result<Vec<HistoryPoint>> {
+        let from = query.from_ms as i64;
+        let to = query.to_ms as i64;
+        let map_point = |row: &rusqlite::Row| {
+            Ok(HistoryPoint {
+                ts_ms: row.get(0)?,
+                avg: row.get(1)?,
+                min: row.get(2)?,
+                max: row.get(3)?,
+                count: row.get(4)?,
+            })
+        };
+
+        let points = match query.bucket_s {
+            None => {
+                let mut stmt = self.conn.prepare_cached(
+                    "SELECT rp.ts_ms, rd.value, rd.value, rd.value, 1
+                     FROM readings rd JOIN reports rp ON rp.id = rd.report_id
+                     WHERE rd.node = ?1 AND rd.metric = ?2 AND rp.ts_ms >= ?3 AND rp.ts_ms < ?4
+                     ORDER BY rp.ts_ms
+                     LIMIT ?5 OFFSET ?6",
+                )?;
+                let rows = stmt.query_map(
+                    params![query.node, query.metric, from, to, page.limit, page.offset],
+                    map_point,
+                )?;
+                rows.collect::<rusqlite::Result<_>>()?
+            }
+            Some(bucket_s) => {
+                let bucket_ms = bucket_s as i64 * 1000;
+                let resolution = ROLLUP_RESOLUTIONS_S
+                    .into_iter()
+                    .rev()
+                    .find(|resolution| bucket_s % resolution == 0);
+
+                match resolution {
+                    Some(resolution_s) => {
+                        let mut stmt = self.conn.prepare_cached(
+                            "SELECT bucket_start_ms - bucket_start_ms % ?1 AS bucket,
+                                    SUM(sum) / SUM(count), MIN(min), MAX(max), SUM(count)
+                             FROM rollups
+                             WHERE resolution_s = ?2 AND node = ?3 AND metric = ?4
+                               AND bucket_start_ms >= ?5 AND bucket_start_ms < ?6
+                             GROUP BY bucket ORDER BY bucket
+                             LIMIT ?7 OFFSET ?8",
+                        )?;
+                        let rows = stmt.query_map(
+                            params![
+                                bucket_ms,
+                                resolution_s,
+                                query.node,
+                                query.metric,
+                                from,
+                                to,
+                                page.limit,
+                                page.offset
+                            ],
+                            map_point,
+                        )?;
+                        rows.collect::<rusqlite::Result<_>>()?
+                    }
+                    None => {
+                        let mut stmt = self.conn.prepare_cached(
+                            "SELECT rp.ts_ms - rp.ts_ms % ?1 AS bucket,
+                                    AVG(rd.value), MIN(rd.value), MAX(rd.value), COUNT(*)
+                             FROM readings rd JOIN reports rp ON rp.id = rd.report_id
+                             WHERE rd.node = ?2 AND rd.metric = ?3
+                               AND rp.ts_ms >= ?4 AND rp.ts_ms < ?5
+                             GROUP BY bucket ORDER BY bucket
+                             LIMIT ?6 OFFSET ?7",
+                        )?;
+                        let rows = stmt.query_map(
+                            params![
+                                bucket_ms,
+                                query.node,
+                                query.metric,
+                                from,
+                                to,
+                                page.limit,
+                                page.offset
+                            ],
+                            map_point,
+                        )?;
+                        rows.collect::<rusqlite::Result<_>>()?
+                    }
+                }
+            }
+        };
+        Ok(points)
+    }
+
+    /// Link quality history in `[from_ms, to_ms)`, oldest first
+    pub fn link_history(&self, from_ms: u64, to_ms: u64, page: Page) -> Result<Vec<LinkSample>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT rp.ts_ms, ls.rssi, ls.snr, ls.packets_received, ls.crc_errors,
+                    ls.rejected_frames
+             FROM link_stats ls JOIN reports rp ON rp.id = ls.report_id
+             WHERE rp.ts_ms >= ?1 AND rp.ts_ms < ?2
+             ORDER BY rp.ts_ms
+             LIMIT ?3 OFFSET ?4",
+        )?;
+        let rows = stmt.query_map(
+            params![from_ms as i64, to_ms as i64, page.limit, page.offset],
+            link_sample,
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Row counts and time span of the stored data
+    pub fn summary(&self) -> Result<StorageSummary> {
+        let (reports, first_report_ms, last_report_ms) = self.conn.query_row(
+            "SELECT COUNT(*), MIN(ts_ms), MAX(ts_ms) FROM reports",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let rollup_buckets = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM rollups", [], |row| row.get(0))?;
+        let last_link = self
+            .conn
+            .query_row(
+                "SELECT rp.ts_ms, ls.rssi, ls.snr, ls.packets_received, ls.crc_errors,
+                        ls.rejected_frames
+                 FROM link_stats ls JOIN reports rp ON rp.id = ls.report_id
+                 ORDER BY ls.report_id DESC LIMIT 1",
+                [],
+                link_sample,
+            )
+            .optional()?;
+
+        Ok(StorageSummary {
+            reports,
+            first_report_ms,
+            last_report_ms,
+            rollup_buckets,
+            last_link,
+        })
+    }
+}
+
+fn link_sample(row: &rusqlite::Row) -> rusqlite::Result<LinkSample> {
+    Ok(LinkSample {
+        ts_ms: row.get(0)?,
+        rssi: row.get(1)?,
+        snr: row.get(2)?,
+        packets_received: row.get(3)?,
+        crc_errors: row.get(4)?,
+        rejected_frames: row.get(5)?,
+    })
+}
+
+fn update_rollups(
+    tx: &Transaction,
+    ts_ms: i64,
+    node: &str,
+    metric: &str,
+    value: f64,
+) -> Result<()> {
+    for resolution_s in ROLLUP_RESOLUTIONS_S {
+        let bucket_ms = resolution_s as i64 * 1000;
+        let bucket_start_ms = ts_ms - ts_ms.rem_euclid(bucket_ms);
+        tx.execute(
+            "INSERT INTO rollups (resolution_s, bucket_start_ms, node, metric, min, max, sum, count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?5, 1)
+             ON CONFLICT (resolution_s, node, metric, bucket_start_ms) DO UPDATE SET
+                 min = min(min, excluded.min),
+                 max = max(max, excluded.max),
+                 sum = sum + excluded.sum,
+                 count = count + 1",
+            params![resolution_s, bucket_start_ms, node, metric, value],
+        )?;
+    }
+    Ok(())
+}
+
+/// Handle for queueing records to the storage writer
+#[derive(Clone)]
+pub struct StorageHandle {
+    tx: mpsc::Sender<ProcessedRecord>,
+}
+
+impl TelemetrySink for StorageHandle {
+    /// Queues the record for the database writer thread
+    async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+        self.tx
+            .send(record.clone())
+            .await
+            .map_err(|_| anyhow!("Storage writer stopped"))
+    }
+}
+
+/// Open the database and start the writer thread
+///
+/// The writer drains its queue and exits once every `StorageHandle` is dropped,
+/// so awaiting the returned task flushes pending records.
+pub fn spawn_writer(config: &StorageConfig) -> Result<(StorageHandle, JoinHandle<()>)> {
+    let mut storage = Storage::open(&config.path)?;
+    info!(path = %config.path.display(), "Telemetry database opened");
+
+    let (tx, mut rx) = mpsc::channel::<ProcessedRecord>(config.queue_capacity.max(1));
+    let config = config.clone();
+    let prune_interval = Duration::from_secs(config.prune_interval_secs.max(1));
+
+    let task = tokio::task::spawn_blocking(move || {
+        let mut last_prune: Option<Instant> = None;
+
+        while let Some(record) = rx.blocking_recv() {
+            if let Err(e) = storage.insert(&record) {
+                error!(error = %e, "Failed to store telemetry record");
+            }
+
+            if last_prune.is_none_or(|at| at.elapsed() >= prune_interval) {
+                match storage.prune(unix_time_ms(), &config) {
+                    Ok(stats) => debug!(?stats, "Retention enforced"),
+                    Err(e) => error!(error = %e, "Failed to enforce retention"),
+                }
+                last_prune = Some(Instant::now());
+            }
+        }
+
+        info!("Storage writer stopped");
+    });
+
+    Ok((StorageHandle { tx }, task))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::{AppliedCalibration, ProfileSource};
+    use crate::telemetry::test_support::record;
+
+    const T0: u64 = 1_767_225_600_000; // 2026-01-01T00:00:00Z, on a 1 h boundary
+
+    fn count(storage: &Storage, sql: &str) -> i64 {
+        storage.conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_migrations_are_idempotent() {
+        let dir = std::env::temp_dir().join(format!("storage-mig-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.db");
+        let _ = std::fs::remove_file(&path);
+
+        drop(Storage::open(&path).unwrap());
+        let storage = Storage::open(&path).unwrap();
+        let version: usize = storage
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_insert_normalizes_record() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        let mut rec = record(T0, 21.5);
+        rec.packet.sts.rej = 2;
+        storage.insert(&rec).unwrap();
+
+        assert_eq!(count(&storage, "SELECT COUNT(*) FROM reports"), 1);
+        assert_eq!(
+            count(&storage, "SELECT COUNT(*) FROM readings"),
+            rec.metrics().len() as i64
+        );
+        assert_eq!(count(&storage, "SELECT rssi FROM link_stats"), -40);
+        assert_eq!(count(&storage, "SELECT rejected_frames FROM link_stats"), 2);
+        let temp: f64 = storage
+            .conn
+            .query_row(
+                "SELECT value FROM readings WHERE node = 'n1' AND metric = 'temperature'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(temp, 21.5);
+        assert_eq!(count(&storage, "SELECT COUNT(*) FROM calibrations"), 0);
+
+        let mut calibrated = record(T0 + 1_000, 20.0);
+        calibrated.calibration.insert(
+            "n1.temperature".to_string(),
+            AppliedCalibration {
+                sensor: "sht31".to_string(),
+                raw: 21.5,
+                source: ProfileSource::Config,
+            },
+        );
+        storage.insert(&calibrated).unwrap();
+        let (raw, source): (f64, String) = storage
+            .conn
+            .query_row(
+                "SELECT raw, source FROM calibrations WHERE node = 'n1' AND metric = 'temperature'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((raw, source.as_str()), (21.5, "config"));
+    }
+
+    #[test]
+    fn test_rollups_aggregate_per_bucket() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        storage.insert(&record(T0 + 1_000, 20.0)).unwrap();
+        storage.insert(&record(T0 + 30_000, 22.0)).unwrap();
+        storage.insert(&record(T0 + 61_000, 27.0)).unwrap();
+
+        let rollup = |resolution: u32, bucket: u64| -> (f64, f64, f64, i64) {
+            storage
+                .conn
+                .query_row(
+                    "SELECT min, max, sum / count, count FROM rollups
+                     WHERE resolution_s = ?1 AND bucket_start_ms = ?2
+                       AND node = 'n1' AND metric = 'temperature'",
+                    params![resolution, bucket as i64],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                )
+                .unwrap()
+        };
+
+        assert_eq!(rollup(60, T0), (20.0, 22.0, 21.0, 2));
+        assert_eq!(rollup(60, T0 + 60_000), (27.0, 27.0, 27.0, 1));
+        assert_eq!(rollup(3600, T0), (20.0, 27.0, 23.0, 3));
+    }
+
+    #[test]
+    fn test_history_raw_and_bucketed() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        for (offset_ms, temp) in [(0, 20.0), (20_000, 22.0), (40_000, 24.0), (70_000, 30.0)] {
+            storage.insert(&record(T0 + offset_ms, temp)).unwrap();
+        }
+        let query = |bucket_s| HistoryQuery {
+            node: "n1",
+            metric: "temperature",
+            from_ms: T0,
+            to_ms: T0 + 3_600_000,
+            bucket_s,
+        };
+        let page = Page {
+            limit: 100,
+            offset: 0,
+        };
+
+        let raw = storage.history(&query(None), page).unwrap();
+        assert_eq!(raw.len(), 4);
+        assert_eq!((raw[1].ts_ms, raw[1].avg), ((T0 + 20_000) as i64, 22.0));
+
+        // 30 s buckets aggregate raw readings
+        let half_minutes = storage.history(&query(Some(30)), page).unwrap();
+        assert_eq!(half_minutes.len(), 3);
+        assert_eq!(half_minutes[0].count, 2);
+        assert_eq!(half_minutes[0].avg, 21.0);
+
+        // 2 min buckets come from the minute rollups
+        let two_minutes = storage.history(&query(Some(120)), page).unwrap();
+        assert_eq!(
+            two_minutes,
+            vec![HistoryPoint {
+                ts_ms: T0 as i64,
+                avg: 24.0,
+                min: 20.0,
+                max: 30.0,
+                count: 4,
+            }]
+        );
+
+        let second_page = storage
+            .history(
+                &query(None),
+                Page {
+                    limit: 2,
+                    offset: 2,
+                },
+            )
+            .unwrap();
+        assert_eq!(second_page.len(), 2);
+        assert_eq!(second_page[0].avg, 24.0);
+    }
+
+    #[test]
+    fn test_latest_and_summary() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        assert!(storage.latest().unwrap().is_empty());
+        assert_eq!(storage.summary().unwrap().last_link, None);
+
+        storage.insert(&record(T0, 20.0)).unwrap();
+        storage.insert(&record(T0 + 10_000, 21.0)).unwrap();
+
+        let latest = storage.latest().unwrap();
+        let temp = latest
+            .iter()
+            .find(|r| r.node == "n1" && r.metric == "temperature")
+            .unwrap();
+        assert_eq!((temp.value, temp.ts_ms), (21.0, (T0 + 10_000) as i64));
+        assert!(latest.iter().any(|r| r.node == "n2"));
+
+        let summary = storage.summary().unwrap();
+        assert_eq!(summary.reports, 2);
+        assert_eq!(summary.first_report_ms, Some(T0 as i64));
+        assert_eq!(summary.last_link.unwrap().rssi, -40);
+    }
+
+    #[test]
+    fn test_retention_keeps_rollups() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        storage.insert(&record(T0, 20.0)).unwrap();
+        storage.insert(&record(T0 + 8 * MS_PER_DAY, 21.0)).unwrap();
+
+        let config = StorageConfig::default();
+        let stats = storage.prune(T0 + 8 * MS_PER_DAY, &config).unwrap();
+        assert_eq!(stats.reports, 1);
+        assert_eq!(stats.rollups, 0);
+
+        assert_eq!(count(&storage, "SELECT COUNT(*) FROM reports"), 1);
+        assert_eq!(count(&storage, "SELECT COUNT(*) FROM link_stats"), 1);
+        assert_eq!(
+            count(
+                &storage,
+                "SELECT COUNT(DISTINCT bucket_start_ms) FROM rollups WHERE resolution_s = 60"
+            ),
+            2
+        );
+    }
+}
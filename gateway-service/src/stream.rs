@@ -0,0 +1,516 @@
+//! Live telemetry streaming over WebSocket and Server-Sent Events
+//!
+//! The processor publishes every record (and gateway events such as a
+//! detected device reboot) into a `tokio::sync::broadcast` channel. Each
+//! client gets its own receiver, so a slow client only ever falls behind
+//! itself: the broadcast overwrites the oldest messages, the client is told
+//! how many it missed, and after too many lag episodes it is disconnected.
+//!
+//! Endpoints (served alongside the query API):
+//! - `GET /api/v1/stream/ws`: WebSocket, one JSON text frame per message
+//! - `GET /api/v1/stream/sse`: SSE, the event name is the message `type`
+//!
+//! Both accept comma-separated `node` and `metric` filters. Filtered
+//! subscriptions receive only the matching `metrics`; unfiltered ones also
+//! get the full processed `record`.
+//!
+//! Messages:
+//! - `{"type":"telemetry","ts_ms":..,"metrics":{"n1":{"temperature":..}},"record":{..}}`
+//! - `{"type":"event","event":"device_reboot","ts_ms":..,"detail":{..}}`
+//! - `{"type":"event","event":"anomaly","ts_ms":..,"detail":{"metric":..,"kind":..,..}}`
+//! - `{"type":"summary","aggregate":..,"start_ms":..,"end_ms":..,"nodes":{..},..}`
+//!   (window summaries, when the `stream` sink has `summaries = true`; not
+//!   filtered)
+//! - `{"type":"lagged","skipped":N}`
+//! - `{"type":"disconnected","reason":".."}` (last message before closing)
+
+use axum::extract::rejection::QueryRejection;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use crate::aggregate::WindowSummary;
+use crate::api::ApiError;
+use crate::clock::unix_time_ms;
+use crate::sink::TelemetrySink;
+use crate::telemetry::{ProcessedRecord, METRIC_NAMES};
+
+/// Streaming configuration (`[stream]` section, used when `[api]` is enabled)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamConfig {
+    /// Messages buffered per client before the oldest are dropped
+    pub buffer: usize,
+    /// Lag episodes tolerated before a client is disconnected (0 = never)
+    pub disconnect_after_lags: u32,
+    /// Seconds between keep-alive pings / comments on idle connections
+    pub keepalive_secs: u64,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            buffer: 256,
+            disconnect_after_lags: 5,
+            keepalive_secs: 15,
+        }
+    }
+}
+
+/// Something that happened in the gateway, as opposed to a measurement
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayEvent {
+    pub event: &'static str,
+    /// Event time, Unix ms
+    pub ts_ms: u64,
+    pub detail: Value,
+}
+
+impl GatewayEvent {
+    pub fn new(event: &'static str, detail: Value) -> Self {
+        Self {
+            event,
+            ts_ms: unix_time_ms(),
+            detail,
+        }
+    }
+}
+
+/// What the processor publishes
+#[derive(Debug, Clone)]
+enum Broadcast {
+    Record(Arc<ProcessedRecord>),
+    Event(Arc<GatewayEvent>),
+    Summary(Arc<WindowSummary>),
+}
+
+/// Message as sent to a client
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage<'a> {
+    Telemetry {
+        ts_ms: u64,
+        metrics: BTreeMap<&'static str, BTreeMap<&'static str, f64>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        record: Option<&'a ProcessedRecord>,
+    },
+    Event(&'a GatewayEvent),
+    Summary(&'a WindowSummary),
+    Lagged {
+        skipped: u64,
+    },
+    Disconnected {
+        reason: String,
+    },
+}
+
+impl ClientMessage<'_> {
+    fn kind(&self) -> &'static str {
+        match self {
+            ClientMessage::Telemetry { .. } => "telemetry",
+            ClientMessage::Event(_) => "event",
+            ClientMessage::Summary(_) => "summary",
+            ClientMessage::Lagged { .. } => "lagged",
+            ClientMessage::Disconnected { .. } => "disconnected",
+        }
+    }
+
+    /// (SSE event name, JSON text)
+    fn encode(&self) -> (&'static str, String) {
+        let json = serde_json::to_string(self).unwrap_or_else(|e| {
+            warn!(error = %e, "Failed to serialize stream message");
+            String::from("{}")
+        });
+        (self.kind(), json)
+    }
+}
+
+/// Fan-out point shared by the processor and the HTTP server
+#[derive(Clone)]
+pub struct StreamHub {
+    tx: broadcast::Sender<Broadcast>,
+    config: StreamConfig,
+}
+
+impl StreamHub {
+    pub fn new(config: &StreamConfig) -> Self {
+        let (tx, _) = broadcast::channel(config.buffer.max(1));
+        Self {
+            tx,
+            config: config.clone(),
+        }
+    }
+
+    /// Publish a processed record (never blocks; no-op without subscribers)
+    pub fn publish_record(&self, record: &ProcessedRecord) {
+        let _ = self.tx.send(Broadcast::Record(Arc::new(record.clone())));
+    }
+
+    /// Publish a gateway event
+    pub fn publish_event(&self, event: GatewayEvent) {
+        let _ = self.tx.send(Broadcast::Event(Arc::new(event)));
+    }
+
+    fn subscribe(&self, filter: Filter) -> Subscription {
+        Subscription {
+            rx: self.tx.subscribe(),
+            filter,
+            lags: 0,
+            max_lags: self.config.disconnect_after_lags,
+            closed: false,
+        }
+    }
+}
+
+impl TelemetrySink for StreamHub {
+    /// Broadcast the record, preceded by a `device_reboot` event when the
+    /// clock correlator saw one and followed by an `anomaly` event per
+    /// detected anomaly
+    async fn deliver(&self, record: &ProcessedRecord) -> anyhow::Result<()> {
+        if record.time.reboot_detected {
+            self.publish_event(GatewayEvent::new(
+                "device_reboot",
+                serde_json::json!({
+                    "device_ts_ms": record.time.device_ts_ms,
+                    "boot_epoch": record.time.boot_epoch,
+                }),
+            ));
+        }
+        self.publish_record(record);
+        for anomaly in &record.anomalies {
+            self.publish_event(GatewayEvent::new("anomaly", serde_json::to_value(anomaly)?));
+        }
+        Ok(())
+    }
+
+    fn accepts_summaries(&self) -> bool {
+        true
+    }
+
+    async fn deliver_summary(&self, summary: &WindowSummary) -> anyhow::Result<()> {
+        let _ = self.tx.send(Broadcast::Summary(Arc::new(summary.clone())));
+        Ok(())
+    }
+}
+
+/// Per-client node/metric filter (empty sets match everything)
+#[derive(Debug, Default)]
+struct Filter {
+    nodes: BTreeSet<String>,
+    metrics: BTreeSet<String>,
+}
+
+impl Filter {
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.metrics.is_empty()
+    }
+
+    fn matches(&self, node: &str, metric: &str) -> bool {
+        (self.nodes.is_empty() || self.nodes.contains(node))
+            && (self.metrics.is_empty() || self.metrics.contains(metric))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilterParams {
+    node: Option<String>,
+    metric: Option<String>,
+}
+
+impl TryFrom<FilterParams> for Filter {
+    type Error = ApiError;
+
+    fn try_from(params: FilterParams) -> Result<Self, ApiError> {
+        let split = |list: Option<String>| -> BTreeSet<String> {
+            list.iter()
+                .flat_map(|l| l.split(','))
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let filter = Filter {
+            nodes: split(params.node),
+            metrics: split(params.metric),
+        };
+
+        if let Some(node) = filter
+            .nodes
+            .iter()
+            .find(|n| !METRIC_NAMES.iter().any(|(node, _)| node == n))
+        {
+            return Err(ApiError::BadRequest(format!("unknown node {}", node)));
+        }
+        if let Some(metric) = filter
+            .metrics
+            .iter()
+            .find(|m| !METRIC_NAMES.iter().any(|(_, metric)| metric == m))
+        {
+            return Err(ApiError::BadRequest(format!("unknown metric {}", metric)));
+        }
+        Ok(filter)
+    }
+}
+
+/// One client's view of the broadcast
+struct Subscription {
+    rx: broadcast::Receiver<Broadcast>,
+    filter: Filter,
+    lags: u32,
+    max_lags: u32,
+    closed: bool,
+}
+
+impl Subscription {
+    /// Next encoded message for this client, `None` once the stream is over
+    async fn next(&mut self) -> Option<(&'static str, String)> {
+        if self.closed {
+            return None;
+        }
+
+        loop {
+            match self.rx.recv().await {
+                Ok(Broadcast::Record(record)) => {
+                    if let Some(message) = self.telemetry(&record) {
+                        return Some(message.encode());
+                    }
+                }
+                Ok(Broadcast::Event(event)) => return Some(ClientMessage::Event(&event).encode()),
+                Ok(Broadcast::Summary(summary)) => {
+                    return Some(ClientMessage::Summary(&summary).encode())
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    self.lags += 1;
+                    if self.max_lags > 0 && self.lags >= self.max_lags {
+                        self.closed = true;
+                        warn!(lags = self.lags, "Disconnecting slow stream client");
+                        let reason = format!("too slow: fell behind {} times", self.lags);
+                        return Some(ClientMessage::Disconnected { reason }.encode());
+                    }
+                    debug!(skipped, "Stream client lagged");
+                    return Some(ClientMessage::Lagged { skipped }.encode());
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    self.closed = true;
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Telemetry message for this client, `None` if the filter removes everything
+    fn telemetry<'a>(&self, record: &'a ProcessedRecord) -> Option<ClientMessage<'a>> {
+        let mut metrics: BTreeMap<_, BTreeMap<_, _>> = BTreeMap::new();
+        for metric in record.metrics() {
+            if self.filter.matches(metric.node, metric.name) {
+                metrics
+                    .entry(metric.node)
+                    .or_default()
+                    .insert(metric.name, metric.value);
+            }
+        }
+        if metrics.is_empty() {
+            return None;
+        }
+
+        Some(ClientMessage::Telemetry {
+            ts_ms: record.timestamp_ms(),
+            metrics,
+            record: self.filter.is_empty().then_some(record),
+        })
+    }
+}
+
+async fn websocket(
+    State(hub): State<StreamHub>,
+    params: Result<Query<FilterParams>, QueryRejection>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let Query(params) = params?;
+    let subscription = hub.subscribe(Filter::try_from(params)?);
+    let keepalive = Duration::from_secs(hub.config.keepalive_secs.max(1));
+    Ok(upgrade
+        .on_upgrade(move |socket| serve_websocket(socket, subscription, keepalive))
+        .into_response())
+}
+
+async fn serve_websocket(
+    mut socket: WebSocket,
+    mut subscription: Subscription,
+    keepalive: Duration,
+) {
+    info!("WebSocket stream client connected");
+    let mut ping = tokio::time::interval(keepalive);
+
+    loop {
+        tokio::select! {
+            message = subscription.next() => {
+                let Some((_, text)) = message else { break };
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                // Clients only send pings/pongs and close frames
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Default::default())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let _ = socket.send(Message::Close(None)).await;
+    info!("WebSocket stream client disconnected");
+}
+
+async fn sse(
+    State(hub): State<StreamHub>,
+    params: Result<Query<FilterParams>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(params) = params?;
+    let subscription = hub.subscribe(Filter::try_from(params)?);
+    info!("SSE stream client connected");
+
+    let events = futures_util::stream::unfold(subscription, |mut subscription| async move {
+        let (kind, text) = subscription.next().await?;
+        let event = Event::default().event(kind).data(text);
+        Some((Ok::<_, Infallible>(event), subscription))
+    });
+
+    let keepalive =
+        KeepAlive::new().interval(Duration::from_secs(hub.config.keepalive_secs.max(1)));
+    Ok(Sse::new(events).keep_alive(keepalive).into_response())
+}
+
+pub fn router(hub: StreamHub) -> Router {
+    Router::new()
+        .route("/api/v1/stream/ws", get(websocket))
+        .route("/api/v1/stream/sse", get(sse))
+        .with_state(hub)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Anomaly;
+    use crate::telemetry::test_support::record;
+
+    const T0: u64 = 1_767_225_600_000;
+
+    fn hub(buffer: usize, disconnect_after_lags: u32) -> StreamHub {
+        StreamHub::new(&StreamConfig {
+            buffer,
+            disconnect_after_lags,
+            ..StreamConfig::default()
+        })
+    }
+
+    fn filter(node: Option<&str>, metric: Option<&str>) -> Result<Filter, ApiError> {
+        Filter::try_from(FilterParams {
+            node: node.map(str::to_string),
+            metric: metric.map(str::to_string),
+        })
+    }
+
+    async fn next_json(subscription: &mut Subscription) -> Value {
+        let (_, text) = subscription.next().await.unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unfiltered_clients_get_full_record() {
+        let hub = hub(16, 0);
+        let mut subscription = hub.subscribe(Filter::default());
+        hub.publish_record(&record(T0, 21.5));
+        hub.publish_event(GatewayEvent::new("device_reboot", serde_json::json!({})));
+
+        let message = next_json(&mut subscription).await;
+        assert_eq!(message["type"], "telemetry");
+        assert_eq!(message["ts_ms"], T0);
+        assert_eq!(message["metrics"]["n1"]["temperature"], 21.5);
+        assert_eq!(message["record"]["n1"]["t"], 21.5);
+
+        let message = next_json(&mut subscription).await;
+        assert_eq!(message["type"], "event");
+        assert_eq!(message["event"], "device_reboot");
+    }
+
+    #[tokio::test]
+    async fn test_anomalies_follow_their_record() {
+        let hub = hub(16, 0);
+        let mut subscription = hub.subscribe(Filter::default());
+        let mut r = record(T0, 21.5);
+        let anomaly: Anomaly = serde_json::from_value(serde_json::json!({
+            "metric": "n1.rssi",
+            "kind": "link_degradation",
+            "value": -80.0,
+            "expected": -60.0,
+            "score": -12.5,
+            "confidence": 0.99,
+        }))
+        .unwrap();
+        r.anomalies.push(anomaly);
+        hub.deliver(&r).await.unwrap();
+
+        assert_eq!(next_json(&mut subscription).await["type"], "telemetry");
+        let message = next_json(&mut subscription).await;
+        assert_eq!(message["event"], "anomaly");
+        assert_eq!(message["detail"]["kind"], "link_degradation");
+        assert_eq!(message["detail"]["metric"], "n1.rssi");
+    }
+
+    #[tokio::test]
+    async fn test_filters_by_node_and_metric() {
+        let hub = hub(16, 0);
+        let mut subscription =
+            hub.subscribe(filter(Some("n2"), Some("pressure,altitude")).unwrap());
+        hub.publish_record(&record(T0, 21.5));
+
+        let message = next_json(&mut subscription).await;
+        let metrics = message["metrics"].as_object().unwrap();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics["n2"].as_object().unwrap().len(), 2);
+        assert!(message.get("record").is_none());
+
+        assert!(filter(Some("n9"), None).is_err());
+        assert!(filter(None, Some("bogus")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_client_is_notified_then_dropped() {
+        let hub = hub(2, 2);
+        let mut subscription = hub.subscribe(Filter::default());
+
+        for i in 0..5 {
+            hub.publish_record(&record(T0 + i, 20.0));
+        }
+        let message = next_json(&mut subscription).await;
+        assert_eq!(message["type"], "lagged");
+        assert_eq!(message["skipped"], 3);
+        // Catches up on what's still buffered
+        assert_eq!(next_json(&mut subscription).await["type"], "telemetry");
+        assert_eq!(next_json(&mut subscription).await["type"], "telemetry");
+
+        for i in 0..5 {
+            hub.publish_record(&record(T0 + i, 20.0));
+        }
+        assert_eq!(next_json(&mut subscription).await["type"], "disconnected");
+        assert!(subscription.next().await.is_none());
+    }
+}
@@ -0,0 +1,320 @@
+//! Telemetry data model
+//!
+//! `TelemetryPacket` mirrors the NDJSON emitted by node2 over the VCP.
+//! `ProcessedRecord` is what the processor hands to every sink: the packet
+//! plus everything the gateway derives from it (wall-clock time, derived
+//! metrics, IAQ, applied calibration).
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::calibration::AppliedCalibration;
+use crate::clock::RecordTime;
+use crate::derived::DerivedMetrics;
+use crate::iaq::IaqReading;
+use crate::pipeline::{Anomaly, FilteredValue};
+
+/// Telemetry packet from Node 2 gateway (matches Week 5 JSON format)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryPacket {
+    /// Timestamp in milliseconds since boot
+    pub ts: u32,
+    /// node2 wall-clock time in Unix ms (only once the gateway has synced it)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utc: Option<u64>,
+    /// Node ID (should be "N2" for gateway)
+    pub id: String,
+    /// Node 1 sensor data (remote sensor via LoRa)
+    pub n1: Node1Data,
+    /// Node 2 sensor data (gateway local sensor)
+    pub n2: Node2Data,
+    /// Signal quality metrics
+    pub sig: SignalQuality,
+    /// Statistics
+    pub sts: Statistics,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node1Data {
+    /// Temperature in °C
+    pub t: f32,
+    /// Humidity in %
+    pub h: f32,
+    /// Gas resistance in ohms
+    pub g: u32,
+    /// Sample time from node1's clock, Unix seconds (only once synced via ACKs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node2Data {
+    /// Temperature in °C (optional, BMP280 may not be reading yet)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub t: Option<f32>,
+    /// Pressure in hPa (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalQuality {
+    /// RSSI in dBm
+    pub rssi: i16,
+    /// SNR in dB
+    pub snr: i16,
+    /// Spreading factor node1 transmitted with (set by ADR; older node2 firmware omits it)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sf: Option<u8>,
+    /// node1 output power in dBm (set by ADR)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwr: Option<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Statistics {
+    /// Packets received
+    pub rx: u32,
+    /// CRC errors (frames node2 couldn't read)
+    pub err: u32,
+    /// Frames node2 refused: unknown node, failed authentication, replayed
+    /// or sent from another address (older firmware omits it)
+    #[serde(default)]
+    pub rej: u32,
+}
+
+/// A packet as handed from the parser to the processor
+#[derive(Debug, Clone)]
+pub struct ReceivedPacket {
+    pub packet: TelemetryPacket,
+    /// Host time the line was read, Unix ms
+    pub received_at_ms: u64,
+}
+
+/// A packet after gateway-side processing, as delivered to sinks
+///
+/// Serializes as the packet's own fields plus the derived sections, so
+/// `{{n1.t}}` and `{{derived.dew_point_c}}` both resolve in templates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessedRecord {
+    #[serde(flatten)]
+    pub packet: TelemetryPacket,
+    /// Device uptime and corrected wall-clock time
+    pub time: RecordTime,
+    /// Environmental metrics computed from the raw readings
+    pub derived: DerivedMetrics,
+    /// Node 1 indoor air quality estimate from the BME680 gas resistance
+    pub iaq: IaqReading,
+    /// Calibration profiles applied to the sensor readings, keyed by
+    /// `<node>.<metric>` (the packet fields hold the calibrated values)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub calibration: BTreeMap<String, AppliedCalibration>,
+    /// Values run through pipeline `smooth` filters, keyed by `<node>.<metric>`,
+    /// with the unfiltered reading and whether it was rejected as an outlier
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub filtered: BTreeMap<String, FilteredValue>,
+    /// Anomalies flagged by pipeline `anomaly` stages
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anomalies: Vec<Anomaly>,
+    /// Site metadata added by pipeline `tag` stages
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Units of values converted for a sink with `units`, keyed by
+    /// `<node>.<metric>` (everything else is in the units documented on the
+    /// packet fields)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub units: BTreeMap<String, String>,
+}
+
+/// One numeric value from a record, flattened for storage and export
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metric {
+    /// Node the value describes ("n1" remote sensor, "n2" gateway)
+    pub node: &'static str,
+    /// Metric name, e.g. "temperature"
+    pub name: &'static str,
+    pub value: f64,
+}
+
+/// Every (node, metric) pair `ProcessedRecord::metrics` can produce
+pub const METRIC_NAMES: &[(&str, &str)] = &[
+    ("n1", "temperature"),
+    ("n1", "humidity"),
+    ("n1", "gas_resistance"),
+    ("n1", "dew_point"),
+    ("n1", "absolute_humidity"),
+    ("n1", "heat_index"),
+    ("n1", "iaq"),
+    ("n1", "rssi"),
+    ("n1", "snr"),
+    ("n1", "link_margin"),
+    ("n2", "temperature"),
+    ("n2", "pressure"),
+    ("n2", "altitude"),
+];
+
+/// Look up a `<node>.<metric>` name (e.g. `n1.temperature`) in `METRIC_NAMES`
+pub fn parse_metric(s: &str) -> Option<(&'static str, &'static str)> {
+    METRIC_NAMES
+        .iter()
+        .find(|(node, name)| s.split_once('.') == Some((node, name)))
+        .copied()
+}
+
+impl ProcessedRecord {
+    /// Record time used for storage and aggregation: the corrected wall clock, Unix ms
+    pub fn timestamp_ms(&self) -> u64 {
+        self.time.corrected_at_ms
+    }
+
+    /// All numeric values in the record, skipping any that aren't available
+    ///
+    /// Link quality (RSSI/SNR) belongs to node1: it's measured by node2 on
+    /// node1's transmissions.
+    pub fn metrics(&self) -> Vec<Metric> {
+        let p = &self.packet;
+        let d = &self.derived;
+        let values = [
+            ("n1", "temperature", Some(p.n1.t as f64)),
+            ("n1", "humidity", Some(p.n1.h as f64)),
+            ("n1", "gas_resistance", Some(p.n1.g as f64)),
+            ("n1", "dew_point", d.dew_point_c.map(f64::from)),
+            (
+                "n1",
+                "absolute_humidity",
+                d.absolute_humidity_gm3.map(f64::from),
+            ),
+            ("n1", "heat_index", d.heat_index_c.map(f64::from)),
+            ("n1", "iaq", self.iaq.iaq.map(f64::from)),
+            ("n1", "rssi", Some(p.sig.rssi as f64)),
+            ("n1", "snr", Some(p.sig.snr as f64)),
+            ("n1", "link_margin", d.link_margin_db.map(f64::from)),
+            ("n2", "temperature", p.n2.t.map(f64::from)),
+            ("n2", "pressure", p.n2.p.map(f64::from)),
+            ("n2", "altitude", d.altitude_m.map(f64::from)),
+        ];
+
+        values
+            .into_iter()
+            .filter_map(|(node, name, value)| value.map(|value| Metric { node, name, value }))
+            .collect()
+    }
+
+    /// One value by node and metric name, if available
+    pub fn metric(&self, node: &str, name: &str) -> Option<f64> {
+        self.metrics()
+            .into_iter()
+            .find(|m| m.node == node && m.name == name)
+            .map(|m| m.value)
+    }
+
+    /// Overwrite one value by node and metric name
+    ///
+    /// Returns `false` (and changes nothing) for unknown names and for values
+    /// that aren't available in this record. Integer fields are rounded and
+    /// clamped to their range.
+    pub fn set_metric(&mut self, node: &str, name: &str, value: f64) -> bool {
+        fn set(slot: &mut Option<f32>, value: f64) -> bool {
+            match slot {
+                Some(v) => {
+                    *v = value as f32;
+                    true
+                }
+                None => false,
+            }
+        }
+
+        let p = &mut self.packet;
+        let d = &mut self.derived;
+        match (node, name) {
+            ("n1", "temperature") => p.n1.t = value as f32,
+            ("n1", "humidity") => p.n1.h = value as f32,
+            ("n1", "gas_resistance") => p.n1.g = value.round().clamp(0.0, u32::MAX as f64) as u32,
+            ("n1", "dew_point") => return set(&mut d.dew_point_c, value),
+            ("n1", "absolute_humidity") => return set(&mut d.absolute_humidity_gm3, value),
+            ("n1", "heat_index") => return set(&mut d.heat_index_c, value),
+            ("n1", "iaq") => return set(&mut self.iaq.iaq, value),
+            ("n1", "rssi") => {
+                p.sig.rssi = value.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+            }
+            ("n1", "snr") => {
+                p.sig.snr = value.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+            }
+            ("n1", "link_margin") => return set(&mut d.link_margin_db, value),
+            ("n2", "temperature") => return set(&mut p.n2.t, value),
+            ("n2", "pressure") => return set(&mut p.n2.p, value),
+            ("n2", "altitude") => return set(&mut d.altitude_m, value),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+pub mod test_support {
+    //! Fixtures for tests in other modules
+
+    use super::*;
+    use crate::derived::DerivedConfig;
+    use crate::iaq::IaqAccuracy;
+
+    /// A packet as node2 would send it
+    pub fn packet(ts: u32, n1_temp: f32) -> TelemetryPacket {
+        TelemetryPacket {
+            ts,
+            utc: None,
+            id: "N2".to_string(),
+            n1: Node1Data {
+                t: n1_temp,
+                h: 45.0,
+                g: 50_000,
+                ts: None,
+            },
+            n2: Node2Data {
+                t: Some(24.0),
+                p: Some(1013.25),
+            },
+            sig: SignalQuality {
+                rssi: -40,
+                snr: 10,
+                sf: None,
+                pwr: None,
+            },
+            sts: Statistics {
+                rx: 1,
+                err: 0,
+                rej: 0,
+            },
+        }
+    }
+
+    /// A fully processed record stamped at `at_ms` (wall clock)
+    pub fn record(at_ms: u64, n1_temp: f32) -> ProcessedRecord {
+        let packet = packet((at_ms % 1_000_000) as u32, n1_temp);
+        ProcessedRecord {
+            time: RecordTime {
+                device_ts_ms: packet.ts,
+                device_uptime_ms: packet.ts as u64,
+                received_at_ms: at_ms,
+                corrected_at_ms: at_ms,
+                boot_time_ms: at_ms - packet.ts as u64,
+                drift_ppm: 0.0,
+                boot_epoch: 1,
+                reboot_detected: false,
+            },
+            derived: DerivedMetrics::compute(&packet, &DerivedConfig::default()),
+            iaq: IaqReading {
+                iaq: None,
+                accuracy: IaqAccuracy::BurnIn,
+                burn_in_pct: 0,
+                baseline_ohm: 0.0,
+            },
+            packet,
+            calibration: BTreeMap::new(),
+            filtered: BTreeMap::new(),
+            anomalies: Vec::new(),
+            tags: BTreeMap::new(),
+            units: BTreeMap::new(),
+        }
+    }
+}
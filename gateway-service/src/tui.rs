@@ -0,0 +1,767 @@
+//! Full-screen terminal monitor (`wk6-async-gateway tui`)
+//!
+//! Replaces the scrolling log with a live view for bench debugging:
+//! - Node panels with the latest node1 (BME680) and node2 (BMP280) values
+//! - RSSI/SNR gauges, node2's rx/err counters and their per-minute rates
+//! - A firmware log pane (probe-rs/defmt lines) with level and text filters
+//!
+//! Keys: `q` quit, `p` pause/follow the log, `l` cycle minimum level,
+//! `/` text filter, `:` raw downlink command, `t` time sync now, `c` clear
+//! the log, arrows / PgUp / PgDn to scroll (pauses), `End` to follow again.
+//!
+//! The gateway's own tracing output goes to `[tui] log_file` while the TUI
+//! owns the terminal.
+
+use anyhow::Result;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures_util::StreamExt;
+use node_protocol::downlink::{parse_downlink, Downlink};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::clock::unix_time_ms;
+use crate::downlink::DownlinkHandle;
+use crate::sink::TelemetrySink;
+use crate::telemetry::ProcessedRecord;
+
+/// Events buffered between the pipeline and the UI
+const EVENT_QUEUE_CAPACITY: usize = 1000;
+
+/// Window for rx/err rates
+const RATE_WINDOW_MS: u64 = 60_000;
+
+/// How long status messages stay in the header
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// TUI configuration (`[tui]` section, only used in `tui` mode)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuiConfig {
+    /// Where gateway logs go while the TUI owns the terminal
+    pub log_file: PathBuf,
+    /// Firmware log lines kept for scrollback
+    pub log_lines: usize,
+    /// Redraw interval (ages and rates tick even without new data)
+    pub refresh_ms: u64,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        Self {
+            log_file: PathBuf::from("gateway-tui.log"),
+            log_lines: 2000,
+            refresh_ms: 250,
+        }
+    }
+}
+
+/// What the pipeline feeds the monitor
+#[derive(Debug)]
+pub enum MonitorEvent {
+    Record(Box<ProcessedRecord>),
+    FirmwareLog(String),
+}
+
+/// Handle for feeding the monitor from the parser and processor
+#[derive(Clone)]
+pub struct MonitorHandle {
+    tx: mpsc::Sender<MonitorEvent>,
+}
+
+impl MonitorHandle {
+    /// Append a raw probe-rs output line to the firmware log pane
+    pub fn firmware_log(&self, line: &str) {
+        let line = line.trim_end();
+        if !line.is_empty() {
+            let _ = self
+                .tx
+                .try_send(MonitorEvent::FirmwareLog(line.to_string()));
+        }
+    }
+}
+
+impl TelemetrySink for MonitorHandle {
+    /// Show a processed record (dropped if the UI is behind, it's display only)
+    async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+        let _ = self
+            .tx
+            .try_send(MonitorEvent::Record(Box::new(record.clone())));
+        Ok(())
+    }
+}
+
+/// defmt log level, parsed from probe-rs output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Trace,
+        Level::Debug,
+        Level::Info,
+        Level::Warn,
+        Level::Error,
+    ];
+
+    /// Level tag in a probe-rs line (`[INFO ]`, `[WARN]`, ...); untagged lines count as info
+    fn parse(line: &str) -> Level {
+        Level::ALL
+            .into_iter()
+            .find(|level| line.contains(&format!("[{}", level.label())))
+            .unwrap_or(Level::Info)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+
+    fn next(self) -> Level {
+        Level::ALL[(self as usize + 1) % Level::ALL.len()]
+    }
+
+    fn style(self) -> Style {
+        match self {
+            Level::Trace | Level::Debug => Style::new().fg(Color::DarkGray),
+            Level::Info => Style::new(),
+            Level::Warn => Style::new().fg(Color::Yellow),
+            Level::Error => Style::new().fg(Color::Red),
+        }
+    }
+}
+
+struct LogLine {
+    level: Level,
+    text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Input {
+    Normal,
+    Filter(String),
+    Command(String),
+}
+
+/// What a key press asks the event loop to do
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    None,
+    Quit,
+    Send(Downlink),
+}
+
+/// Everything on screen, independent of the terminal
+struct MonitorState {
+    latest: Option<ProcessedRecord>,
+    packets: u64,
+    /// (received_at_ms, rx, err) over the rate window
+    counters: VecDeque<(u64, u32, u32)>,
+    logs: VecDeque<LogLine>,
+    log_capacity: usize,
+    min_level: Level,
+    text_filter: String,
+    paused: bool,
+    /// Visible lines between the bottom of the log and the view while paused
+    scroll: usize,
+    input: Input,
+    status: Option<(String, Instant)>,
+}
+
+impl MonitorState {
+    fn new(config: &TuiConfig) -> Self {
+        Self {
+            latest: None,
+            packets: 0,
+            counters: VecDeque::new(),
+            logs: VecDeque::new(),
+            log_capacity: config.log_lines.max(1),
+            min_level: Level::Info,
+            text_filter: String::new(),
+            paused: false,
+            scroll: 0,
+            input: Input::Normal,
+            status: None,
+        }
+    }
+
+    fn apply(&mut self, event: MonitorEvent) {
+        match event {
+            MonitorEvent::Record(record) => {
+                let at = record.time.received_at_ms;
+                let sts = &record.packet.sts;
+                // Counters restart when node2 reboots
+                if self
+                    .counters
+                    .back()
+                    .is_some_and(|&(_, rx, err)| sts.rx < rx || sts.err < err)
+                {
+                    self.counters.clear();
+                }
+                self.counters.push_back((at, sts.rx, sts.err));
+                while self
+                    .counters
+                    .front()
+                    .is_some_and(|&(t, _, _)| t + RATE_WINDOW_MS < at)
+                {
+                    self.counters.pop_front();
+                }
+
+                self.packets += 1;
+                self.latest = Some(*record);
+            }
+            MonitorEvent::FirmwareLog(text) => {
+                let line = LogLine {
+                    level: Level::parse(&text),
+                    text,
+                };
+                // Keep a paused view anchored on the same lines
+                if self.paused && self.is_visible(&line) {
+                    self.scroll += 1;
+                }
+                self.logs.push_back(line);
+                if self.logs.len() > self.log_capacity {
+                    self.logs.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Packets and CRC errors per minute over the rate window
+    fn rates(&self) -> Option<(f64, f64)> {
+        let (&(t0, rx0, err0), &(t1, rx1, err1)) = (self.counters.front()?, self.counters.back()?);
+        if t1 <= t0 {
+            return None;
+        }
+        let minutes = (t1 - t0) as f64 / 60_000.0;
+        Some(((rx1 - rx0) as f64 / minutes, (err1 - err0) as f64 / minutes))
+    }
+
+    fn is_visible(&self, line: &LogLine) -> bool {
+        line.level >= self.min_level
+            && (self.text_filter.is_empty()
+                || line
+                    .text
+                    .to_lowercase()
+                    .contains(&self.text_filter.to_lowercase()))
+    }
+
+    fn visible_logs(&self) -> Vec<&LogLine> {
+        self.logs.iter().filter(|l| self.is_visible(l)).collect()
+    }
+
+    fn set_status(&mut self, message: impl Into<String>) {
+        self.status = Some((message.into(), Instant::now()));
+    }
+
+    fn scroll_by(&mut self, delta: isize) {
+        self.paused = true;
+        let max = self.visible_logs().len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+
+    fn follow(&mut self) {
+        self.paused = false;
+        self.scroll = 0;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+
+        match &mut self.input {
+            Input::Normal => {}
+            Input::Filter(text) => {
+                match key.code {
+                    KeyCode::Char(c) => text.push(c),
+                    KeyCode::Backspace => {
+                        text.pop();
+                    }
+                    KeyCode::Esc => text.clear(),
+                    _ => {}
+                }
+                // Filter applies as you type
+                self.text_filter = text.clone();
+                if matches!(key.code, KeyCode::Enter | KeyCode::Esc) {
+                    self.input = Input::Normal;
+                    self.scroll = 0;
+                }
+                return Action::None;
+            }
+            Input::Command(text) => {
+                match key.code {
+                    KeyCode::Char(c) => text.push(c),
+                    KeyCode::Backspace => {
+                        text.pop();
+                    }
+                    KeyCode::Enter => {
+                        let line = std::mem::take(text);
+                        self.input = Input::Normal;
+                        return match parse_downlink(&line) {
+                            Some(command) => Action::Send(command),
+                            None => {
+                                self.set_status(format!("Unknown downlink command: {}", line));
+                                Action::None
+                            }
+                        };
+                    }
+                    KeyCode::Esc => self.input = Input::Normal,
+                    _ => {}
+                }
+                return Action::None;
+            }
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                if self.paused {
+                    self.follow();
+                } else {
+                    self.paused = true;
+                }
+            }
+            KeyCode::Char('l') => {
+                self.min_level = self.min_level.next();
+                self.scroll = 0;
+            }
+            KeyCode::Char('/') => self.input = Input::Filter(self.text_filter.clone()),
+            KeyCode::Char(':') => self.input = Input::Command(String::new()),
+            KeyCode::Char('t') => {
+                return Action::Send(Downlink::TimeSync {
+                    unix_ms: unix_time_ms(),
+                })
+            }
+            KeyCode::Char('c') => {
+                self.logs.clear();
+                self.scroll = 0;
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.scroll_by(1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll_by(-1),
+            KeyCode::PageUp => self.scroll_by(10),
+            KeyCode::PageDown => self.scroll_by(-10),
+            KeyCode::End | KeyCode::Char('G') => self.follow(),
+            _ => {}
+        }
+        Action::None
+    }
+}
+
+/// The monitor UI; owns the terminal while running
+pub struct Monitor {
+    config: TuiConfig,
+    rx: mpsc::Receiver<MonitorEvent>,
+    downlink: Option<DownlinkHandle>,
+}
+
+/// Create the monitor and the handle the pipeline feeds it through
+pub fn channel(config: &TuiConfig, downlink: Option<DownlinkHandle>) -> (Monitor, MonitorHandle) {
+    let (tx, rx) = mpsc::channel(EVENT_QUEUE_CAPACITY);
+    let monitor = Monitor {
+        config: config.clone(),
+        rx,
+        downlink,
+    };
+    (monitor, MonitorHandle { tx })
+}
+
+impl Monitor {
+    /// Run until the user quits, restoring the terminal afterwards
+    pub async fn run(self) -> Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal).await;
+        ratatui::restore();
+        result
+    }
+
+    async fn event_loop(mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let mut state = MonitorState::new(&self.config);
+        let mut keys = EventStream::new();
+        let mut redraw =
+            tokio::time::interval(Duration::from_millis(self.config.refresh_ms.max(10)));
+        let mut pipeline_open = true;
+
+        loop {
+            terminal.draw(|frame| render(frame, &state))?;
+
+            tokio::select! {
+                _ = redraw.tick() => {}
+                event = self.rx.recv(), if pipeline_open => match event {
+                    Some(event) => {
+                        state.apply(event);
+                        // Catch up on bursts before the next draw
+                        while let Ok(event) = self.rx.try_recv() {
+                            state.apply(event);
+                        }
+                    }
+                    None => {
+                        pipeline_open = false;
+                        state.set_status("Telemetry pipeline stopped (probe-rs exited?)");
+                    }
+                },
+                input = keys.next() => match input {
+                    Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                        match state.handle_key(key) {
+                            Action::Quit => return Ok(()),
+                            Action::Send(command) => self.send(&mut state, command),
+                            Action::None => {}
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+
+    fn send(&self, state: &mut MonitorState, command: Downlink) {
+        let Some(downlink) = &self.downlink else {
+            state.set_status("Downlink not configured (add a [downlink] section)");
+            return;
+        };
+        match downlink.send(command) {
+            Ok(()) => state.set_status(format!("Sent {}", command)),
+            Err(e) => state.set_status(e.to_string()),
+        }
+    }
+}
+
+fn render(frame: &mut Frame, state: &MonitorState) {
+    let [header, panels, logs, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(7),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [node1, node2, link] = Layout::horizontal([Constraint::Ratio(1, 3); 3]).areas(panels);
+
+    render_header(frame, header, state);
+    render_nodes(frame, node1, node2, state);
+    render_link(frame, link, state);
+    render_logs(frame, logs, state);
+    render_footer(frame, footer, state);
+}
+
+fn render_header(frame: &mut Frame, area: Rect, state: &MonitorState) {
+    let bold = Style::new().add_modifier(Modifier::BOLD);
+    let mut spans = vec![
+        Span::styled(" Gateway monitor ", bold.fg(Color::Cyan)),
+        Span::raw(format!("│ packets {} ", state.packets)),
+    ];
+    if let Some(record) = &state.latest {
+        let age_s = unix_time_ms().saturating_sub(record.time.received_at_ms) / 1000;
+        spans.push(Span::raw(format!("│ last {}s ago ", age_s)));
+    }
+    if state.paused {
+        spans.push(Span::styled("│ PAUSED ", bold.fg(Color::Yellow)));
+    }
+    if let Some((message, at)) = &state.status {
+        if at.elapsed() < STATUS_TIMEOUT {
+            spans.push(Span::styled(
+                format!("│ {}", message),
+                Style::new().fg(Color::Green),
+            ));
+        }
+    }
+    frame.render_widget(Line::from(spans), area);
+}
+
+fn value_line(label: &str, value: String) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{:<13}", label), Style::new().fg(Color::DarkGray)),
+        Span::styled(value, Style::new().add_modifier(Modifier::BOLD)),
+    ])
+}
+
+fn fmt_value(value: Option<f32>, precision: usize, unit: &str) -> String {
+    match value {
+        Some(v) => format!("{:.*} {}", precision, v, unit),
+        None => "-".to_string(),
+    }
+}
+
+fn render_nodes(frame: &mut Frame, node1: Rect, node2: Rect, state: &MonitorState) {
+    let (n1, n2) = match &state.latest {
+        Some(r) => (
+            vec![
+                value_line("Temperature", fmt_value(Some(r.packet.n1.t), 1, "°C")),
+                value_line("Humidity", fmt_value(Some(r.packet.n1.h), 1, "%")),
+                value_line("Gas", format!("{} Ω", r.packet.n1.g)),
+                value_line(
+                    "IAQ",
+                    match r.iaq.iaq {
+                        Some(iaq) => format!("{:.0} ({:?})", iaq, r.iaq.accuracy),
+                        None => format!("burn-in {}%", r.iaq.burn_in_pct),
+                    },
+                ),
+                value_line("Dew point", fmt_value(r.derived.dew_point_c, 1, "°C")),
+            ],
+            vec![
+                value_line("Temperature", fmt_value(r.packet.n2.t, 1, "°C")),
+                value_line("Pressure", fmt_value(r.packet.n2.p, 1, "hPa")),
+                value_line("Altitude", fmt_value(r.derived.altitude_m, 0, "m")),
+            ],
+        ),
+        None => (
+            vec![Line::from("Waiting for data…")],
+            vec![Line::from("Waiting for data…")],
+        ),
+    };
+
+    frame.render_widget(
+        Paragraph::new(n1).block(Block::bordered().title(" Node 1 · BME680 ")),
+        node1,
+    );
+    frame.render_widget(
+        Paragraph::new(n2).block(Block::bordered().title(" Node 2 · BMP280 ")),
+        node2,
+    );
+}
+
+/// Fraction of `value` within `[min, max]`
+fn ratio(value: f64, min: f64, max: f64) -> f64 {
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+fn quality_color(ratio: f64) -> Color {
+    if ratio >= 0.6 {
+        Color::Green
+    } else if ratio >= 0.3 {
+        Color::Yellow
+    } else {
+        Color::Red
+    }
+}
+
+fn render_link(frame: &mut Frame, area: Rect, state: &MonitorState) {
+    let block = Block::bordered().title(" Link ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let Some(record) = &state.latest else {
+        frame.render_widget(Paragraph::new("Waiting for data…"), inner);
+        return;
+    };
+    let [rssi, snr, counters] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(1),
+        Constraint::Min(0),
+    ])
+    .spacing(1)
+    .areas(inner);
+
+    let sig = &record.packet.sig;
+    for (area, label, value, unit, min, max) in [
+        (rssi, "RSSI", sig.rssi, "dBm", -130.0, -30.0),
+        (snr, "SNR", sig.snr, "dB", -20.0, 15.0),
+    ] {
+        let r = ratio(value as f64, min, max);
+        let gauge = Gauge::default()
+            .ratio(r)
+            .label(format!("{} {} {}", label, value, unit))
+            .gauge_style(Style::new().fg(quality_color(r)).bg(Color::Black));
+        frame.render_widget(gauge, area);
+    }
+
+    let sts = &record.packet.sts;
+    let rates = match state.rates() {
+        Some((rx, err)) => format!("{:.1} rx/min, {:.1} err/min", rx, err),
+        None => "rate: collecting…".to_string(),
+    };
+    frame.render_widget(
+        Paragraph::new(vec![
+            value_line(
+                "rx / err / rej",
+                format!("{} / {} / {}", sts.rx, sts.err, sts.rej),
+            ),
+            Line::from(rates),
+        ]),
+        counters,
+    );
+}
+
+fn render_logs(frame: &mut Frame, area: Rect, state: &MonitorState) {
+    let visible = state.visible_logs();
+    let height = area.height.saturating_sub(2) as usize;
+    let end = visible.len().saturating_sub(state.scroll);
+    let start = end.saturating_sub(height);
+
+    let lines: Vec<Line> = visible[start..end]
+        .iter()
+        .map(|l| Line::styled(l.text.as_str(), l.level.style()))
+        .collect();
+
+    let mut title = format!(" Firmware log · ≥ {} ", state.min_level.label());
+    if !state.text_filter.is_empty() {
+        title.push_str(&format!("· \"{}\" ", state.text_filter));
+    }
+    if state.scroll > 0 {
+        title.push_str(&format!("· {} newer below ", state.scroll));
+    }
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+fn render_footer(frame: &mut Frame, area: Rect, state: &MonitorState) {
+    let line = match &state.input {
+        Input::Filter(text) => Line::from(format!("/{}█  (Enter keep, Esc clear)", text)),
+        Input::Command(text) => Line::from(format!(":{}█  (Enter send, Esc cancel)", text)),
+        Input::Normal => Line::styled(
+            " q quit  p pause  l level  / filter  : command  t time sync  c clear  ↑↓ PgUp PgDn scroll  End follow",
+            Style::new().fg(Color::DarkGray),
+        ),
+    };
+    frame.render_widget(line, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::test_support::record;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    const T0: u64 = 1_767_225_600_000;
+
+    fn state() -> MonitorState {
+        MonitorState::new(&TuiConfig::default())
+    }
+
+    fn press(state: &mut MonitorState, code: KeyCode) -> Action {
+        state.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn type_text(state: &mut MonitorState, text: &str) {
+        for c in text.chars() {
+            press(state, KeyCode::Char(c));
+        }
+    }
+
+    fn log(state: &mut MonitorState, line: &str) {
+        state.apply(MonitorEvent::FirmwareLog(line.to_string()));
+    }
+
+    #[test]
+    fn test_log_level_and_text_filters() {
+        let mut state = state();
+        log(&mut state, "0.1 [DEBUG] radio irq");
+        log(&mut state, "0.2 [INFO ] Packet received seq=4");
+        log(&mut state, "0.3 [WARN ] CRC error");
+        log(&mut state, "0.4 [ERROR] LoRa timeout");
+        assert_eq!(state.visible_logs().len(), 3);
+
+        press(&mut state, KeyCode::Char('l'));
+        assert_eq!(state.min_level, Level::Warn);
+        assert_eq!(state.visible_logs().len(), 2);
+
+        press(&mut state, KeyCode::Char('/'));
+        type_text(&mut state, "crc");
+        press(&mut state, KeyCode::Enter);
+        let visible = state.visible_logs();
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].level, Level::Warn);
+    }
+
+    #[test]
+    fn test_paused_view_stays_anchored() {
+        let mut state = state();
+        for i in 0..5 {
+            log(&mut state, &format!("[INFO] line {}", i));
+        }
+        press(&mut state, KeyCode::Char('p'));
+        log(&mut state, "[INFO] line 5");
+        log(&mut state, "[DEBUG] hidden");
+        assert_eq!(state.scroll, 1);
+
+        press(&mut state, KeyCode::End);
+        assert!(!state.paused);
+        assert_eq!(state.scroll, 0);
+    }
+
+    #[test]
+    fn test_rates_reset_on_node2_reboot() {
+        let mut state = state();
+        for (i, (rx, err)) in [(10, 0), (16, 1), (22, 2)].into_iter().enumerate() {
+            let mut rec = record(T0 + i as u64 * 30_000, 20.0);
+            rec.packet.sts.rx = rx;
+            rec.packet.sts.err = err;
+            state.apply(MonitorEvent::Record(Box::new(rec)));
+        }
+        assert_eq!(state.rates(), Some((12.0, 2.0)));
+
+        let mut rec = record(T0 + 90_000, 20.0);
+        rec.packet.sts.rx = 1;
+        state.apply(MonitorEvent::Record(Box::new(rec)));
+        assert_eq!(state.rates(), None);
+        assert_eq!(state.packets, 4);
+    }
+
+    #[test]
+    fn test_downlink_commands() {
+        let mut state = state();
+        assert!(matches!(
+            press(&mut state, KeyCode::Char('t')),
+            Action::Send(Downlink::TimeSync { .. })
+        ));
+
+        press(&mut state, KeyCode::Char(':'));
+        type_text(&mut state, "TIME=1767225600000");
+        assert_eq!(
+            press(&mut state, KeyCode::Enter),
+            Action::Send(Downlink::TimeSync {
+                unix_ms: 1_767_225_600_000
+            })
+        );
+
+        press(&mut state, KeyCode::Char(':'));
+        type_text(&mut state, "REBOOT");
+        assert_eq!(press(&mut state, KeyCode::Enter), Action::None);
+        assert!(state.status.as_ref().unwrap().0.contains("REBOOT"));
+        assert_eq!(state.input, Input::Normal);
+    }
+
+    #[test]
+    fn test_renders_latest_values() {
+        let mut state = state();
+        state.apply(MonitorEvent::Record(Box::new(record(T0, 21.5))));
+        log(&mut state, "[WARN ] CRC error");
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        terminal.draw(|frame| render(frame, &state)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+
+        assert!(screen.contains("21.5 °C"));
+        assert!(screen.contains("1013."));
+        assert!(screen.contains("RSSI -40 dBm"));
+        assert!(screen.contains("CRC error"));
+    }
+}
@@ -0,0 +1,182 @@
+//! Per-sink unit conversion (`units` under `[router.sinks.<name>]`)
+//!
+//! Records stay in °C and hPa from the processor through the router, so
+//! storage, aggregation and the servers always see the units they're
+//! documented in. A sink that asks for other units gets its own converted
+//! copy of each record, with the new units listed in `record.units`.
+
+use serde::Deserialize;
+
+use crate::telemetry::ProcessedRecord;
+
+/// Metrics in °C
+const TEMPERATURE_METRICS: &[(&str, &str)] = &[
+    ("n1", "temperature"),
+    ("n1", "dew_point"),
+    ("n1", "heat_index"),
+    ("n2", "temperature"),
+];
+
+/// Metrics in hPa
+const PRESSURE_METRICS: &[(&str, &str)] = &[("n2", "pressure")];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl TemperatureUnit {
+    fn convert(self, c: f64) -> f64 {
+        match self {
+            Self::Celsius => c,
+            Self::Fahrenheit => c * 9.0 / 5.0 + 32.0,
+            Self::Kelvin => c + 273.15,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+            Self::Kelvin => "K",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PressureUnit {
+    Hpa,
+    Kpa,
+    Inhg,
+    Mmhg,
+}
+
+impl PressureUnit {
+    fn convert(self, hpa: f64) -> f64 {
+        match self {
+            Self::Hpa => hpa,
+            Self::Kpa => hpa / 10.0,
+            Self::Inhg => hpa * 0.029_529_983,
+            Self::Mmhg => hpa * 0.750_061_68,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Hpa => "hPa",
+            Self::Kpa => "kPa",
+            Self::Inhg => "inHg",
+            Self::Mmhg => "mmHg",
+        }
+    }
+}
+
+/// Units a sink wants its records in
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnitsConfig {
+    /// Unit for every temperature (sensor readings, dew point, heat index)
+    pub temperature: Option<TemperatureUnit>,
+    /// Unit for node2's pressure
+    pub pressure: Option<PressureUnit>,
+}
+
+impl UnitsConfig {
+    /// True if values stay in °C and hPa
+    pub fn is_native(&self) -> bool {
+        matches!(self.temperature, None | Some(TemperatureUnit::Celsius))
+            && matches!(self.pressure, None | Some(PressureUnit::Hpa))
+    }
+
+    /// Copy of `record` in these units
+    ///
+    /// Smoothing's unfiltered values are converted along with the metrics;
+    /// anomaly baselines are left in native units.
+    pub fn convert(&self, record: &ProcessedRecord) -> ProcessedRecord {
+        let mut record = record.clone();
+        if let Some(unit) = self.temperature.filter(|&u| u != TemperatureUnit::Celsius) {
+            convert(&mut record, TEMPERATURE_METRICS, unit.symbol(), |c| {
+                unit.convert(c)
+            });
+        }
+        if let Some(unit) = self.pressure.filter(|&u| u != PressureUnit::Hpa) {
+            convert(&mut record, PRESSURE_METRICS, unit.symbol(), |hpa| {
+                unit.convert(hpa)
+            });
+        }
+        record
+    }
+}
+
+fn convert(
+    record: &mut ProcessedRecord,
+    metrics: &[(&str, &str)],
+    symbol: &str,
+    f: impl Fn(f64) -> f64,
+) {
+    for &(node, name) in metrics {
+        let Some(value) = record.metric(node, name) else {
+            continue;
+        };
+        if record.set_metric(node, name, f(value)) {
+            let key = format!("{}.{}", node, name);
+            if let Some(filtered) = record.filtered.get_mut(&key) {
+                filtered.raw = f(filtered.raw);
+            }
+            record.units.insert(key, symbol.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::FilteredValue;
+    use crate::telemetry::test_support::record;
+
+    #[test]
+    fn test_convert_units() {
+        let units = UnitsConfig {
+            temperature: Some(TemperatureUnit::Fahrenheit),
+            pressure: Some(PressureUnit::Inhg),
+        };
+        let mut native = record(0, 100.0);
+        native.packet.n2.t = None;
+        native.filtered.insert(
+            "n1.temperature".to_string(),
+            FilteredValue {
+                filter: "median".to_string(),
+                raw: 0.0,
+                rejected: false,
+            },
+        );
+        let r = units.convert(&native);
+
+        assert_eq!(r.packet.n1.t, 212.0);
+        assert_eq!(r.filtered["n1.temperature"].raw, 32.0);
+        assert!((r.packet.n2.p.unwrap() - 29.92).abs() < 0.01);
+        assert_eq!(r.units["n1.temperature"], "°F");
+        assert_eq!(r.units["n2.pressure"], "inHg");
+        // Nothing to convert, no unit entry
+        assert!(!r.units.contains_key("n2.temperature"));
+        // The original stays native
+        assert_eq!(native.packet.n1.t, 100.0);
+        assert!(native.units.is_empty());
+    }
+
+    #[test]
+    fn test_native_units_add_nothing() {
+        let units = UnitsConfig {
+            temperature: Some(TemperatureUnit::Celsius),
+            pressure: None,
+        };
+        assert!(units.is_native());
+        let r = units.convert(&record(0, 21.5));
+        assert_eq!(r.packet.n1.t, 21.5);
+        assert!(r.units.is_empty());
+    }
+}
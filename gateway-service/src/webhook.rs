@@ -0,0 +1,620 @@
+//! Webhook notifier
+//!
+//! Delivers telemetry and alert events to an HTTP endpoint:
+//! - Body rendered from a JSON template with `{{path}}` placeholders
+//! - Optional HMAC-SHA256 signature header (`X-Signature-256: sha256=<hex>`)
+//! - Retry with exponential backoff on transport errors, 408, 429 and 5xx
+//! - Undeliverable messages appended to a dead-letter NDJSON file
+//!
+//! Delivery runs in its own task behind a bounded queue; once that is full,
+//! the `webhook` sink queue absorbs the backlog under its own policy.
+
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::aggregate::WindowSummary;
+use crate::clock::unix_time_ms;
+use crate::sink::TelemetrySink;
+use crate::telemetry::ProcessedRecord;
+
+/// Header carrying the HMAC-SHA256 signature of the request body
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// Webhook configuration (`[webhook]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Endpoint URL (required)
+    pub url: String,
+    /// HTTP method (POST, PUT, PATCH)
+    pub method: String,
+    /// Extra request headers
+    pub headers: BTreeMap<String, String>,
+    /// JSON body template; `None` sends `{"event": ..., "data": ...}`
+    pub template: Option<Value>,
+    /// HMAC secret (prefer `secret_env` so it stays out of the config file)
+    pub secret: Option<String>,
+    /// Environment variable holding the HMAC secret
+    pub secret_env: Option<String>,
+    /// Event names to deliver (e.g. "telemetry")
+    pub events: Vec<String>,
+    /// Total delivery attempts before dead-lettering
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on each further retry
+    pub initial_backoff_ms: u64,
+    /// Upper bound for the retry delay
+    pub max_backoff_ms: u64,
+    /// Per-request timeout
+    pub timeout_ms: u64,
+    /// Pending notifications buffered by the notifier
+    pub queue_capacity: usize,
+    /// NDJSON file for undeliverable messages
+    pub dead_letter_path: PathBuf,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            method: "POST".to_string(),
+            headers: BTreeMap::new(),
+            template: None,
+            secret: None,
+            secret_env: None,
+            events: vec!["telemetry".to_string()],
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            timeout_ms: 5_000,
+            queue_capacity: 100,
+            dead_letter_path: PathBuf::from("webhook-dead-letter.ndjson"),
+        }
+    }
+}
+
+/// An event to deliver: a name plus the context the template is rendered from
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub event: String,
+    pub context: Value,
+}
+
+impl Notification {
+    pub fn new(event: impl Into<String>, context: Value) -> Self {
+        Self {
+            event: event.into(),
+            context,
+        }
+    }
+
+    /// Look up a dotted path (`n1.t`, `sig.rssi`, `event`) in the context
+    fn lookup(&self, path: &str) -> Option<Value> {
+        if path == "event" {
+            return Some(Value::String(self.event.clone()));
+        }
+
+        let mut current = &self.context;
+        for key in path.split('.') {
+            current = match current {
+                Value::Object(map) => map.get(key)?,
+                Value::Array(items) => items.get(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(current.clone())
+    }
+}
+
+/// Render a body template against a notification
+///
+/// A string that is exactly one placeholder (`"{{n1.t}}"`) is replaced by the
+/// typed value, so numbers stay numbers. Placeholders embedded in longer
+/// strings are interpolated as text. Unknown paths render as `null` / `""`.
+pub fn render_template(template: &Value, notification: &Notification) -> Value {
+    match template {
+        Value::String(s) => render_string(s, notification),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_template(item, notification))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), render_template(v, notification)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn render_string(s: &str, notification: &Notification) -> Value {
+    let trimmed = s.trim();
+    if let Some(path) = trimmed
+        .strip_prefix("{{")
+        .and_then(|rest| rest.strip_suffix("}}"))
+    {
+        if !path.contains("{{") && !path.contains("}}") {
+            return notification.lookup(path.trim()).unwrap_or(Value::Null);
+        }
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let path = rest[start + 2..start + len].trim();
+        match notification.lookup(path) {
+            Some(Value::String(text)) => out.push_str(&text),
+            Some(Value::Null) | None => {}
+            Some(value) => out.push_str(&value.to_string()),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+    Value::String(out)
+}
+
+/// Compute the `X-Signature-256` header value for a body
+pub fn sign_payload(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Why a single delivery attempt failed
+#[derive(Debug, thiserror::Error)]
+pub enum DeliveryError {
+    #[error("endpoint returned HTTP {0}")]
+    Status(u16),
+    #[error("request failed: {0}")]
+    Transport(#[from] reqwest::Error),
+}
+
+impl DeliveryError {
+    /// Transport errors, timeouts, rate limiting and server errors are worth retrying
+    fn is_retryable(&self) -> bool {
+        match self {
+            DeliveryError::Status(code) => *code == 408 || *code == 429 || *code >= 500,
+            DeliveryError::Transport(_) => true,
+        }
+    }
+}
+
+/// Webhook client: renders, signs and delivers notifications
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    method: reqwest::Method,
+    headers: BTreeMap<String, String>,
+    template: Option<Value>,
+    secret: Option<Vec<u8>>,
+    events: Vec<String>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    queue_capacity: usize,
+    dead_letter_path: PathBuf,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookConfig) -> Result<Self> {
+        if config.url.is_empty() {
+            bail!("webhook.url must be set");
+        }
+        reqwest::Url::parse(&config.url)
+            .with_context(|| format!("Invalid webhook URL {}", config.url))?;
+
+        let method = reqwest::Method::from_bytes(config.method.to_uppercase().as_bytes())
+            .with_context(|| format!("Invalid webhook method {}", config.method))?;
+
+        let secret = match (&config.secret, &config.secret_env) {
+            (Some(secret), _) => Some(secret.as_bytes().to_vec()),
+            (None, Some(var)) => Some(
+                std::env::var(var)
+                    .with_context(|| format!("Webhook secret variable {} not set", var))?
+                    .into_bytes(),
+            ),
+            (None, None) => None,
+        };
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .context("Failed to build webhook HTTP client")?;
+
+        Ok(Self {
+            client,
+            url: config.url.clone(),
+            method,
+            headers: config.headers.clone(),
+            template: config.template.clone(),
+            secret,
+            events: config.events.clone(),
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            queue_capacity: config.queue_capacity.max(1),
+            dead_letter_path: config.dead_letter_path.clone(),
+        })
+    }
+
+    /// Render the JSON body for a notification
+    pub fn render_body(&self, notification: &Notification) -> Value {
+        match &self.template {
+            Some(template) => render_template(template, notification),
+            None => json!({
+                "event": notification.event,
+                "data": notification.context,
+            }),
+        }
+    }
+
+    /// Delay before retry number `retry` (1-based)
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    async fn send_once(&self, body: &[u8]) -> Result<(), DeliveryError> {
+        let mut request = self
+            .client
+            .request(self.method.clone(), &self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, sign_payload(secret, body));
+        }
+
+        let response = request.body(body.to_vec()).send().await?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(DeliveryError::Status(status.as_u16()))
+        }
+    }
+
+    /// Deliver a notification, retrying with backoff and dead-lettering on failure
+    ///
+    /// Returns `true` if the endpoint accepted it.
+    pub async fn deliver(&self, notification: &Notification) -> bool {
+        let body = self.render_body(notification);
+        let bytes = body.to_string().into_bytes();
+
+        let mut attempt = 1;
+        loop {
+            match self.send_once(&bytes).await {
+                Ok(()) => {
+                    debug!(event = %notification.event, attempt, "Webhook delivered");
+                    return true;
+                }
+                Err(e) if e.is_retryable() && attempt < self.max_attempts => {
+                    let delay = self.backoff(attempt);
+                    warn!(
+                        event = %notification.event,
+                        attempt,
+                        error = %e,
+                        retry_in_ms = delay.as_millis() as u64,
+                        "Webhook delivery failed, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    error!(
+                        event = %notification.event,
+                        attempt,
+                        error = %e,
+                        "Webhook delivery failed, dead-lettering"
+                    );
+                    self.dead_letter(notification, &body, attempt, &e.to_string());
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Append an undeliverable message to the dead-letter log
+    fn dead_letter(&self, notification: &Notification, body: &Value, attempts: u32, reason: &str) {
+        let entry = json!({
+            "failed_at_ms": unix_time_ms(),
+            "event": notification.event,
+            "url": self.url,
+            "attempts": attempts,
+            "error": reason,
+            "body": body,
+        });
+
+        let result = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.dead_letter_path)
+            .and_then(|mut file| writeln!(file, "{}", entry));
+
+        if let Err(e) = result {
+            error!(
+                error = %e,
+                path = %self.dead_letter_path.display(),
+                "Failed to write webhook dead-letter log"
+            );
+        }
+    }
+
+    /// Start the delivery task and return a handle for queueing notifications
+    pub fn spawn(self) -> (WebhookHandle, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::channel::<Notification>(self.queue_capacity);
+        let notifier = std::sync::Arc::new(self);
+
+        let handle = WebhookHandle {
+            tx,
+            notifier: notifier.clone(),
+        };
+
+        let task = tokio::spawn(async move {
+            info!(url = %notifier.url, "Starting webhook notifier");
+            while let Some(notification) = rx.recv().await {
+                notifier.deliver(&notification).await;
+            }
+            info!("Webhook notifier stopped");
+        });
+
+        (handle, task)
+    }
+}
+
+/// Cheap handle used by the processor to queue notifications without blocking
+#[derive(Clone)]
+pub struct WebhookHandle {
+    tx: mpsc::Sender<Notification>,
+    notifier: std::sync::Arc<WebhookNotifier>,
+}
+
+impl WebhookHandle {
+    /// Queue a notification if its event is enabled, waiting while the queue is full
+    pub async fn send(&self, notification: Notification) -> Result<()> {
+        if !self
+            .notifier
+            .events
+            .iter()
+            .any(|e| e == &notification.event)
+        {
+            return Ok(());
+        }
+
+        self.tx
+            .send(notification)
+            .await
+            .map_err(|_| anyhow::anyhow!("Webhook notifier stopped"))
+    }
+}
+
+impl TelemetrySink for WebhookHandle {
+    /// Send the record as `telemetry`, then each of its anomalies as
+    /// `anomaly` (the anomaly's fields plus the record's `id` and `ts_ms`)
+    async fn deliver(&self, record: &ProcessedRecord) -> Result<()> {
+        let context = serde_json::to_value(record).context("Failed to serialize record")?;
+        self.send(Notification::new("telemetry", context)).await?;
+
+        for anomaly in &record.anomalies {
+            let mut context = serde_json::to_value(anomaly)?;
+            context["id"] = json!(record.packet.id);
+            context["ts_ms"] = json!(record.timestamp_ms());
+            self.send(Notification::new("anomaly", context)).await?;
+        }
+        Ok(())
+    }
+
+    fn accepts_summaries(&self) -> bool {
+        true
+    }
+
+    fn accepts_units(&self) -> bool {
+        true
+    }
+
+    /// Send the window summary as `summary`
+    async fn deliver_summary(&self, summary: &WindowSummary) -> Result<()> {
+        let context = serde_json::to_value(summary).context("Failed to serialize summary")?;
+        self.send(Notification::new("summary", context)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Request captured by the HTTP stub
+    #[derive(Debug, Clone)]
+    struct StubRequest {
+        head: String,
+        body: Vec<u8>,
+    }
+
+    impl StubRequest {
+        fn header(&self, name: &str) -> Option<String> {
+            self.head.lines().find_map(|line| {
+                let (k, v) = line.split_once(':')?;
+                k.eq_ignore_ascii_case(name).then(|| v.trim().to_string())
+            })
+        }
+    }
+
+    /// Minimal HTTP/1.1 stub answering with the given status codes in order
+    /// (the last one repeats)
+    async fn spawn_stub(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<StubRequest>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let captured = requests.clone();
+
+        tokio::spawn(async move {
+            let mut index = 0;
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 1024];
+
+                let head_end = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                    if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break pos + 4;
+                    }
+                };
+                let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+                let request = StubRequest {
+                    head,
+                    body: Vec::new(),
+                };
+                let length: usize = request
+                    .header("content-length")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                while data.len() < head_end + length {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                }
+                captured.lock().unwrap().push(StubRequest {
+                    body: data[head_end..head_end + length].to_vec(),
+                    ..request
+                });
+
+                let status = statuses[index.min(statuses.len() - 1)];
+                index += 1;
+                let response = format!(
+                    "HTTP/1.1 {} Stub\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn test_config(url: String, dead_letter_path: PathBuf) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            max_attempts: 3,
+            dead_letter_path,
+            ..WebhookConfig::default()
+        }
+    }
+
+    fn sample_notification() -> Notification {
+        Notification::new(
+            "telemetry",
+            json!({"ts": 12000, "id": "N2", "n1": {"t": 23.5, "h": 45.0}, "sig": {"rssi": -40}}),
+        )
+    }
+
+    #[test]
+    fn test_render_template_typed_and_interpolated() {
+        let template = json!({
+            "text": "{{id}} reports {{n1.t}}C ({{event}})",
+            "temperature": "{{n1.t}}",
+            "missing": "{{n2.p}}",
+            "fixed": 1
+        });
+        let rendered = render_template(&template, &sample_notification());
+        assert_eq!(
+            rendered,
+            json!({
+                "text": "N2 reports 23.5C (telemetry)",
+                "temperature": 23.5,
+                "missing": null,
+                "fixed": 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_sign_payload_known_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_payload(b"Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delivery_is_signed_and_retried() {
+        let (url, requests) = spawn_stub(vec![503, 200]).await;
+        let dir = std::env::temp_dir().join(format!("webhook-ok-{}", std::process::id()));
+        let config = WebhookConfig {
+            secret: Some("s3cret".to_string()),
+            headers: BTreeMap::from([("X-Site".to_string(), "lab".to_string())]),
+            ..test_config(url, dir.join("dead.ndjson"))
+        };
+        let notifier = WebhookNotifier::new(&config).unwrap();
+
+        assert!(notifier.deliver(&sample_notification()).await);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let last = &requests[1];
+        assert!(last.head.starts_with("POST /hook"));
+        assert_eq!(last.header("x-site").as_deref(), Some("lab"));
+        assert_eq!(
+            last.header(SIGNATURE_HEADER),
+            Some(sign_payload(b"s3cret", &last.body))
+        );
+        let body: Value = serde_json::from_slice(&last.body).unwrap();
+        assert_eq!(body["event"], "telemetry");
+        assert_eq!(body["data"]["n1"]["t"], 23.5);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_retries_are_dead_lettered() {
+        let (url, requests) = spawn_stub(vec![500]).await;
+        let dir = std::env::temp_dir().join(format!("webhook-dlq-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dead_letter = dir.join("dead.ndjson");
+        let _ = std::fs::remove_file(&dead_letter);
+
+        let notifier = WebhookNotifier::new(&test_config(url, dead_letter.clone())).unwrap();
+        assert!(!notifier.deliver(&sample_notification()).await);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        let log = std::fs::read_to_string(&dead_letter).unwrap();
+        let entry: Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+        assert_eq!(entry["attempts"], 3);
+        assert_eq!(entry["event"], "telemetry");
+        assert_eq!(entry["body"]["data"]["id"], "N2");
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let (url, requests) = spawn_stub(vec![400]).await;
+        let dir = std::env::temp_dir().join(format!("webhook-4xx-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let notifier = WebhookNotifier::new(&test_config(url, dir.join("dead.ndjson"))).unwrap();
+        assert!(!notifier.deliver(&sample_notification()).await);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,80 @@
+//! Build a flash config image for one node
+//!
+//! ```text
+//! cargo run -p node-protocol --example provision -- <address> <out.bin> <node>=<key hex>...
+//! ```
+//!
+//! node1 gets its own key, node2 the key of every sensor node it serves:
+//!
+//! ```text
+//! cargo run -p node-protocol --example provision -- 1 node1.bin 1=<32 hex digits>
+//! cargo run -p node-protocol --example provision -- 2 node2.bin 1=<32 hex digits>
+//! probe-rs download --chip STM32F446RETx --binary-format bin --base-address 0x08060000 node1.bin
+//! ```
+//!
+//! Generate keys with e.g. `openssl rand -hex 16`, and use a new one
+//! whenever you re-provision (flashing erases the frame-counter epoch logs).
+
+use std::process::ExitCode;
+
+use node_protocol::provision::{FlashConfig, MAX_CONFIG_LEN};
+use node_protocol::secure::Key;
+
+fn parse_key(hex: &str) -> Option<Key> {
+    if hex.len() != 32 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0u8; 16];
+    for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(key)
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let [address, out, keys @ ..] = args else {
+        return Err("usage: provision <address> <out.bin> <node>=<key hex>...".into());
+    };
+    if keys.is_empty() {
+        return Err("at least one <node>=<key hex> is required".into());
+    }
+
+    let address = address
+        .parse()
+        .map_err(|_| format!("bad address '{}'", address))?;
+    let mut config = FlashConfig::new(address);
+    for entry in keys {
+        let (node, key) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("expected <node>=<key hex>, got '{}'", entry))?;
+        let node = node.parse().map_err(|_| format!("bad node '{}'", node))?;
+        let key =
+            parse_key(key).ok_or_else(|| format!("key for node {} must be 32 hex digits", node))?;
+        config
+            .add_key(node, key)
+            .map_err(|e| format!("node {}: {}", node, e))?;
+    }
+
+    let mut image = [0u8; MAX_CONFIG_LEN];
+    let len = config.encode(&mut image).map_err(|e| e.to_string())?;
+    std::fs::write(out, &image[..len]).map_err(|e| format!("writing {}: {}", out, e))?;
+    println!(
+        "Wrote {} ({} bytes, address {}, {} key(s))",
+        out,
+        len,
+        address,
+        keys.len()
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,687 @@
+//! Adaptive data rate (ADR) for the node1 -> node2 link
+//!
+//! node2 watches the SNR of node1's packets and picks node1's spreading
+//! factor and transmit power (`AdrController`), LoRaWAN style: the best SNR
+//! of the last `HISTORY` packets, less the current SF's demodulation floor
+//! and an installation margin, is turned into 3 dB steps. Spare steps lower
+//! the SF first (shorter airtime), then the power; missing steps raise the
+//! power first, then the SF.
+//!
+//! A change rides on an ACK (`AckPacket::radio`). node2 switches its own
+//! radio to the new SF once the module has sent it (`AdrSwitch`); node1
+//! switches when it gets
+//! it (`AdrFollower`) and confirms by reporting the new settings in its next
+//! packet (`SensorDataPacket::radio`). If the ACK is lost the two end up on
+//! different settings and can't hear each other, so both go back to the
+//! settings from before the change: node2 after `pending_timeout_ms` without
+//! the confirming packet, node1 after `FALLBACK_MISSES` unacknowledged
+//! packets. ADR only raises the SF when the link needs it, so the boot
+//! settings are no place to meet after a loss. When the link is lost on
+//! confirmed settings both move to `RadioConfig::FALLBACK`, the most robust
+//! ones: node1 after `FALLBACK_MISSES` unacknowledged packets, node2 after
+//! `fallback_ms` without a packet.
+
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Lowest spreading factor ADR picks
+pub const MIN_SF: u8 = 7;
+/// Highest spreading factor the RYLR998 supports
+pub const MAX_SF: u8 = 11;
+/// Highest `AT+CRFOP` output power in dBm
+pub const MAX_TX_POWER_DBM: u8 = 22;
+
+/// Bandwidth both nodes run at, in kHz (`AT+PARAMETER` code 9)
+pub const BANDWIDTH_KHZ: u16 = 500;
+
+/// `AT+PARAMETER` fields ADR leaves alone: bandwidth code 9 (500 kHz),
+/// coding rate 4/5, preamble length 7
+const BANDWIDTH_CODE: u8 = 9;
+const CODING_RATE: u8 = 1;
+const PREAMBLE: u8 = 7;
+
+/// dB per ADR step
+const STEP_DB: i16 = 3;
+
+/// SNR samples a decision is based on
+pub const HISTORY: usize = 8;
+
+/// Unacknowledged packets after which node1 reverts or falls back
+pub const FALLBACK_MISSES: u8 = 3;
+
+/// How long node2 waits for the LoRa module's answer to a command, in ms
+pub const REPLY_TIMEOUT_MS: u32 = 2_000;
+
+/// node1's radio settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RadioConfig {
+    pub spreading_factor: u8,
+    /// Output power in dBm (`AT+CRFOP`)
+    pub tx_power_dbm: u8,
+}
+
+impl RadioConfig {
+    /// Settings both nodes boot with
+    pub const BOOT: Self = Self {
+        spreading_factor: MIN_SF,
+        tx_power_dbm: MAX_TX_POWER_DBM,
+    };
+
+    /// Most robust settings, where both nodes meet when the link is lost
+    pub const FALLBACK: Self = Self {
+        spreading_factor: MAX_SF,
+        tx_power_dbm: MAX_TX_POWER_DBM,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (MIN_SF..=MAX_SF).contains(&self.spreading_factor) && self.tx_power_dbm <= MAX_TX_POWER_DBM
+    }
+
+    /// `AT+PARAMETER=...` for this spreading factor (both nodes)
+    pub fn parameter_command(&self) -> ParameterCommand {
+        ParameterCommand(self.spreading_factor)
+    }
+
+    /// `AT+CRFOP=...` for this output power (node1)
+    pub fn power_command(&self) -> PowerCommand {
+        PowerCommand(self.tx_power_dbm)
+    }
+}
+
+/// `AT+PARAMETER` command text (without `\r\n`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParameterCommand(u8);
+
+impl fmt::Display for ParameterCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AT+PARAMETER={},{},{},{}",
+            self.0, BANDWIDTH_CODE, CODING_RATE, PREAMBLE
+        )
+    }
+}
+
+/// `AT+CRFOP` command text (without `\r\n`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerCommand(u8);
+
+impl fmt::Display for PowerCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AT+CRFOP={}", self.0)
+    }
+}
+
+/// Lowest SNR a spreading factor still demodulates, in tenths of a dB
+pub fn snr_floor_tenths_db(spreading_factor: u8) -> i16 {
+    // -7.5 dB at SF7, 2.5 dB lower per step, as in the Semtech datasheets
+    -25 * (spreading_factor as i16 - 4)
+}
+
+/// Lowest SNR (dB) a spreading factor still demodulates, rounded towards 0
+pub fn snr_floor_db(spreading_factor: u8) -> i16 {
+    snr_floor_tenths_db(spreading_factor) / 10
+}
+
+/// Tuning for `AdrController`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdrPolicy {
+    /// SNR headroom kept above the demodulation floor, in dB
+    pub margin_db: i16,
+    /// Lowest output power ADR picks, in dBm
+    pub min_tx_power_dbm: u8,
+    /// How long node2 waits for the packet confirming a change before
+    /// going back to the previous settings, in ms
+    pub pending_timeout_ms: u32,
+    /// How long node2 waits for a packet before falling back, in ms
+    pub fallback_ms: u32,
+}
+
+impl Default for AdrPolicy {
+    fn default() -> Self {
+        Self {
+            margin_db: 10,
+            min_tx_power_dbm: 10,
+            // Past node1's next packet (10 s interval) and its retries, but
+            // well before node1 gives up on the change (3 misses)
+            pending_timeout_ms: 15_000,
+            // Well past node1's own fallback (3 misses at a 10 s interval)
+            fallback_ms: 60_000,
+        }
+    }
+}
+
+/// Next settings for `current` given the best recent SNR
+pub fn decide(current: RadioConfig, best_snr_db: i16, policy: &AdrPolicy) -> RadioConfig {
+    let spare = best_snr_db - snr_floor_db(current.spreading_factor) - policy.margin_db;
+    let mut steps = spare.div_euclid(STEP_DB);
+    let mut next = current;
+
+    while steps > 0 && next.spreading_factor > MIN_SF {
+        next.spreading_factor -= 1;
+        steps -= 1;
+    }
+    while steps > 0 && next.tx_power_dbm >= policy.min_tx_power_dbm + STEP_DB as u8 {
+        next.tx_power_dbm -= STEP_DB as u8;
+        steps -= 1;
+    }
+    while steps < 0 && next.tx_power_dbm < MAX_TX_POWER_DBM {
+        next.tx_power_dbm = (next.tx_power_dbm + STEP_DB as u8).min(MAX_TX_POWER_DBM);
+        steps += 1;
+    }
+    while steps < 0 && next.spreading_factor < MAX_SF {
+        next.spreading_factor += 1;
+        steps += 1;
+    }
+    next
+}
+
+/// Change sent to node1 and not confirmed yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pending {
+    target: RadioConfig,
+    previous: RadioConfig,
+}
+
+/// node2's side: decides, sends and tracks changes
+#[derive(Debug, Clone)]
+pub struct AdrController {
+    policy: AdrPolicy,
+    /// Settings node1 is believed to use (node2 listens on its SF)
+    current: RadioConfig,
+    pending: Option<Pending>,
+    snr: [i16; HISTORY],
+    samples: usize,
+    last_heard_ms: u32,
+    /// Settings node2's radio was on before the last switch
+    before_switch: RadioConfig,
+}
+
+impl AdrController {
+    pub fn new(policy: AdrPolicy, uptime_ms: u32) -> Self {
+        Self {
+            policy,
+            current: RadioConfig::BOOT,
+            pending: None,
+            snr: [0; HISTORY],
+            samples: 0,
+            last_heard_ms: uptime_ms,
+            before_switch: RadioConfig::BOOT,
+        }
+    }
+
+    /// Settings node2's own radio should be on
+    pub fn current(&self) -> RadioConfig {
+        self.current
+    }
+
+    /// A valid packet arrived with node1 reporting `reported` settings
+    ///
+    /// Returns the settings to piggyback on this packet's ACK, if they
+    /// should change. node2 switches its own SF right after sending it.
+    pub fn on_packet(
+        &mut self,
+        snr_db: i16,
+        reported: RadioConfig,
+        uptime_ms: u32,
+    ) -> Option<RadioConfig> {
+        self.last_heard_ms = uptime_ms;
+
+        if let Some(pending) = self.pending {
+            if reported != pending.target {
+                // Heard on the new SF but node1 reports other settings: the
+                // command was garbled; keep listening here and resend it
+                return Some(pending.target);
+            }
+            self.pending = None;
+            self.samples = 0;
+        } else if reported != self.current {
+            // node1 rebooted or fell back on its own: follow it
+            self.current = reported;
+            self.samples = 0;
+        }
+
+        self.snr[self.samples % HISTORY] = snr_db;
+        self.samples += 1;
+        if self.samples < HISTORY {
+            return None;
+        }
+
+        let best = self.snr.iter().copied().max().unwrap_or(snr_db);
+        let next = decide(self.current, best, &self.policy);
+        if next == self.current {
+            return None;
+        }
+        self.pending = Some(Pending {
+            target: next,
+            previous: self.current,
+        });
+        self.switch(next);
+        Some(next)
+    }
+
+    /// Call periodically; returns settings to switch node2's radio to
+    ///
+    /// The settings from before an unconfirmed change once node1 has been
+    /// silent for `pending_timeout_ms`, `RadioConfig::FALLBACK` once it has
+    /// been silent for `fallback_ms` on confirmed settings.
+    pub fn poll(&mut self, uptime_ms: u32) -> Option<RadioConfig> {
+        let silent_ms = uptime_ms.wrapping_sub(self.last_heard_ms);
+        let next = match self.pending {
+            Some(pending) if silent_ms >= self.policy.pending_timeout_ms => pending.previous,
+            None if silent_ms >= self.policy.fallback_ms
+                && self.current != RadioConfig::FALLBACK =>
+            {
+                RadioConfig::FALLBACK
+            }
+            _ => return None,
+        };
+        self.pending = None;
+        self.switch(next);
+        self.last_heard_ms = uptime_ms;
+        Some(next)
+    }
+
+    /// node2's radio didn't take the settings from the last `on_packet` or
+    /// `poll`: stay on the ones it still uses
+    ///
+    /// If the ACK with the change went out anyway, node1 goes back to them
+    /// on its own after `FALLBACK_MISSES` unacknowledged packets.
+    pub fn switch_failed(&mut self) {
+        self.current = self.before_switch;
+        self.pending = None;
+        self.samples = 0;
+    }
+
+    fn switch(&mut self, next: RadioConfig) {
+        self.before_switch = self.current;
+        self.current = next;
+        self.samples = 0;
+    }
+
+    /// Settings before the unconfirmed change, if one is in flight
+    pub fn pending_from(&self) -> Option<RadioConfig> {
+        self.pending.map(|pending| pending.previous)
+    }
+}
+
+/// What node2's radio switch waits for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SwitchState {
+    Idle,
+    /// The ACK carrying `target` went to the module; switch once it's sent
+    AckSent { target: RadioConfig, since_ms: u32 },
+    /// `AT+PARAMETER` for `target` went to the module
+    Switching { target: RadioConfig, since_ms: u32 },
+}
+
+/// What node2 does next in a radio switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchStep {
+    /// Nothing to do
+    Wait,
+    /// The ACK is out: send `AT+PARAMETER` for these settings
+    SendParameter(RadioConfig),
+    /// The radio runs on these settings now
+    Done(RadioConfig),
+    /// The module refused or didn't answer: `AdrController::switch_failed`
+    Failed,
+}
+
+/// node2's side of a radio switch, driven by the module's `+OK`/`+ERR`
+/// replies so nothing waits for them in an interrupt
+///
+/// The module answers commands in order; replies to plain ACKs sent while
+/// a switch is in flight are counted and skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdrSwitch {
+    state: SwitchState,
+    /// Replies due before the one `state` waits for
+    skip: u8,
+}
+
+impl Default for AdrSwitch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdrSwitch {
+    pub const fn new() -> Self {
+        Self {
+            state: SwitchState::Idle,
+            skip: 0,
+        }
+    }
+
+    /// No switch in flight
+    pub fn is_idle(&self) -> bool {
+        self.state == SwitchState::Idle
+    }
+
+    /// An ACK went to the module, carrying `radio` from `AdrController::on_packet`
+    ///
+    /// Returns false if a switch is already in flight and this one can't
+    /// start; call `AdrController::switch_failed` then.
+    pub fn ack_sent(&mut self, radio: Option<RadioConfig>, uptime_ms: u32) -> bool {
+        match (self.state, radio) {
+            (SwitchState::Idle, Some(target)) => {
+                self.state = SwitchState::AckSent {
+                    target,
+                    since_ms: uptime_ms,
+                };
+                true
+            }
+            (SwitchState::Idle, None) => true,
+            (_, radio) => {
+                self.skip = self.skip.saturating_add(1);
+                radio.is_none()
+            }
+        }
+    }
+
+    /// `AT+PARAMETER` for `target` went to the module (after `SendParameter`
+    /// or for a change from `AdrController::poll` while idle)
+    pub fn parameter_sent(&mut self, target: RadioConfig, uptime_ms: u32) {
+        self.state = SwitchState::Switching {
+            target,
+            since_ms: uptime_ms,
+        };
+    }
+
+    /// The module answered "+OK" (`ok`) or "+ERR"
+    pub fn on_reply(&mut self, ok: bool) -> SwitchStep {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return SwitchStep::Wait;
+        }
+        let step = match (self.state, ok) {
+            (SwitchState::Idle, _) => return SwitchStep::Wait,
+            (SwitchState::AckSent { target, .. }, true) => SwitchStep::SendParameter(target),
+            (SwitchState::Switching { target, .. }, true) => SwitchStep::Done(target),
+            (_, false) => SwitchStep::Failed,
+        };
+        self.state = SwitchState::Idle;
+        step
+    }
+
+    /// Call periodically; gives up once the module has been silent for
+    /// `REPLY_TIMEOUT_MS`
+    pub fn poll(&mut self, uptime_ms: u32) -> SwitchStep {
+        let since_ms = match self.state {
+            SwitchState::Idle => return SwitchStep::Wait,
+            SwitchState::AckSent { since_ms, .. } | SwitchState::Switching { since_ms, .. } => {
+                since_ms
+            }
+        };
+        if uptime_ms.wrapping_sub(since_ms) < REPLY_TIMEOUT_MS {
+            return SwitchStep::Wait;
+        }
+        *self = Self::new();
+        SwitchStep::Failed
+    }
+}
+
+/// node1's side: applies commands from ACKs, reverts when ACKs stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdrFollower {
+    current: RadioConfig,
+    /// Settings before a change no ACK has confirmed yet
+    unconfirmed_from: Option<RadioConfig>,
+    misses: u8,
+}
+
+impl Default for AdrFollower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdrFollower {
+    pub const fn new() -> Self {
+        Self {
+            current: RadioConfig::BOOT,
+            unconfirmed_from: None,
+            misses: 0,
+        }
+    }
+
+    /// Settings to transmit with (and report in sensor packets)
+    pub fn current(&self) -> RadioConfig {
+        self.current
+    }
+
+    /// An ACK arrived; returns settings to switch to
+    ///
+    /// Any ACK heard on the current settings confirms them.
+    pub fn on_ack(&mut self, command: Option<RadioConfig>) -> Option<RadioConfig> {
+        self.misses = 0;
+        let Some(command) = command.filter(|c| c.is_valid() && *c != self.current) else {
+            self.unconfirmed_from = None;
+            return None;
+        };
+        self.unconfirmed_from = Some(self.current);
+        self.current = command;
+        Some(command)
+    }
+
+    /// A packet went unacknowledged; after `FALLBACK_MISSES` in a row returns
+    /// the settings from before an unconfirmed change, or else
+    /// `RadioConfig::FALLBACK`
+    pub fn on_miss(&mut self) -> Option<RadioConfig> {
+        self.misses = self.misses.saturating_add(1);
+        if self.misses < FALLBACK_MISSES {
+            return None;
+        }
+        self.misses = 0;
+        let next = self
+            .unconfirmed_from
+            .take()
+            .unwrap_or(RadioConfig::FALLBACK);
+        if next == self.current {
+            return None;
+        }
+        self.current = next;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::string::ToString;
+
+    const BOOT: RadioConfig = RadioConfig::BOOT;
+    const FALLBACK: RadioConfig = RadioConfig::FALLBACK;
+
+    fn config(spreading_factor: u8, tx_power_dbm: u8) -> RadioConfig {
+        RadioConfig {
+            spreading_factor,
+            tx_power_dbm,
+        }
+    }
+
+    #[test]
+    fn test_at_commands() {
+        let c = config(9, 16);
+        assert_eq!(c.parameter_command().to_string(), "AT+PARAMETER=9,9,1,7");
+        assert_eq!(c.power_command().to_string(), "AT+CRFOP=16");
+        assert_eq!(snr_floor_db(7), -7);
+        assert_eq!(snr_floor_db(11), -17);
+        assert!(!config(12, 22).is_valid());
+    }
+
+    #[test]
+    fn test_decide_steps() {
+        let policy = AdrPolicy::default();
+        // SF7 floor -7 dB + 10 dB margin: SNR 3 dB is exactly enough
+        assert_eq!(decide(BOOT, 3, &policy), BOOT);
+        // 12 dB to spare: power down in 3 dB steps (SF7 is the floor)
+        assert_eq!(decide(BOOT, 15, &policy), config(7, 10));
+        // From SF9, spare steps go to the SF first
+        assert_eq!(decide(config(9, 22), 1, &policy), config(8, 22));
+        // Weak: raise the power first, then the SF
+        assert_eq!(decide(config(7, 16), -4, &policy), config(8, 22));
+        assert_eq!(decide(config(11, 22), -30, &policy), config(11, 22));
+    }
+
+    /// Feed `HISTORY` packets with the given SNR
+    fn feed(
+        adr: &mut AdrController,
+        snr: i16,
+        reported: RadioConfig,
+        t: &mut u32,
+    ) -> Option<RadioConfig> {
+        let mut command = None;
+        for _ in 0..HISTORY {
+            *t += 10_000;
+            command = adr.on_packet(snr, reported, *t).or(command);
+        }
+        command
+    }
+
+    #[test]
+    fn test_change_is_confirmed_by_the_next_packet() {
+        let mut t = 0;
+        let mut adr = AdrController::new(AdrPolicy::default(), t);
+        let mut node1 = AdrFollower::new();
+
+        let command = feed(&mut adr, 1, BOOT, &mut t);
+        assert_eq!(command, Some(config(8, 22)));
+        assert_eq!(adr.current(), config(8, 22));
+        assert_eq!(adr.pending_from(), Some(BOOT));
+
+        assert_eq!(node1.on_ack(command), Some(config(8, 22)));
+        assert_eq!(node1.on_ack(command), None);
+        t += 10_000;
+        assert_eq!(adr.on_packet(1, node1.current(), t), None);
+        assert_eq!(adr.pending_from(), None);
+    }
+
+    #[test]
+    fn test_lost_ack_on_weak_link_reverts_to_previous_settings() {
+        let mut t = 0;
+        let mut adr = AdrController::new(AdrPolicy::default(), t);
+        let mut node1 = AdrFollower::new();
+
+        // The link needed SF9 and node1 confirmed it
+        let weak = config(9, 22);
+        adr.on_packet(-4, weak, t);
+        node1.on_ack(Some(weak));
+        node1.on_ack(None);
+
+        // It gets weaker still: SF10 rides on an ACK that node1 never gets
+        let command = feed(&mut adr, -4, weak, &mut t);
+        assert_eq!(command, Some(config(10, 22)));
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), None);
+
+        // node2 goes back to SF9 before node1 gives up on it
+        assert_eq!(adr.poll(t + 14_500), None);
+        assert_eq!(adr.poll(t + 15_000), Some(weak));
+        assert_eq!(adr.current(), weak);
+        assert_eq!(node1.current(), weak);
+        node1.on_ack(None);
+        assert_eq!(adr.on_packet(-4, node1.current(), t + 20_000), None);
+
+        // Had node1 got the ACK but node2 missed the confirmation, node1
+        // goes back on its own
+        let mut node1 = AdrFollower::new();
+        node1.on_ack(Some(weak));
+        node1.on_ack(None);
+        assert_eq!(node1.on_ack(command), Some(config(10, 22)));
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), Some(weak));
+    }
+
+    #[test]
+    fn test_lost_link_falls_back_to_most_robust_settings() {
+        let mut t = 0;
+        let mut adr = AdrController::new(AdrPolicy::default(), t);
+        let mut node1 = AdrFollower::new();
+        feed(&mut adr, 14, BOOT, &mut t);
+        t += 10_000;
+        adr.on_packet(14, adr.current(), t);
+        assert_eq!(adr.pending_from(), None);
+
+        // node1 confirmed earlier changes but now its ACKs stop arriving
+        node1.on_ack(Some(config(7, 13)));
+        node1.on_ack(None);
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), Some(FALLBACK));
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), None);
+
+        assert_eq!(adr.poll(t + 59_000), None);
+        assert_eq!(adr.poll(t + 60_000), Some(FALLBACK));
+        assert_eq!(adr.poll(t + 200_000), None);
+        assert_eq!(adr.current(), FALLBACK);
+
+        // A rebooted node1 finds node2 there too
+        let mut node1 = AdrFollower::new();
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), None);
+        assert_eq!(node1.on_miss(), Some(FALLBACK));
+    }
+
+    #[test]
+    fn test_failed_switch_keeps_the_settings_in_use() {
+        let mut t = 0;
+        let mut adr = AdrController::new(AdrPolicy::default(), t);
+        assert_eq!(feed(&mut adr, 1, BOOT, &mut t), Some(config(8, 22)));
+        adr.switch_failed();
+        assert_eq!(adr.current(), BOOT);
+        assert_eq!(adr.pending_from(), None);
+
+        // node1 switched anyway: node2 keeps listening on the old settings
+        // and node1 comes back once its packets go unacknowledged
+        assert_eq!(adr.poll(t + 30_000), None);
+    }
+
+    #[test]
+    fn test_switch_follows_module_replies() {
+        let target = config(8, 22);
+        let mut switch = AdrSwitch::new();
+        assert!(switch.ack_sent(Some(target), 0));
+        // A plain ACK sent meanwhile: its reply comes first
+        assert!(switch.ack_sent(None, 10));
+        assert_eq!(switch.on_reply(true), SwitchStep::Wait);
+        assert_eq!(switch.on_reply(true), SwitchStep::SendParameter(target));
+        switch.parameter_sent(target, 20);
+        assert_eq!(switch.poll(1_000), SwitchStep::Wait);
+        assert_eq!(switch.on_reply(true), SwitchStep::Done(target));
+        assert!(switch.is_idle());
+
+        // Refused ACK: no switch
+        assert!(switch.ack_sent(Some(target), 3_000));
+        assert_eq!(switch.on_reply(false), SwitchStep::Failed);
+
+        // No answer at all
+        switch.parameter_sent(target, 4_000);
+        assert_eq!(switch.poll(5_999), SwitchStep::Wait);
+        assert_eq!(switch.poll(6_000), SwitchStep::Failed);
+        assert!(switch.is_idle());
+        assert_eq!(switch.on_reply(true), SwitchStep::Wait);
+
+        // A second switch can't start while one is in flight
+        assert!(switch.ack_sent(Some(target), 7_000));
+        assert!(!switch.ack_sent(Some(config(9, 22)), 7_100));
+    }
+
+    #[test]
+    fn test_follows_node1_reboot() {
+        let mut t = 0;
+        let mut adr = AdrController::new(AdrPolicy::default(), t);
+        feed(&mut adr, 1, BOOT, &mut t);
+        t += 10_000;
+        // node1 confirms, then reboots onto the boot settings
+        adr.on_packet(1, config(8, 22), t);
+        t += 10_000;
+        assert_eq!(adr.on_packet(0, BOOT, t), None);
+        assert_eq!(adr.current(), BOOT);
+    }
+}
@@ -0,0 +1,63 @@
+//! Downlink commands from gateway-service to node2 over the ST-Link VCP
+//!
+//! One ASCII command per line, `KEY=value\n`:
+//! - `TIME=<unix_ms>`: current wall-clock time from the host
+
+use core::fmt;
+
+/// Anything before 2020-01-01 is treated as a host with an unset clock
+pub const MIN_VALID_UNIX_MS: u64 = 1_577_836_800_000;
+
+/// A command sent down the VCP to node2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Downlink {
+    /// Set node2's wall clock
+    TimeSync { unix_ms: u64 },
+}
+
+impl fmt::Display for Downlink {
+    /// Wire format, without the trailing newline
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Downlink::TimeSync { unix_ms } => write!(f, "TIME={}", unix_ms),
+        }
+    }
+}
+
+/// Parse one received line (trailing `\r\n` allowed)
+pub fn parse_downlink(line: &str) -> Option<Downlink> {
+    let (key, value) = line.trim().split_once('=')?;
+    match key {
+        "TIME" => {
+            let unix_ms: u64 = value.parse().ok()?;
+            (unix_ms >= MIN_VALID_UNIX_MS).then_some(Downlink::TimeSync { unix_ms })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn test_time_sync_roundtrip() {
+        let cmd = Downlink::TimeSync {
+            unix_ms: 1_767_225_600_123,
+        };
+        let line = cmd.to_string();
+        assert_eq!(line, "TIME=1767225600123");
+        assert_eq!(parse_downlink(&line), Some(cmd));
+        assert_eq!(parse_downlink("TIME=1767225600123\r\n"), Some(cmd));
+    }
+
+    #[test]
+    fn test_rejects_garbage_and_unset_clocks() {
+        assert_eq!(parse_downlink("TIME=abc"), None);
+        assert_eq!(parse_downlink("TIME=1000"), None);
+        assert_eq!(parse_downlink("HELLO"), None);
+        assert_eq!(parse_downlink("FOO=1"), None);
+    }
+}
@@ -0,0 +1,22 @@
+//! Shared protocol definitions for the sensor network
+//!
+//! Used by node1 (sensor), node2 (LoRa gateway firmware) and gateway-service,
+//! so the wire formats can't drift apart:
+//! - `packet`: LoRa payloads (`SensorDataPacket`, `AckPacket`) and CRC-16
+//! - `secure`: AES-128-CCM framing of those payloads, with replay protection
+//! - `provision`: flash config holding each node's address and keys
+//! - `adr`: adaptive data rate, choosing node1's spreading factor and power
+//! - `downlink`: text commands sent from gateway-service to node2 over the VCP
+//! - `time`: wall-clock keeping on top of a millisecond uptime counter
+//!
+//! Everything here is `no_std` and free of hardware access so it can be
+//! unit-tested on the host with `cargo test -p node-protocol`.
+
+#![no_std]
+
+pub mod adr;
+pub mod downlink;
+pub mod packet;
+pub mod provision;
+pub mod secure;
+pub mod time;
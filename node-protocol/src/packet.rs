@@ -0,0 +1,94 @@
+//! LoRa payloads exchanged between node1 and node2
+//!
+//! Payloads are postcard-serialized and sent as `secure` frames, whose
+//! authentication tag covers integrity. `calculate_crc16` protects the
+//! flash config (`provision`).
+
+use serde::{Deserialize, Serialize};
+
+use crate::adr::RadioConfig;
+
+/// ACK: packet received, authenticated and fresh
+///
+/// The only message type node2 sends: a frame that fails to open can't be
+/// attributed or answered safely, so it gets no reply and node1 times out.
+pub const MSG_TYPE_ACK: u8 = 1;
+
+/// Sensor data packet for binary transmission (node1 -> node2)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SensorDataPacket {
+    pub seq_num: u16,        // Sequence number for duplicate detection
+    pub temperature: i16,    // Temperature in centidegrees (e.g., 2710 = 27.1°C)
+    pub humidity: u16,       // Humidity in basis points (e.g., 5600 = 56.0%)
+    pub gas_resistance: u32, // Gas resistance in ohms
+    pub timestamp: u32,      // Sample time, Unix seconds (0 = node1 clock not synced)
+    pub radio: RadioConfig,  // Settings node1 transmitted with (confirms ADR changes)
+}
+
+/// ACK packet for acknowledgment (node2 -> node1)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AckPacket {
+    pub msg_type: u8,               // MSG_TYPE_ACK
+    pub seq_num: u16,               // Which packet we're acknowledging
+    pub time: u32,                  // node2 wall clock, Unix seconds (0 = not synced)
+    pub radio: Option<RadioConfig>, // ADR: settings node1 should switch to
+}
+
+/// Calculate CRC-16 checksum for data integrity
+/// Uses CRC-16-IBM-3740 (CCITT with 0xFFFF initial value)
+pub fn calculate_crc16(data: &[u8]) -> u16 {
+    use crc::{Crc, CRC_16_IBM_3740};
+    const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
+    CRC16.checksum(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16_check_value() {
+        // Standard check value for CRC-16/IBM-3740
+        assert_eq!(calculate_crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn test_ack_roundtrip_stays_compact() {
+        let ack = AckPacket {
+            msg_type: MSG_TYPE_ACK,
+            seq_num: 42,
+            time: 1_767_225_600,
+            radio: None,
+        };
+        let mut buf = [0u8; 16];
+        let bytes = postcard::to_slice(&ack, &mut buf).unwrap();
+        assert!(bytes.len() <= 8, "ACK grew to {} bytes", bytes.len());
+        assert_eq!(postcard::from_bytes::<AckPacket>(bytes).unwrap(), ack);
+
+        let ack = AckPacket {
+            radio: Some(RadioConfig::BOOT),
+            ..ack
+        };
+        let bytes = postcard::to_slice(&ack, &mut buf).unwrap();
+        assert!(bytes.len() <= 10, "ADR ACK grew to {} bytes", bytes.len());
+        assert_eq!(postcard::from_bytes::<AckPacket>(bytes).unwrap(), ack);
+    }
+
+    #[test]
+    fn test_sensor_packet_roundtrip() {
+        let packet = SensorDataPacket {
+            seq_num: 7,
+            temperature: 271,
+            humidity: 5600,
+            gas_resistance: 123_456,
+            timestamp: 0,
+            radio: RadioConfig::BOOT,
+        };
+        let mut buf = [0u8; 32];
+        let bytes = postcard::to_slice(&packet, &mut buf).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<SensorDataPacket>(bytes).unwrap(),
+            packet
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Flash config: LoRa address, per-node keys and frame-counter epochs
+//!
+//! Both nodes keep the last 128 KB flash sector (sector 7 on the
+//! STM32F446RE, `SECTOR_OFFSET` from the start of flash) out of the linker's
+//! reach. It holds:
+//! - at `CONFIG_OFFSET`: a `FlashConfig` record, written when the node is
+//!   provisioned (`examples/provision.rs` builds the image)
+//! - from `EPOCH_LOG_OFFSET`: node1's epoch log, one byte per `FrameCounter`
+//!   epoch, programmed to `EPOCH_USED` before the epoch is counted in. The
+//!   log has room for all 65536 epochs, so the sector is never erased after
+//!   provisioning: a power loss can at worst skip an epoch, never lose the
+//!   config or hand out an epoch twice.
+//! - node2 keeps its own epoch log there: one little-endian word
+//!   `node << 16 | epoch` for each sensor node epoch it accepted frames in,
+//!   written before the first of them is acknowledged. That's the replay
+//!   high-water mark `ReplayGuard::resume` starts from after a reboot.
+//!
+//! Re-provisioning erases the whole sector, epoch logs included, so it must
+//! come with a new key - otherwise node1 would reuse nonces from epoch 0,
+//! and node2 would take replays of old frames.
+
+use core::fmt;
+
+use crate::packet::calculate_crc16;
+use crate::secure::Key;
+
+/// Config sector offset from the start of flash (0x0806_0000)
+pub const SECTOR_OFFSET: usize = 0x6_0000;
+/// Sector number for `erase`
+pub const SECTOR_NUMBER: u8 = 7;
+/// Sector size in bytes
+pub const SECTOR_LEN: usize = 0x2_0000;
+
+/// `FlashConfig` record offset within the sector
+pub const CONFIG_OFFSET: usize = 0;
+/// Epoch log offset within the sector
+pub const EPOCH_LOG_OFFSET: usize = 0x1000;
+/// Epoch log size in bytes
+pub const EPOCH_LOG_LEN: usize = SECTOR_LEN - EPOCH_LOG_OFFSET;
+/// What node1 programs into an epoch's log byte
+pub const EPOCH_USED: u8 = 0x00;
+
+// Room for every epoch, so the log never needs an erase
+const _: () = assert!(EPOCH_LOG_LEN > u16::MAX as usize);
+
+/// Keys a config can hold (node2 needs one per sensor node)
+pub const MAX_KEYS: usize = 4;
+/// Largest encoded `FlashConfig`
+pub const MAX_CONFIG_LEN: usize = HEADER_LEN + MAX_KEYS * ENTRY_LEN + 2;
+
+const MAGIC: [u8; 4] = *b"NCFG";
+const VERSION: u8 = 1;
+// Magic, version, address, key count
+const HEADER_LEN: usize = 7;
+// Node address, key
+const ENTRY_LEN: usize = 17;
+const ERASED: u8 = 0xFF;
+
+/// Why a config couldn't be built or read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Sector is blank: the node was never provisioned
+    Erased,
+    BadMagic,
+    UnsupportedVersion(u8),
+    BadCrc,
+    Truncated,
+    /// More than `MAX_KEYS` keys
+    TooManyKeys,
+    /// Two keys for the same node
+    DuplicateNode(u8),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Erased => write!(f, "not provisioned"),
+            ConfigError::BadMagic => write!(f, "bad magic"),
+            ConfigError::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            ConfigError::BadCrc => write!(f, "CRC mismatch"),
+            ConfigError::Truncated => write!(f, "truncated"),
+            ConfigError::TooManyKeys => write!(f, "more than {} keys", MAX_KEYS),
+            ConfigError::DuplicateNode(node) => write!(f, "duplicate key for node {}", node),
+        }
+    }
+}
+
+/// Provisioned settings of one node
+#[derive(Clone, PartialEq, Eq)]
+pub struct FlashConfig {
+    /// This node's LoRa address (`AT+ADDRESS`)
+    pub address: u8,
+    keys: [(u8, Key); MAX_KEYS],
+    key_count: usize,
+}
+
+// Keep keys out of logs
+impl fmt::Debug for FlashConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FlashConfig")
+            .field("address", &self.address)
+            .field("key_count", &self.key_count)
+            .finish()
+    }
+}
+
+impl FlashConfig {
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            keys: [(0, [0; 16]); MAX_KEYS],
+            key_count: 0,
+        }
+    }
+
+    /// Add the key of sensor node `node`
+    pub fn add_key(&mut self, node: u8, key: Key) -> Result<(), ConfigError> {
+        if self.key_for(node).is_some() {
+            return Err(ConfigError::DuplicateNode(node));
+        }
+        let slot = self
+            .keys
+            .get_mut(self.key_count)
+            .ok_or(ConfigError::TooManyKeys)?;
+        *slot = (node, key);
+        self.key_count += 1;
+        Ok(())
+    }
+
+    /// Key protecting frames of sensor node `node`
+    pub fn key_for(&self, node: u8) -> Option<&Key> {
+        self.keys[..self.key_count]
+            .iter()
+            .find(|(n, _)| *n == node)
+            .map(|(_, key)| key)
+    }
+
+    /// Sensor nodes with a key
+    pub fn nodes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.keys[..self.key_count].iter().map(|(node, _)| *node)
+    }
+
+    /// Serialize into `out`; returns the record length
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, ConfigError> {
+        let len = HEADER_LEN + self.key_count * ENTRY_LEN + 2;
+        let out = out.get_mut(..len).ok_or(ConfigError::Truncated)?;
+        out[..4].copy_from_slice(&MAGIC);
+        out[4] = VERSION;
+        out[5] = self.address;
+        out[6] = self.key_count as u8;
+        for (entry, (node, key)) in out[HEADER_LEN..]
+            .chunks_exact_mut(ENTRY_LEN)
+            .zip(&self.keys[..self.key_count])
+        {
+            entry[0] = *node;
+            entry[1..].copy_from_slice(key);
+        }
+        let crc = calculate_crc16(&out[..len - 2]);
+        out[len - 2..].copy_from_slice(&crc.to_be_bytes());
+        Ok(len)
+    }
+
+    /// Parse a record from the start of `bytes` (e.g. the whole sector)
+    pub fn decode(bytes: &[u8]) -> Result<Self, ConfigError> {
+        let header = bytes.get(..HEADER_LEN).ok_or(ConfigError::Truncated)?;
+        if header.iter().all(|&b| b == ERASED) {
+            return Err(ConfigError::Erased);
+        }
+        if header[..4] != MAGIC {
+            return Err(ConfigError::BadMagic);
+        }
+        if header[4] != VERSION {
+            return Err(ConfigError::UnsupportedVersion(header[4]));
+        }
+        let key_count = header[6] as usize;
+        if key_count > MAX_KEYS {
+            return Err(ConfigError::TooManyKeys);
+        }
+        let len = HEADER_LEN + key_count * ENTRY_LEN + 2;
+        let record = bytes.get(..len).ok_or(ConfigError::Truncated)?;
+        let crc = u16::from_be_bytes([record[len - 2], record[len - 1]]);
+        if calculate_crc16(&record[..len - 2]) != crc {
+            return Err(ConfigError::BadCrc);
+        }
+
+        let mut config = Self::new(header[5]);
+        for entry in record[HEADER_LEN..len - 2].chunks_exact(ENTRY_LEN) {
+            let mut key = [0u8; 16];
+            key.copy_from_slice(&entry[1..]);
+            config.add_key(entry[0], key)?;
+        }
+        Ok(config)
+    }
+}
+
+/// Where node1's next frame-counter epoch gets recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochSlot {
+    /// Epoch to count in after recording it
+    pub epoch: u16,
+    /// Byte to program with `EPOCH_USED`, within the epoch log
+    pub offset: usize,
+}
+
+/// Where node2 records a sensor node's epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeenEpochSlot {
+    /// Byte offset of `word`, within the epoch log
+    pub offset: usize,
+    pub word: [u8; 4],
+}
+
+/// Highest epoch recorded for `node` in node2's epoch log
+pub fn last_seen_epoch(log: &[u8], node: u8) -> Option<u16> {
+    // A word half-written at power loss only has extra bits set: it reads as
+    // a later epoch (or another node), which is merely stricter
+    log.chunks_exact(4)
+        .filter(|w| w != &[ERASED; 4] && w[2] == node)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]))
+        .max()
+}
+
+/// Where to record `epoch` of `node` in node2's epoch log; `None` when full
+pub fn seen_epoch_slot(log: &[u8], node: u8, epoch: u16) -> Option<SeenEpochSlot> {
+    let words = log.len() / 4;
+    let next = log
+        .chunks_exact(4)
+        .rposition(|w| w != [ERASED; 4])
+        .map_or(0, |last| last + 1);
+    if next >= words {
+        return None;
+    }
+    let [lo, hi] = epoch.to_le_bytes();
+    Some(SeenEpochSlot {
+        offset: next * 4,
+        word: [lo, hi, node, 0],
+    })
+}
+
+/// Next unused epoch given the epoch log contents
+///
+/// Any programmed byte counts as used, including one a power loss left
+/// half-written. Returns `None` once all 65536 epochs are used; the node
+/// then needs a new key.
+pub fn next_epoch(log: &[u8]) -> Option<EpochSlot> {
+    let next = log
+        .iter()
+        .rposition(|&b| b != ERASED)
+        .map_or(0, |last| last + 1);
+    if next >= log.len() {
+        return None;
+    }
+    Some(EpochSlot {
+        epoch: u16::try_from(next).ok()?,
+        offset: next,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_roundtrip() {
+        let mut config = FlashConfig::new(2);
+        config.add_key(1, [0x11; 16]).unwrap();
+        config.add_key(3, [0x33; 16]).unwrap();
+        assert_eq!(
+            config.add_key(1, [0; 16]),
+            Err(ConfigError::DuplicateNode(1))
+        );
+
+        // Followed by erased flash, as in the sector
+        let mut sector = [0xFFu8; 128];
+        let len = config.encode(&mut sector).unwrap();
+        assert_eq!(len, 7 + 2 * 17 + 2);
+        let decoded = FlashConfig::decode(&sector).unwrap();
+        assert_eq!(decoded, config);
+        assert_eq!(decoded.key_for(3), Some(&[0x33; 16]));
+        assert_eq!(decoded.key_for(2), None);
+        assert!(decoded.nodes().eq([1, 3]));
+
+        sector[10] ^= 0x01;
+        assert_eq!(FlashConfig::decode(&sector), Err(ConfigError::BadCrc));
+        assert_eq!(FlashConfig::decode(&[0xFF; 64]), Err(ConfigError::Erased));
+        assert_eq!(
+            FlashConfig::decode(&sector[..20]),
+            Err(ConfigError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_epoch_log() {
+        let mut log = [0xFFu8; 8];
+        let slot = next_epoch(&log).unwrap();
+        assert_eq!(
+            slot,
+            EpochSlot {
+                epoch: 0,
+                offset: 0
+            }
+        );
+
+        log[slot.offset] = EPOCH_USED;
+        let slot = next_epoch(&log).unwrap();
+        assert_eq!((slot.epoch, slot.offset), (1, 1));
+
+        // A byte half-programmed when power failed still counts as used
+        log[1] = 0x7F;
+        assert_eq!(next_epoch(&log).unwrap().epoch, 2);
+
+        log[7] = EPOCH_USED;
+        assert_eq!(next_epoch(&log), None);
+    }
+
+    #[test]
+    fn test_seen_epoch_log() {
+        let mut log = [0xFFu8; 12];
+        assert_eq!(last_seen_epoch(&log, 1), None);
+
+        for (node, epoch) in [(1, 4), (3, 9), (1, 5)] {
+            let slot = seen_epoch_slot(&log, node, epoch).unwrap();
+            log[slot.offset..][..4].copy_from_slice(&slot.word);
+        }
+        assert_eq!(last_seen_epoch(&log, 1), Some(5));
+        assert_eq!(last_seen_epoch(&log, 3), Some(9));
+        assert_eq!(last_seen_epoch(&log, 2), None);
+        assert_eq!(seen_epoch_slot(&log, 1, 6), None);
+    }
+}
@@ -0,0 +1,493 @@
+//! Authenticated encryption of LoRa frames (AES-128-CCM)
+//!
+//! Every payload between node1 and node2 travels as
+//!
+//! ```text
+//! [node u8][direction u8][counter u32 BE][ciphertext][tag 8 bytes]
+//! ```
+//!
+//! `node` is the sensor node whose key protects the frame, so node2 can keep
+//! one key per node (see `provision`). The 6-byte header is authenticated but
+//! not encrypted; the tag replaces the CRC-16 of plaintext frames.
+//!
+//! The nonce is the header zero-padded to 13 bytes, so it must never repeat
+//! under one key:
+//! - uplinks count up with `FrameCounter`, whose upper half is an epoch that
+//!   node1 bumps in flash on every boot (and whenever the lower half wraps)
+//! - an ACK reuses the counter of the uplink it answers, with the downlink
+//!   direction; node2 answers each counter at most once because
+//!   `ReplayGuard` rejects anything not newer than the last accepted frame.
+//!   The guard's counters live in RAM, so node2 also records every epoch in
+//!   flash before accepting frames in it; after a reboot it rejects the rest
+//!   of each recorded epoch, and node1, no longer acknowledged, moves on to a
+//!   new one (`EpochRenewal`)
+//!
+//! ACKs are also bound to the exact uplink they answer: `seal_ack` adds the
+//! uplink's tag (a MAC over its header and payload) to the authenticated
+//! data, so `open_ack` fails unless node1 supplies the tag of the frame it
+//! sent. Nothing extra goes over the air.
+
+use aes::Aes128;
+use ccm::aead::generic_array::GenericArray;
+use ccm::aead::{AeadInPlace, KeyInit};
+use ccm::consts::{U13, U8};
+use ccm::Ccm;
+use core::fmt;
+
+/// Per-node AES-128 key
+pub type Key = [u8; 16];
+
+/// Authentication tag of a frame
+pub type Tag = [u8; TAG_LEN];
+
+/// Header bytes in front of the ciphertext
+pub const HEADER_LEN: usize = 6;
+/// Authentication tag bytes after the ciphertext
+pub const TAG_LEN: usize = 8;
+/// Bytes a frame adds around its plaintext
+pub const OVERHEAD: usize = HEADER_LEN + TAG_LEN;
+
+/// Unacknowledged uplinks after which node1 starts a new epoch
+pub const RENEW_AFTER_MISSES: u8 = 3;
+
+const NONCE_LEN: usize = 13;
+
+type Aes128Ccm = Ccm<Aes128, U8, U13>;
+
+/// Which way a frame travels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// node1 -> node2 (sensor data)
+    Uplink = 0,
+    /// node2 -> node1 (ACKs)
+    Downlink = 1,
+}
+
+/// Cleartext (but authenticated) frame header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Address of the sensor node whose key protects the frame
+    pub node: u8,
+    pub direction: Direction,
+    pub counter: u32,
+}
+
+impl FrameHeader {
+    /// Upper half of the counter (see `FrameCounter`)
+    pub fn epoch(self) -> u16 {
+        (self.counter >> 16) as u16
+    }
+
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let c = self.counter.to_be_bytes();
+        [self.node, self.direction as u8, c[0], c[1], c[2], c[3]]
+    }
+
+    fn nonce(self) -> [u8; NONCE_LEN] {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..HEADER_LEN].copy_from_slice(&self.to_bytes());
+        nonce
+    }
+}
+
+/// Why a frame couldn't be sealed or opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Output buffer can't hold the frame
+    BufferTooSmall,
+    /// Shorter than header + tag
+    Truncated,
+    /// Unknown direction byte
+    BadHeader,
+    /// Wrong key, or the frame was corrupted or forged
+    AuthFailed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BufferTooSmall => "buffer too small",
+            Error::Truncated => "frame truncated",
+            Error::BadHeader => "bad frame header",
+            Error::AuthFailed => "authentication failed",
+        })
+    }
+}
+
+/// Read the header without checking anything else, to pick the key
+pub fn peek_header(frame: &[u8]) -> Result<FrameHeader, Error> {
+    if frame.len() < OVERHEAD {
+        return Err(Error::Truncated);
+    }
+    let direction = match frame[1] {
+        0 => Direction::Uplink,
+        1 => Direction::Downlink,
+        _ => return Err(Error::BadHeader),
+    };
+    Ok(FrameHeader {
+        node: frame[0],
+        direction,
+        counter: u32::from_be_bytes([frame[2], frame[3], frame[4], frame[5]]),
+    })
+}
+
+/// The tag at the end of a sealed frame, to bind an ACK to it
+pub fn frame_tag(frame: &[u8]) -> Result<Tag, Error> {
+    if frame.len() < OVERHEAD {
+        return Err(Error::Truncated);
+    }
+    let mut tag = [0u8; TAG_LEN];
+    tag.copy_from_slice(&frame[frame.len() - TAG_LEN..]);
+    Ok(tag)
+}
+
+/// Encrypt `plaintext` into `out`; returns the frame length
+pub fn seal(
+    key: &Key,
+    header: FrameHeader,
+    plaintext: &[u8],
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    seal_bound(key, header, &[], plaintext, out)
+}
+
+/// Authenticate and decrypt `frame` in place; returns the header and plaintext
+///
+/// Callers still have to check the counter (`ReplayGuard`, or the expected
+/// counter for ACKs) - a valid tag only proves the frame was sealed with `key`.
+pub fn open<'a>(key: &Key, frame: &'a mut [u8]) -> Result<(FrameHeader, &'a [u8]), Error> {
+    open_bound(key, frame, &[])
+}
+
+/// Like `seal`, for an ACK answering the uplink whose tag is `acked`
+pub fn seal_ack(
+    key: &Key,
+    header: FrameHeader,
+    acked: &Tag,
+    plaintext: &[u8],
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    seal_bound(key, header, acked, plaintext, out)
+}
+
+/// Like `open`, for an ACK that must answer the uplink whose tag is `acked`
+pub fn open_ack<'a>(
+    key: &Key,
+    frame: &'a mut [u8],
+    acked: &Tag,
+) -> Result<(FrameHeader, &'a [u8]), Error> {
+    open_bound(key, frame, acked)
+}
+
+/// Header followed by `binding` (at most a tag), the authenticated data
+fn associated_data(header: &[u8], binding: &[u8]) -> ([u8; HEADER_LEN + TAG_LEN], usize) {
+    let mut aad = [0u8; HEADER_LEN + TAG_LEN];
+    let len = HEADER_LEN + binding.len();
+    aad[..HEADER_LEN].copy_from_slice(header);
+    aad[HEADER_LEN..len].copy_from_slice(binding);
+    (aad, len)
+}
+
+fn seal_bound(
+    key: &Key,
+    header: FrameHeader,
+    binding: &[u8],
+    plaintext: &[u8],
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    let len = plaintext.len() + OVERHEAD;
+    if out.len() < len {
+        return Err(Error::BufferTooSmall);
+    }
+    let header_bytes = header.to_bytes();
+    let (aad, aad_len) = associated_data(&header_bytes, binding);
+    let (head, rest) = out.split_at_mut(HEADER_LEN);
+    head.copy_from_slice(&header_bytes);
+    let (body, rest) = rest.split_at_mut(plaintext.len());
+    body.copy_from_slice(plaintext);
+
+    let tag = Aes128Ccm::new(GenericArray::from_slice(key))
+        .encrypt_in_place_detached(
+            GenericArray::from_slice(&header.nonce()),
+            &aad[..aad_len],
+            body,
+        )
+        .map_err(|_| Error::BufferTooSmall)?;
+    rest[..TAG_LEN].copy_from_slice(&tag);
+    Ok(len)
+}
+
+fn open_bound<'a>(
+    key: &Key,
+    frame: &'a mut [u8],
+    binding: &[u8],
+) -> Result<(FrameHeader, &'a [u8]), Error> {
+    let header = peek_header(frame)?;
+    let (head, rest) = frame.split_at_mut(HEADER_LEN);
+    let (aad, aad_len) = associated_data(head, binding);
+    let (body, tag) = rest.split_at_mut(rest.len() - TAG_LEN);
+
+    Aes128Ccm::new(GenericArray::from_slice(key))
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(&header.nonce()),
+            &aad[..aad_len],
+            body,
+            GenericArray::from_slice(tag),
+        )
+        .map_err(|_| Error::AuthFailed)?;
+    Ok((header, body))
+}
+
+/// node1's uplink counter: `epoch << 16 | frame`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCounter {
+    next: u32,
+    remaining: u32,
+}
+
+impl FrameCounter {
+    /// Start counting in `epoch`, which must not have been used before
+    pub fn new(epoch: u16) -> Self {
+        Self {
+            next: (epoch as u32) << 16,
+            remaining: 1 << 16,
+        }
+    }
+
+    /// Next unused counter, or `None` once the epoch is used up
+    pub fn next_counter(&mut self) -> Option<u32> {
+        self.remaining = self.remaining.checked_sub(1)?;
+        let counter = self.next;
+        self.next = self.next.wrapping_add(1);
+        Some(counter)
+    }
+}
+
+/// node2's replay protection for one node: counters must strictly increase
+///
+/// Only epochs survive a reboot (node2 records them in flash), so a resumed
+/// guard rejects everything up to the end of the last recorded epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayGuard {
+    last: Option<u32>,
+}
+
+impl ReplayGuard {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Guard after a reboot, given the node's last recorded epoch
+    pub fn resume(last_epoch: Option<u16>) -> Self {
+        Self {
+            last: last_epoch.map(|epoch| (epoch as u32) << 16 | 0xFFFF),
+        }
+    }
+
+    /// Whether `counter` is newer than anything accepted so far
+    pub fn is_fresh(&self, counter: u32) -> bool {
+        self.last.is_none_or(|last| counter > last)
+    }
+
+    /// Whether `counter` is the first in its epoch, which must be recorded
+    /// before the frame is accepted
+    pub fn is_new_epoch(&self, counter: u32) -> bool {
+        self.last.is_none_or(|last| counter >> 16 > last >> 16)
+    }
+
+    /// Record an authenticated, fresh frame
+    pub fn accept(&mut self, counter: u32) {
+        self.last = Some(counter);
+    }
+}
+
+/// node1's side of `ReplayGuard::resume`: notices when node2 stops
+/// acknowledging the current epoch, e.g. because it rebooted
+///
+/// Renews at most once per outage - only an epoch node2 had acknowledged -
+/// so a long silence doesn't burn through epochs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EpochRenewal {
+    acked: bool,
+    misses: u8,
+}
+
+impl EpochRenewal {
+    pub const fn new() -> Self {
+        Self {
+            acked: false,
+            misses: 0,
+        }
+    }
+
+    /// A frame of the current epoch was acknowledged
+    pub fn on_ack(&mut self) {
+        self.acked = true;
+        self.misses = 0;
+    }
+
+    /// A frame went unacknowledged; `true` when node1 should start a new
+    /// epoch (the renewal then starts over)
+    pub fn on_miss(&mut self) -> bool {
+        self.misses = self.misses.saturating_add(1);
+        if self.acked && self.misses >= RENEW_AFTER_MISSES {
+            *self = Self::new();
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: Key = [0x42; 16];
+
+    fn uplink(counter: u32) -> FrameHeader {
+        FrameHeader {
+            node: 1,
+            direction: Direction::Uplink,
+            counter,
+        }
+    }
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let mut frame = [0u8; 64];
+        let len = seal(&KEY, uplink(0x0003_0007), b"hello node2", &mut frame).unwrap();
+        assert_eq!(len, 11 + OVERHEAD);
+        assert_eq!(&frame[..HEADER_LEN], &[1, 0, 0, 3, 0, 7]);
+        assert_ne!(&frame[HEADER_LEN..HEADER_LEN + 11], b"hello node2");
+
+        let (header, plaintext) = open(&KEY, &mut frame[..len]).unwrap();
+        assert_eq!(header, uplink(0x0003_0007));
+        assert_eq!(plaintext, b"hello node2");
+    }
+
+    #[test]
+    fn test_tampering_and_wrong_keys_are_rejected() {
+        let mut sealed = [0u8; 32];
+        let len = seal(&KEY, uplink(9), b"21.5C", &mut sealed).unwrap();
+
+        // Flip one bit anywhere: header, ciphertext or tag
+        for i in 0..len {
+            let mut frame = sealed;
+            frame[i] ^= 0x01;
+            let result = open(&KEY, &mut frame[..len]);
+            assert!(result.is_err(), "bit flip at byte {} accepted", i);
+        }
+        let mut frame = sealed;
+        assert_eq!(
+            open(&[0x24; 16], &mut frame[..len]).unwrap_err(),
+            Error::AuthFailed
+        );
+        assert_eq!(
+            open(&KEY, &mut frame[..OVERHEAD - 1]).unwrap_err(),
+            Error::Truncated
+        );
+        assert_eq!(
+            seal(&KEY, uplink(1), b"21.5C", &mut [0u8; 8]).unwrap_err(),
+            Error::BufferTooSmall
+        );
+    }
+
+    #[test]
+    fn test_ack_cannot_pass_as_uplink() {
+        // Same key and counter, other direction: a different nonce, and the
+        // header (direction byte included) is authenticated
+        let mut frame = [0u8; 32];
+        let header = FrameHeader {
+            direction: Direction::Downlink,
+            ..uplink(5)
+        };
+        let len = seal(&KEY, header, b"ack", &mut frame).unwrap();
+        frame[1] = Direction::Uplink as u8;
+        assert_eq!(
+            open(&KEY, &mut frame[..len]).unwrap_err(),
+            Error::AuthFailed
+        );
+    }
+
+    #[test]
+    fn test_ack_is_bound_to_the_uplink() {
+        let mut uplink_frame = [0u8; 32];
+        let len = seal(&KEY, uplink(7), b"21.5C", &mut uplink_frame).unwrap();
+        let acked = frame_tag(&uplink_frame[..len]).unwrap();
+
+        let header = FrameHeader {
+            direction: Direction::Downlink,
+            ..uplink(7)
+        };
+        let mut sealed = [0u8; 32];
+        let len = seal_ack(&KEY, header, &acked, b"ack", &mut sealed).unwrap();
+        assert_eq!(len, 3 + OVERHEAD);
+
+        let mut frame = sealed;
+        let (opened, plaintext) = open_ack(&KEY, &mut frame[..len], &acked).unwrap();
+        assert_eq!((opened, plaintext), (header, &b"ack"[..]));
+
+        // An ACK for another payload with the same counter, or read as a
+        // plain frame, doesn't open
+        let mut other = acked;
+        other[0] ^= 0x80;
+        let mut frame = sealed;
+        assert_eq!(
+            open_ack(&KEY, &mut frame[..len], &other).unwrap_err(),
+            Error::AuthFailed
+        );
+        let mut frame = sealed;
+        assert_eq!(
+            open(&KEY, &mut frame[..len]).unwrap_err(),
+            Error::AuthFailed
+        );
+    }
+
+    #[test]
+    fn test_counters_and_replays() {
+        let mut counter = FrameCounter::new(2);
+        assert_eq!(counter.next_counter(), Some(0x0002_0000));
+        assert_eq!(counter.next_counter(), Some(0x0002_0001));
+        for _ in 2..=0xFFFF {
+            assert!(counter.next_counter().is_some());
+        }
+        assert_eq!(counter.next_counter(), None);
+
+        let mut guard = ReplayGuard::new();
+        assert!(guard.is_fresh(0x0002_0000));
+        guard.accept(0x0002_0000);
+        assert!(!guard.is_fresh(0x0002_0000));
+        assert!(!guard.is_fresh(0x0001_FFFF));
+        // A reboot moves node1 to a later epoch
+        assert!(guard.is_fresh(0x0003_0000));
+        assert!(!guard.is_new_epoch(0x0002_0001));
+        assert!(guard.is_new_epoch(0x0003_0000));
+    }
+
+    #[test]
+    fn test_guard_resumes_after_node2_reboot() {
+        // Nothing recorded: the first authentic frame sets the bar
+        assert!(ReplayGuard::resume(None).is_new_epoch(0x0002_0005));
+
+        // Frames captured in a recorded epoch stay rejected after a reboot
+        let guard = ReplayGuard::resume(Some(2));
+        assert!(!guard.is_fresh(0x0002_0005));
+        assert!(!guard.is_fresh(0x0002_FFFF));
+        assert!(guard.is_fresh(0x0003_0000));
+        assert!(guard.is_new_epoch(0x0003_0000));
+
+        // node1 renews once node2 stops acknowledging an epoch it had acked
+        let mut renewal = EpochRenewal::new();
+        for _ in 0..RENEW_AFTER_MISSES {
+            assert!(!renewal.on_miss());
+        }
+        renewal.on_ack();
+        assert!(!renewal.on_miss());
+        assert!(!renewal.on_miss());
+        assert!(renewal.on_miss());
+        // The new epoch was never acknowledged: no renewal for that
+        for _ in 0..2 * RENEW_AFTER_MISSES {
+            assert!(!renewal.on_miss());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Wall-clock keeping on top of a millisecond uptime counter
+//!
+//! Neither node has an RTC set to real time. A `WallClock` remembers the
+//! Unix time received at a known uptime and extrapolates from there. Uptime
+//! differences use wrapping arithmetic, so a u32 counter wrapping between
+//! syncs (~49.7 days) is handled.
+
+/// Unix time anchored to an uptime reading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SyncPoint {
+    unix_ms: u64,
+    uptime_ms: u32,
+}
+
+/// Wall clock derived from the last time sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WallClock {
+    sync: Option<SyncPoint>,
+}
+
+impl WallClock {
+    pub const fn new() -> Self {
+        Self { sync: None }
+    }
+
+    /// Record that it was `unix_ms` when the uptime counter read `uptime_ms`
+    pub fn sync(&mut self, unix_ms: u64, uptime_ms: u32) {
+        self.sync = Some(SyncPoint { unix_ms, uptime_ms });
+    }
+
+    /// Sync from a whole-second time (as relayed in ACKs)
+    pub fn sync_secs(&mut self, unix_secs: u32, uptime_ms: u32) {
+        self.sync(unix_secs as u64 * 1000, uptime_ms);
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.sync.is_some()
+    }
+
+    /// Milliseconds of uptime elapsed since the last sync
+    pub fn since_sync_ms(&self, uptime_ms: u32) -> Option<u32> {
+        self.sync.map(|sync| uptime_ms.wrapping_sub(sync.uptime_ms))
+    }
+
+    /// Current Unix time in ms, if synced
+    pub fn now_ms(&self, uptime_ms: u32) -> Option<u64> {
+        let sync = self.sync?;
+        Some(sync.unix_ms + uptime_ms.wrapping_sub(sync.uptime_ms) as u64)
+    }
+
+    /// Current Unix time in whole seconds, or 0 if not synced
+    ///
+    /// This is the compact form carried in ACKs and sensor packets.
+    pub fn now_secs_or_zero(&self, uptime_ms: u32) -> u32 {
+        self.now_ms(uptime_ms)
+            .map(|ms| (ms / 1000) as u32)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIX_MS: u64 = 1_767_225_600_000; // 2026-01-01T00:00:00Z
+
+    #[test]
+    fn test_unsynced_clock() {
+        let clock = WallClock::new();
+        assert!(!clock.is_synced());
+        assert_eq!(clock.now_ms(1234), None);
+        assert_eq!(clock.now_secs_or_zero(1234), 0);
+    }
+
+    #[test]
+    fn test_extrapolates_from_sync_point() {
+        let mut clock = WallClock::new();
+        clock.sync(UNIX_MS, 10_000);
+        assert_eq!(clock.now_ms(10_000), Some(UNIX_MS));
+        assert_eq!(clock.now_ms(15_500), Some(UNIX_MS + 5_500));
+        assert_eq!(clock.now_secs_or_zero(15_500), 1_767_225_605);
+        assert_eq!(clock.since_sync_ms(15_500), Some(5_500));
+    }
+
+    #[test]
+    fn test_handles_uptime_wrap_between_syncs() {
+        let mut clock = WallClock::new();
+        clock.sync(UNIX_MS, u32::MAX - 499);
+        assert_eq!(clock.now_ms(500), Some(UNIX_MS + 1_000));
+    }
+
+    #[test]
+    fn test_resync_from_seconds() {
+        let mut clock = WallClock::new();
+        clock.sync_secs(1_767_225_600, 2_000);
+        assert_eq!(clock.now_ms(3_000), Some(UNIX_MS + 1_000));
+    }
+}
@@ -14,6 +14,7 @@ mod app {
         serial::{Serial, Config as SerialConfig, Event as SerialEvent},
         i2c::I2c,
         rcc::Config,
+        flash::FlashExt,
     };
 
     use shared_bus::CortexMMutex;
@@ -37,31 +38,14 @@ mod app {
     const AUTO_TX_INTERVAL_SECS: u32 = 10;  // Auto-transmit every 10 seconds
     const NETWORK_ID: u8 = 18;               // LoRa network ID
     const LORA_FREQ: u32 = 915;              // LoRa frequency in MHz (915 for US)
+    const GATEWAY_ADDRESS: u8 = 2;           // Node 2's LoRa address; ACKs must come from it
 
-    // --- Binary Protocol Data Structures ---
-    use serde::{Serialize, Deserialize};
-
-    /// Sensor data packet for binary transmission
-    /// Size: ~12 bytes (postcard serialized) vs 24 bytes (text format)
-    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-    pub struct SensorDataPacket {
-        pub seq_num: u16,           // Sequence number for duplicate detection
-        pub temperature: i16,       // Temperature in centidegrees (e.g., 2710 = 27.1°C)
-        pub humidity: u16,          // Humidity in basis points (e.g., 5600 = 56.0%)
-        pub gas_resistance: u32,    // Gas resistance in ohms
-    }
-
-    /// ACK/NACK packet for acknowledgment
-    /// Size: 3 bytes (1 byte msg_type + 2 bytes seq_num)
-    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-    pub struct AckPacket {
-        pub msg_type: u8,   // 1 = ACK (success), 2 = NACK (CRC failure)
-        pub seq_num: u16,   // Which packet we're acknowledging
-    }
-
-    // Message type constants
-    const MSG_TYPE_ACK: u8 = 1;
-    const MSG_TYPE_NACK: u8 = 2;
+    // --- Binary Protocol Data Structures (shared with Node 2) ---
+    use node_protocol::packet::{AckPacket, SensorDataPacket, MSG_TYPE_ACK};
+    use node_protocol::provision::{self, FlashConfig};
+    use node_protocol::secure::{self, Direction, EpochRenewal, FrameCounter, FrameHeader, Key, Tag};
+    use node_protocol::adr::{AdrFollower, RadioConfig};
+    use node_protocol::time::WallClock;
 
     // Transmission retry configuration
     const MAX_RETRIES: u8 = 3;
@@ -73,22 +57,24 @@ mod app {
         Idle,                    // Waiting for next transmission trigger
         WaitingForAck {          // Packet sent, waiting for ACK
             seq_num: u16,        // Which packet we're waiting for
+            frame: SentFrame,    // The ACK must answer exactly this frame
             timeout_counter: u32, // Countdown in seconds until timeout
             retry_count: u8,     // How many retries attempted so far
         },
     }
 
-    /// Calculate CRC-16 checksum for data integrity
-    /// Uses CRC-16-IBM-3740 (CCITT with 0xFFFF initial value)
-    fn calculate_crc16(data: &[u8]) -> u16 {
-        use crc::{Crc, CRC_16_IBM_3740};
-        const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
-        CRC16.checksum(data)
+    /// The sealed frame an ACK has to answer
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct SentFrame {
+        pub counter: u32, // Frame counter, reused by the ACK
+        pub tag: Tag,     // Frame tag, the ACK is bound to it
     }
 
-    /// Parse ACK/NACK message from Node 2
+    /// Parse ACK message from Node 2
     /// Format: +RCV=<Address>,<Length>,<BinaryData>,<RSSI>,<SNR>\r\n
-    fn parse_ack_message(buffer: &[u8]) -> Option<AckPacket> {
+    /// where <BinaryData> is a sealed frame. Only an ACK from Node 2 that
+    /// answers `awaiting` (same counter, bound to its tag) is returned.
+    fn parse_ack_message(buffer: &mut [u8], address: u8, key: &Key, awaiting: &SentFrame) -> Option<AckPacket> {
         // Check prefix: must start with "+RCV="
         if buffer.len() < 10 || &buffer[0..5] != b"+RCV=" {
             return None;
@@ -112,6 +98,13 @@ mod app {
         let comma1 = comma1_pos?;
         let comma2 = comma2_pos?;
 
+        // Source address: anyone on our network ID can transmit, only Node 2 may ACK
+        let source: u8 = core::str::from_utf8(&buffer[5..comma1]).ok()?.parse().ok()?;
+        if source != GATEWAY_ADDRESS {
+            defmt::warn!("Ignoring message from address {}", source);
+            return None;
+        }
+
         // Extract length
         let len_bytes = &buffer[comma1 + 1..comma2];
         let len_str = core::str::from_utf8(len_bytes).ok()?;
@@ -125,10 +118,27 @@ mod app {
             return None;
         }
 
-        let binary_payload = &buffer[payload_start..payload_end];
+        // Only a downlink for us answering the awaited frame; anything else
+        // is stale or replayed
+        let frame = &mut buffer[payload_start..payload_end];
+        let header = secure::peek_header(frame).ok()?;
+        if header.direction != Direction::Downlink
+            || header.node != address
+            || header.counter != awaiting.counter
+        {
+            defmt::warn!("Ignoring ACK frame with counter 0x{:08X}", header.counter);
+            return None;
+        }
 
-        // Deserialize ACK packet (no CRC on ACK packets - they're tiny!)
-        postcard::from_bytes(binary_payload).ok()
+        // Authenticate and decrypt in place, checking the binding to our frame
+        let plaintext = match secure::open_ack(key, frame, &awaiting.tag) {
+            Ok((_, plaintext)) => plaintext,
+            Err(e) => {
+                defmt::warn!("Rejected ACK frame: {}", defmt::Display2Format(&e));
+                return None;
+            }
+        };
+        postcard::from_bytes(plaintext).ok()
     }
 
     // --- Bridge for embedded-hal 1.0 -> 0.2.7 ---
@@ -173,6 +183,12 @@ mod app {
         sht31: SHT3x<I2cProxy, ShtDelay>,
         bme680: Bme680<I2cProxy, BmeDelay>,
         tx_state: TxState,     // Transmission state machine (shared between tim2 and uart4)
+        address: u8,           // Our LoRa address, from the flash config
+        key: Key,              // Our AES-128 key, from the flash config
+        uptime_ms: u32,        // Milliseconds since boot (1 Hz timer, so 1000ms per tick)
+        wall_clock: WallClock, // Wall-clock time relayed by Node 2 in ACKs
+        adr: AdrFollower,      // Radio settings commanded by Node 2 in ACKs
+        epoch_renewal: EpochRenewal, // Whether Node 2 still acknowledges our epoch
     }
 
     #[local]
@@ -183,7 +199,9 @@ mod app {
         bme_delay: BmeDelay,
         packet_counter: u32,   // Counts packets sent
         tx_countdown: u32,     // Seconds until next auto-transmit
-        rx_buffer: Vec<u8, 128>,  // Buffer for incoming ACK/NACK packets
+        rx_buffer: Vec<u8, 128>,  // Buffer for incoming ACK packets
+        flash: pac::FLASH,        // For recording frame-counter epochs
+        frame_counter: FrameCounter,
     }
 
     // Helper function to send AT command and wait for response
@@ -203,6 +221,37 @@ mod app {
         cortex_m::asm::delay(8_400_000); // ~100ms at 84 MHz
     }
 
+    /// Switch the LoRa module to new ADR settings (spreading factor and power)
+    fn apply_radio_config(uart: &mut Serial<pac::UART4>, radio: RadioConfig) {
+        let mut cmd_buf: String<32> = String::new();
+        let _ = core::write!(cmd_buf, "{}", radio.parameter_command());
+        send_at_command(uart, cmd_buf.as_str());
+
+        cmd_buf.clear();
+        let _ = core::write!(cmd_buf, "{}", radio.power_command());
+        send_at_command(uart, cmd_buf.as_str());
+    }
+
+    /// Reserve the next frame-counter epoch in the flash epoch log
+    ///
+    /// Runs at boot and whenever an epoch's 65536 counters are used up, so a
+    /// counter (and with it a nonce) is never reused, even across resets.
+    /// Programs a single byte (no erase), so it's fine from the TIM2 handler.
+    fn start_epoch(flash: &mut pac::FLASH) -> FrameCounter {
+        let log = &flash.read()[provision::SECTOR_OFFSET + provision::EPOCH_LOG_OFFSET..][..provision::EPOCH_LOG_LEN];
+        let Some(slot) = provision::next_epoch(log) else {
+            defmt::panic!("All frame-counter epochs used: re-provision with a new key");
+        };
+
+        let offset = provision::SECTOR_OFFSET + provision::EPOCH_LOG_OFFSET + slot.offset;
+        if flash.unlocked().program(offset, [provision::EPOCH_USED].iter()).is_err() {
+            defmt::panic!("Recording frame-counter epoch {} failed", slot.epoch);
+        }
+
+        defmt::info!("Frame-counter epoch {}", slot.epoch);
+        FrameCounter::new(slot.epoch)
+    }
+
     #[init]
     fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
         let dp = cx.device;
@@ -224,6 +273,17 @@ mod app {
         // BME680 delay (TIM3) will be moved to Local for use in handler
         let mut bme_delay = dp.TIM3.delay_us(&mut rcc);
 
+        // --- Flash config: LoRa address, key and frame-counter epoch ---
+        let mut flash = dp.FLASH;
+        let config = match FlashConfig::decode(&flash.read()[provision::SECTOR_OFFSET..]) {
+            Ok(config) => config,
+            Err(e) => defmt::panic!("Flash config: {} - provision this node first", defmt::Display2Format(&e)),
+        };
+        let Some(&key) = config.key_for(config.address) else {
+            defmt::panic!("Flash config has no key for our address {}", config.address);
+        };
+        let frame_counter = start_epoch(&mut flash);
+
         // --- UART4 ---
         let tx = gpioc.pc10.into_alternate();
         let rx = gpioc.pc11.into_alternate();
@@ -237,9 +297,11 @@ mod app {
         // Configure LoRa module before enabling RX interrupt
         defmt::info!("Configuring LoRa module (Node 1)...");
         send_at_command(&mut lora_uart, "AT");
-        send_at_command(&mut lora_uart, "AT+ADDRESS=1");
-
         let mut cmd_buf: String<32> = String::new();
+        let _ = core::write!(cmd_buf, "AT+ADDRESS={}", config.address);
+        send_at_command(&mut lora_uart, cmd_buf.as_str());
+
+        cmd_buf.clear();
         let _ = core::write!(cmd_buf, "AT+NETWORKID={}", NETWORK_ID);
         send_at_command(&mut lora_uart, cmd_buf.as_str());
 
@@ -247,7 +309,8 @@ mod app {
         let _ = core::write!(cmd_buf, "AT+BAND={}000000", LORA_FREQ);
         send_at_command(&mut lora_uart, cmd_buf.as_str());
 
-        send_at_command(&mut lora_uart, "AT+PARAMETER=7,9,1,7");
+        // Boot on the ADR boot settings; Node 2 starts there too
+        apply_radio_config(&mut lora_uart, RadioConfig::BOOT);
 
         // Flush any pending responses from configuration
         while lora_uart.read().is_ok() {}
@@ -305,6 +368,12 @@ mod app {
                 sht31,
                 bme680,
                 tx_state: TxState::Idle,              // Start in Idle state
+                address: config.address,
+                key,
+                uptime_ms: 0,
+                wall_clock: WallClock::new(),         // Unsynced until the first ACK with time
+                adr: AdrFollower::new(),              // Boot settings until Node 2 says otherwise
+                epoch_renewal: EpochRenewal::new(),
             },
             Local {
                 led,
@@ -314,24 +383,35 @@ mod app {
                 packet_counter: 0,                    // Start at packet #0
                 tx_countdown: AUTO_TX_INTERVAL_SECS,  // First TX in 10 seconds
                 rx_buffer: Vec::new(),                // Empty RX buffer
+                flash,
+                frame_counter,
             },
             init::Monotonics()
         )
     }
 
-    #[task(binds = TIM2, shared = [sht31, bme680, display, lora_uart, tx_state], local = [led, button, timer, bme_delay, packet_counter, tx_countdown])]
+    #[task(binds = TIM2, shared = [sht31, bme680, display, lora_uart, tx_state, address, key, uptime_ms, wall_clock, adr, epoch_renewal], local = [led, button, timer, bme_delay, packet_counter, tx_countdown, flash, frame_counter])]
     fn tim2_handler(mut cx: tim2_handler::Context) {
         cx.local.timer.clear_flags(stm32f4xx_hal::timer::Flag::Update);
         cx.local.led.toggle();
 
+        // Increment uptime (timer runs at 1 Hz)
+        let uptime = cx.shared.uptime_ms.lock(|t| {
+            *t = t.wrapping_add(1000);
+            *t
+        });
+        let sample_time = cx.shared.wall_clock.lock(|c| c.now_secs_or_zero(uptime));
+
         // State machine: Handle ACK timeout
+        let mut gave_up = false;
         cx.shared.tx_state.lock(|state| {
             match *state {
-                TxState::WaitingForAck { seq_num, timeout_counter, retry_count } => {
+                TxState::WaitingForAck { seq_num, frame, timeout_counter, retry_count } => {
                     if timeout_counter > 0 {
                         // Countdown timeout
                         *state = TxState::WaitingForAck {
                             seq_num,
+                            frame,
                             timeout_counter: timeout_counter - 1,
                             retry_count,
                         };
@@ -344,12 +424,14 @@ mod app {
                             // Keep waiting with incremented retry counter and reset timeout
                             *state = TxState::WaitingForAck {
                                 seq_num,
+                                frame,
                                 timeout_counter: ACK_TIMEOUT_SECS,
                                 retry_count: new_retry_count,
                             };
                         } else {
                             defmt::error!("Max retries ({}) exceeded for packet #{}, giving up", MAX_RETRIES, seq_num);
                             *state = TxState::Idle;
+                            gave_up = true;
                         }
                     }
                 }
@@ -359,6 +441,24 @@ mod app {
             }
         });
 
+        // Lost packets after an ADR change mean Node 2 never heard us on the
+        // new settings: go back to the old ones. Otherwise the link is gone
+        // and we meet Node 2 on the most robust settings
+        if gave_up {
+            if let Some(radio) = cx.shared.adr.lock(|adr| adr.on_miss()) {
+                defmt::warn!("ADR: link lost, falling back to SF{} {}dBm", radio.spreading_factor, radio.tx_power_dbm);
+                cx.shared.lora_uart.lock(|uart| apply_radio_config(uart, radio));
+            }
+
+            // After a reboot Node 2 rejects the rest of every epoch it had
+            // seen, so a silence after ACKs calls for a new one
+            if cx.shared.epoch_renewal.lock(|renewal| renewal.on_miss()) {
+                defmt::warn!("Epoch no longer acknowledged, starting a new one");
+                *cx.local.frame_counter = start_epoch(cx.local.flash);
+            }
+        }
+        let radio = cx.shared.adr.lock(|adr| adr.current());
+
         // Determine if we should transmit this cycle
         let mut should_transmit = false;
         let mut trigger_source = "AUTO";
@@ -386,6 +486,18 @@ mod app {
         let is_idle = cx.shared.tx_state.lock(|state| *state == TxState::Idle);
         if should_transmit && is_idle {
             let delay = cx.local.bme_delay;
+            let address = cx.shared.address.lock(|a| *a);
+            let key = cx.shared.key.lock(|k| *k);
+
+            // Every frame gets a fresh counter; move to a new epoch when one runs out
+            let frame_counter = match cx.local.frame_counter.next_counter() {
+                Some(counter) => counter,
+                None => {
+                    *cx.local.frame_counter = start_epoch(cx.local.flash);
+                    cx.shared.epoch_renewal.lock(|renewal| *renewal = EpochRenewal::new());
+                    cx.local.frame_counter.next_counter().unwrap_or_default()
+                }
+            };
 
             cx.shared.bme680.lock(|bme| {
                 let _ = bme.set_sensor_mode(delay, PowerMode::ForcedMode);
@@ -429,8 +541,8 @@ mod app {
                                 Text::new(&buf, Point::new(0, 32), style).draw(disp).ok();
 
                                 buf.clear();
-                                // Line 4: Network ID and frequency
-                                let _ = core::write!(buf, "Net:{} {}MHz", NETWORK_ID, LORA_FREQ);
+                                // Line 4: Network ID, frequency and spreading factor
+                                let _ = core::write!(buf, "Net:{} {}MHz SF{}", NETWORK_ID, LORA_FREQ, radio.spreading_factor);
                                 Text::new(&buf, Point::new(0, 44), style).draw(disp).ok();
 
                                 buf.clear();
@@ -442,7 +554,7 @@ mod app {
                             });
 
                             let current_seq = *cx.local.packet_counter as u16;
-                            let mut tx_success = false;
+                            let mut sent_frame = None;
 
                             cx.shared.lora_uart.lock(|uart| {
                                 // === BINARY PROTOCOL ===
@@ -455,43 +567,38 @@ mod app {
                                     temperature: temp_centidegrees,
                                     humidity: humid_basis_points,
                                     gas_resistance: gas,
+                                    timestamp: sample_time,
+                                    radio,
                                 };
 
-                                // Serialize to binary
+                                // Serialize to binary, then seal (encrypt + authenticate)
                                 let mut binary_buffer = [0u8; 32];
-                                match postcard::to_slice(&binary_packet, &mut binary_buffer) {
-                                    Ok(serialized) => {
-                                        let data_len = serialized.len();
-                                        let crc = calculate_crc16(serialized);
-
-                                        // Total payload: data + 2-byte CRC
-                                        let total_len = data_len + 2;
-
-                                        defmt::info!("Binary packet: {} bytes data + 2 bytes CRC = {} total, CRC: 0x{:04X}",
-                                            data_len, total_len, crc);
-
-                                        // Send AT command prefix: "AT+SEND=2,<total_length>,"
-                                        let cmd_prefix = "AT+SEND=2,";
+                                let mut frame = [0u8; 32 + secure::OVERHEAD];
+                                let header = FrameHeader {
+                                    node: address,
+                                    direction: Direction::Uplink,
+                                    counter: frame_counter,
+                                };
+                                let sealed = postcard::to_slice(&binary_packet, &mut binary_buffer)
+                                    .ok()
+                                    .and_then(|serialized| secure::seal(&key, header, serialized, &mut frame).ok());
+                                match sealed {
+                                    Some(total_len) => {
+                                        defmt::info!("Sealed packet: {} bytes, frame counter 0x{:08X}",
+                                            total_len, frame_counter);
+
+                                        // Send AT command prefix: "AT+SEND=<gateway>,<total_length>,"
+                                        let mut cmd_prefix: String<16> = String::new();
+                                        let _ = core::write!(cmd_prefix, "AT+SEND={},{},", GATEWAY_ADDRESS, total_len);
                                         for b in cmd_prefix.as_bytes() {
                                             let _ = nb::block!(uart.write(*b));
                                         }
 
-                                        // Send total length as ASCII (includes CRC)
-                                        let mut len_str: String<8> = String::new();
-                                        let _ = core::write!(len_str, "{},", total_len);
-                                        for b in len_str.as_bytes() {
+                                        // Send sealed frame
+                                        for b in &frame[..total_len] {
                                             let _ = nb::block!(uart.write(*b));
                                         }
 
-                                        // Send binary payload (data)
-                                        for b in serialized {
-                                            let _ = nb::block!(uart.write(*b));
-                                        }
-
-                                        // Send CRC-16 (big-endian: high byte first, low byte second)
-                                        let _ = nb::block!(uart.write((crc >> 8) as u8));   // High byte
-                                        let _ = nb::block!(uart.write((crc & 0xFF) as u8)); // Low byte
-
                                         // Send \r\n terminator
                                         let _ = nb::block!(uart.write(b'\r'));
                                         let _ = nb::block!(uart.write(b'\n'));
@@ -499,19 +606,22 @@ mod app {
                                         defmt::info!("Binary TX [{}]: {} bytes sent, packet #{}",
                                             trigger_source, total_len, current_seq);
 
-                                        tx_success = true;
+                                        sent_frame = secure::frame_tag(&frame[..total_len])
+                                            .ok()
+                                            .map(|tag| SentFrame { counter: frame_counter, tag });
                                     }
-                                    Err(_) => {
+                                    None => {
                                         defmt::error!("Binary serialization failed!");
                                     }
                                 }
                             });
 
                             // Transition to WaitingForAck state (outside uart lock)
-                            if tx_success {
+                            if let Some(frame) = sent_frame {
                                 cx.shared.tx_state.lock(|state| {
                                     *state = TxState::WaitingForAck {
                                         seq_num: current_seq,
+                                        frame,
                                         timeout_counter: ACK_TIMEOUT_SECS,
                                         retry_count: 0,
                                     };
@@ -525,10 +635,16 @@ mod app {
         }
     }
 
-    // UART interrupt: Collect incoming bytes for ACK/NACK parsing
-    #[task(binds = UART4, shared = [lora_uart, tx_state], local = [rx_buffer])]
+    // UART interrupt: Collect incoming bytes for ACK parsing
+    #[task(binds = UART4, shared = [lora_uart, tx_state, address, key, uptime_ms, wall_clock, adr, epoch_renewal], local = [rx_buffer])]
     fn uart4_handler(mut cx: uart4_handler::Context) {
         let mut ack_packet: Option<AckPacket> = None;
+        let address = cx.shared.address.lock(|a| *a);
+        let key = cx.shared.key.lock(|k| *k);
+        let awaiting = cx.shared.tx_state.lock(|state| match *state {
+            TxState::WaitingForAck { frame, .. } => Some(frame),
+            TxState::Idle => None,
+        });
 
         // Collect bytes and parse (inside uart lock)
         cx.shared.lora_uart.lock(|uart| {
@@ -546,8 +662,10 @@ mod app {
                         // Complete message received
                         defmt::info!("N1 UART: {} bytes received", cx.local.rx_buffer.len());
 
-                        // Try to parse ACK/NACK
-                        ack_packet = parse_ack_message(cx.local.rx_buffer.as_slice());
+                        // Try to parse an ACK (nothing to acknowledge when idle)
+                        ack_packet = awaiting.as_ref().and_then(|frame| {
+                            parse_ack_message(cx.local.rx_buffer.as_mut_slice(), address, &key, frame)
+                        });
 
                         // Clear buffer for next message
                         cx.local.rx_buffer.clear();
@@ -566,10 +684,25 @@ mod app {
             }
         });
 
-        // Handle ACK/NACK state transitions (outside uart lock)
+        // Handle ACK state transitions (outside uart lock)
         if let Some(ack_pkt) = ack_packet {
             if ack_pkt.msg_type == MSG_TYPE_ACK {
                 defmt::info!("ACK received for packet #{}", ack_pkt.seq_num);
+                cx.shared.epoch_renewal.lock(|renewal| renewal.on_ack());
+
+                // Node 2 relays its wall clock (0 until gateway-service has synced it)
+                if ack_pkt.time != 0 {
+                    let uptime = cx.shared.uptime_ms.lock(|t| *t);
+                    cx.shared.wall_clock.lock(|c| c.sync_secs(ack_pkt.time, uptime));
+                    defmt::info!("Clock synced from ACK: {}", ack_pkt.time);
+                }
+
+                // Node 2 may piggyback new radio settings; our next packet
+                // reports them back as confirmation
+                if let Some(radio) = cx.shared.adr.lock(|adr| adr.on_ack(ack_pkt.radio)) {
+                    defmt::info!("ADR: switching to SF{} {}dBm", radio.spreading_factor, radio.tx_power_dbm);
+                    cx.shared.lora_uart.lock(|uart| apply_radio_config(uart, radio));
+                }
 
                 // Check if this ACK matches what we're waiting for
                 cx.shared.tx_state.lock(|state| {
@@ -582,28 +715,6 @@ mod app {
                         }
                     }
                 });
-            } else if ack_pkt.msg_type == MSG_TYPE_NACK {
-                defmt::warn!("NACK received for packet #{}", ack_pkt.seq_num);
-
-                // NACK means CRC failed - should retry
-                cx.shared.tx_state.lock(|state| {
-                    if let TxState::WaitingForAck { seq_num, retry_count, .. } = *state {
-                        if ack_pkt.seq_num == seq_num {
-                            if retry_count < MAX_RETRIES {
-                                defmt::warn!("Will retry packet #{}", seq_num);
-                                // Reset timeout for retry
-                                *state = TxState::WaitingForAck {
-                                    seq_num,
-                                    timeout_counter: 0, // Trigger immediate retry
-                                    retry_count: retry_count + 1,
-                                };
-                            } else {
-                                defmt::error!("Max retries reached after NACK");
-                                *state = TxState::Idle;
-                            }
-                        }
-                    }
-                });
             }
         }
     }
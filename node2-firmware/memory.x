@@ -1,6 +1,7 @@
 MEMORY
 {
-  /* STM32F446RE has 512 KB Flash and 128 KB RAM */
-  FLASH (rx) : ORIGIN = 0x08000000, LENGTH = 512K
+  /* STM32F446RE has 512 KB Flash and 128 KB RAM. The last 128 KB sector
+     (0x08060000) holds the flash config, see node_protocol::provision */
+  FLASH (rx) : ORIGIN = 0x08000000, LENGTH = 384K
   RAM (xrw)  : ORIGIN = 0x20000000, LENGTH = 128K
 }
\ No newline at end of file
@@ -11,6 +11,7 @@ mod app {
         i2c::I2c,
         pac,
         prelude::*,
+        flash::FlashExt,
         rcc::Config,
         serial::{Config as SerialConfig, Event as SerialEvent, Serial},
         timer::{CounterHz, Event},
@@ -44,70 +45,68 @@ mod app {
     const NETWORK_ID: u8 = 18; // LoRa network ID
     const LORA_FREQ: u32 = 915; // LoRa frequency in MHz (915 for US)
 
-    // --- Binary Protocol Data Structures ---
-    use serde::{Deserialize, Serialize};
-
-    /// Sensor data packet for binary transmission (must match Node 1)
-    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-    pub struct SensorDataPacket {
-        pub seq_num: u16,        // Sequence number for duplicate detection
-        pub temperature: i16,    // Temperature in centidegrees (e.g., 2710 = 27.1°C)
-        pub humidity: u16,       // Humidity in basis points (e.g., 5600 = 56.0%)
-        pub gas_resistance: u32, // Gas resistance in ohms
-    }
-
-    /// ACK/NACK packet for acknowledgment (must match Node 1)
-    /// Size: 3 bytes (1 byte msg_type + 2 bytes seq_num)
-    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-    pub struct AckPacket {
-        pub msg_type: u8, // 1 = ACK (success), 2 = NACK (CRC failure)
-        pub seq_num: u16, // Which packet we're acknowledging
-    }
-
-    // Message type constants
-    const MSG_TYPE_ACK: u8 = 1;
-    const MSG_TYPE_NACK: u8 = 2;
-
-    /// Calculate CRC-16 checksum for data integrity
-    fn calculate_crc16(data: &[u8]) -> u16 {
-        use crc::{Crc, CRC_16_IBM_3740};
-        const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
-        CRC16.checksum(data)
-    }
-
-    /// Send ACK packet to Node 1
-    /// Format: AT+SEND=1,<length>,<binary_ack_packet>\r\n
-    fn send_ack(uart: &mut Serial<pac::UART4>, seq_num: u16, is_ack: bool) {
+    // --- Binary Protocol Data Structures (shared with Node 1) ---
+    use node_protocol::adr::{AdrController, AdrPolicy, AdrSwitch, RadioConfig, SwitchStep};
+    use node_protocol::downlink::{parse_downlink, Downlink};
+    use node_protocol::packet::{AckPacket, SensorDataPacket, MSG_TYPE_ACK};
+    use node_protocol::provision::{self, FlashConfig, MAX_KEYS};
+    use node_protocol::secure::{self, Direction, FrameHeader, Key, ReplayGuard, Tag};
+    use node_protocol::time::WallClock;
+
+    // VCP downlink line buffer (commands from gateway-service, e.g. TIME=<unix_ms>)
+    const VCP_RX_BUFFER_SIZE: usize = 64;
+
+    /// Send ACK packet to the node that sent `acked`
+    /// Format: AT+SEND=<node>,<length>,<sealed_ack_frame>\r\n
+    /// The frame reuses the acknowledged frame's counter (downlink direction)
+    /// and is bound to its tag, so Node 1 can tell it answers exactly that
+    /// frame. `time` relays our wall clock (Unix seconds, 0 = not synced) so
+    /// Node 1 can timestamp its samples; `radio` piggybacks an ADR change.
+    /// Returns true once the AT+SEND went to the module; its +OK/+ERR comes
+    /// in through the UART4 handler.
+    fn send_ack(
+        uart: &mut Serial<pac::UART4>,
+        key: &Key,
+        acked: &ParsedMessage,
+        time: u32,
+        radio: Option<RadioConfig>,
+    ) -> bool {
         use core::fmt::Write;
         use heapless::String;
 
+        let seq_num = acked.sensor_data.packet_num;
         let ack_packet = AckPacket {
-            msg_type: if is_ack { MSG_TYPE_ACK } else { MSG_TYPE_NACK },
+            msg_type: MSG_TYPE_ACK,
             seq_num,
+            time,
+            radio,
         };
 
-        // Serialize ACK packet
-        let mut ack_buffer = [0u8; 8];
-        match postcard::to_slice(&ack_packet, &mut ack_buffer) {
-            Ok(serialized) => {
-                let ack_len = serialized.len();
-
-                // Send AT command: AT+SEND=1,<length>,<ack_data>\r\n
-                // Address 1 = Node 1 (sender)
-                let cmd_prefix = "AT+SEND=1,";
+        // Serialize and seal ACK packet
+        let mut ack_buffer = [0u8; 16];
+        let mut frame = [0u8; 16 + secure::OVERHEAD];
+        let header = FrameHeader {
+            node: acked.node,
+            direction: Direction::Downlink,
+            counter: acked.frame_counter,
+        };
+        let sealed = postcard::to_slice(&ack_packet, &mut ack_buffer)
+            .ok()
+            .and_then(|serialized| {
+                secure::seal_ack(key, header, &acked.frame_tag, serialized, &mut frame).ok()
+            });
+        match sealed {
+            Some(ack_len) => {
+                // Send AT command: AT+SEND=<node>,<length>,<ack_data>\r\n
+                // back to the sender (its LoRa address is its node id)
+                let mut cmd_prefix: String<16> = String::new();
+                let _ = core::write!(cmd_prefix, "AT+SEND={},{},", acked.node, ack_len);
                 for b in cmd_prefix.as_bytes() {
                     let _ = nb::block!(uart.write(*b));
                 }
 
-                // Send length as ASCII
-                let mut len_str: String<8> = String::new();
-                let _ = core::write!(len_str, "{},", ack_len);
-                for b in len_str.as_bytes() {
-                    let _ = nb::block!(uart.write(*b));
-                }
-
-                // Send binary ACK payload
-                for b in serialized {
+                // Send sealed ACK frame
+                for b in &frame[..ack_len] {
                     let _ = nb::block!(uart.write(*b));
                 }
 
@@ -115,14 +114,12 @@ mod app {
                 let _ = nb::block!(uart.write(b'\r'));
                 let _ = nb::block!(uart.write(b'\n'));
 
-                defmt::info!(
-                    "{} sent for packet #{}",
-                    if is_ack { "ACK" } else { "NACK" },
-                    seq_num
-                );
+                defmt::info!("ACK sent for packet #{}", seq_num);
+                true
             }
-            Err(_) => {
+            None => {
                 defmt::error!("Failed to serialize ACK packet");
+                false
             }
         }
     }
@@ -178,6 +175,8 @@ mod app {
         pub humidity: f32,
         pub gas_resistance: u32,
         pub packet_num: u16,
+        pub timestamp: u32, // Node 1 sample time, Unix seconds (0 = not synced)
+        pub radio: RadioConfig, // Node 1 radio settings (confirms ADR changes)
     }
 
     #[shared]
@@ -188,10 +187,14 @@ mod app {
         last_packet: Option<ParsedMessage>,
         packets_received: u32,
         crc_errors: u32,                  // Week 5: Track CRC validation failures
+        rejected_frames: u32,             // Frames refused by authentication/replay checks
         bmp280: Option<BMP280<I2cProxy>>, // Week 5: Gateway local sensor (optional if not wired)
         gateway_temp: Option<f32>,        // Week 5: Local temperature
         gateway_pressure: Option<f32>,    // Week 5: Local pressure
         uptime_ms: u32,                   // Week 5: Milliseconds since boot (shared between tasks)
+        wall_clock: WallClock,            // Wall-clock time from gateway-service TIME beacons
+        adr: AdrController,               // Picks Node 1's spreading factor and power
+        adr_switch: AdrSwitch,            // Our radio's switch, paced by module replies
     }
 
     #[local]
@@ -199,10 +202,17 @@ mod app {
         led: Pin<'A', 5, Output>,
         timer: CounterHz<pac::TIM2>,
         rx_buffer: Vec<u8, RX_BUFFER_SIZE>,
+        vcp_rx_buffer: Vec<u8, VCP_RX_BUFFER_SIZE>,
+        flash_config: FlashConfig, // Per-node keys
+        replay_guards: Vec<(u8, ReplayGuard), MAX_KEYS>, // Last accepted frame counter per node
+        flash: pac::FLASH,         // For recording accepted frame-counter epochs
     }
 
     #[derive(Debug, Clone, Copy)]
     pub struct ParsedMessage {
+        pub node: u8,           // Sender, from the authenticated frame header
+        pub frame_counter: u32, // Frame counter, echoed in the ACK
+        pub frame_tag: Tag,     // Frame tag, the ACK is bound to it
         pub sensor_data: SensorData,
         pub rssi: i16,
         pub snr: i16,
@@ -225,6 +235,21 @@ mod app {
         cortex_m::asm::delay(8_400_000); // ~100ms at 84 MHz
     }
 
+    /// Listen on Node 1's ADR spreading factor (we always transmit at full power)
+    ///
+    /// Only writes the command; the module's +OK/+ERR comes in through the
+    /// UART4 handler (`AdrSwitch`).
+    fn apply_radio_config(uart: &mut Serial<pac::UART4>, radio: RadioConfig) {
+        let mut cmd_buf: String<32> = String::new();
+        let _ = core::write!(cmd_buf, "{}", radio.parameter_command());
+        defmt::info!("Sending AT command: {}", cmd_buf.as_str());
+        for byte in cmd_buf.as_bytes() {
+            let _ = nb::block!(uart.write(*byte));
+        }
+        let _ = nb::block!(uart.write(b'\r'));
+        let _ = nb::block!(uart.write(b'\n'));
+    }
+
     #[init]
     fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
         let dp = cx.device;
@@ -239,6 +264,26 @@ mod app {
 
         let led = gpioa.pa5.into_push_pull_output();
 
+        // --- Flash config: LoRa address and per-node keys ---
+        let flash = dp.FLASH;
+        let flash_config = match FlashConfig::decode(&flash.read()[provision::SECTOR_OFFSET..]) {
+            Ok(config) => config,
+            Err(e) => defmt::panic!(
+                "Flash config: {} - provision this node first",
+                defmt::Display2Format(&e)
+            ),
+        };
+        // Replays stay rejected across our reboots up to the last recorded epochs
+        let epoch_log = &flash.read()[provision::SECTOR_OFFSET + provision::EPOCH_LOG_OFFSET..]
+            [..provision::EPOCH_LOG_LEN];
+        let replay_guards = flash_config
+            .nodes()
+            .map(|node| {
+                let last_epoch = provision::last_seen_epoch(epoch_log, node);
+                (node, ReplayGuard::resume(last_epoch))
+            })
+            .collect();
+
         // --- UART4 for LoRa ---
         let tx = gpioc.pc10.into_alternate();
         let rx = gpioc.pc11.into_alternate();
@@ -253,9 +298,11 @@ mod app {
         // Configure LoRa module before enabling RX interrupt
         defmt::info!("Configuring LoRa module (Node 2)...");
         send_at_command(&mut lora_uart, "AT");
-        send_at_command(&mut lora_uart, "AT+ADDRESS=2");
-
         let mut cmd_buf: String<32> = String::new();
+        let _ = core::write!(cmd_buf, "AT+ADDRESS={}", flash_config.address);
+        send_at_command(&mut lora_uart, cmd_buf.as_str());
+
+        cmd_buf.clear();
         let _ = core::write!(cmd_buf, "AT+NETWORKID={}", NETWORK_ID);
         send_at_command(&mut lora_uart, cmd_buf.as_str());
 
@@ -263,7 +310,10 @@ mod app {
         let _ = core::write!(cmd_buf, "AT+BAND={}000000", LORA_FREQ);
         send_at_command(&mut lora_uart, cmd_buf.as_str());
 
-        send_at_command(&mut lora_uart, "AT+PARAMETER=7,9,1,7");
+        // Boot on the ADR boot settings; Node 1 starts there too
+        cmd_buf.clear();
+        let _ = core::write!(cmd_buf, "{}", RadioConfig::BOOT.parameter_command());
+        send_at_command(&mut lora_uart, cmd_buf.as_str());
 
         // Flush any pending responses from configuration BEFORE enabling interrupt
         while lora_uart.read().is_ok() {}
@@ -325,7 +375,7 @@ mod app {
         let vcp_tx = gpioa.pa2.into_alternate();
         let vcp_rx = gpioa.pa3.into_alternate();
 
-        let vcp_uart = Serial::new(
+        let mut vcp_uart = Serial::new(
             dp.USART2,
             (vcp_tx, vcp_rx),
             SerialConfig::default().baudrate(115200.bps()),
@@ -333,6 +383,9 @@ mod app {
         )
         .unwrap();
 
+        // Listen for downlink commands (time sync) from gateway-service
+        vcp_uart.listen(SerialEvent::RxNotEmpty);
+
         defmt::info!("USART2 VCP initialized at 115200 baud");
 
         // --- Week 5: BMP280 Sensor Initialization ---
@@ -362,21 +415,29 @@ mod app {
                 last_packet: None,
                 packets_received: 0,
                 crc_errors: 0,
+                rejected_frames: 0,
                 bmp280: bmp,
                 gateway_temp: None,
                 gateway_pressure: None,
                 uptime_ms: 0,
+                wall_clock: WallClock::new(),
+                adr: AdrController::new(AdrPolicy::default(), 0),
+                adr_switch: AdrSwitch::new(),
             },
             Local {
                 led,
                 timer,
                 rx_buffer: Vec::new(),
+                vcp_rx_buffer: Vec::new(),
+                flash_config,
+                replay_guards,
+                flash,
             },
             init::Monotonics(),
         )
     }
 
-    #[task(binds = TIM2, shared = [display, last_packet, packets_received, bmp280, gateway_temp, gateway_pressure, uptime_ms], local = [led, timer])]
+    #[task(binds = TIM2, shared = [display, last_packet, packets_received, bmp280, gateway_temp, gateway_pressure, uptime_ms, adr, adr_switch, lora_uart], local = [led, timer])]
     fn tim2_handler(mut cx: tim2_handler::Context) {
         cx.local
             .timer
@@ -384,7 +445,25 @@ mod app {
         cx.local.led.toggle();
 
         // Increment uptime (timer runs at 2 Hz, so 500ms per tick)
-        cx.shared.uptime_ms.lock(|uptime| *uptime += 500);
+        let uptime = cx.shared.uptime_ms.lock(|uptime| {
+            *uptime += 500;
+            *uptime
+        });
+
+        // ADR: give up on a switch the module never answered
+        if cx.shared.adr_switch.lock(|switch| switch.poll(uptime)) == SwitchStep::Failed {
+            defmt::warn!("ADR: no reply from LoRa module, staying on current settings");
+            cx.shared.adr.lock(|adr| adr.switch_failed());
+        }
+
+        // ADR: if Node 1 went quiet, go back to the settings before an
+        // unconfirmed change, or meet it on the most robust ones
+        let idle = cx.shared.adr_switch.lock(|switch| switch.is_idle());
+        if let Some(radio) = idle.then(|| cx.shared.adr.lock(|adr| adr.poll(uptime))).flatten() {
+            defmt::warn!("ADR: no packets from Node 1, switching to SF{}", radio.spreading_factor);
+            cx.shared.lora_uart.lock(|uart| apply_radio_config(uart, radio));
+            cx.shared.adr_switch.lock(|switch| switch.parameter_sent(radio, uptime));
+        }
 
         // Read BMP280 sensor (gateway local sensor)
         cx.shared.bmp280.lock(|bmp_opt| {
@@ -449,8 +528,14 @@ mod app {
                 Text::new(&buf, Point::new(0, 32), style).draw(disp).ok();
 
                 buf.clear();
-                // Line 4: Network ID and frequency
-                let _ = core::write!(buf, "Net:{} {}MHz", NETWORK_ID, LORA_FREQ);
+                // Line 4: Network ID, frequency and Node 1 spreading factor
+                let _ = core::write!(
+                    buf,
+                    "Net:{} {}MHz SF{}",
+                    NETWORK_ID,
+                    LORA_FREQ,
+                    parsed.sensor_data.radio.spreading_factor
+                );
                 Text::new(&buf, Point::new(0, 44), style).draw(disp).ok();
 
                 buf.clear();
@@ -482,7 +567,7 @@ mod app {
     // 4. Clear buffer for next message
     //
     // NO display updates here - those happen in the timer interrupt
-    #[task(binds = UART4, shared = [lora_uart, vcp_uart, last_packet, packets_received, crc_errors, gateway_temp, gateway_pressure, uptime_ms], local = [rx_buffer])]
+    #[task(binds = UART4, shared = [lora_uart, vcp_uart, last_packet, packets_received, crc_errors, rejected_frames, gateway_temp, gateway_pressure, uptime_ms, wall_clock, adr, adr_switch], local = [rx_buffer, flash_config, replay_guards, flash])]
     fn uart4_handler(mut cx: uart4_handler::Context) {
         // FIRST: Clear any UART error flags (ORE, FE, NE) that would block reception
         let uart_ptr = unsafe { &*pac::UART4::ptr() };
@@ -529,6 +614,31 @@ mod app {
             );
         }
 
+        // Replies to our own AT commands share the buffer with received frames
+        if should_process {
+            for ok in skip_module_replies(cx.local.rx_buffer) {
+                // They pace our side of an ADR switch
+                match cx.shared.adr_switch.lock(|switch| switch.on_reply(ok)) {
+                    SwitchStep::SendParameter(radio) => {
+                        let uptime = cx.shared.uptime_ms.lock(|t| *t);
+                        cx.shared.lora_uart.lock(|uart| apply_radio_config(uart, radio));
+                        cx.shared
+                            .adr_switch
+                            .lock(|switch| switch.parameter_sent(radio, uptime));
+                    }
+                    SwitchStep::Done(radio) => {
+                        defmt::info!("ADR: listening on SF{}", radio.spreading_factor);
+                    }
+                    SwitchStep::Failed => {
+                        defmt::warn!("ADR: radio switch failed, staying on current settings");
+                        cx.shared.adr.lock(|adr| adr.switch_failed());
+                    }
+                    SwitchStep::Wait => {}
+                }
+            }
+            should_process = !cx.local.rx_buffer.is_empty();
+        }
+
         // Process message OUTSIDE uart lock to allow new interrupts
         if should_process {
             // Debug: log buffer length and attempt to show as text
@@ -539,7 +649,13 @@ mod app {
 
             // Parse +RCV message format: +RCV=<Address>,<Length>,<Data>,<RSSI>,<SNR>\r\n
             // The <Data> part is now BINARY (not text), but RSSI/SNR are still text
-            if let Some(parsed) = parse_binary_lora_message(cx.local.rx_buffer.as_slice()) {
+            let result = parse_binary_lora_message(
+                cx.local.rx_buffer.as_mut_slice(),
+                cx.local.flash_config,
+                cx.local.replay_guards,
+                cx.local.flash,
+            );
+            if let Ok((parsed, key)) = result {
                 defmt::info!(
                     "Binary RX - T:{} H:{} G:{} Pkt:{} RSSI:{} SNR:{}",
                     parsed.sensor_data.temperature,
@@ -559,20 +675,58 @@ mod app {
                     *count += 1;
                 });
 
-                // Send ACK back to Node 1 (CRC validation passed)
-                cx.shared.lora_uart.lock(|uart| {
-                    send_ack(uart, parsed.sensor_data.packet_num, true);
+                // Send ACK back to the sender (frame authenticated), relaying our time
+                // and any ADR change; we follow the change once the module has
+                // sent the ACK (see the module replies above)
+                let timestamp = cx.shared.uptime_ms.lock(|t| *t);
+                let clock = cx.shared.wall_clock.lock(|c| *c);
+                let adr_command = cx.shared.adr.lock(|adr| {
+                    adr.on_packet(parsed.snr, parsed.sensor_data.radio, timestamp)
                 });
+                let sent = cx.shared.lora_uart.lock(|uart| {
+                    send_ack(
+                        uart,
+                        &key,
+                        &parsed,
+                        clock.now_secs_or_zero(timestamp),
+                        adr_command,
+                    )
+                });
+                let switching = sent
+                    && cx
+                        .shared
+                        .adr_switch
+                        .lock(|switch| switch.ack_sent(adr_command, timestamp));
+                if let Some(radio) = adr_command {
+                    if switching {
+                        defmt::info!(
+                            "ADR: Node 1 -> SF{} {}dBm",
+                            radio.spreading_factor,
+                            radio.tx_power_dbm
+                        );
+                    } else {
+                        defmt::warn!("ADR: radio switch failed, staying on current settings");
+                        cx.shared.adr.lock(|adr| adr.switch_failed());
+                    }
+                }
 
                 // Send JSON telemetry via USB
-                let timestamp = cx.shared.uptime_ms.lock(|t| *t);
                 let total = cx.shared.packets_received.lock(|c| *c);
                 let errors = cx.shared.crc_errors.lock(|e| *e);
+                let rejected = cx.shared.rejected_frames.lock(|r| *r);
                 let gw_temp = cx.shared.gateway_temp.lock(|t| *t);
                 let gw_press = cx.shared.gateway_pressure.lock(|p| *p);
 
-                let json =
-                    format_json_telemetry(&parsed, timestamp, total, errors, gw_temp, gw_press);
+                let json = format_json_telemetry(
+                    &parsed,
+                    timestamp,
+                    clock.now_ms(timestamp),
+                    total,
+                    errors,
+                    rejected,
+                    gw_temp,
+                    gw_press,
+                );
 
                 // Write JSON to USART2 (ST-Link VCP)
                 cx.shared.vcp_uart.lock(|uart| {
@@ -582,9 +736,11 @@ mod app {
                 });
 
                 defmt::info!("JSON sent via VCP: {}", json.as_str());
+            } else if let Err(RxError::Rejected) = result {
+                defmt::warn!("Frame rejected");
+                cx.shared.rejected_frames.lock(|rejected| *rejected += 1);
             } else {
                 defmt::warn!("Failed to parse binary message");
-                // Increment CRC error counter on parse failure
                 cx.shared.crc_errors.lock(|errors| *errors += 1);
             }
 
@@ -593,13 +749,115 @@ mod app {
         }
     }
 
+    // USART2 (VCP) interrupt: downlink commands from gateway-service
+    //
+    // Lines are short and rare (one TIME beacon a minute), so they're parsed
+    // right here. Only the wall clock is touched - LoRa handling is untouched.
+    #[task(binds = USART2, shared = [vcp_uart, uptime_ms, wall_clock], local = [vcp_rx_buffer])]
+    fn usart2_handler(mut cx: usart2_handler::Context) {
+        // Clear overrun/framing/noise errors so reception keeps going
+        let uart_ptr = unsafe { &*pac::USART2::ptr() };
+        let sr = uart_ptr.sr().read();
+        if sr.ore().bit_is_set() || sr.nf().bit_is_set() || sr.fe().bit_is_set() {
+            let _ = uart_ptr.dr().read();
+        }
+
+        let mut line_complete = false;
+        cx.shared.vcp_uart.lock(|uart| {
+            while let Ok(byte) = uart.read() {
+                if byte == b'\n' {
+                    line_complete = true;
+                    break;
+                }
+                if cx.local.vcp_rx_buffer.push(byte).is_err() {
+                    defmt::warn!("VCP RX line too long, dropping");
+                    cx.local.vcp_rx_buffer.clear();
+                }
+            }
+        });
+
+        if !line_complete {
+            return;
+        }
+
+        let command = core::str::from_utf8(cx.local.vcp_rx_buffer.as_slice())
+            .ok()
+            .and_then(parse_downlink);
+        match command {
+            Some(Downlink::TimeSync { unix_ms }) => {
+                let uptime = cx.shared.uptime_ms.lock(|t| *t);
+                cx.shared.wall_clock.lock(|clock| clock.sync(unix_ms, uptime));
+                defmt::info!("Time sync: unix_ms={} at uptime {}ms", unix_ms, uptime);
+            }
+            None => defmt::warn!("Unknown VCP downlink command"),
+        }
+        cx.local.vcp_rx_buffer.clear();
+    }
+
+    /// Drop complete lines at the front of `buffer` that aren't received frames:
+    /// the module's "+OK" / "+ERR=<n>" answers to our AT commands. They are
+    /// not frame errors and must not count as such. Returns the answers in
+    /// order (true for "+OK").
+    fn skip_module_replies(buffer: &mut Vec<u8, RX_BUFFER_SIZE>) -> Vec<bool, 4> {
+        let mut replies = Vec::new();
+        while !buffer.is_empty() && !buffer.starts_with(b"+RCV=") {
+            let Some(end) = buffer.iter().position(|&b| b == b'\n') else {
+                break;
+            };
+            if buffer.starts_with(b"+OK") {
+                let _ = replies.push(true);
+            } else if buffer.starts_with(b"+ERR") {
+                defmt::warn!("LoRa module replied {=[u8]:a}", buffer[..end]);
+                let _ = replies.push(false);
+            }
+            *buffer = Vec::from_slice(&buffer[end + 1..]).unwrap_or_default();
+        }
+        replies
+    }
+
+    /// Record in the flash epoch log that we accept `node`'s frames in `epoch`
+    ///
+    /// A single word program (no erase), quick enough for the UART4 handler.
+    fn record_epoch(flash: &mut pac::FLASH, node: u8, epoch: u16) -> bool {
+        let log = &flash.read()[provision::SECTOR_OFFSET + provision::EPOCH_LOG_OFFSET..]
+            [..provision::EPOCH_LOG_LEN];
+        let Some(slot) = provision::seen_epoch_slot(log, node, epoch) else {
+            defmt::error!("Epoch log full: re-provision with new keys");
+            return false;
+        };
+        let offset = provision::SECTOR_OFFSET + provision::EPOCH_LOG_OFFSET + slot.offset;
+        if flash.unlocked().program(offset, slot.word.iter()).is_err() {
+            defmt::error!("Recording epoch {} of node {} failed", epoch, node);
+            return false;
+        }
+        defmt::info!("Node {} frame-counter epoch {}", node, epoch);
+        true
+    }
+
+    /// Why a received frame was dropped
+    enum RxError {
+        /// Unreadable: bad +RCV line, truncated or undecodable payload
+        Malformed,
+        /// Unknown node, replayed, failed authentication or sent from
+        /// another address
+        Rejected,
+    }
+
     /// Parse binary LoRa message from RYLR998
     /// Format: +RCV=<Address>,<Length>,<BinaryData>,<RSSI>,<SNR>\r\n
-    /// where <BinaryData> is postcard-serialized SensorDataPacket
-    fn parse_binary_lora_message(buffer: &[u8]) -> Option<ParsedMessage> {
+    /// where <BinaryData> is a sealed frame holding a postcard-serialized
+    /// SensorDataPacket. Returns the message and the sender's key.
+    fn parse_binary_lora_message(
+        buffer: &mut [u8],
+        config: &FlashConfig,
+        replay_guards: &mut [(u8, ReplayGuard)],
+        flash: &mut pac::FLASH,
+    ) -> Result<(ParsedMessage, Key), RxError> {
+        use RxError::{Malformed, Rejected};
+
         // Check prefix: must start with "+RCV="
         if buffer.len() < 10 || &buffer[0..5] != b"+RCV=" {
-            return None;
+            return Err(Malformed);
         }
 
         // Find first two commas by scanning bytes
@@ -617,13 +875,19 @@ mod app {
             }
         }
 
-        let comma1 = comma1_pos?;
-        let comma2 = comma2_pos?;
+        let comma1 = comma1_pos.ok_or(Malformed)?;
+        let comma2 = comma2_pos.ok_or(Malformed)?;
+
+        // LoRa address the module received the frame from (ASCII)
+        let source: u16 = core::str::from_utf8(&buffer[5..comma1])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or(Malformed)?;
 
         // Extract length from between commas (this is ASCII text)
         let len_bytes = &buffer[comma1 + 1..comma2];
-        let len_str = core::str::from_utf8(len_bytes).ok()?;
-        let payload_len: usize = len_str.parse().ok()?;
+        let len_str = core::str::from_utf8(len_bytes).map_err(|_| Malformed)?;
+        let payload_len: usize = len_str.parse().map_err(|_| Malformed)?;
 
         // Binary payload starts after second comma
         let payload_start = comma2 + 1;
@@ -631,76 +895,97 @@ mod app {
 
         if payload_end > buffer.len() {
             defmt::warn!("Payload exceeds buffer");
-            return None;
+            return Err(Malformed);
         }
 
-        let binary_payload = &buffer[payload_start..payload_end];
-
-        // Payload format: [data bytes...][CRC high byte][CRC low byte]
-        // Minimum payload: 3 bytes (1 byte data + 2 bytes CRC)
-        if binary_payload.len() < 3 {
-            defmt::warn!("Payload too short for CRC validation");
-            return None;
-        }
-
-        // Split payload: data is everything except last 2 bytes
-        let data_len = binary_payload.len() - 2;
-        let data_bytes = &binary_payload[0..data_len];
-        let received_crc_high = binary_payload[data_len];
-        let received_crc_low = binary_payload[data_len + 1];
-        let received_crc = ((received_crc_high as u16) << 8) | (received_crc_low as u16);
-
-        // Calculate CRC on data portion
-        let calculated_crc = calculate_crc16(data_bytes);
-
-        // Validate CRC
-        if received_crc != calculated_crc {
-            defmt::error!(
-                "CRC FAIL! Received: 0x{:04X}, Calculated: 0x{:04X}",
-                received_crc,
-                calculated_crc
+        // Pick the sender's key and replay guard from the (not yet trusted) header
+        let header = match secure::peek_header(&buffer[payload_start..payload_end]) {
+            Ok(header) if header.direction == Direction::Uplink => header,
+            _ => {
+                defmt::warn!("Not an uplink frame");
+                return Err(Malformed);
+            }
+        };
+        let (Some(key), Some((_, guard))) = (
+            config.key_for(header.node),
+            replay_guards.iter_mut().find(|(node, _)| *node == header.node),
+        ) else {
+            defmt::warn!("No key for node {}", header.node);
+            return Err(Rejected);
+        };
+        if !guard.is_fresh(header.counter) {
+            defmt::warn!(
+                "Replayed frame from node {} (counter 0x{:08X})",
+                header.node,
+                header.counter
             );
-            return None;
+            return Err(Rejected);
         }
 
-        defmt::info!("CRC OK: 0x{:04X}", received_crc);
+        // Authenticate and decrypt in place (the tag stays as it was)
+        let frame_tag =
+            secure::frame_tag(&buffer[payload_start..payload_end]).map_err(|_| Malformed)?;
+        let plaintext = match secure::open(key, &mut buffer[payload_start..payload_end]) {
+            Ok((_, plaintext)) => plaintext,
+            Err(e) => {
+                defmt::error!("Frame rejected: {}", defmt::Display2Format(&e));
+                return Err(Rejected);
+            }
+        };
+        // The sender's key opened it, so it must also have come from that address
+        if source != header.node as u16 {
+            defmt::warn!("Frame of node {} sent from address {}", header.node, source);
+            return Err(Rejected);
+        }
+        // Remember a new epoch across reboots before acknowledging anything in
+        // it; a frame we can't remember is one we can't accept
+        if guard.is_new_epoch(header.counter) && !record_epoch(flash, header.node, header.epoch()) {
+            return Err(Rejected);
+        }
+        guard.accept(header.counter);
 
-        // Deserialize with postcard (only the data portion, not the CRC)
-        let sensor_packet: SensorDataPacket = match postcard::from_bytes(data_bytes) {
+        // Deserialize with postcard
+        let sensor_packet: SensorDataPacket = match postcard::from_bytes(plaintext) {
             Ok(pkt) => pkt,
             Err(_) => {
                 defmt::error!("Postcard deserialization failed");
-                return None;
+                return Err(Malformed);
             }
         };
 
         // Parse RSSI and SNR after the binary payload (this is ASCII text)
         // Format: ,<rssi>,<snr>\r\n
         let after_payload_bytes = &buffer[payload_end..];
-        let after_payload_str = core::str::from_utf8(after_payload_bytes).ok()?;
+        let after_payload_str = core::str::from_utf8(after_payload_bytes).map_err(|_| Malformed)?;
 
         let parts: Vec<&str, 4> = after_payload_str.split(',').collect();
         if parts.len() < 3 {
-            return None;
+            return Err(Malformed);
         }
 
-        let rssi: i16 = parts[1].parse().ok()?;
-        let snr: i16 = parts[2].trim().parse().ok()?;
+        let rssi: i16 = parts[1].parse().map_err(|_| Malformed)?;
+        let snr: i16 = parts[2].trim().parse().map_err(|_| Malformed)?;
 
         // Convert from binary format to display format
         let temp_c = sensor_packet.temperature as f32 / 10.0;
         let humid_pct = sensor_packet.humidity as f32 / 100.0;
 
-        Some(ParsedMessage {
+        let message = ParsedMessage {
+            node: header.node,
+            frame_counter: header.counter,
+            frame_tag,
             sensor_data: SensorData {
                 temperature: temp_c,
                 humidity: humid_pct,
                 gas_resistance: sensor_packet.gas_resistance,
                 packet_num: sensor_packet.seq_num,
+                timestamp: sensor_packet.timestamp,
+                radio: sensor_packet.radio,
             },
             rssi,
             snr,
-        })
+        };
+        Ok((message, *key))
     }
 
     /// Format telemetry as JSON for USB output
@@ -708,8 +993,10 @@ mod app {
     fn format_json_telemetry(
         parsed: &ParsedMessage,
         timestamp_ms: u32,
+        utc_ms: Option<u64>,
         packets_received: u32,
         crc_errors: u32,
+        rejected_frames: u32,
         gateway_temp: Option<f32>,
         gateway_pressure: Option<f32>,
     ) -> heapless::String<512> {
@@ -718,6 +1005,9 @@ mod app {
 
         // Start JSON object (compact format to fit in USB buffer)
         let _ = write!(json, "{{\"ts\":{},", timestamp_ms);
+        if let Some(utc) = utc_ms {
+            let _ = write!(json, "\"utc\":{},", utc);
+        }
         let _ = write!(json, "\"id\":\"N2\",");
 
         // Node 1 sensor data (remote sensor via LoRa) - use short keys
@@ -729,6 +1019,9 @@ mod app {
         let _ = write!(json, "\"t\":{:.1},", temp);
         let _ = write!(json, "\"h\":{:.1},", hum);
         let _ = write!(json, "\"g\":{}", gas);
+        if parsed.sensor_data.timestamp != 0 {
+            let _ = write!(json, ",\"ts\":{}", parsed.sensor_data.timestamp);
+        }
         let _ = write!(json, "}},");
 
         // Node 2 (gateway) sensor data (BMP280 local sensor)
@@ -744,16 +1037,19 @@ mod app {
         }
         let _ = write!(json, "}},");
 
-        // Signal quality (RSSI and SNR from LoRa)
+        // Signal quality (RSSI and SNR from LoRa, Node 1 ADR settings)
         let _ = write!(json, "\"sig\":{{");
         let _ = write!(json, "\"rssi\":{},", parsed.rssi);
-        let _ = write!(json, "\"snr\":{}", parsed.snr);
+        let _ = write!(json, "\"snr\":{},", parsed.snr);
+        let _ = write!(json, "\"sf\":{},", parsed.sensor_data.radio.spreading_factor);
+        let _ = write!(json, "\"pwr\":{}", parsed.sensor_data.radio.tx_power_dbm);
         let _ = write!(json, "}},");
 
         // Statistics (packet counts and errors)
         let _ = write!(json, "\"sts\":{{");
         let _ = write!(json, "\"rx\":{},", packets_received);
-        let _ = write!(json, "\"err\":{}", crc_errors);
+        let _ = write!(json, "\"err\":{},", crc_errors);
+        let _ = write!(json, "\"rej\":{}", rejected_frames);
         let _ = write!(json, "}}}}\\n"); // Close stats, close root, add newline
 
         json